@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+use jpeg_encoder::{
+    quantize_block, ColorType, Encoder, QuantizationTable, QuantizationTableType, SamplingFactor,
+};
 use std::time::Duration;
 
 fn create_bench_img() -> (Vec<u8>, u16, u16) {
@@ -54,6 +56,23 @@ fn create_bench_img() -> (Vec<u8>, u16, u16) {
     (data, width as u16, height as u16)
 }
 
+fn create_bench_frame() -> (Vec<u8>, u16, u16) {
+    let width = 64;
+    let height = 48;
+
+    let mut data = Vec::with_capacity(width * height * 3);
+
+    for y in 0..height {
+        for x in 0..width {
+            data.push((x * 4) as u8);
+            data.push((y * 5) as u8);
+            data.push(((x * y) % 256) as u8);
+        }
+    }
+
+    (data, width as u16, height as u16)
+}
+
 fn encode_rgb_100(res: &mut Vec<u8>, data: &[u8], width: u16, height: u16) {
     let encoder = Encoder::new(res, 100);
     encoder.encode(data, width, height, ColorType::Rgb).unwrap();
@@ -186,6 +205,91 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 
     group.finish();
+
+    let (frame, frame_width, frame_height) = create_bench_frame();
+
+    let mut group = c.benchmark_group("encode frames");
+    group.measurement_time(Duration::from_secs(30));
+    group.warm_up_time(Duration::from_secs(5));
+
+    group.bench_function("encode 100 frames new encoder", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                let encoder = Encoder::new(std::io::sink(), 80);
+                encoder
+                    .encode(
+                        black_box(&frame),
+                        black_box(frame_width),
+                        black_box(frame_height),
+                        ColorType::Rgb,
+                    )
+                    .unwrap();
+            }
+        })
+    });
+
+    group.bench_function("encode 100 frames reused encoder", |b| {
+        let mut encoder = Encoder::new(std::io::sink(), 80);
+
+        b.iter(|| {
+            for _ in 0..100 {
+                encoder
+                    .encode_frame(
+                        black_box(&frame),
+                        black_box(frame_width),
+                        black_box(frame_height),
+                        ColorType::Rgb,
+                    )
+                    .unwrap();
+            }
+        })
+    });
+
+    group.finish();
+
+    let table =
+        QuantizationTable::new_with_quality(&QuantizationTableType::Default, 80, true, false);
+
+    let mut block = [0i16; 64];
+    for (i, v) in block.iter_mut().enumerate() {
+        *v = (i as i16 * 397 % 4096 - 2048) * 4;
+    }
+
+    let mut group = c.benchmark_group("quantize");
+    group.measurement_time(Duration::from_secs(30));
+    group.warm_up_time(Duration::from_secs(5));
+
+    group.bench_function("default quantize", |b| {
+        b.iter(|| {
+            let mut q_block = [0i16; 64];
+            quantize_block(black_box(&block), &mut q_block, black_box(&table));
+            black_box(&q_block);
+        })
+    });
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    group.bench_function("quantize avx2", |b| {
+        b.iter(|| {
+            use jpeg_encoder::quantize_block_avx2;
+
+            let mut q_block = [0i16; 64];
+            quantize_block_avx2(black_box(&block), &mut q_block, black_box(&table));
+            black_box(&q_block);
+        })
+    });
+
+    #[cfg(feature = "std_simd")]
+    group.bench_function("quantize std_simd", |b| {
+        b.iter(|| {
+            use jpeg_encoder::quantize_block_std_simd;
+
+            let mut q_block = [0i16; 64];
+            quantize_block_std_simd(black_box(&block), &mut q_block, black_box(&table));
+            black_box(&q_block);
+        })
+    });
+
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
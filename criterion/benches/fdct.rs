@@ -39,6 +39,33 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    #[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+    group.bench_function("fdct avx512", |b| {
+        b.iter(|| {
+            use jpeg_encoder::fdct_avx512;
+
+            // Two blocks per call
+            let mut input = [INPUT1, INPUT1];
+            fdct_avx512(
+                black_box(&mut input),
+            );
+            black_box(&input);
+        })
+    });
+
+    #[cfg(feature = "std_simd")]
+    group.bench_function("fdct std_simd", |b| {
+        b.iter(|| {
+            use jpeg_encoder::fdct_std_simd;
+
+            let mut input = INPUT1.clone();
+            fdct_std_simd(
+                black_box(&mut input),
+            );
+            black_box(&input);
+        })
+    });
+
     group.finish();
 }
 
@@ -18,7 +18,7 @@ fuzz_target!(|data: &[u8]| {
             let mut table = [0;64];
             for i in 0..64 {
                 let v = q_table[i*2] as u16 + (q_table[i*2+1] as u16) << 8;
-                table[i] = v.max(1);
+                table[i] = v;
             }
 
             let table = QuantizationTableType::Custom(Box::new(table));
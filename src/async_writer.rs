@@ -0,0 +1,173 @@
+use crate::{ColorType, Encoder, EncodingError, ImageBuffer};
+
+use alloc::vec::Vec;
+use core::future::Future;
+
+/// An async alternative for [JfifWrite](crate::JfifWrite)
+///
+/// This trait is implemented for `tokio::io::AsyncWrite` if the `tokio` feature is enabled.
+pub trait AsyncJfifWrite {
+    /// Writes the whole buffer. The behavior must be identical to `AsyncWriteExt::write_all`
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the data can't be written
+    fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = Result<(), EncodingError>> + Send;
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: AsyncJfifWrite + Send + ?Sized> AsyncJfifWrite for &mut W {
+    fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = Result<(), EncodingError>> + Send {
+        (**self).write_all(buf)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl AsyncJfifWrite for Vec<u8> {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin + Send + ?Sized> AsyncJfifWrite for W {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        tokio::io::AsyncWriteExt::write_all(self, buf).await?;
+        Ok(())
+    }
+}
+
+/// # Encoder for async writers
+///
+/// Same as [Encoder] but writes the image into an [AsyncJfifWrite].
+///
+/// The image is encoded by a wrapped [Encoder] one MCU row at a time. The output of each
+/// row is collected in an internal buffer and written before the next row is encoded, so the
+/// buffer only holds a single row. The DCT, quantization and entropy coding still run
+/// synchronously on the calling task, only writing the output is awaited.
+///
+/// Progressive images, [optimized huffman tables](Encoder::set_optimized_huffman_tables) and
+/// sampling factors of 4 need all blocks of the image before the first scan can be written,
+/// so their output is buffered completely and written at once.
+///
+/// The buffer is kept between calls to [encode](AsyncEncoder::encode), so encoding a sequence
+/// of images doesn't need further allocations for the output.
+///
+/// # Example
+/// ```
+/// # use jpeg_encoder::EncodingError;
+/// # pub async fn encode(socket: &mut Vec<u8>) -> Result<(), EncodingError> {
+/// use jpeg_encoder::{AsyncEncoder, ColorType};
+///
+/// let mut encoder = AsyncEncoder::new(socket, 90);
+/// encoder.encoder_mut().set_progressive(true);
+///
+/// encoder.encode(&[255, 0, 0, 0, 255, 0], 2, 1, ColorType::Rgb).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncEncoder<W: AsyncJfifWrite> {
+    encoder: Encoder<Vec<u8>>,
+    writer: W,
+}
+
+impl<W: AsyncJfifWrite> AsyncEncoder<W> {
+    /// Create a new async encoder with the given quality
+    ///
+    /// See [Encoder::new] for the default settings.
+    pub fn new(writer: W, quality: u8) -> AsyncEncoder<W> {
+        AsyncEncoder {
+            encoder: Encoder::new(Vec::new(), quality),
+            writer,
+        }
+    }
+
+    /// Returns the encoder used for encoding
+    pub fn encoder(&self) -> &Encoder<Vec<u8>> {
+        &self.encoder
+    }
+
+    /// Returns the encoder used for encoding to change its settings
+    pub fn encoder_mut(&mut self) -> &mut Encoder<Vec<u8>> {
+        &mut self.encoder
+    }
+
+    /// Returns the async writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Encode an image and write it into the async writer
+    ///
+    /// Data format and length must conform to specified width, height and color type.
+    pub async fn encode(
+        &mut self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+    ) -> Result<(), EncodingError> {
+        self.begin();
+
+        loop {
+            let result = self.encoder.encode_frame(data, width, height, color_type);
+
+            if !self.write_rows(result).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Encode an image buffer and write it into the async writer
+    pub async fn encode_image<I: ImageBuffer>(&mut self, image: I) -> Result<(), EncodingError> {
+        self.begin();
+
+        loop {
+            let result = self.encoder.encode_image_frame(&image);
+
+            if !self.write_rows(result).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn begin(&mut self) {
+        self.encoder.writer_mut().clear();
+        self.encoder.set_write_rows(true);
+    }
+
+    /// Writes the output of an encoding step and returns true if the frame isn't complete yet
+    async fn write_rows(
+        &mut self,
+        result: Result<(), EncodingError>,
+    ) -> Result<bool, EncodingError> {
+        let pending = result.is_ok() && self.encoder.has_pending_rows();
+
+        let result = match result {
+            Ok(()) => self.flush().await,
+            Err(err) => {
+                self.encoder.writer_mut().clear();
+                Err(err)
+            }
+        };
+
+        if !pending || result.is_err() {
+            self.encoder.set_write_rows(false);
+        }
+
+        result.map(|_| pending)
+    }
+
+    async fn flush(&mut self) -> Result<(), EncodingError> {
+        let output = self.encoder.writer_mut();
+
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.writer.write_all(output).await;
+        output.clear();
+        result
+    }
+}
@@ -1,8 +1,11 @@
 mod fdct;
+mod quantize;
 mod ycbcr;
 
 use crate::encoder::Operations;
+use crate::quantization::QuantizationTable;
 pub use fdct::fdct_avx2;
+pub use quantize::quantize_block_avx2;
 pub(crate) use ycbcr::*;
 
 pub(crate) struct AVX2Operations;
@@ -12,4 +15,9 @@ impl Operations for AVX2Operations {
     fn fdct(data: &mut [i16; 64]) {
         fdct_avx2(data);
     }
+
+    #[inline(always)]
+    fn quantize_block(block: &[i16; 64], q_block: &mut [i16; 64], table: &QuantizationTable) {
+        quantize_block_avx2(block, q_block, table);
+    }
 }
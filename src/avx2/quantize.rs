@@ -0,0 +1,74 @@
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{
+    __m128i, __m256i, _mm256_abs_epi32, _mm256_add_epi32, _mm256_cvtepi16_epi32,
+    _mm256_loadu_si256, _mm256_mullo_epi32, _mm256_srai_epi32, _mm256_storeu_si256,
+    _mm256_sub_epi32, _mm256_xor_si256, _mm_loadu_si128,
+};
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{
+    __m128i, __m256i, _mm256_abs_epi32, _mm256_add_epi32, _mm256_cvtepi16_epi32,
+    _mm256_loadu_si256, _mm256_mullo_epi32, _mm256_srai_epi32, _mm256_storeu_si256,
+    _mm256_sub_epi32, _mm256_xor_si256, _mm_loadu_si128,
+};
+
+use crate::quantization::{QuantizationTable, SHIFT};
+use crate::writer::ZIGZAG;
+
+/// Quantizes a block with the same math as [QuantizationTable::quantize]
+///
+/// The input block is in natural order while the quantized block is in zigzag order.
+#[inline(always)]
+pub fn quantize_block_avx2(block: &[i16; 64], q_block: &mut [i16; 64], table: &QuantizationTable) {
+    unsafe {
+        quantize_block_avx2_internal(block, q_block, table);
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn quantize_block_avx2_internal(
+    block: &[i16; 64],
+    q_block: &mut [i16; 64],
+    table: &QuantizationTable,
+) {
+    let reciprocals = table.reciprocals();
+    let corrections = table.corrections();
+
+    let mut quantized = [0i32; 64];
+
+    for i in (0..64).step_by(8) {
+        let value = _mm256_cvtepi16_epi32(_mm_loadu_si128(block.as_ptr().add(i) as *const __m128i));
+        let reciprocal = _mm256_loadu_si256(reciprocals.as_ptr().add(i) as *const __m256i);
+        let correction = _mm256_loadu_si256(corrections.as_ptr().add(i) as *const __m256i);
+
+        let abs_value = _mm256_abs_epi32(value);
+
+        let product = _mm256_mullo_epi32(_mm256_add_epi32(abs_value, correction), reciprocal);
+        let product = _mm256_srai_epi32(product, SHIFT as i32);
+
+        // Restore the sign: all bits set for negative values, which negates the product
+        let sign = _mm256_srai_epi32(value, 31);
+        let product = _mm256_sub_epi32(_mm256_xor_si256(product, sign), sign);
+
+        _mm256_storeu_si256(quantized.as_mut_ptr().add(i) as *mut __m256i, product);
+    }
+
+    for i in 0..64 {
+        q_block[i] = quantized[ZIGZAG[i] as usize & 0x3f] as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avx2::quantize::quantize_block_avx2;
+    use crate::quantization::check_quantize_block_parity;
+
+    #[test]
+    fn test_quantize_block_parity() {
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        check_quantize_block_parity(quantize_block_avx2);
+    }
+}
@@ -12,7 +12,71 @@ use core::arch::x86_64::{
 
 use alloc::vec::Vec;
 
-use crate::{rgb_to_ycbcr, ImageBuffer, JpegColorType};
+use crate::{cmyk_to_ycck, rgb_to_ycbcr, ImageBuffer, JpegColorType};
+
+/// Converts 8 RGB values to YCbCr with the same math as [rgb_to_ycbcr]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn rgb_to_ycbcr_avx2(r: __m256i, g: __m256i, b: __m256i) -> ([i32; 8], [i32; 8], [i32; 8]) {
+    let ymulr = _mm256_set1_epi32(19595);
+    let ymulg = _mm256_set1_epi32(38470);
+    let ymulb = _mm256_set1_epi32(7471);
+
+    let cbmulr = _mm256_set1_epi32(-11059);
+    let cbmulg = _mm256_set1_epi32(21709);
+    let cbmulb = _mm256_set1_epi32(32768);
+
+    let crmulr = _mm256_set1_epi32(32768);
+    let crmulg = _mm256_set1_epi32(27439);
+    let crmulb = _mm256_set1_epi32(5329);
+
+    let yr = _mm256_mullo_epi32(ymulr, r);
+    let yg = _mm256_mullo_epi32(ymulg, g);
+    let yb = _mm256_mullo_epi32(ymulb, b);
+
+    let y = _mm256_add_epi32(_mm256_add_epi32(yr, yg), yb);
+    let y = _mm256_add_epi32(y, _mm256_set1_epi32(0x7FFF));
+    let y = _mm256_srli_epi32(y, 16);
+    let y: [i32; 8] = core::mem::transmute(y);
+
+    let cbr = _mm256_mullo_epi32(cbmulr, r);
+    let cbg = _mm256_mullo_epi32(cbmulg, g);
+    let cbb = _mm256_mullo_epi32(cbmulb, b);
+
+    let cb = _mm256_add_epi32(_mm256_sub_epi32(cbr, cbg), cbb);
+    let cb = _mm256_add_epi32(cb, _mm256_set1_epi32(128 << 16));
+    let cb = _mm256_add_epi32(cb, _mm256_set1_epi32(0x7FFF));
+    let cb = _mm256_srli_epi32(cb, 16);
+    let cb: [i32; 8] = core::mem::transmute(cb);
+
+    let crr = _mm256_mullo_epi32(crmulr, r);
+    let crg = _mm256_mullo_epi32(crmulg, g);
+    let crb = _mm256_mullo_epi32(crmulb, b);
+
+    let cr = _mm256_sub_epi32(_mm256_sub_epi32(crr, crg), crb);
+    let cr = _mm256_add_epi32(cr, _mm256_set1_epi32(128 << 16));
+    let cr = _mm256_add_epi32(cr, _mm256_set1_epi32(0x7FFF));
+    let cr = _mm256_srli_epi32(cr, 16);
+    let cr: [i32; 8] = core::mem::transmute(cr);
+
+    (y, cb, cr)
+}
+
+/// Loads 8 values of a channel of pixels with the given number of colors
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn load_channel(data: *const u8, num_colors: isize) -> __m256i {
+    _mm256_set_epi32(
+        *data as i32,
+        *data.offset(num_colors) as i32,
+        *data.offset(2 * num_colors) as i32,
+        *data.offset(3 * num_colors) as i32,
+        *data.offset(4 * num_colors) as i32,
+        *data.offset(5 * num_colors) as i32,
+        *data.offset(6 * num_colors) as i32,
+        *data.offset(7 * num_colors) as i32,
+    )
+}
 
 macro_rules! ycbcr_image_avx2 {
     ($name:ident, $num_colors:expr, $o1:expr, $o2:expr, $o3:expr) => {
@@ -21,19 +85,6 @@ macro_rules! ycbcr_image_avx2 {
         impl<'a> $name<'a> {
             #[target_feature(enable = "avx2")]
             unsafe fn fill_buffers_avx2(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
-                unsafe fn load3(data: *const u8) -> __m256i {
-                    _mm256_set_epi32(
-                        *data as i32,
-                        *data.offset(1 * $num_colors) as i32,
-                        *data.offset(2 * $num_colors) as i32,
-                        *data.offset(3 * $num_colors) as i32,
-                        *data.offset(4 * $num_colors) as i32,
-                        *data.offset(5 * $num_colors) as i32,
-                        *data.offset(6 * $num_colors) as i32,
-                        *data.offset(7 * $num_colors) as i32,
-                    )
-                }
-
                 let mut y_buffer = buffers[0].as_mut_ptr().add(buffers[0].len());
                 buffers[0].set_len(buffers[0].len() + self.width() as usize);
                 let mut cb_buffer = buffers[1].as_mut_ptr().add(buffers[1].len());
@@ -41,58 +92,19 @@ macro_rules! ycbcr_image_avx2 {
                 let mut cr_buffer = buffers[2].as_mut_ptr().add(buffers[2].len());
                 buffers[2].set_len(buffers[2].len() + self.width() as usize);
 
-                let ymulr = _mm256_set1_epi32(19595);
-                let ymulg = _mm256_set1_epi32(38470);
-                let ymulb = _mm256_set1_epi32(7471);
-
-                let cbmulr = _mm256_set1_epi32(-11059);
-                let cbmulg = _mm256_set1_epi32(21709);
-                let cbmulb = _mm256_set1_epi32(32768);
-
-                let crmulr = _mm256_set1_epi32(32768);
-                let crmulg = _mm256_set1_epi32(27439);
-                let crmulb = _mm256_set1_epi32(5329);
-
                 let mut data = self
                     .0
                     .as_ptr()
                     .offset((y as isize * self.1 as isize * $num_colors));
 
                 for _ in 0..self.width() / 8 {
-                    let r = load3(data.offset($o1));
-                    let g = load3(data.offset($o2));
-                    let b = load3(data.offset($o3));
+                    let r = load_channel(data.offset($o1), $num_colors);
+                    let g = load_channel(data.offset($o2), $num_colors);
+                    let b = load_channel(data.offset($o3), $num_colors);
 
                     data = data.add($num_colors * 8);
 
-                    let yr = _mm256_mullo_epi32(ymulr, r);
-                    let yg = _mm256_mullo_epi32(ymulg, g);
-                    let yb = _mm256_mullo_epi32(ymulb, b);
-
-                    let y = _mm256_add_epi32(_mm256_add_epi32(yr, yg), yb);
-                    let y = _mm256_add_epi32(y, _mm256_set1_epi32(0x7FFF));
-                    let y = _mm256_srli_epi32(y, 16);
-                    let y: [i32; 8] = core::mem::transmute(y);
-
-                    let cbr = _mm256_mullo_epi32(cbmulr, r);
-                    let cbg = _mm256_mullo_epi32(cbmulg, g);
-                    let cbb = _mm256_mullo_epi32(cbmulb, b);
-
-                    let cb = _mm256_add_epi32(_mm256_sub_epi32(cbr, cbg), cbb);
-                    let cb = _mm256_add_epi32(cb, _mm256_set1_epi32(128 << 16));
-                    let cb = _mm256_add_epi32(cb, _mm256_set1_epi32(0x7FFF));
-                    let cb = _mm256_srli_epi32(cb, 16);
-                    let cb: [i32; 8] = core::mem::transmute(cb);
-
-                    let crr = _mm256_mullo_epi32(crmulr, r);
-                    let crg = _mm256_mullo_epi32(crmulg, g);
-                    let crb = _mm256_mullo_epi32(crmulb, b);
-
-                    let cr = _mm256_sub_epi32(_mm256_sub_epi32(crr, crg), crb);
-                    let cr = _mm256_add_epi32(cr, _mm256_set1_epi32(128 << 16));
-                    let cr = _mm256_add_epi32(cr, _mm256_set1_epi32(0x7FFF));
-                    let cr = _mm256_srli_epi32(cr, 16);
-                    let cr: [i32; 8] = core::mem::transmute(cr);
+                    let (y, cb, cr) = rgb_to_ycbcr_avx2(r, g, b);
 
                     for y in y.iter().rev() {
                         *y_buffer = *y as u8;
@@ -155,3 +167,207 @@ ycbcr_image_avx2!(RgbImageAVX2, 3, 0, 1, 2);
 ycbcr_image_avx2!(RgbaImageAVX2, 4, 0, 1, 2);
 ycbcr_image_avx2!(BgrImageAVX2, 3, 2, 1, 0);
 ycbcr_image_avx2!(BgraImageAVX2, 4, 2, 1, 0);
+
+pub(crate) struct CmykAsYcckImageAVX2<'a>(pub &'a [u8], pub u16, pub u16);
+
+impl<'a> CmykAsYcckImageAVX2<'a> {
+    #[target_feature(enable = "avx2")]
+    unsafe fn fill_buffers_avx2(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let mut y_buffer = buffers[0].as_mut_ptr().add(buffers[0].len());
+        buffers[0].set_len(buffers[0].len() + self.width() as usize);
+        let mut cb_buffer = buffers[1].as_mut_ptr().add(buffers[1].len());
+        buffers[1].set_len(buffers[1].len() + self.width() as usize);
+        let mut cr_buffer = buffers[2].as_mut_ptr().add(buffers[2].len());
+        buffers[2].set_len(buffers[2].len() + self.width() as usize);
+        let mut k_buffer = buffers[3].as_mut_ptr().add(buffers[3].len());
+        buffers[3].set_len(buffers[3].len() + self.width() as usize);
+
+        let mut data = self.0.as_ptr().offset(y as isize * self.1 as isize * 4);
+
+        for _ in 0..self.width() / 8 {
+            let c = load_channel(data, 4);
+            let m = load_channel(data.offset(1), 4);
+            let y = load_channel(data.offset(2), 4);
+            let k = load_channel(data.offset(3), 4);
+
+            data = data.add(4 * 8);
+
+            let (y, cb, cr) = rgb_to_ycbcr_avx2(c, m, y);
+
+            let k = _mm256_sub_epi32(_mm256_set1_epi32(255), k);
+            let k: [i32; 8] = core::mem::transmute(k);
+
+            for y in y.iter().rev() {
+                *y_buffer = *y as u8;
+                y_buffer = y_buffer.offset(1);
+            }
+
+            for cb in cb.iter().rev() {
+                *cb_buffer = *cb as u8;
+                cb_buffer = cb_buffer.offset(1);
+            }
+
+            for cr in cr.iter().rev() {
+                *cr_buffer = *cr as u8;
+                cr_buffer = cr_buffer.offset(1);
+            }
+
+            for k in k.iter().rev() {
+                *k_buffer = *k as u8;
+                k_buffer = k_buffer.offset(1);
+            }
+        }
+
+        for _ in 0..self.width() % 8 {
+            let (y, cb, cr, k) =
+                cmyk_to_ycck(*data, *data.offset(1), *data.offset(2), *data.offset(3));
+
+            data = data.add(4);
+
+            *y_buffer = y;
+            y_buffer = y_buffer.offset(1);
+
+            *cb_buffer = cb;
+            cb_buffer = cb_buffer.offset(1);
+
+            *cr_buffer = cr;
+            cr_buffer = cr_buffer.offset(1);
+
+            *k_buffer = k;
+            k_buffer = k_buffer.offset(1);
+        }
+    }
+}
+
+impl<'a> ImageBuffer for CmykAsYcckImageAVX2<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycck
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    #[inline(always)]
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        unsafe {
+            self.fill_buffers_avx2(y, buffers);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::avx2::ycbcr::{
+        BgrImageAVX2, BgraImageAVX2, CmykAsYcckImageAVX2, RgbImageAVX2, RgbaImageAVX2,
+    };
+    use crate::image_buffer::{BgrImage, BgraImage, CmykAsYcckImage, RgbImage, RgbaImage};
+    use crate::ImageBuffer;
+
+    // Odd width to test the scalar fallback for the remaining pixels
+    const WIDTH: u16 = 37;
+    const HEIGHT: u16 = 3;
+
+    fn create_test_img(num_colors: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(usize::from(WIDTH) * usize::from(HEIGHT) * num_colors);
+
+        for y in 0..usize::from(HEIGHT) {
+            for x in 0..usize::from(WIDTH) {
+                data.push((x * 7 + y * 3) as u8);
+                data.push((x * 13 + 40) as u8);
+                data.push((255 - x * 5 - y) as u8);
+
+                if num_colors == 4 {
+                    // Alpha values which differ from all color channels
+                    data.push((x * 31 + 200) as u8);
+                }
+            }
+        }
+
+        data
+    }
+
+    fn get_buffers<I: ImageBuffer>(image: &I) -> [Vec<u8>; 4] {
+        let width = usize::from(image.width());
+        let height = usize::from(image.height());
+
+        // The AVX2 implementations need the buffers to be preallocated
+        let mut buffers = [
+            Vec::with_capacity(width * height),
+            Vec::with_capacity(width * height),
+            Vec::with_capacity(width * height),
+            Vec::with_capacity(width * height),
+        ];
+
+        for y in 0..image.height() {
+            image.fill_buffers(y, &mut buffers);
+        }
+
+        buffers
+    }
+
+    #[test]
+    fn test_rgb_parity() {
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let data = create_test_img(3);
+
+        assert_eq!(
+            get_buffers(&RgbImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&RgbImage(&data, WIDTH, HEIGHT))
+        );
+        assert_eq!(
+            get_buffers(&BgrImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&BgrImage(&data, WIDTH, HEIGHT))
+        );
+    }
+
+    #[test]
+    fn test_rgba_parity() {
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let data = create_test_img(4);
+
+        assert_eq!(
+            get_buffers(&RgbaImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&RgbaImage(&data, WIDTH, HEIGHT))
+        );
+        assert_eq!(
+            get_buffers(&BgraImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&BgraImage(&data, WIDTH, HEIGHT))
+        );
+
+        // The same colors without alpha must give the same result,
+        // which wouldn't be the case if alpha values were read as color values
+        let rgb = create_test_img(3);
+
+        assert_eq!(
+            get_buffers(&RgbaImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&RgbImageAVX2(&rgb, WIDTH, HEIGHT))
+        );
+    }
+
+    #[test]
+    fn test_cmyk_as_ycck_parity() {
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let data = create_test_img(4);
+
+        assert_eq!(
+            get_buffers(&CmykAsYcckImageAVX2(&data, WIDTH, HEIGHT)),
+            get_buffers(&CmykAsYcckImage(&data, WIDTH, HEIGHT))
+        );
+    }
+}
@@ -0,0 +1,3 @@
+mod fdct;
+
+pub use fdct::fdct_avx512;
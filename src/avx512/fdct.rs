@@ -0,0 +1,404 @@
+/*
+ * Ported from mozjpeg / jfdctint-avx2.asm to rust
+ * Copyright 2009 Pierre Ossman <ossman@cendio.se> for Cendio AB
+ * Copyright (C) 2009, 2016, 2018, 2020, D. R. Commander.
+ *
+ * Based on the x86 SIMD extension for IJG JPEG library
+ * Copyright (C) 1999-2006, MIYASAKA Masaru.
+ */
+
+// Same algorithm as the AVX2 version but with the rows of two blocks in the lower and the
+// upper 256 bits of each register. All lane crossing AVX2 operations are replaced by
+// permutations that stay within the 256 bit halves.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{
+    __m256i, __m512i, _mm256_loadu_si256, _mm256_set_epi16, _mm256_storeu_si256, _mm512_add_epi16,
+    _mm512_add_epi32, _mm512_broadcast_i64x4, _mm512_castsi256_si512, _mm512_castsi512_si256,
+    _mm512_extracti64x4_epi64, _mm512_inserti64x4, _mm512_madd_epi16, _mm512_mask_sub_epi16,
+    _mm512_packs_epi32, _mm512_permutex2var_epi64, _mm512_permutex_epi64, _mm512_set1_epi32,
+    _mm512_set_epi64, _mm512_setzero_si512, _mm512_slli_epi16, _mm512_srai_epi16,
+    _mm512_srai_epi32, _mm512_sub_epi16, _mm512_unpackhi_epi16, _mm512_unpackhi_epi32,
+    _mm512_unpacklo_epi16, _mm512_unpacklo_epi32,
+};
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{
+    __m256i, __m512i, _mm256_loadu_si256, _mm256_set_epi16, _mm256_storeu_si256, _mm512_add_epi16,
+    _mm512_add_epi32, _mm512_broadcast_i64x4, _mm512_castsi256_si512, _mm512_castsi512_si256,
+    _mm512_extracti64x4_epi64, _mm512_inserti64x4, _mm512_madd_epi16, _mm512_mask_sub_epi16,
+    _mm512_packs_epi32, _mm512_permutex2var_epi64, _mm512_permutex_epi64, _mm512_set1_epi32,
+    _mm512_set_epi64, _mm512_setzero_si512, _mm512_slli_epi16, _mm512_srai_epi16,
+    _mm512_srai_epi32, _mm512_sub_epi16, _mm512_unpackhi_epi16, _mm512_unpackhi_epi32,
+    _mm512_unpacklo_epi16, _mm512_unpacklo_epi32,
+};
+
+const CONST_BITS: i32 = 13;
+const PASS1_BITS: i32 = 2;
+
+// FIX(0.298631336)
+const F_0_298: i16 = 2446;
+// FIX(0.390180644)
+const F_0_390: i16 = 3196;
+// FIX(0.541196100)
+const F_0_541: i16 = 4433;
+// FIX(0.765366865)
+const F_0_765: i16 = 6270;
+//FIX(0.899976223)
+const F_0_899: i16 = 7373;
+//FIX(1.175875602)
+const F_1_175: i16 = 9633;
+//FIX(1.501321110)
+const F_1_501: i16 = 12299;
+//FIX(1.847759065)
+const F_1_847: i16 = 15137;
+//FIX(1.961570560)
+const F_1_961: i16 = 16069;
+//FIX(2.053119869)
+const F_2_053: i16 = 16819;
+//FIX(2.562915447)
+const F_2_562: i16 = 20995;
+//FIX(3.072711026)
+const F_3_072: i16 = 25172;
+
+const DESCALE_P1: i32 = CONST_BITS - PASS1_BITS;
+const DESCALE_P2: i32 = CONST_BITS + PASS1_BITS;
+
+/// Forward DCT of two blocks
+///
+/// Computes the same coefficients as calling [fdct_avx2](crate::avx2::fdct_avx2) for
+/// each block.
+#[inline(always)]
+pub fn fdct_avx512(blocks: &mut [[i16; 64]; 2]) {
+    unsafe {
+        fdct_avx512_internal(blocks);
+    }
+}
+
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn fdct_avx512_internal(blocks: &mut [[i16; 64]; 2]) {
+    #[allow(non_snake_case)]
+    #[inline(always)]
+    unsafe fn PW_F130_F054_MF130_F054() -> __m512i {
+        _mm512_broadcast_i64x4(_mm256_set_epi16(
+            F_0_541,
+            F_0_541 - F_1_847,
+            F_0_541,
+            F_0_541 - F_1_847,
+            F_0_541,
+            F_0_541 - F_1_847,
+            F_0_541,
+            F_0_541 - F_1_847,
+            F_0_541,
+            F_0_541 + F_0_765,
+            F_0_541,
+            F_0_541 + F_0_765,
+            F_0_541,
+            F_0_541 + F_0_765,
+            F_0_541,
+            F_0_541 + F_0_765,
+        ))
+    }
+
+    #[allow(non_snake_case)]
+    #[inline(always)]
+    unsafe fn PW_MF078_F117_F078_F117() -> __m512i {
+        _mm512_broadcast_i64x4(_mm256_set_epi16(
+            F_1_175,
+            F_1_175 - F_0_390,
+            F_1_175,
+            F_1_175 - F_0_390,
+            F_1_175,
+            F_1_175 - F_0_390,
+            F_1_175,
+            F_1_175 - F_0_390,
+            F_1_175,
+            F_1_175 - F_1_961,
+            F_1_175,
+            F_1_175 - F_1_961,
+            F_1_175,
+            F_1_175 - F_1_961,
+            F_1_175,
+            F_1_175 - F_1_961,
+        ))
+    }
+
+    #[allow(non_snake_case)]
+    #[inline(always)]
+    unsafe fn PW_MF060_MF089_MF050_MF256() -> __m512i {
+        _mm512_broadcast_i64x4(_mm256_set_epi16(
+            -F_2_562,
+            F_2_053 - F_2_562,
+            -F_2_562,
+            F_2_053 - F_2_562,
+            -F_2_562,
+            F_2_053 - F_2_562,
+            -F_2_562,
+            F_2_053 - F_2_562,
+            -F_0_899,
+            F_0_298 - F_0_899,
+            -F_0_899,
+            F_0_298 - F_0_899,
+            -F_0_899,
+            F_0_298 - F_0_899,
+            -F_0_899,
+            F_0_298 - F_0_899,
+        ))
+    }
+
+    #[allow(non_snake_case)]
+    #[inline(always)]
+    unsafe fn PW_F050_MF256_F060_MF089() -> __m512i {
+        _mm512_broadcast_i64x4(_mm256_set_epi16(
+            -F_0_899,
+            F_1_501 - F_0_899,
+            -F_0_899,
+            F_1_501 - F_0_899,
+            -F_0_899,
+            F_1_501 - F_0_899,
+            -F_0_899,
+            F_1_501 - F_0_899,
+            -F_2_562,
+            F_3_072 - F_2_562,
+            -F_2_562,
+            F_3_072 - F_2_562,
+            -F_2_562,
+            F_3_072 - F_2_562,
+            -F_2_562,
+            F_3_072 - F_2_562,
+        ))
+    }
+
+    #[allow(non_snake_case)]
+    #[inline(always)]
+    unsafe fn PW_DESCALE_P2X() -> __m512i {
+        _mm512_set1_epi32(1 << (PASS1_BITS - 1))
+    }
+
+    #[inline(always)]
+    unsafe fn descale(first_pass: bool, value: __m512i) -> __m512i {
+        if first_pass {
+            let value = _mm512_add_epi32(value, _mm512_set1_epi32(1 << (DESCALE_P1 - 1)));
+            _mm512_srai_epi32::<{ DESCALE_P1 as u32 }>(value)
+        } else {
+            let value = _mm512_add_epi32(value, _mm512_set1_epi32(1 << (DESCALE_P2 - 1)));
+            _mm512_srai_epi32::<{ DESCALE_P2 as u32 }>(value)
+        }
+    }
+
+    // Equivalent of _mm256_permute2x128_si256 applied to both 256 bit halves
+    #[inline(always)]
+    unsafe fn permute2x128(a: __m512i, b: __m512i, imm: i64) -> __m512i {
+        let index = |half: i64, select: i64| (select & 2) * 4 + half * 4 + (select & 1) * 2;
+
+        let lo0 = index(0, imm & 3);
+        let hi0 = index(0, (imm >> 4) & 3);
+        let lo1 = index(1, imm & 3);
+        let hi1 = index(1, (imm >> 4) & 3);
+
+        _mm512_permutex2var_epi64(
+            a,
+            _mm512_set_epi64(hi1 + 1, hi1, lo1 + 1, lo1, hi0 + 1, hi0, lo0 + 1, lo0),
+            b,
+        )
+    }
+
+    // In-place 8x8x16-bit matrix transpose of two blocks using AVX-512 instructions
+    #[inline(always)]
+    unsafe fn do_transpose(
+        i1: __m512i,
+        i2: __m512i,
+        i3: __m512i,
+        i4: __m512i,
+    ) -> (__m512i, __m512i, __m512i, __m512i) {
+        let t5 = _mm512_unpacklo_epi16(i1, i2);
+        let t6 = _mm512_unpackhi_epi16(i1, i2);
+        let t7 = _mm512_unpacklo_epi16(i3, i4);
+        let t8 = _mm512_unpackhi_epi16(i3, i4);
+
+        let t1 = _mm512_unpacklo_epi32(t5, t7);
+        let t2 = _mm512_unpackhi_epi32(t5, t7);
+        let t3 = _mm512_unpacklo_epi32(t6, t8);
+        let t4 = _mm512_unpackhi_epi32(t6, t8);
+
+        (
+            _mm512_permutex_epi64::<0x8D>(t1),
+            _mm512_permutex_epi64::<0x8D>(t2),
+            _mm512_permutex_epi64::<0xD8>(t3),
+            _mm512_permutex_epi64::<0xD8>(t4),
+        )
+    }
+
+    // In-place 8x8x16-bit accurate integer forward DCT of two blocks using AVX-512 instructions
+    #[inline(always)]
+    unsafe fn do_dct(
+        first_pass: bool,
+        i1: __m512i,
+        i2: __m512i,
+        i3: __m512i,
+        i4: __m512i,
+    ) -> (__m512i, __m512i, __m512i, __m512i) {
+        let t5 = _mm512_sub_epi16(i1, i4); // data1_0 - data6_7 = tmp6_7
+        let t6 = _mm512_add_epi16(i1, i4); // data1_0 + data6_7 = tmp1_0
+        let t7 = _mm512_add_epi16(i2, i3); // data3_2 + data4_5 = tmp3_2
+        let t8 = _mm512_sub_epi16(i2, i3); // data3_2 - data4_5 = tmp4_5
+
+        // Even part
+
+        let t6 = permute2x128(t6, t6, 0x01); // t6=tmp0_1
+        let t1 = _mm512_add_epi16(t6, t7); // t1 = tmp0_1 + tmp3_2 = tmp10_11
+        let t6 = _mm512_sub_epi16(t6, t7); // t6 = tmp0_1 - tmp3_2 = tmp13_12
+
+        let t7 = permute2x128(t1, t1, 0x01); // t7 = tmp11_10
+
+        // Negate the upper 128 bits of each half
+        let t1 = _mm512_mask_sub_epi16(t1, 0xFF00_FF00, _mm512_setzero_si512(), t1); // tmp10_neg11
+
+        let t7 = _mm512_add_epi16(t7, t1); // t7 = (tmp10 + tmp11)_(tmp10 - tmp11)
+
+        let t1 = if first_pass {
+            _mm512_slli_epi16::<{ PASS1_BITS as u32 }>(t7)
+        } else {
+            let t7 = _mm512_add_epi16(t7, PW_DESCALE_P2X());
+            _mm512_srai_epi16::<{ PASS1_BITS as u32 }>(t7)
+        };
+
+        let t7 = permute2x128(t6, t6, 0x01); // t7 = tmp12_13
+        let t2 = _mm512_unpacklo_epi16(t6, t7);
+        let t6 = _mm512_unpackhi_epi16(t6, t7);
+
+        let t2 = _mm512_madd_epi16(t2, PW_F130_F054_MF130_F054()); // t2 = data2_6L
+        let t6 = _mm512_madd_epi16(t6, PW_F130_F054_MF130_F054()); // t6 = data2_6H
+
+        let t2 = descale(first_pass, t2);
+        let t6 = descale(first_pass, t6);
+
+        let t3 = _mm512_packs_epi32(t2, t6); // t6 = data2_6
+
+        // Odd part
+
+        let t7 = _mm512_add_epi16(t8, t5); // t7 = tmp4_5 + tmp6_7 = z3_4
+
+        let t2 = permute2x128(t7, t7, 0x01); // t2 = z4_3
+        let t6 = _mm512_unpacklo_epi16(t7, t2);
+        let t7 = _mm512_unpackhi_epi16(t7, t2);
+
+        let t6 = _mm512_madd_epi16(t6, PW_MF078_F117_F078_F117()); // t6 = z3_4L
+        let t7 = _mm512_madd_epi16(t7, PW_MF078_F117_F078_F117()); // t7 = z3_4H
+
+        let t4 = permute2x128(t5, t5, 0x01); // t4 = tmp7_6
+        let t2 = _mm512_unpacklo_epi16(t8, t4);
+        let t4 = _mm512_unpackhi_epi16(t8, t4);
+
+        let t2 = _mm512_madd_epi16(t2, PW_MF060_MF089_MF050_MF256()); //t2 = tmp4_5L
+        let t4 = _mm512_madd_epi16(t4, PW_MF060_MF089_MF050_MF256()); // t4 = tmp4_5H
+
+        let t2 = _mm512_add_epi32(t2, t6); // t2 = data7_5L
+        let t4 = _mm512_add_epi32(t4, t7); // t4 = data7_5H
+
+        let t2 = descale(first_pass, t2);
+        let t4 = descale(first_pass, t4);
+
+        let t4 = _mm512_packs_epi32(t2, t4); // t4 = data7_5
+
+        let t2 = permute2x128(t8, t8, 0x01); // t2 = tmp5_4
+
+        let t8 = _mm512_unpacklo_epi16(t5, t2);
+        let t5 = _mm512_unpackhi_epi16(t5, t2);
+
+        let t8 = _mm512_madd_epi16(t8, PW_F050_MF256_F060_MF089()); // t8 = tmp6_7L
+        let t5 = _mm512_madd_epi16(t5, PW_F050_MF256_F060_MF089()); // t5 = tmp6_7H
+
+        let t8 = _mm512_add_epi32(t8, t6); // t8 = data3_1L
+        let t5 = _mm512_add_epi32(t5, t7); // t5 = data3_1H
+
+        let t8 = descale(first_pass, t8);
+        let t5 = descale(first_pass, t5);
+
+        let t2 = _mm512_packs_epi32(t8, t5); // t2 = data3_1
+
+        (t1, t2, t3, t4)
+    }
+
+    #[inline(always)]
+    unsafe fn load(blocks: &[[i16; 64]; 2], index: usize) -> __m512i {
+        let first = (blocks[0].as_ptr() as *const __m256i).add(index);
+        let second = (blocks[1].as_ptr() as *const __m256i).add(index);
+
+        _mm512_inserti64x4::<1>(
+            _mm512_castsi256_si512(_mm256_loadu_si256(first)),
+            _mm256_loadu_si256(second),
+        )
+    }
+
+    #[inline(always)]
+    unsafe fn store(blocks: &mut [[i16; 64]; 2], index: usize, value: __m512i) {
+        let first = (blocks[0].as_mut_ptr() as *mut __m256i).add(index);
+        let second = (blocks[1].as_mut_ptr() as *mut __m256i).add(index);
+
+        _mm256_storeu_si256(first, _mm512_castsi512_si256(value));
+        _mm256_storeu_si256(second, _mm512_extracti64x4_epi64::<1>(value));
+    }
+
+    let zmm4 = load(blocks, 0);
+    let zmm5 = load(blocks, 1);
+    let zmm6 = load(blocks, 2);
+    let zmm7 = load(blocks, 3);
+
+    // ---- Pass 1: process rows.
+
+    let zmm0 = permute2x128(zmm4, zmm6, 0x20);
+    let zmm1 = permute2x128(zmm4, zmm6, 0x31);
+    let zmm2 = permute2x128(zmm5, zmm7, 0x20);
+    let zmm3 = permute2x128(zmm5, zmm7, 0x31);
+
+    let (zmm0, zmm1, zmm2, zmm3) = do_transpose(zmm0, zmm1, zmm2, zmm3);
+    let (zmm0, zmm1, zmm2, zmm3) = do_dct(true, zmm0, zmm1, zmm2, zmm3);
+
+    // ---- Pass 2: process columns.
+
+    let zmm4 = permute2x128(zmm1, zmm3, 0x20); // zmm4=data3_7
+    let zmm1 = permute2x128(zmm1, zmm3, 0x31); // zmm1=data1_5
+
+    let (zmm0, zmm1, zmm2, zmm4) = do_transpose(zmm0, zmm1, zmm2, zmm4);
+    let (zmm0, zmm1, zmm2, zmm4) = do_dct(false, zmm0, zmm1, zmm2, zmm4);
+
+    let zmm3 = permute2x128(zmm0, zmm1, 0x30); // zmm3=data0_1
+    let zmm5 = permute2x128(zmm2, zmm1, 0x20); // zmm5=data2_3
+    let zmm6 = permute2x128(zmm0, zmm4, 0x31); // zmm6=data4_5
+    let zmm7 = permute2x128(zmm2, zmm4, 0x21); // zmm7=data6_7
+
+    store(blocks, 0, zmm3);
+    store(blocks, 1, zmm5);
+    store(blocks, 2, zmm6);
+    store(blocks, 3, zmm7);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avx2::fdct_avx2;
+    use crate::avx512::fdct_avx512;
+
+    #[test]
+    fn test_fdct_avx512_parity() {
+        if !std::is_x86_feature_detected!("avx512f") || !std::is_x86_feature_detected!("avx512bw") {
+            return;
+        }
+
+        let mut blocks = [[0i16; 64]; 2];
+        for (i, v) in blocks.iter_mut().flatten().enumerate() {
+            *v = (i as i32 * 397 % 2048 - 1024) as i16;
+        }
+        blocks[1][0] = -1024;
+        blocks[1][63] = 1023;
+
+        let mut expected = blocks;
+        for block in expected.iter_mut() {
+            fdct_avx2(block);
+        }
+
+        fdct_avx512(&mut blocks);
+
+        assert_eq!(blocks, expected);
+    }
+}
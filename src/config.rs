@@ -0,0 +1,96 @@
+use crate::{Density, QuantizationTableType, SamplingFactor};
+
+/// Settings to create an [Encoder](crate::Encoder) with [from_config](crate::Encoder::from_config)
+///
+/// With the `serde` feature the settings can be (de)serialized, which allows to store presets
+/// e.g. in a TOML or JSON file. Missing fields are set to their default value.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EncoderConfig {
+    /// Quality between 1 and 100 like the quality given to [new](crate::Encoder::new)
+    ///
+    /// Defaults to 75.
+    pub quality: u8,
+
+    /// Sampling factor for chroma subsampling
+    ///
+    /// If `None` the sampling factor depends on the quality like with [new](crate::Encoder::new).
+    pub sampling_factor: Option<SamplingFactor>,
+
+    /// Number of scans per component for progressive encoding or `None` for a baseline image
+    pub progressive_scans: Option<u8>,
+
+    /// Create optimized huffman tables
+    pub optimized_huffman_tables: bool,
+
+    /// Number of MCUs between restart markers or `None` to not write any restart markers
+    pub restart_interval: Option<u16>,
+
+    /// Pixel density written into the JFIF header
+    pub density: Density,
+
+    /// Quantization table for luma components
+    pub luma_quantization_table: QuantizationTableType,
+
+    /// Quantization table for chroma components
+    pub chroma_quantization_table: QuantizationTableType,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        EncoderConfig {
+            quality: 75,
+            sampling_factor: None,
+            progressive_scans: None,
+            optimized_huffman_tables: false,
+            restart_interval: None,
+            density: Density::None,
+            luma_quantization_table: QuantizationTableType::Default,
+            chroma_quantization_table: QuantizationTableType::Default,
+        }
+    }
+}
+
+/// (De)serialization of user supplied quantization tables
+///
+/// Serde only supports arrays up to 32 elements, so tables are written as a sequence.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_table {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(table: &[u16; 64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(table.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u16; 64], D::Error> {
+        let values = Vec::<u16>::deserialize(deserializer)?;
+
+        values
+            .try_into()
+            .map_err(|values: Vec<u16>| D::Error::invalid_length(values.len(), &"64 values"))
+    }
+
+    pub mod boxed {
+        use super::*;
+
+        // The signature is given by the boxed field of the variant
+        #[allow(clippy::borrowed_box)]
+        pub fn serialize<S: Serializer>(
+            table: &Box<[u16; 64]>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            super::serialize(table, serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Box<[u16; 64]>, D::Error> {
+            super::deserialize(deserializer).map(Box::new)
+        }
+    }
+}
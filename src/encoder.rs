@@ -1,14 +1,21 @@
-use crate::fdct::fdct;
+use crate::config::EncoderConfig;
+use crate::fdct::{fdct, fdct_12bit};
 use crate::huffman::{CodingClass, HuffmanTable};
 use crate::image_buffer::*;
-use crate::marker::Marker;
-use crate::quantization::{QuantizationTable, QuantizationTableType};
-use crate::writer::{JfifWrite, JfifWriter, ZIGZAG};
+use crate::marker::{Marker, SOFType};
+use crate::md5::md5;
+use crate::quantization::{QualityRegion, QuantizationTable, QuantizationTableType, RegionTables};
+use crate::stats::{EncodingStats, ScanStats};
+use crate::trellis::trellis_quantize_block;
+use crate::writer::{for_each_ac_symbol, JfifWrite, JfifWriter, ZIGZAG};
 use crate::{Density, EncodingError};
 
+use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 
+use core::convert::Infallible;
+
 #[cfg(feature = "std")]
 use std::io::BufWriter;
 
@@ -34,18 +41,6 @@ pub enum JpegColorType {
     Ycck,
 }
 
-impl JpegColorType {
-    pub(crate) fn get_num_components(self) -> usize {
-        use JpegColorType::*;
-
-        match self {
-            Luma => 1,
-            Ycbcr => 3,
-            Cmyk | Ycck => 4,
-        }
-    }
-}
-
 /// # Color types for input images
 ///
 /// Available color input formats for [Encoder::encode]. Other types can be used
@@ -78,26 +73,132 @@ pub enum ColorType {
 
     /// YCCK (YCbCrK) with 4 bytes per pixel.
     Ycck,
+
+    /// Grayscale with 2 bytes per pixel. Values are scaled down to 8 bits.
+    ///
+    /// The byte order is set with [set_luma16_endianness](Encoder::set_luma16_endianness).
+    Luma16,
+
+    /// RGB with 5, 6 and 5 bits packed into 2 bytes per pixel in little endian order
+    Rgb565,
+
+    /// RGB with a 32 bit float per channel in native byte order (12 bytes per pixel)
+    ///
+    /// Values are clamped to 0.0 - 1.0 and NaN or infinite values are treated as 0.0.
+    /// See [set_input_transfer](Encoder::set_input_transfer) for linear light input.
+    RgbF32,
+
+    /// Packed YCbCr 4:2:2 with the byte order Y0, Cb, Y1, Cr (also known as YUY2)
+    ///
+    /// Two horizontally adjacent pixels share one Cb and Cr sample. Rows of an odd width
+    /// must be padded to a full macro-pixel of 4 bytes, the second luma sample of it is ignored.<br>
+    /// Use the [4:2:2 sampling factor](SamplingFactor::R_4_2_2) to encode the chroma samples
+    /// at their original resolution.
+    Yuyv,
+
+    /// Packed YCbCr 4:2:2 with the byte order Cb, Y0, Cr, Y1
+    ///
+    /// See [Yuyv](ColorType::Yuyv) for the layout of rows.
+    Uyvy,
+
+    /// Grayscale with alpha with 2 bytes per pixel
+    ///
+    /// The alpha channel is ignored unless an [alpha background](Encoder::set_alpha_background)
+    /// is set. In that case pixels are blended over the luma value of the background color.
+    LumaA,
+
+    /// Alpha, Red, Green, Blue with 4 bytes per pixel. The alpha channel will be ignored during encoding.
+    Argb,
+
+    /// Alpha, Blue, Green, Red with 4 bytes per pixel. The alpha channel will be ignored during encoding.
+    Abgr,
 }
 
 impl ColorType {
+    /// Get the color type for the given number of channels
+    ///
+    /// - 1 channel: [Luma](ColorType::Luma)
+    /// - 2 channels with alpha: [LumaA](ColorType::LumaA)
+    /// - 3 channels: [Rgb](ColorType::Rgb) or [Bgr](ColorType::Bgr)
+    /// - 4 channels with alpha: [Rgba](ColorType::Rgba) or [Bgra](ColorType::Bgra)
+    ///
+    /// Returns None for all other combinations.
+    pub fn from_channels(channels: usize, has_alpha: bool, is_bgr: bool) -> Option<ColorType> {
+        use ColorType::*;
+
+        match (channels, has_alpha, is_bgr) {
+            (1, false, _) => Some(Luma),
+            (2, true, _) => Some(LumaA),
+            (3, false, false) => Some(Rgb),
+            (3, false, true) => Some(Bgr),
+            (4, true, false) => Some(Rgba),
+            (4, true, true) => Some(Bgra),
+            _ => None,
+        }
+    }
+
     pub(crate) fn get_bytes_per_pixel(self) -> usize {
         use ColorType::*;
 
         match self {
             Luma => 1,
+            Luma16 | LumaA | Rgb565 | Yuyv | Uyvy => 2,
             Rgb | Bgr | Ycbcr => 3,
-            Rgba | Bgra | Cmyk | CmykAsYcck | Ycck => 4,
+            Rgba | Bgra | Argb | Abgr | Cmyk | CmykAsYcck | Ycck => 4,
+            RgbF32 => 12,
         }
     }
+
+    pub(crate) fn get_data_len(self, width: u16, height: u16) -> usize {
+        use ColorType::*;
+
+        let width = match self {
+            // Packed 4:2:2 rows are padded to an even width
+            Yuyv | Uyvy => (usize::from(width) + 1) / 2 * 2,
+            _ => usize::from(width),
+        };
+
+        width * usize::from(height) * self.get_bytes_per_pixel()
+    }
+
+    /// Same as [get_data_len](ColorType::get_data_len) but returns `None` on overflow
+    pub(crate) fn checked_data_len(self, width: u16, height: u16) -> Option<usize> {
+        self.get_data_len(width, 1).checked_mul(usize::from(height))
+    }
+}
+
+/// # Byte order of multi byte samples
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Endianness {
+    /// Least significant byte first
+    Little,
+
+    /// Most significant byte first
+    Big,
 }
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// # Sampling factors for chroma subsampling
 ///
+/// The `F_h_v` variants name the horizontal and vertical sampling factors of the luma component
+/// relative to the chroma components, while the `R_4_x_y` aliases use the common ratio notation.
+///
+/// | Factors | Ratio     | Chroma resolution                |
+/// |---------|-----------|----------------------------------|
+/// | `F_1_1` | `R_4_4_4` | Full                             |
+/// | `F_1_2` | `R_4_4_0` | Full width, half height          |
+/// | `F_1_4` | `R_4_4_1` | Full width, quarter height       |
+/// | `F_2_1` | `R_4_2_2` | Half width, full height          |
+/// | `F_2_2` | `R_4_2_0` | Half width, half height          |
+/// | `F_2_4` | `R_4_2_1` | Half width, quarter height       |
+/// | `F_4_1` | `R_4_1_1` | Quarter width, full height       |
+/// | `F_4_2` | `R_4_1_0` | Quarter width, half height       |
+///
 /// ## Warning
-/// Sampling factor of 4 are not supported by all decoders or applications
+/// Sampling factor of 4 are not supported by all decoders or applications.
+/// They can be rejected with [set_strict_compatibility](Encoder::set_strict_compatibility).
 #[allow(non_camel_case_types)]
 pub enum SamplingFactor {
     F_1_1 = 1 << 4 | 1,
@@ -157,18 +258,35 @@ impl SamplingFactor {
         ((value >> 4) & 0x07, value & 0xf)
     }
 
-    pub(crate) fn supports_interleaved(self) -> bool {
-        use SamplingFactor::*;
-
-        // Interleaved mode is only supported with h/v sampling factors of 1 or 2.
-        // Sampling factors of 4 needs sequential encoding
-        matches!(
-            self,
-            F_1_1 | F_2_1 | F_1_2 | F_2_2 | R_4_4_4 | R_4_4_0 | R_4_2_2 | R_4_2_0
-        )
+    /// Returns false for factors of 4, which some decoders like browsers can't decode
+    pub fn is_widely_supported(self) -> bool {
+        let (horizontal, vertical) = self.get_sampling_factors();
+        horizontal <= 2 && vertical <= 2
     }
 }
 
+/// # Filter used for chroma downsampling
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DownsampleFilter {
+    /// Use a single sample for each subsampled value
+    ///
+    /// This is faster but may result in visible color artifacts at sharp color edges.
+    Point,
+
+    /// Use the average of all samples covered by a subsampled value
+    Average,
+
+    /// Average the colors covered by a subsampled value in linear light
+    ///
+    /// The YCbCr samples are converted back to RGB and averaged after the sRGB transfer
+    /// function was removed. The chroma of the averaged color is used for the subsampled value.
+    /// This avoids the darkening of [Average](DownsampleFilter::Average) at the boundaries of
+    /// saturated colors, but is considerably slower.<br>
+    /// Only YCbCr images are affected, other components are downsampled like with
+    /// [Average](DownsampleFilter::Average).
+    AverageLinear,
+}
+
 pub(crate) struct Component {
     pub id: u8,
     pub quantization_table: u8,
@@ -192,24 +310,111 @@ macro_rules! add_component {
 }
 
 /// # The JPEG encoder
+///
+/// The encoder is `Send` and `Sync` if the underlying writer is.
+/// All configuration types like [SamplingFactor], [QuantizationTableType] and
+/// [Density] are `Send` and `Sync` and can be shared between threads.
 pub struct Encoder<W: JfifWrite> {
     writer: JfifWriter<W>,
     density: Density,
-    quality: u8,
+    quality: f32,
 
     components: Vec<Component>,
     quantization_tables: [QuantizationTableType; 2],
-    huffman_tables: [(HuffmanTable, HuffmanTable); 2],
+    huffman_tables: [(HuffmanTable, HuffmanTable); 4],
 
     sampling_factor: SamplingFactor,
 
+    auto_sampling_factor: bool,
+
+    downsample_filter: DownsampleFilter,
+
+    color_space: ColorSpace,
+
+    full_range: bool,
+
+    luma16_endianness: Endianness,
+
+    input_transfer: Transfer,
+
+    input_dither: Dither,
+
+    alpha_background: Option<[u8; 3]>,
+
     progressive_scans: Option<u8>,
 
     restart_interval: Option<u16>,
 
     optimize_huffman_table: bool,
 
+    trellis_quantization: bool,
+
+    dead_zone: f32,
+
+    adaptive_quantization: bool,
+
+    quality_regions: Vec<QualityRegion>,
+
+    // Tables of the quality regions for the current encode
+    region_tables: Option<RegionTables>,
+
+    // Tables rescaled for each pass of encode_to_size instead of creating new ones
+    table_search: bool,
+    search_tables: Option<[QuantizationTable; 4]>,
+
+    low_memory: bool,
+
+    force_baseline: bool,
+
+    strict_compatibility: bool,
+
+    write_soi_eoi: bool,
+
+    write_jfif: bool,
+
+    force_grayscale: bool,
+
+    jfif_version: (u8, u8),
+
+    deferred_height: bool,
+
+    // Number of lines which are written in a DNL segment after the first scan
+    pending_dnl: Option<u16>,
+
+    jfif_thumbnail: Option<Vec<u8>>,
+
+    adobe_color_transform: Option<u8>,
+
+    component_sampling_factors: [Option<(u8, u8)>; 4],
+
+    component_quantization_tables: [Option<QuantizationTableType>; 4],
+
+    component_huffman_tables: [Option<(u8, u8)>; 4],
+
+    // Component with its own table and if the luma variant of a preset is used
+    // for each quantization table destination, set by init_components
+    quantization_sources: [(Option<usize>, bool); 4],
+
     app_segments: Vec<(u8, Vec<u8>)>,
+
+    comments: Vec<Vec<u8>>,
+
+    icc_profile: Option<Vec<u8>>,
+
+    cancel_check: Option<Box<dyn Fn() -> bool + Send + Sync>>,
+
+    // Scratch buffers which are kept between encodes to avoid reallocations
+    scratch_rows: [Vec<u8>; 4],
+    scratch_blocks: [Vec<[i16; 64]>; 4],
+    scratch_band: Vec<[i16; 64]>,
+
+    // Set by the AsyncEncoder to write the frame in several calls
+    #[cfg(feature = "async")]
+    write_rows: bool,
+    #[cfg(feature = "async")]
+    pending_rows: Option<PendingRows>,
+
+    stats: EncodingStats,
 }
 
 impl<W: JfifWrite> Encoder<W> {
@@ -217,7 +422,8 @@ impl<W: JfifWrite> Encoder<W> {
     ///
     /// The quality must be between 1 and 100 where 100 is the highest image quality.<br>
     /// By default, quality settings below 90 use a chroma subsampling (2x2 / 4:2:0) which can
-    /// be changed with [set_sampling_factor](Encoder::set_sampling_factor)
+    /// be changed with [set_sampling_factor](Encoder::set_sampling_factor) or avoided by
+    /// creating the encoder with [new_no_subsampling](Encoder::new_no_subsampling).
     pub fn new(w: W, quality: u8) -> Encoder<W> {
         let huffman_tables = [
             (
@@ -228,6 +434,15 @@ impl<W: JfifWrite> Encoder<W> {
                 HuffmanTable::default_chroma_dc(),
                 HuffmanTable::default_chroma_ac(),
             ),
+            // Tables 2 and 3 are only used for optimized tables of 4 component images
+            (
+                HuffmanTable::default_luma_dc(),
+                HuffmanTable::default_luma_ac(),
+            ),
+            (
+                HuffmanTable::default_chroma_dc(),
+                HuffmanTable::default_chroma_ac(),
+            ),
         ];
 
         let quantization_tables = [
@@ -244,21 +459,106 @@ impl<W: JfifWrite> Encoder<W> {
         Encoder {
             writer: JfifWriter::new(w),
             density: Density::None,
-            quality,
+            quality: f32::from(quality),
             components: vec![],
             quantization_tables,
             huffman_tables,
             sampling_factor,
+            auto_sampling_factor: false,
+            downsample_filter: DownsampleFilter::Average,
+            color_space: ColorSpace::Bt601,
+            full_range: true,
+            luma16_endianness: Endianness::Little,
+            input_transfer: Transfer::Srgb,
+            input_dither: Dither::None,
+            alpha_background: None,
             progressive_scans: None,
             restart_interval: None,
             optimize_huffman_table: false,
+            trellis_quantization: false,
+            dead_zone: 0.0,
+            adaptive_quantization: false,
+            quality_regions: Vec::new(),
+            region_tables: None,
+            table_search: false,
+            search_tables: None,
+            low_memory: false,
+            force_baseline: false,
+            strict_compatibility: false,
+            write_soi_eoi: true,
+            write_jfif: true,
+            force_grayscale: false,
+            jfif_version: (1, 2),
+            deferred_height: false,
+            pending_dnl: None,
+            jfif_thumbnail: None,
+            adobe_color_transform: None,
+            component_sampling_factors: [None; 4],
+            component_quantization_tables: Default::default(),
+            component_huffman_tables: [None; 4],
+            quantization_sources: DEFAULT_QUANTIZATION_SOURCES,
             app_segments: Vec::new(),
+            comments: Vec::new(),
+            icc_profile: None,
+            cancel_check: None,
+            scratch_rows: Default::default(),
+            scratch_blocks: Default::default(),
+            scratch_band: Vec::new(),
+            #[cfg(feature = "async")]
+            write_rows: false,
+            #[cfg(feature = "async")]
+            pending_rows: None,
+            stats: EncodingStats::default(),
+        }
+    }
+
+    /// Create a new encoder which keeps the full chroma resolution
+    ///
+    /// Unlike [new](Encoder::new) the chroma components are never subsampled based on the
+    /// quality, so the sampling factor is always [F_1_1](SamplingFactor::F_1_1) (4:4:4).
+    /// This preserves colored detail like text in screenshots or charts at the cost of
+    /// larger files.<br>
+    /// The factor can still be changed with [set_sampling_factor](Encoder::set_sampling_factor)
+    /// or [set_sampling_factor_auto](Encoder::set_sampling_factor_auto).
+    pub fn new_no_subsampling(w: W, quality: u8) -> Encoder<W> {
+        let mut encoder = Self::new(w, quality);
+        encoder.sampling_factor = SamplingFactor::F_1_1;
+        encoder
+    }
+
+    /// Create a new encoder with the settings of a config
+    ///
+    /// Settings not contained in the [EncoderConfig] keep their default values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of progressive scans isn't between 2 and 64
+    pub fn from_config(w: W, config: &EncoderConfig) -> Result<Encoder<W>, EncodingError> {
+        let mut encoder = Self::new(w, config.quality);
+
+        if let Some(sampling_factor) = config.sampling_factor {
+            encoder.set_sampling_factor(sampling_factor);
+        }
+
+        if let Some(scans) = config.progressive_scans {
+            encoder.set_progressive_scans(scans)?;
         }
+
+        encoder.set_optimized_huffman_tables(config.optimized_huffman_tables);
+        encoder.set_restart_interval(config.restart_interval.unwrap_or(0));
+        encoder.set_density(config.density);
+        encoder.set_quantization_tables(
+            config.luma_quantization_table.clone(),
+            config.chroma_quantization_table.clone(),
+        );
+
+        Ok(encoder)
     }
 
     /// Set pixel density for the image
     ///
-    /// By default, this value is None which is equal to "1 pixel per pixel".
+    /// By default, this value is None which is equal to "1 pixel per pixel".<br>
+    /// See [Density] for the units written into the JFIF header.
     pub fn set_density(&mut self, density: Density) {
         self.density = density;
     }
@@ -269,16 +569,224 @@ impl<W: JfifWrite> Encoder<W> {
     }
 
     /// Set chroma subsampling factor
+    ///
+    /// The quantization tables only depend on the quality and the configured
+    /// [quantization tables](Encoder::set_quantization_tables). They are not adjusted
+    /// if the sampling factor is changed after the encoder was created.<br>
+    /// This disables the [automatic selection](Encoder::set_sampling_factor_auto).
     pub fn set_sampling_factor(&mut self, sampling: SamplingFactor) {
         self.sampling_factor = sampling;
+        self.auto_sampling_factor = false;
     }
 
     /// Get chroma subsampling factor
+    ///
+    /// If the [automatic selection](Encoder::set_sampling_factor_auto) is enabled, this returns
+    /// the factor chosen for the last encoded image.
     pub fn sampling_factor(&self) -> SamplingFactor {
         self.sampling_factor
     }
 
+    /// Choose the chroma subsampling factor based on the content of each image
+    ///
+    /// Instead of the quality based default, the chroma components of YCbCr images are
+    /// analyzed before encoding. Images with sharp colored detail are encoded with
+    /// [F_1_1](SamplingFactor::F_1_1) (4:4:4) and smoother images with
+    /// [F_2_2](SamplingFactor::F_2_2) (4:2:0).<br>
+    /// The measure is the mean absolute difference between horizontally and vertically
+    /// neighbouring Cb and Cr samples. If it exceeds [AUTO_SAMPLING_THRESHOLD] the full chroma
+    /// resolution is kept.
+    ///
+    /// The analysis needs an additional pass over the image, which roughly costs as much as
+    /// the color conversion. Images with other color types or already subsampled chroma
+    /// components keep the current factor.<br>
+    /// The chosen factor can be read with [sampling_factor](Encoder::sampling_factor) after
+    /// encoding a frame.
+    pub fn set_sampling_factor_auto(&mut self) {
+        self.auto_sampling_factor = true;
+    }
+
+    /// Returns true if the sampling factor is chosen based on the image content
+    pub fn sampling_factor_auto(&self) -> bool {
+        self.auto_sampling_factor
+    }
+
+    /// Set the sampling factors of a single component
+    ///
+    /// The component index is the position of the component in the JPEG image, e.g. 0 for Y,
+    /// 1 for Cb and 2 for Cr. The factors override the ones derived from the
+    /// [sampling factor](Encoder::set_sampling_factor), which allows layouts like 4:4:0 where
+    /// only the vertical chroma resolution is halved by setting the luma component to 1x2.
+    /// Factors of components that don't exist in the encoded image are ignored.<br>
+    /// Components are interleaved into one scan only if all factors are 1 or 2 and a MCU
+    /// doesn't contain more than 10 blocks. Other combinations are encoded with one scan
+    /// per component.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component index isn't between 0 and 3 or a factor isn't 1, 2 or 4
+    pub fn set_component_sampling(
+        &mut self,
+        component: usize,
+        horizontal: u8,
+        vertical: u8,
+    ) -> Result<(), EncodingError> {
+        if component >= self.component_sampling_factors.len() {
+            return Err(EncodingError::InvalidComponentIndex(component));
+        }
+
+        if !matches!(horizontal, 1 | 2 | 4) || !matches!(vertical, 1 | 2 | 4) {
+            return Err(EncodingError::InvalidSamplingFactor {
+                horizontal,
+                vertical,
+            });
+        }
+
+        self.component_sampling_factors[component] = Some((horizontal, vertical));
+
+        Ok(())
+    }
+
+    /// Returns the sampling factors set for a component with [set_component_sampling](Encoder::set_component_sampling)
+    pub fn component_sampling(&self, component: usize) -> Option<(u8, u8)> {
+        self.component_sampling_factors
+            .get(component)
+            .copied()
+            .flatten()
+    }
+
+    /// Set independent sampling factors for the Cb and Cr components
+    ///
+    /// By default both chroma components are sampled with 1x1 and share the resolution defined by
+    /// the [sampling factor](Encoder::set_sampling_factor) of the luma component.
+    /// Setting a higher factor for Cb than for Cr keeps more blue detail while reducing the
+    /// resolution of the less visible red detail, e.g. `(2, 1)` and `(1, 1)` with
+    /// [F_2_2](SamplingFactor::F_2_2) samples Cb with 4:2:2 and Cr with 4:2:0.
+    ///
+    /// This is a shorthand for calling [set_component_sampling](Encoder::set_component_sampling)
+    /// for the components 1 and 2.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a factor isn't 1, 2 or 4
+    pub fn set_chroma_sampling(&mut self, cb: (u8, u8), cr: (u8, u8)) -> Result<(), EncodingError> {
+        // Validate both before changing anything
+        for (horizontal, vertical) in [cb, cr] {
+            if !matches!(horizontal, 1 | 2 | 4) || !matches!(vertical, 1 | 2 | 4) {
+                return Err(EncodingError::InvalidSamplingFactor {
+                    horizontal,
+                    vertical,
+                });
+            }
+        }
+
+        self.set_component_sampling(1, cb.0, cb.1)?;
+        self.set_component_sampling(2, cr.0, cr.1)
+    }
+
+    /// Set the filter used to downsample subsampled components
+    ///
+    /// By default, this is [Average](DownsampleFilter::Average).
+    /// [Point](DownsampleFilter::Point) sampling is faster and matches the output of older versions.
+    /// [AverageLinear](DownsampleFilter::AverageLinear) gives the most accurate colors.
+    pub fn set_chroma_downsample_filter(&mut self, filter: DownsampleFilter) {
+        self.downsample_filter = filter;
+    }
+
+    /// Get the filter used to downsample subsampled components
+    pub fn chroma_downsample_filter(&self) -> DownsampleFilter {
+        self.downsample_filter
+    }
+
+    /// Set the color space used to convert RGB input to YCbCr
+    ///
+    /// By default, [Bt601](ColorSpace::Bt601) is used as defined by JFIF. Decoders assume this
+    /// color space, so [Bt709](ColorSpace::Bt709) is only useful if the consumer of the image expects it.<br>
+    /// This only affects the RGB color types of [encode](Encoder::encode).
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    /// Get the color space used to convert RGB input to YCbCr
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Controls if full range YCbCr values are written
+    ///
+    /// By default, the full range of 0-255 is used as defined by JFIF. If disabled, luma values
+    /// are scaled to 16-235 and chroma values to 16-240. Limited (studio) range is only meant for
+    /// specialized interoperability, because decoders assume full range values.<br>
+    /// The input is always expected to be full range, including YCbCr input and custom
+    /// [image buffers](crate::ImageBuffer). CMYK images and the K channel of YCCK images are not scaled.
+    pub fn set_full_range(&mut self, full_range: bool) {
+        self.full_range = full_range;
+    }
+
+    /// Returns if full range YCbCr values are written
+    pub fn full_range(&self) -> bool {
+        self.full_range
+    }
+
+    /// Set the byte order of [Luma16](ColorType::Luma16) input
+    ///
+    /// By default, samples are expected in little endian order.
+    pub fn set_luma16_endianness(&mut self, endianness: Endianness) {
+        self.luma16_endianness = endianness;
+    }
+
+    /// Get the byte order of [Luma16](ColorType::Luma16) input
+    pub fn luma16_endianness(&self) -> Endianness {
+        self.luma16_endianness
+    }
+
+    /// Set the transfer function of [RgbF32](ColorType::RgbF32) input
+    ///
+    /// By default, values are expected to be [sRGB](Transfer::Srgb) encoded.
+    /// [Linear](Transfer::Linear) values get encoded with the sRGB transfer function
+    /// before they are converted to 8 bits.
+    pub fn set_input_transfer(&mut self, transfer: Transfer) {
+        self.input_transfer = transfer;
+    }
+
+    /// Get the transfer function of [RgbF32](ColorType::RgbF32) input
+    pub fn input_transfer(&self) -> Transfer {
+        self.input_transfer
+    }
+
+    /// Set the dithering used to reduce [Luma16](ColorType::Luma16) and
+    /// [RgbF32](ColorType::RgbF32) input to 8 bits
+    ///
+    /// By default, values are rounded to the nearest 8 bit value, which can cause banding in
+    /// smooth gradients, e.g. of HDR content. Dithering hides the banding at the cost of
+    /// slightly noisier flat areas.
+    pub fn set_input_dither(&mut self, dither: Dither) {
+        self.input_dither = dither;
+    }
+
+    /// Get the dithering of [Luma16](ColorType::Luma16) and [RgbF32](ColorType::RgbF32) input
+    pub fn input_dither(&self) -> Dither {
+        self.input_dither
+    }
+
+    /// Set the RGB color to blend [Rgba](ColorType::Rgba), [Bgra](ColorType::Bgra),
+    /// [Argb](ColorType::Argb), [Abgr](ColorType::Abgr) and [LumaA](ColorType::LumaA) input over
+    ///
+    /// By default, this is `None` and the alpha channel is ignored. If a background color is set,
+    /// each pixel is blended over it according to its alpha value before the color conversion.
+    pub fn set_alpha_background(&mut self, background: Option<[u8; 3]>) {
+        self.alpha_background = background;
+    }
+
+    /// Get the RGB color to blend input with an alpha channel over
+    pub fn alpha_background(&self) -> Option<[u8; 3]> {
+        self.alpha_background
+    }
+
     /// Set quantization tables for luma and chroma components
+    ///
+    /// The preset tables are scaled according to the quality of the encoder.
+    /// [Custom](QuantizationTableType::Custom) tables are used as given.
     pub fn set_quantization_tables(
         &mut self,
         luma: QuantizationTableType,
@@ -292,6 +800,96 @@ impl<W: JfifWrite> Encoder<W> {
         &self.quantization_tables
     }
 
+    /// Set the quantization table of a single component
+    ///
+    /// The component index is the position of the component in the JPEG image, e.g. 0 for Y,
+    /// 1 for Cb and 2 for Cr or 0 to 3 for C, M, Y and K. Each component with its own table
+    /// gets a separate table destination, which allows up to four distinct tables.
+    /// Other components keep using the luma or chroma table of
+    /// [set_quantization_tables](Encoder::set_quantization_tables). Preset tables use the
+    /// luma or chroma variant the component would use otherwise.<br>
+    /// Only the tables referenced by a component are written. Tables of components that don't
+    /// exist in the encoded image are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component index isn't between 0 and 3
+    pub fn set_component_quantization(
+        &mut self,
+        component: usize,
+        table: QuantizationTableType,
+    ) -> Result<(), EncodingError> {
+        if component >= self.component_quantization_tables.len() {
+            return Err(EncodingError::InvalidComponentIndex(component));
+        }
+
+        self.component_quantization_tables[component] = Some(table);
+
+        Ok(())
+    }
+
+    /// Returns the table set for a component with [set_component_quantization](Encoder::set_component_quantization)
+    pub fn component_quantization(&self, component: usize) -> Option<&QuantizationTableType> {
+        self.component_quantization_tables
+            .get(component)
+            .and_then(Option::as_ref)
+    }
+
+    /// Set the quality as a fractional value
+    ///
+    /// The quality is clamped to the range between 1.0 and 100.0.
+    /// Integral values result in the same tables as the quality given to [new](Encoder::new).<br>
+    /// In contrast to the constructor this doesn't change the [sampling factor](Encoder::set_sampling_factor).
+    pub fn set_quality_f32(&mut self, quality: f32) {
+        self.quality = if quality.is_nan() {
+            1.0
+        } else {
+            quality.clamp(1.0, 100.0)
+        };
+    }
+
+    /// Get the quality
+    pub fn quality(&self) -> f32 {
+        self.quality
+    }
+
+    /// Get the effective quantization table of a component
+    ///
+    /// The component index is the same as for
+    /// [set_component_quantization](Encoder::set_component_quantization). The color type
+    /// decides if a component without its own table uses the luma or chroma table.<br>
+    /// Returns the values in natural (row-major) order after scaling by quality,
+    /// as they are written into the JPEG file, or `None` if the color type has no such component.
+    pub fn quantization_table(
+        &self,
+        color_type: JpegColorType,
+        component: usize,
+    ) -> Option<[u16; 64]> {
+        // Same assignment of the luma and chroma tables as in init_components
+        let luma = match (color_type, component) {
+            (JpegColorType::Luma | JpegColorType::Ycbcr, 0)
+            | (JpegColorType::Ycck, 0 | 3)
+            | (JpegColorType::Cmyk, 3) => true,
+            (JpegColorType::Ycbcr | JpegColorType::Ycck | JpegColorType::Cmyk, 1 | 2)
+            | (JpegColorType::Cmyk, 0) => false,
+            _ => return None,
+        };
+
+        let table_type = self.component_quantization_tables[component]
+            .as_ref()
+            .unwrap_or(&self.quantization_tables[if luma { 0 } else { 1 }]);
+
+        let table = self.create_quantization_table_from(table_type, luma, self.written_quality());
+
+        let mut values = [0u16; 64];
+
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = table.get(i);
+        }
+
+        Some(values)
+    }
+
     /// Controls if progressive encoding is used.
     ///
     /// By default, progressive encoding uses 4 scans.<br>
@@ -305,15 +903,17 @@ impl<W: JfifWrite> Encoder<W> {
     /// Number of scans must be between 2 and 64.
     /// There is at least one scan for the DC coefficients and one for the remaining 63 AC coefficients.
     ///
-    /// # Panics
-    /// If number of scans is not within valid range
-    pub fn set_progressive_scans(&mut self, scans: u8) {
-        assert!(
-            (2..=64).contains(&scans),
-            "Invalid number of scans: {}",
-            scans
-        );
+    /// # Errors
+    ///
+    /// Returns an error if number of scans is not within valid range
+    pub fn set_progressive_scans(&mut self, scans: u8) -> Result<(), EncodingError> {
+        if !(2..=64).contains(&scans) {
+            return Err(EncodingError::InvalidProgressiveScans(scans));
+        }
+
         self.progressive_scans = Some(scans);
+
+        Ok(())
     }
 
     /// Return number of progressive scans if progressive encoding is enabled
@@ -328,14 +928,17 @@ impl<W: JfifWrite> Encoder<W> {
         self.restart_interval = if interval == 0 { None } else { Some(interval) };
     }
 
-    /// Return the restart interval
+    /// Return the restart interval or `None` if no restart markers are written
     pub fn restart_interval(&self) -> Option<u16> {
         self.restart_interval
     }
 
     /// Set if optimized huffman table should be created
     ///
-    /// Optimized tables result in slightly smaller file sizes but decrease encoding performance.
+    /// Optimized tables result in slightly smaller file sizes but decrease encoding performance.<br>
+    /// The symbols are counted in a first pass over the image, so the layout of the scans
+    /// stays the same, e.g. baseline images are still written with one interleaved scan.<br>
+    /// Tables set with [set_huffman_tables](Encoder::set_huffman_tables) are ignored if enabled.
     pub fn set_optimized_huffman_tables(&mut self, optimize_huffman_table: bool) {
         self.optimize_huffman_table = optimize_huffman_table;
     }
@@ -345,172 +948,1646 @@ impl<W: JfifWrite> Encoder<W> {
         self.optimize_huffman_table
     }
 
-    /// Appends a custom app segment to the JFIF file
-    ///
-    /// Segment numbers need to be in the range between 1 and 15<br>
-    /// The maximum allowed data length is 2^16 - 2 bytes.
+    /// Controls if trellis quantization is used
     ///
-    /// # Errors
+    /// Trellis quantization chooses the quantized AC coefficients of each block based on the
+    /// resulting distortion and the number of bits needed with the AC huffman table of the component.
+    /// This results in smaller files at a similar visual quality but decreases encoding performance.<br>
+    /// If [optimized huffman tables](Encoder::set_optimized_huffman_tables) are enabled the costs are
+    /// based on the default tables.
+    pub fn set_trellis_quantization(&mut self, trellis_quantization: bool) {
+        self.trellis_quantization = trellis_quantization;
+    }
+
+    /// Returns if trellis quantization is used
+    pub fn trellis_quantization(&self) -> bool {
+        self.trellis_quantization
+    }
+
+    /// Set the dead zone of the quantization
     ///
-    /// Returns an error if the segment number is invalid or data exceeds the allowed size
-    pub fn add_app_segment(&mut self, segment_nr: u8, data: &[u8]) -> Result<(), EncodingError> {
-        if segment_nr == 0 || segment_nr > 15 {
-            Err(EncodingError::InvalidAppSegment(segment_nr))
-        } else if data.len() > 65533 {
-            Err(EncodingError::AppSegmentTooLarge(data.len()))
+    /// Coefficients are rounded to the nearest quantized value by default. A dead zone reduces
+    /// the rounding correction by the given fraction of the quantization value, which widens
+    /// the interval around zero that is quantized to zero. This trades a little quality for
+    /// smaller files.<br>
+    /// The value is clamped to the range between 0.0 and 0.5, where 0.0 is the default and
+    /// 0.5 truncates all coefficients towards zero. The written quantization tables aren't changed.
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = if dead_zone.is_nan() {
+            0.0
         } else {
-            self.app_segments.push((segment_nr, data.to_vec()));
-            Ok(())
-        }
+            dead_zone.clamp(0.0, 0.5)
+        };
     }
 
-    /// Add an ICC profile
-    ///
-    /// The maximum allowed data length is 16,707,345 bytes.
+    /// Returns the dead zone of the quantization
+    pub fn dead_zone(&self) -> f32 {
+        self.dead_zone
+    }
+
+    /// Controls if the quantization is adapted to the activity of each block
     ///
-    /// # Errors
+    /// Flat regions tolerate a coarser quantization than busy regions at the same perceptual
+    /// quality. If enabled, the AC coefficients of each block are scaled down before the
+    /// quantization based on the variance of its samples. Blocks with a low variance are
+    /// scaled by up to a half, which results in more zero coefficients, while blocks with
+    /// a lot of detail are kept almost unchanged.<br>
+    /// The quantization tables written into the image aren't changed, so this reduces the
+    /// file size at the cost of slightly flattened low contrast areas like skies or skin.
+    /// Combining it with a higher [quality](Encoder::set_quality_f32) keeps more detail in busy
+    /// regions at a similar file size.
     ///
-    /// Returns an Error if the data exceeds the maximum size for the ICC profile
-    pub fn add_icc_profile(&mut self, data: &[u8]) -> Result<(), EncodingError> {
-        // Based on https://www.color.org/ICC_Minor_Revision_for_Web.pdf
-        // B.4  Embedding ICC profiles in JFIF files
+    /// Disabled by default.
+    pub fn set_adaptive_quantization(&mut self, adaptive_quantization: bool) {
+        self.adaptive_quantization = adaptive_quantization;
+    }
 
-        const MARKER: &[u8; 12] = b"ICC_PROFILE\0";
-        const MAX_CHUNK_LENGTH: usize = 65535 - 2 - 12 - 2;
+    /// Returns if the quantization is adapted to the activity of each block
+    pub fn adaptive_quantization(&self) -> bool {
+        self.adaptive_quantization
+    }
+
+    /// Add a region of the image which is encoded with a different quality
+    ///
+    /// Blocks overlapping the rectangle are quantized with the quality of the encoder plus
+    /// `quality_delta`, e.g. to keep faces or text sharper than the background. If a block
+    /// overlaps multiple regions the maximum delta is used.
+    ///
+    /// The image still contains only one quantization table per component type, which is based on
+    /// the highest quality of the background and all regions. The blocks of lower qualities
+    /// are quantized with their own table first and then converted to the written table, so
+    /// they keep the precision of their quality. This doesn't have an effect on
+    /// [custom](QuantizationTableType::Custom) and [raw](QuantizationTableType::Raw) tables.
+    pub fn add_quality_region(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        quality_delta: f32,
+    ) {
+        let quality_delta = if quality_delta.is_nan() {
+            0.0
+        } else {
+            quality_delta
+        };
+
+        self.quality_regions.push(QualityRegion {
+            x: usize::from(x),
+            y: usize::from(y),
+            width: usize::from(width),
+            height: usize::from(height),
+            quality_delta,
+        });
+    }
+
+    /// Remove all regions added with [add_quality_region](Encoder::add_quality_region)
+    pub fn clear_quality_regions(&mut self) {
+        self.quality_regions.clear();
+    }
+
+    /// Controls if the quantized blocks of the whole image are buffered
+    ///
+    /// Images which are encoded with one scan per component (with sampling factors of 4)
+    /// or with [optimized huffman tables](Encoder::set_optimized_huffman_tables) normally keep
+    /// all blocks in memory, which needs 128 bytes per block of each component.<br>
+    /// If enabled, the blocks are computed one MCU row at a time and written immediately.
+    /// This bounds the memory to a few MCU rows, but the image is read and transformed once per
+    /// component and once more per component if optimized huffman tables are used.
+    ///
+    /// Progressive images always buffer all blocks.
+    pub fn set_low_memory(&mut self, low_memory: bool) {
+        self.low_memory = low_memory;
+    }
+
+    /// Returns if the blocks are computed one MCU row at a time
+    pub fn low_memory(&self) -> bool {
+        self.low_memory
+    }
+
+    /// Set a callback to cancel the encoding
+    ///
+    /// The callback is called at least once per MCU row. If it returns true, encoding is aborted
+    /// with [EncodingError::Cancelled] and no EOI marker is written.<br>
+    /// Data written up to this point doesn't form a valid JPEG file and should be discarded.
+    pub fn set_cancel_check(&mut self, cancel_check: Box<dyn Fn() -> bool + Send + Sync>) {
+        self.cancel_check = Some(cancel_check);
+    }
+
+    /// Set huffman tables for luma and chroma components
+    ///
+    /// Each pair contains the table for the DC and the AC coefficients.<br>
+    /// The tables are used as given, so this disables the generation of
+    /// [optimized huffman tables](Encoder::set_optimized_huffman_tables).
+    pub fn set_huffman_tables(
+        &mut self,
+        luma: (HuffmanTable, HuffmanTable),
+        chroma: (HuffmanTable, HuffmanTable),
+    ) {
+        self.huffman_tables[0] = luma;
+        self.huffman_tables[1] = chroma;
+        self.optimize_huffman_table = false;
+    }
+
+    /// Get configured huffman tables for luma and chroma components
+    pub fn huffman_tables(&self) -> &[(HuffmanTable, HuffmanTable); 2] {
+        self.huffman_tables[..2].try_into().unwrap()
+    }
+
+    /// Set the huffman table destinations of a single component
+    ///
+    /// By default luma components use destination 0 and chroma components destination 1.
+    /// Destinations 0 and 1 contain the tables of [set_huffman_tables](Encoder::set_huffman_tables),
+    /// while 2 and 3 contain the default luma and chroma tables. With
+    /// [optimized huffman tables](Encoder::set_optimized_huffman_tables) each destination
+    /// gets a table optimized for the components which reference it, e.g. sharing one
+    /// destination between all components of a CMYK image shrinks the headers.<br>
+    /// Only referenced tables are written. Destinations above 1 need an extended sequential
+    /// (SOF1) frame and are rejected if [baseline](Encoder::set_force_baseline) is forced.
+    /// The destinations of components that don't exist in the encoded image
+    /// are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component index or a destination isn't between 0 and 3
+    pub fn set_component_huffman(
+        &mut self,
+        component: usize,
+        dc_destination: u8,
+        ac_destination: u8,
+    ) -> Result<(), EncodingError> {
+        if component >= self.component_huffman_tables.len() {
+            return Err(EncodingError::InvalidComponentIndex(component));
+        }
+
+        for destination in [dc_destination, ac_destination] {
+            if destination >= 4 {
+                return Err(EncodingError::InvalidHuffmanDestination(destination));
+            }
+        }
+
+        self.component_huffman_tables[component] = Some((dc_destination, ac_destination));
+
+        Ok(())
+    }
+
+    /// Returns the DC and AC destinations set for a component with [set_component_huffman](Encoder::set_component_huffman)
+    pub fn component_huffman(&self, component: usize) -> Option<(u8, u8)> {
+        self.component_huffman_tables
+            .get(component)
+            .copied()
+            .flatten()
+    }
+
+    /// Controls if quantization values are limited to 8 bits
+    ///
+    /// If enabled, all values of custom quantization tables are clamped to 255 to make sure
+    /// the image is encoded as a baseline JPEG (SOF0) which is readable by all decoders.<br>
+    /// Otherwise, tables with values above 255 are written with 16 bit precision which
+    /// needs an extended sequential (SOF1) frame.
+    ///
+    /// Baseline frames also only allow two huffman tables per class. Optimized tables of
+    /// 4 component images then share the default destinations, and encoding fails with
+    /// [EncodingError::BaselineHuffmanDestination] if a destination above 1 is set with
+    /// [set_component_huffman](Encoder::set_component_huffman).
+    pub fn set_force_baseline(&mut self, force_baseline: bool) {
+        self.force_baseline = force_baseline;
+    }
+
+    /// Returns if quantization values are limited to 8 bits
+    pub fn force_baseline(&self) -> bool {
+        self.force_baseline
+    }
+
+    /// Controls if sampling factors which aren't supported by all decoders are rejected
+    ///
+    /// If enabled, encoding fails with [EncodingError::UnsupportedSamplingFactor] if a
+    /// component would be written with a sampling factor of 4, either from the
+    /// [sampling factor](Encoder::set_sampling_factor) or a
+    /// [component sampling](Encoder::set_component_sampling).
+    /// See [is_widely_supported](SamplingFactor::is_widely_supported).
+    pub fn set_strict_compatibility(&mut self, strict_compatibility: bool) {
+        self.strict_compatibility = strict_compatibility;
+    }
+
+    /// Returns if sampling factors which aren't supported by all decoders are rejected
+    pub fn strict_compatibility(&self) -> bool {
+        self.strict_compatibility
+    }
+
+    /// Controls if the SOI (start of image) and EOI (end of image) markers are written
+    ///
+    /// By default, the encoder writes a complete JPEG file from SOI to EOI. This is also the case
+    /// if the writer already contains other data, so images can be embedded into larger files.
+    /// Disabling the markers is only needed if the framing is done externally.
+    ///
+    /// # Example
+    /// ```
+    /// # use jpeg_encoder::EncodingError;
+    /// # pub fn main() -> Result<(), EncodingError> {
+    /// use jpeg_encoder::{ColorType, Encoder};
+    ///
+    /// let mut result = Vec::new();
+    ///
+    /// let mut encoder = Encoder::new(&mut result, 90);
+    /// encoder.set_write_soi_eoi(false);
+    /// encoder.encode(&[255, 0, 0], 1, 1, ColorType::Rgb)?;
+    ///
+    /// // The image starts directly with the JFIF header
+    /// assert_eq!(&result[0..2], &[0xFF, 0xE0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_write_soi_eoi(&mut self, write_soi_eoi: bool) {
+        self.write_soi_eoi = write_soi_eoi;
+    }
+
+    /// Returns if the SOI and EOI markers are written
+    pub fn write_soi_eoi(&self) -> bool {
+        self.write_soi_eoi
+    }
+
+    /// Controls if the JFIF APP0 header is written
+    ///
+    /// By default, every image starts with a JFIF header. Disabling it is needed for files that
+    /// must start with another app segment like EXIF files (APP1) or to save some bytes for small
+    /// thumbnails.<br>
+    /// The density and the JFIF thumbnail are stored in the JFIF header, so encoding fails
+    /// with [EncodingError::JfifRequired] if any of them is set while the header is disabled.
+    pub fn set_write_jfif(&mut self, write_jfif: bool) {
+        self.write_jfif = write_jfif;
+    }
+
+    /// Returns if the JFIF APP0 header is written
+    pub fn write_jfif(&self) -> bool {
+        self.write_jfif
+    }
+
+    /// Controls if RGB input is encoded as a grayscale image
+    ///
+    /// If enabled only the luma of [ColorType::Rgb], [ColorType::Rgba], [ColorType::Bgr],
+    /// [ColorType::Bgra], [ColorType::Argb] and [ColorType::Abgr] images is computed and
+    /// written as a single component image. The alpha background and color space settings
+    /// are still used for the conversion. Other color types aren't affected.
+    pub fn set_force_grayscale(&mut self, force_grayscale: bool) {
+        self.force_grayscale = force_grayscale;
+    }
+
+    /// Returns if RGB input is encoded as a grayscale image
+    pub fn force_grayscale(&self) -> bool {
+        self.force_grayscale
+    }
+
+    /// Set the version written into the JFIF header
+    ///
+    /// By default, version 1.02 is written. Overriding it is only needed to match the output of
+    /// other encoders or for decoders that expect a specific version.
+    pub fn set_jfif_version(&mut self, major: u8, minor: u8) {
+        self.jfif_version = (major, minor);
+    }
+
+    /// Returns the version written into the JFIF header as major and minor version
+    pub fn jfif_version(&self) -> (u8, u8) {
+        self.jfif_version
+    }
+
+    /// Controls if the number of lines is written in a DNL segment
+    ///
+    /// If enabled the frame header contains a height of 0 and the actual height is written in a
+    /// DNL (define number of lines) segment directly after the first scan as allowed by the JPEG
+    /// standard for sources which don't know their final height when the header is written.
+    ///
+    /// Many decoders don't support DNL segments and reject such images. This includes
+    /// libjpeg-turbo and the `jpeg-decoder` crate, so this should only be enabled if the
+    /// consumer of the images is known to handle them.
+    pub fn set_deferred_height(&mut self, deferred_height: bool) {
+        self.deferred_height = deferred_height;
+    }
+
+    /// Returns if the number of lines is written in a DNL segment
+    pub fn deferred_height(&self) -> bool {
+        self.deferred_height
+    }
+
+    /// Set the color transform of the Adobe APP14 segment
+    ///
+    /// Possible values are 0 for unknown (RGB or CMYK), 1 for YCbCr and 2 for YCCK.<br>
+    /// By default, this value is None which writes an APP14 segment only for CMYK (0)
+    /// and YCCK (2) images. Any other value forces an APP14 segment with the given transform.
+    pub fn set_adobe_color_transform(&mut self, transform: Option<u8>) {
+        self.adobe_color_transform = transform;
+    }
+
+    /// Return the color transform of the Adobe APP14 segment if one is forced
+    pub fn adobe_color_transform(&self) -> Option<u8> {
+        self.adobe_color_transform
+    }
+
+    /// Set pixel density for the image and return the encoder
+    ///
+    /// Builder variant of [set_density](Encoder::set_density).
+    pub fn with_density(mut self, density: Density) -> Self {
+        self.set_density(density);
+        self
+    }
+
+    /// Set chroma subsampling factor and return the encoder
+    ///
+    /// Builder variant of [set_sampling_factor](Encoder::set_sampling_factor).
+    pub fn with_sampling_factor(mut self, sampling: SamplingFactor) -> Self {
+        self.set_sampling_factor(sampling);
+        self
+    }
+
+    /// Choose the chroma subsampling factor based on the image content and return the encoder
+    ///
+    /// Builder variant of [set_sampling_factor_auto](Encoder::set_sampling_factor_auto).
+    pub fn with_sampling_factor_auto(mut self) -> Self {
+        self.set_sampling_factor_auto();
+        self
+    }
+
+    /// Set the filter used to downsample subsampled components and return the encoder
+    ///
+    /// Builder variant of [set_chroma_downsample_filter](Encoder::set_chroma_downsample_filter).
+    pub fn with_chroma_downsample_filter(mut self, filter: DownsampleFilter) -> Self {
+        self.set_chroma_downsample_filter(filter);
+        self
+    }
+
+    /// Set independent sampling factors for the Cb and Cr components and return the encoder
+    ///
+    /// Builder variant of [set_chroma_sampling](Encoder::set_chroma_sampling).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a factor isn't 1, 2 or 4
+    pub fn with_chroma_sampling(
+        mut self,
+        cb: (u8, u8),
+        cr: (u8, u8),
+    ) -> Result<Self, EncodingError> {
+        self.set_chroma_sampling(cb, cr)?;
+        Ok(self)
+    }
+
+    /// Set the color space used to convert RGB input to YCbCr and return the encoder
+    ///
+    /// Builder variant of [set_color_space](Encoder::set_color_space).
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.set_color_space(color_space);
+        self
+    }
+
+    /// Controls if full range YCbCr values are written and return the encoder
+    ///
+    /// Builder variant of [set_full_range](Encoder::set_full_range).
+    pub fn with_full_range(mut self, full_range: bool) -> Self {
+        self.set_full_range(full_range);
+        self
+    }
+
+    /// Set the byte order of [Luma16](ColorType::Luma16) input and return the encoder
+    ///
+    /// Builder variant of [set_luma16_endianness](Encoder::set_luma16_endianness).
+    pub fn with_luma16_endianness(mut self, endianness: Endianness) -> Self {
+        self.set_luma16_endianness(endianness);
+        self
+    }
+
+    /// Set the transfer function of [RgbF32](ColorType::RgbF32) input and return the encoder
+    ///
+    /// Builder variant of [set_input_transfer](Encoder::set_input_transfer).
+    pub fn with_input_transfer(mut self, transfer: Transfer) -> Self {
+        self.set_input_transfer(transfer);
+        self
+    }
+
+    /// Set the dithering of high bit depth input and return the encoder
+    ///
+    /// Builder variant of [set_input_dither](Encoder::set_input_dither).
+    pub fn with_input_dither(mut self, dither: Dither) -> Self {
+        self.set_input_dither(dither);
+        self
+    }
+
+    /// Set the RGB color to blend input with an alpha channel over and return the encoder
+    ///
+    /// Builder variant of [set_alpha_background](Encoder::set_alpha_background).
+    pub fn with_alpha_background(mut self, background: Option<[u8; 3]>) -> Self {
+        self.set_alpha_background(background);
+        self
+    }
+
+    /// Set quantization tables for luma and chroma components and return the encoder
+    ///
+    /// Builder variant of [set_quantization_tables](Encoder::set_quantization_tables).
+    pub fn with_quantization_tables(
+        mut self,
+        luma: QuantizationTableType,
+        chroma: QuantizationTableType,
+    ) -> Self {
+        self.set_quantization_tables(luma, chroma);
+        self
+    }
+
+    /// Controls if progressive encoding is used and return the encoder
+    ///
+    /// Builder variant of [set_progressive](Encoder::set_progressive).
+    pub fn with_progressive(mut self, progressive: bool) -> Self {
+        self.set_progressive(progressive);
+        self
+    }
+
+    /// Set number of scans per component for progressive encoding and return the encoder
+    ///
+    /// Builder variant of [set_progressive_scans](Encoder::set_progressive_scans).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if number of scans is not within valid range
+    pub fn with_progressive_scans(mut self, scans: u8) -> Result<Self, EncodingError> {
+        self.set_progressive_scans(scans)?;
+        Ok(self)
+    }
+
+    /// Set restart interval and return the encoder
+    ///
+    /// Builder variant of [set_restart_interval](Encoder::set_restart_interval).
+    pub fn with_restart_interval(mut self, interval: u16) -> Self {
+        self.set_restart_interval(interval);
+        self
+    }
+
+    /// Set if optimized huffman table should be created and return the encoder
+    ///
+    /// Builder variant of [set_optimized_huffman_tables](Encoder::set_optimized_huffman_tables).
+    pub fn with_optimized_huffman_tables(mut self, optimize_huffman_table: bool) -> Self {
+        self.set_optimized_huffman_tables(optimize_huffman_table);
+        self
+    }
+
+    /// Controls if trellis quantization is used and return the encoder
+    ///
+    /// Builder variant of [set_trellis_quantization](Encoder::set_trellis_quantization).
+    pub fn with_trellis_quantization(mut self, trellis_quantization: bool) -> Self {
+        self.set_trellis_quantization(trellis_quantization);
+        self
+    }
+
+    /// Set the dead zone of the quantization and return the encoder
+    ///
+    /// Builder variant of [set_dead_zone](Encoder::set_dead_zone).
+    pub fn with_dead_zone(mut self, dead_zone: f32) -> Self {
+        self.set_dead_zone(dead_zone);
+        self
+    }
+
+    /// Controls if the quantization is adapted to the activity of each block and return the encoder
+    ///
+    /// Builder variant of [set_adaptive_quantization](Encoder::set_adaptive_quantization).
+    pub fn with_adaptive_quantization(mut self, adaptive_quantization: bool) -> Self {
+        self.set_adaptive_quantization(adaptive_quantization);
+        self
+    }
+
+    /// Set huffman tables for luma and chroma components and return the encoder
+    ///
+    /// Builder variant of [set_huffman_tables](Encoder::set_huffman_tables).
+    pub fn with_huffman_tables(
+        mut self,
+        luma: (HuffmanTable, HuffmanTable),
+        chroma: (HuffmanTable, HuffmanTable),
+    ) -> Self {
+        self.set_huffman_tables(luma, chroma);
+        self
+    }
+
+    /// Controls if quantization values are limited to 8 bits and return the encoder
+    ///
+    /// Builder variant of [set_force_baseline](Encoder::set_force_baseline).
+    pub fn with_force_baseline(mut self, force_baseline: bool) -> Self {
+        self.set_force_baseline(force_baseline);
+        self
+    }
+
+    /// Controls if sampling factors which aren't supported by all decoders are rejected
+    /// and return the encoder
+    ///
+    /// Builder variant of [set_strict_compatibility](Encoder::set_strict_compatibility).
+    pub fn with_strict_compatibility(mut self, strict_compatibility: bool) -> Self {
+        self.set_strict_compatibility(strict_compatibility);
+        self
+    }
+
+    /// Set the quality as a fractional value and return the encoder
+    ///
+    /// Builder variant of [set_quality_f32](Encoder::set_quality_f32).
+    pub fn with_quality_f32(mut self, quality: f32) -> Self {
+        self.set_quality_f32(quality);
+        self
+    }
+
+    /// Controls if the SOI and EOI markers are written and return the encoder
+    ///
+    /// Builder variant of [set_write_soi_eoi](Encoder::set_write_soi_eoi).
+    pub fn with_write_soi_eoi(mut self, write_soi_eoi: bool) -> Self {
+        self.set_write_soi_eoi(write_soi_eoi);
+        self
+    }
+
+    /// Controls if the JFIF APP0 header is written and return the encoder
+    ///
+    /// Builder variant of [set_write_jfif](Encoder::set_write_jfif).
+    pub fn with_write_jfif(mut self, write_jfif: bool) -> Self {
+        self.set_write_jfif(write_jfif);
+        self
+    }
+
+    /// Controls if RGB input is encoded as a grayscale image and return the encoder
+    ///
+    /// Builder variant of [set_force_grayscale](Encoder::set_force_grayscale).
+    pub fn with_force_grayscale(mut self, force_grayscale: bool) -> Self {
+        self.set_force_grayscale(force_grayscale);
+        self
+    }
+
+    /// Set the version written into the JFIF header and return the encoder
+    ///
+    /// Builder variant of [set_jfif_version](Encoder::set_jfif_version).
+    pub fn with_jfif_version(mut self, major: u8, minor: u8) -> Self {
+        self.set_jfif_version(major, minor);
+        self
+    }
+
+    /// Controls if the number of lines is written in a DNL segment and return the encoder
+    ///
+    /// Builder variant of [set_deferred_height](Encoder::set_deferred_height).
+    pub fn with_deferred_height(mut self, deferred_height: bool) -> Self {
+        self.set_deferred_height(deferred_height);
+        self
+    }
+
+    /// Set the color transform of the Adobe APP14 segment and return the encoder
+    ///
+    /// Builder variant of [set_adobe_color_transform](Encoder::set_adobe_color_transform).
+    pub fn with_adobe_color_transform(mut self, transform: Option<u8>) -> Self {
+        self.set_adobe_color_transform(transform);
+        self
+    }
+
+    /// Controls if the blocks are computed one MCU row at a time and return the encoder
+    ///
+    /// Builder variant of [set_low_memory](Encoder::set_low_memory).
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.set_low_memory(low_memory);
+        self
+    }
+
+    /// Set a callback to cancel the encoding and return the encoder
+    ///
+    /// Builder variant of [set_cancel_check](Encoder::set_cancel_check).
+    pub fn with_cancel_check(mut self, cancel_check: Box<dyn Fn() -> bool + Send + Sync>) -> Self {
+        self.set_cancel_check(cancel_check);
+        self
+    }
+
+    /// Appends a custom app segment to the JFIF file
+    ///
+    /// Segment numbers need to be in the range between 1 and 15<br>
+    /// The maximum allowed data length is 2^16 - 2 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the segment number is invalid or data exceeds the allowed size
+    pub fn add_app_segment(&mut self, segment_nr: u8, data: &[u8]) -> Result<(), EncodingError> {
+        if segment_nr == 0 || segment_nr > 15 {
+            Err(EncodingError::InvalidAppSegment(segment_nr))
+        } else if data.len() > 65533 {
+            Err(EncodingError::AppSegmentTooLarge(data.len()))
+        } else {
+            self.app_segments.push((segment_nr, data.to_vec()));
+            Ok(())
+        }
+    }
+
+    /// Appends a comment (COM segment) to the JFIF file
+    ///
+    /// Comments are written after the app segments in the order they were added.<br>
+    /// The maximum allowed data length is 2^16 - 2 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exceeds the allowed size
+    pub fn add_comment(&mut self, text: &[u8]) -> Result<(), EncodingError> {
+        if text.len() > 65533 {
+            Err(EncodingError::CommentTooLarge(text.len()))
+        } else {
+            self.comments.push(text.to_vec());
+            Ok(())
+        }
+    }
+
+    /// Set an uncompressed RGB thumbnail
+    ///
+    /// The thumbnail is written into a JFXX extension segment (APP0) directly after the JFIF header.
+    /// Data must contain 3 bytes per pixel and the maximum allowed data length is 65525 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data length doesn't match the dimensions or exceeds the allowed size
+    pub fn set_jfif_thumbnail(&mut self, rgb: &[u8], w: u8, h: u8) -> Result<(), EncodingError> {
+        const MARKER: &[u8; 5] = b"JFXX\0";
+
+        // Extension code for thumbnails stored using 3 bytes/pixel
+        const RGB_THUMBNAIL: u8 = 0x13;
+
+        let required = usize::from(w) * usize::from(h) * 3;
+
+        if rgb.len() != required {
+            return Err(EncodingError::BadImageData {
+                length: rgb.len(),
+                required,
+            });
+        }
+
+        let length = MARKER.len() + 3 + required;
+
+        if length > 65533 {
+            return Err(EncodingError::AppSegmentTooLarge(length));
+        }
+
+        let mut data = Vec::with_capacity(length);
+        data.extend_from_slice(MARKER);
+        data.push(RGB_THUMBNAIL);
+        data.push(w);
+        data.push(h);
+        data.extend_from_slice(rgb);
+
+        self.jfif_thumbnail = Some(data);
+
+        Ok(())
+    }
+
+    /// Add an EXIF block
+    ///
+    /// The payload is prefixed with the `Exif\0\0` identifier and written as an APP1 segment.
+    /// It's placed in front of all other app segments except for previously added APP1 segments.<br>
+    /// The maximum allowed payload length is 65527 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exceeds the allowed size
+    pub fn add_exif(&mut self, exif_payload: &[u8]) -> Result<(), EncodingError> {
+        self.insert_app_segment(1, b"Exif\0\0", exif_payload)
+    }
+
+    /// Add an XMP packet
+    ///
+    /// The packet is prefixed with the `http://ns.adobe.com/xap/1.0/\0` namespace and written
+    /// as an APP1 segment after previously added APP1 segments.<br>
+    /// The maximum allowed packet length is 65504 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exceeds the allowed size
+    pub fn add_xmp(&mut self, xml: &[u8]) -> Result<(), EncodingError> {
+        self.insert_app_segment(1, b"http://ns.adobe.com/xap/1.0/\0", xml)
+    }
+
+    /// Add an XMP packet with an extended part
+    ///
+    /// The standard packet is written like with [add_xmp](Encoder::add_xmp). The extended packet
+    /// is split into chunks which are written as APP1 segments with the
+    /// `http://ns.adobe.com/xmp/extension/\0` namespace directly after it. The GUID of the
+    /// chunks is the MD5 digest of the extended packet as 32 uppercase hex digits.<br>
+    /// According to the XMP specification the standard packet should reference this GUID with an
+    /// `xmpNote:HasExtendedXMP` property.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the standard packet exceeds the allowed size of 65504 bytes or the
+    /// extended packet doesn't fit into 4 GiB
+    pub fn add_extended_xmp(
+        &mut self,
+        standard: &[u8],
+        extended: &[u8],
+    ) -> Result<(), EncodingError> {
+        const MARKER: &[u8; 35] = b"http://ns.adobe.com/xmp/extension/\0";
+        const MAX_CHUNK_LENGTH: usize = 65533 - MARKER.len() - 32 - 4 - 4;
+
+        let full_length = u32::try_from(extended.len())
+            .map_err(|_| EncodingError::AppSegmentTooLarge(extended.len()))?;
+
+        self.add_xmp(standard)?;
+
+        let mut guid = [0u8; 32];
+        for (hex, byte) in guid.chunks_exact_mut(2).zip(md5(extended)) {
+            const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+            hex[0] = DIGITS[usize::from(byte >> 4)];
+            hex[1] = DIGITS[usize::from(byte & 0xF)];
+        }
+
+        let mut chunk_data = Vec::with_capacity(32 + 4 + 4 + MAX_CHUNK_LENGTH);
+
+        for (i, chunk) in extended.chunks(MAX_CHUNK_LENGTH).enumerate() {
+            let offset = (i * MAX_CHUNK_LENGTH) as u32;
+
+            chunk_data.clear();
+            chunk_data.extend_from_slice(&guid);
+            chunk_data.extend_from_slice(&full_length.to_be_bytes());
+            chunk_data.extend_from_slice(&offset.to_be_bytes());
+            chunk_data.extend_from_slice(chunk);
+
+            self.insert_app_segment(1, MARKER, &chunk_data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a Photoshop image resource block
+    ///
+    /// The data must contain the `8BIM` resources, e.g. for IPTC metadata. It's prefixed with
+    /// the `Photoshop 3.0\0` identifier and written as an APP13 segment after the APP1 segments
+    /// like EXIF but in front of all other app segments like the ICC profile.<br>
+    /// The maximum allowed data length is 65519 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exceeds the allowed size
+    pub fn add_photoshop_irb(&mut self, data: &[u8]) -> Result<(), EncodingError> {
+        self.insert_app_segment(13, b"Photoshop 3.0\0", data)
+    }
+
+    fn insert_app_segment(
+        &mut self,
+        segment_nr: u8,
+        marker: &[u8],
+        payload: &[u8],
+    ) -> Result<(), EncodingError> {
+        let length = marker.len() + payload.len();
+
+        if length > 65533 {
+            return Err(EncodingError::AppSegmentTooLarge(length));
+        }
+
+        let mut data = Vec::with_capacity(length);
+        data.extend_from_slice(marker);
+        data.extend_from_slice(payload);
+
+        // APP1 segments are placed in front of all other app segments
+        // and APP13 segments directly after them
+        let index = self
+            .app_segments
+            .iter()
+            .position(|(nr, _)| *nr != 1 && *nr != segment_nr)
+            .unwrap_or(self.app_segments.len());
+
+        self.app_segments.insert(index, (segment_nr, data));
+
+        Ok(())
+    }
+
+    /// Add an ICC profile
+    ///
+    /// The profile replaces a previously added one and can be read back with
+    /// [icc_profile](Encoder::icc_profile).<br>
+    /// The maximum allowed data length is 16,707,345 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an Error if the data exceeds the maximum size for the ICC profile or if it's
+    /// shorter than the 128 byte profile header or doesn't match the size stored in the header
+    pub fn add_icc_profile(&mut self, data: &[u8]) -> Result<(), EncodingError> {
+        // Based on https://www.color.org/ICC_Minor_Revision_for_Web.pdf
+        // B.4  Embedding ICC profiles in JFIF files
+
+        const MARKER: &[u8; 12] = b"ICC_PROFILE\0";
+        const MAX_CHUNK_LENGTH: usize = 65535 - 2 - 12 - 2;
 
         let num_chunks = ceil_div(data.len(), MAX_CHUNK_LENGTH);
 
-        // Sequence number is stored as a byte and starts with 1
-        if num_chunks >= 255 {
-            return Err(EncodingError::IccTooLarge(data.len()));
+        // Sequence number is stored as a byte and starts with 1
+        if num_chunks >= 255 {
+            return Err(EncodingError::IccTooLarge(data.len()));
+        }
+
+        // The profile header starts with the size of the whole profile
+        if data.len() < 128
+            || u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize != data.len()
+        {
+            return Err(EncodingError::InvalidIccProfile);
+        }
+
+        if self.icc_profile.is_some() {
+            self.app_segments
+                .retain(|(nr, segment)| *nr != 2 || !segment.starts_with(MARKER));
+        }
+
+        let mut chunk_data = Vec::with_capacity(MAX_CHUNK_LENGTH);
+
+        for (i, chunk) in data.chunks(MAX_CHUNK_LENGTH).enumerate() {
+            chunk_data.clear();
+            chunk_data.extend_from_slice(MARKER);
+            chunk_data.push(i as u8 + 1);
+            chunk_data.push(num_chunks as u8);
+            chunk_data.extend_from_slice(chunk);
+
+            self.add_app_segment(2, &chunk_data)?;
+        }
+
+        self.icc_profile = Some(data.to_vec());
+
+        Ok(())
+    }
+
+    /// Returns the ICC profile added with [add_icc_profile](Encoder::add_icc_profile)
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// Add a multi-picture format (MPF) block
+    ///
+    /// The data is prefixed with the `MPF\0` identifier and written as an APP2 segment in the
+    /// order the app segments were added.<br>
+    /// Only the segment framing is handled by the encoder. The caller is responsible for the
+    /// contents of the MP header including the offsets and sizes of the individual images.<br>
+    /// The maximum allowed data length is 65529 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data exceeds the allowed size
+    pub fn add_mpf(&mut self, data: &[u8]) -> Result<(), EncodingError> {
+        const MARKER: &[u8; 4] = b"MPF\0";
+
+        let mut segment = Vec::with_capacity(MARKER.len() + data.len());
+        segment.extend_from_slice(MARKER);
+        segment.extend_from_slice(data);
+
+        self.add_app_segment(2, &segment)
+    }
+
+    /// Encode an image
+    ///
+    /// Data format and length must conform to specified width, height and color type.<br>
+    /// Use [encode_frame](Encoder::encode_frame) and [into_inner](Encoder::into_inner) to
+    /// continue using the writer after encoding.
+    pub fn encode(
+        mut self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+    ) -> Result<(), EncodingError> {
+        self.encode_frame(data, width, height, color_type)
+    }
+
+    /// Encode an image without consuming the encoder
+    ///
+    /// Each call writes a complete JPEG file with the current settings into the writer, which
+    /// allows encoding a sequence of frames like a motion JPEG stream with one encoder.<br>
+    /// Internal buffers are kept between calls, so encoding images of the same size
+    /// doesn't need further allocations once the encoder is warmed up. This doesn't apply to
+    /// [optimized huffman tables](Encoder::set_optimized_huffman_tables) and progressive images.
+    ///
+    /// Data format and length must conform to specified width, height and color type.
+    pub fn encode_frame(
+        &mut self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+    ) -> Result<(), EncodingError> {
+        let required_data_len = color_type
+            .checked_data_len(width, height)
+            .ok_or(EncodingError::ImageTooLarge { width, height })?;
+
+        if data.len() < required_data_len {
+            return Err(EncodingError::BadImageData {
+                length: data.len(),
+                required: required_data_len,
+            });
+        }
+
+        let format = self.input_format();
+        format.visit(data, width, height, color_type, EncodeFrame(self))
+    }
+
+    /// Settings which define how the raw data of [encode_frame](Encoder::encode_frame) is read
+    fn input_format(&self) -> InputFormat {
+        InputFormat {
+            grayscale: self.force_grayscale,
+            color_space: self.color_space,
+            alpha_background: self.alpha_background,
+            endianness: self.luma16_endianness,
+            transfer: self.input_transfer,
+            dither: self.input_dither,
+            first_row: 0,
         }
+    }
 
-        let mut chunk_data = Vec::with_capacity(MAX_CHUNK_LENGTH);
+    /// Encode an image with the highest quality whose output fits into `max_bytes`
+    ///
+    /// The quality is found by a binary search over the integral qualities between 1 and 100
+    /// with up to 8 encoding passes. The passes are written into an internal buffer and only
+    /// the best fitting result is written into the writer. Returns the size of the written data.
+    ///
+    /// This is a lossy search, not an exact one: it assumes the output grows with the quality,
+    /// which is true for almost all images but not guaranteed. The result isn't necessarily
+    /// the largest output that fits and fractional qualities aren't tried.<br>
+    /// Afterwards [quality](Encoder::quality) returns the quality of the written image.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TargetSizeUnreachable` if the output doesn't fit even with the lowest quality.
+    /// Otherwise the same errors as [encode_frame](Encoder::encode_frame) are returned.
+    pub fn encode_to_size(
+        &mut self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+        max_bytes: usize,
+    ) -> Result<usize, EncodingError> {
+        const MAX_PASSES: usize = 8;
+
+        let original_quality = self.quality;
+
+        let mut low = 1u8;
+        let mut high = 100u8;
+
+        let mut best: Option<(u8, Vec<u8>, EncodingStats)> = None;
+        let mut smallest = usize::MAX;
+        let mut buffer = Vec::new();
+
+        // The tables of the first pass are rescaled for the following ones
+        self.table_search = self.quality_regions.is_empty();
+
+        for _ in 0..MAX_PASSES {
+            if low > high {
+                break;
+            }
+
+            let quality = low + (high - low + 1) / 2;
+            self.set_quality_f32(f32::from(quality));
+
+            self.writer.begin_scratch(buffer);
+            let result = self.encode_frame(data, width, height, color_type);
+            buffer = self.writer.end_scratch();
+
+            if let Err(err) = result {
+                self.quality = original_quality;
+                self.table_search = false;
+                self.search_tables = None;
+                return Err(err);
+            }
+
+            if buffer.len() <= max_bytes {
+                low = quality + 1;
+
+                // Reuse the buffer of the previous best result for the next pass
+                let previous = best.replace((quality, buffer, self.stats.clone()));
+                buffer = previous.map(|(_, buffer, _)| buffer).unwrap_or_default();
+            } else {
+                high = quality - 1;
+                smallest = smallest.min(buffer.len());
+            }
+        }
+
+        self.table_search = false;
+        self.search_tables = None;
+
+        match best {
+            Some((quality, output, stats)) => {
+                self.set_quality_f32(f32::from(quality));
+                self.stats = stats;
+                self.writer.write(&output)?;
+
+                Ok(output.len())
+            }
+            None => {
+                self.quality = original_quality;
+
+                Err(EncodingError::TargetSizeUnreachable {
+                    size: smallest,
+                    max_bytes,
+                })
+            }
+        }
+    }
+
+    /// Returns the byte counts of the last encoded image
+    ///
+    /// The statistics are empty before the first image was encoded and incomplete if
+    /// encoding failed. Use [encode_frame](Encoder::encode_frame) to access them after encoding.
+    pub fn stats(&self) -> &EncodingStats {
+        &self.stats
+    }
+
+    /// Returns the underlying writer
+    ///
+    /// This can be called before or after encoding, e.g. to append further data of a
+    /// container format after an image written with [encode_frame](Encoder::encode_frame).
+    /// The writer contains an incomplete image if encoding failed.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+
+    /// Returns a rough estimate of the size of an encoded image in bytes
+    ///
+    /// The estimate is derived from the current quality, the sampling factor and the number of
+    /// components of the color type. It assumes the content of typical photos and includes
+    /// the headers, APP segments and comments. Noisy images are usually larger, while trellis
+    /// quantization, optimized huffman tables and progressive mode make images smaller.
+    ///
+    /// The estimate is advisory only. It is neither an upper nor a lower bound of the actual
+    /// size and must not be used to size buffers that the encoder can't grow.
+    pub fn estimated_size(&self, width: u16, height: u16, color_type: ColorType) -> usize {
+        use ColorType::*;
+
+        let (full_components, subsampled_components) = match color_type {
+            Luma | Luma16 | LumaA => (1, 0),
+            Rgb | Rgba | Bgr | Bgra | Argb | Abgr if self.force_grayscale => (1, 0),
+            Cmyk | CmykAsYcck | Ycck => (2, 2),
+            _ => (1, 2),
+        };
+
+        let (h_sampling, v_sampling) = self.sampling_factor.get_sampling_factors();
+
+        let width = usize::from(width);
+        let height = usize::from(height);
+
+        let full_samples = (width * height) as f32;
+        let subsampled_samples = (ceil_div(width, usize::from(h_sampling))
+            * ceil_div(height, usize::from(v_sampling))) as f32;
+
+        // Scaling of the quantization tables in percent
+        let scale = if self.quality < 50.0 {
+            5000.0 / self.quality
+        } else {
+            200.0 - self.quality * 2.0
+        };
+
+        // About 1.6 bits per luma sample at quality 75 and half of it for chroma samples
+        let bits_per_sample = 8.0 / (1.0 + scale / 12.5);
+
+        let bits = full_components as f32 * full_samples * bits_per_sample
+            + subsampled_components as f32 * subsampled_samples * bits_per_sample / 2.0;
+
+        let segments: usize = self
+            .app_segments
+            .iter()
+            .map(|(_, data)| data.len() + 4)
+            .chain(self.comments.iter().map(|comment| comment.len() + 4))
+            .sum();
+
+        ESTIMATED_HEADER_SIZE + segments + (bits / 8.0) as usize
+    }
+
+    /// Computes the quantized DCT coefficients of a single block
+    ///
+    /// The block contains 8x8 samples in row major order which must be level shifted, i.e.
+    /// 8 bit samples minus 128 in the range of -128 to 127. It is transformed with
+    /// [forward_dct](crate::forward_dct) and quantized with the luma or chroma table of the
+    /// current quality and dead zone. The coefficients are returned in zigzag order, as they
+    /// are written into the image.
+    ///
+    /// Adaptive quantization, trellis quantization and quality regions are not applied.
+    pub fn quantize_external_block(&self, spatial: &[i16; 64], luma: bool) -> [i16; 64] {
+        let mut block = *spatial;
+        DefaultOperations::fdct(&mut block);
+
+        let table = self.create_quantization_table(luma, self.quality);
+
+        let mut q_block = [0i16; 64];
+        DefaultOperations::quantize_block(&block, &mut q_block, &table);
+
+        q_block
+    }
+
+    /// Encode a rectangular region of a larger image
+    ///
+    /// The image has a width of `full_width` pixels and its rows start `stride` bytes apart.
+    /// Only the region of `width` x `height` pixels at position `x`,`y` is encoded without
+    /// copying it out of the image data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region exceeds the image width, the stride is smaller than a row
+    /// of the image or `x` is odd for packed 4:2:2 color types.
+    /// An error is also returned if the data is too short to contain the region or its size
+    /// doesn't fit into `usize`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_region(
+        self,
+        data: &[u8],
+        full_width: u16,
+        stride: usize,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+    ) -> Result<(), EncodingError> {
+        if width == 0 || height == 0 {
+            return Err(EncodingError::ZeroImageDimensions { width, height });
+        }
+
+        let packed_offset = matches!(color_type, ColorType::Yuyv | ColorType::Uyvy) && x % 2 != 0;
+
+        if u32::from(x) + u32::from(width) > u32::from(full_width)
+            || stride < color_type.get_data_len(full_width, 1)
+            || packed_offset
+        {
+            return Err(EncodingError::InvalidRegion {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+
+        let required_data_len = usize::from(y)
+            .checked_add(usize::from(height) - 1)
+            .and_then(|rows| rows.checked_mul(stride))
+            .and_then(|len| len.checked_add(color_type.get_data_len(x, 1)))
+            .and_then(|len| len.checked_add(color_type.get_data_len(width, 1)))
+            .ok_or(EncodingError::ImageTooLarge { width, height })?;
+
+        let start = usize::from(y) * stride + color_type.get_data_len(x, 1);
+
+        if data.len() < required_data_len {
+            return Err(EncodingError::BadImageData {
+                length: data.len(),
+                required: required_data_len,
+            });
+        }
+
+        let image = RegionImage {
+            data: &data[start..],
+            width,
+            height,
+            stride,
+            color_type,
+            format: self.input_format(),
+        };
+
+        self.encode_image(image)
+    }
+
+    /// Encode an image from already subsampled component planes
+    ///
+    /// Each plane contains the samples of one component at its native resolution, so no color
+    /// conversion or downsampling is done. The number of planes defines the color type:
+    /// - 1 plane: Grayscale
+    /// - 3 planes: YCbCr
+    /// - 4 planes: YCCK
+    ///
+    /// Sampling factors must be 1, 2 or 4 and override the [sampling factor](Encoder::set_sampling_factor)
+    /// of the encoder. The size of a plane is `ceil(width * h_sampling / max_h_sampling)` x
+    /// `ceil(height * v_sampling / max_v_sampling)`. For a single plane the sampling factors are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of planes or the sampling factors aren't supported
+    /// or if a plane is too small for the image dimensions
+    pub fn encode_components(
+        mut self,
+        planes: &[ComponentPlane],
+        width: u16,
+        height: u16,
+    ) -> Result<(), EncodingError> {
+        if !matches!(planes.len(), 1 | 3 | 4) {
+            return Err(EncodingError::InvalidComponentCount(planes.len()));
+        }
+
+        for plane in planes {
+            if !matches!(plane.h_sampling, 1 | 2 | 4) || !matches!(plane.v_sampling, 1 | 2 | 4) {
+                return Err(EncodingError::InvalidSamplingFactor {
+                    horizontal: plane.h_sampling,
+                    vertical: plane.v_sampling,
+                });
+            }
+        }
+
+        let image = PlanarImage(planes, width, height);
+        let (max_h, max_v) = image.max_sampling_factors();
+
+        for plane in planes {
+            let (plane_width, plane_height) = plane.size(width, height, max_h, max_v);
+            let required = plane_width
+                .checked_mul(plane_height)
+                .ok_or(EncodingError::ImageTooLarge { width, height })?;
+
+            if plane.data.len() < required {
+                return Err(EncodingError::BadImageData {
+                    length: plane.data.len(),
+                    required,
+                });
+            }
+        }
+
+        if planes.len() > 1 {
+            for (factors, plane) in self.component_sampling_factors.iter_mut().zip(planes) {
+                *factors = Some((plane.h_sampling, plane.v_sampling));
+            }
+        }
+
+        self.encode_image(image)
+    }
+
+    /// Encode a grayscale image with 12 bit samples
+    ///
+    /// Samples must be in the range of 0 to 4095. The image is written as an extended
+    /// sequential JPEG (SOF1) with a precision of 12 bits and always uses optimized huffman
+    /// tables, because the default tables don't cover the larger coefficients.
+    /// Progressive mode, trellis and adaptive quantization and quality regions are ignored.
+    ///
+    /// Many decoders only support 8 bit samples, e.g. browsers and the `jpeg-decoder` crate
+    /// don't decode these images. libjpeg-turbo supports them since version 3.0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SampleOutOfRange` for samples above 4095. Otherwise the same errors as
+    /// [encode_frame](Encoder::encode_frame) are returned.
+    pub fn encode_luma12(
+        mut self,
+        data: &[u16],
+        width: u16,
+        height: u16,
+    ) -> Result<(), EncodingError> {
+        self.encode_luma12_frame(data, width, height)
+    }
+
+    /// Encode a grayscale image with 12 bit samples without consuming the encoder
+    ///
+    /// See [encode_luma12](Encoder::encode_luma12) for further information.
+    pub fn encode_luma12_frame(
+        &mut self,
+        data: &[u16],
+        width: u16,
+        height: u16,
+    ) -> Result<(), EncodingError> {
+        if width == 0 || height == 0 {
+            return Err(EncodingError::ZeroImageDimensions { width, height });
+        }
+
+        let required_data_len = get_buffer_size(width, height, 1, 1)
+            .and(usize::from(width).checked_mul(usize::from(height)))
+            .ok_or(EncodingError::ImageTooLarge { width, height })?;
+
+        if data.len() < required_data_len {
+            return Err(EncodingError::BadImageData {
+                length: data.len(),
+                required: required_data_len,
+            });
+        }
+
+        if let Some(&value) = data[..required_data_len]
+            .iter()
+            .find(|&&value| value > 4095)
+        {
+            return Err(EncodingError::SampleOutOfRange(value));
+        }
+
+        self.stats.clear();
+        let start = self.writer.bytes_written();
+
+        self.init_components(JpegColorType::Luma);
+        let q_tables = self.create_destination_tables(self.quality);
+
+        self.write_header_segments(JpegColorType::Luma)?;
+
+        let q_table = &q_tables[usize::from(self.components[0].quantization_table)];
+        let blocks = get_blocks_12bit(data, width, height, q_table);
+
+        // The settings are changed for the 12 bit scan and restored afterwards
+        let progressive_scans = self.progressive_scans.take();
+        let huffman_tables = self.huffman_tables.clone();
+
+        let result =
+            self.encode_luma12_scan(core::slice::from_ref(&blocks), width, height, &q_tables);
+
+        self.progressive_scans = progressive_scans;
+        self.huffman_tables = huffman_tables;
+
+        result?;
+
+        if self.write_soi_eoi {
+            self.writer.write_marker(Marker::EOI)?;
+        }
+
+        self.stats.total_bytes = self.writer.bytes_written() - start;
+
+        Ok(())
+    }
+
+    fn encode_luma12_scan(
+        &mut self,
+        blocks: &[Vec<[i16; 64]>],
+        width: u16,
+        height: u16,
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        self.optimize_huffman_table(false, |_, i, count| {
+            for block in &blocks[i] {
+                count(block);
+            }
+            Ok(())
+        })?;
+
+        self.write_frame_header_with_precision(width, height, 12, q_tables)?;
+        self.write_sequential_scans(blocks)
+    }
+
+    /// Encode an image
+    pub fn encode_image<I: ImageBuffer>(mut self, image: I) -> Result<(), EncodingError> {
+        self.encode_image_frame(image)
+    }
+
+    /// Encode an image without consuming the encoder
+    ///
+    /// See [encode_frame](Encoder::encode_frame) for further information.
+    pub fn encode_image_frame<I: ImageBuffer>(&mut self, image: I) -> Result<(), EncodingError> {
+        #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if std::is_x86_feature_detected!("avx2") {
+                use crate::avx2::*;
+                return self.encode_image_internal::<_, AVX2Operations>(image);
+            }
+        }
+
+        #[cfg(feature = "std_simd")]
+        {
+            use crate::std_simd::StdSimdOperations;
+            return self.encode_image_internal::<_, StdSimdOperations>(image);
+        }
+
+        #[allow(unreachable_code)]
+        self.encode_image_internal::<_, DefaultOperations>(image)
+    }
+
+    fn encode_image_internal<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
+    ) -> Result<(), EncodingError> {
+        #[cfg(feature = "async")]
+        if self.write_rows {
+            return self.encode_image_rows::<_, OP>(image);
+        }
+
+        let start = self.writer.bytes_written();
+        let q_tables = self.begin_frame(&image)?;
+
+        self.encode_frame_scans::<_, OP>(image, q_tables)?;
+        self.end_frame(start)
+    }
+
+    /// Validates the settings for the image and writes all segments before the frame header
+    ///
+    /// Returns the quantization tables of the frame.
+    fn begin_frame<I: ImageBuffer>(
+        &mut self,
+        image: &I,
+    ) -> Result<[QuantizationTable; 4], EncodingError> {
+        if image.width() == 0 || image.height() == 0 {
+            return Err(EncodingError::ZeroImageDimensions {
+                width: image.width(),
+                height: image.height(),
+            });
+        }
+
+        self.stats.clear();
+
+        let jpeg_color_type = image.get_jpeg_color_type();
+
+        if self.auto_sampling_factor
+            && jpeg_color_type == JpegColorType::Ycbcr
+            && !image.chroma_already_subsampled()
+        {
+            self.check_cancelled()?;
+
+            self.sampling_factor = if has_chroma_detail(image) {
+                SamplingFactor::F_1_1
+            } else {
+                SamplingFactor::F_2_2
+            };
+        }
+
+        self.init_components(jpeg_color_type);
+        self.check_baseline_huffman_tables()?;
+
+        if self.strict_compatibility {
+            if let Some(component) = self.components.iter().find(|component| {
+                component.horizontal_sampling_factor > 2 || component.vertical_sampling_factor > 2
+            }) {
+                return Err(EncodingError::UnsupportedSamplingFactor {
+                    horizontal: component.horizontal_sampling_factor,
+                    vertical: component.vertical_sampling_factor,
+                });
+            }
+        }
+
+        let q_tables = self.create_quantization_tables();
+
+        // Validate buffer sizes once, so the encoding paths don't need to check for overflows
+        let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+        let buffer_size = get_buffer_size(
+            image.width(),
+            image.height(),
+            max_h_sampling,
+            max_v_sampling,
+        );
+
+        if buffer_size.is_none() {
+            return Err(EncodingError::ImageTooLarge {
+                width: image.width(),
+                height: image.height(),
+            });
+        }
+
+        self.write_header_segments(jpeg_color_type)?;
+
+        Ok(q_tables)
+    }
+
+    /// Writes the frame header and all scans of the image
+    fn encode_frame_scans<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
+        q_tables: [QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        // Optimized tables replace the configured ones, so they are restored for the next encode
+        let huffman_tables = if self.optimize_huffman_table {
+            Some(self.huffman_tables.clone())
+        } else {
+            None
+        };
+
+        let result = if self.full_range {
+            self.encode_scans::<_, OP>(image, &q_tables)
+        } else {
+            self.encode_scans::<_, OP>(LimitedRangeImage(image), &q_tables)
+        };
+
+        if let Some(huffman_tables) = huffman_tables {
+            self.huffman_tables = huffman_tables;
+        }
+
+        if self.table_search {
+            self.search_tables = Some(q_tables);
+        }
 
-        for (i, data) in data.chunks(MAX_CHUNK_LENGTH).enumerate() {
-            chunk_data.clear();
-            chunk_data.extend_from_slice(MARKER);
-            chunk_data.push(i as u8 + 1);
-            chunk_data.push(num_chunks as u8);
-            chunk_data.extend_from_slice(data);
+        result
+    }
 
-            self.add_app_segment(2, &chunk_data)?;
+    /// Writes the EOI marker and the size of the frame which started at `start`
+    fn end_frame(&mut self, start: u64) -> Result<(), EncodingError> {
+        if self.write_soi_eoi {
+            self.writer.write_marker(Marker::EOI)?;
         }
 
+        self.stats.total_bytes = self.writer.bytes_written() - start;
+
         Ok(())
     }
 
-    /// Encode an image
+    /// Encodes the next part of a frame which is written one MCU row at a time
     ///
-    /// Data format and length must conform to specified width, height and color type.
-    pub fn encode(
-        self,
-        data: &[u8],
-        width: u16,
-        height: u16,
-        color_type: ColorType,
+    /// The first call writes all segments before the scan data, each further call writes the
+    /// next MCU row of the interleaved scan until the frame is complete.
+    /// Progressive images, optimized huffman tables and sequential scans need all blocks
+    /// before the first scan is written, so these frames are completely written by the
+    /// first call.
+    #[cfg(feature = "async")]
+    fn encode_image_rows<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
     ) -> Result<(), EncodingError> {
-        let required_data_len = width as usize * height as usize * color_type.get_bytes_per_pixel();
+        let mut frame = match self.pending_rows.take() {
+            Some(frame) => frame,
+            None => {
+                let start = self.writer.bytes_written();
+                let q_tables = self.begin_frame(&image)?;
+
+                if self.progressive_scans.is_some()
+                    || self.optimize_huffman_table
+                    || self.table_search
+                    || !self.supports_interleaved()
+                {
+                    self.encode_frame_scans::<_, OP>(image, q_tables)?;
+                    return self.end_frame(start);
+                }
 
-        if data.len() < required_data_len {
-            return Err(EncodingError::BadImageData {
-                length: data.len(),
-                required: required_data_len,
-            });
-        }
+                let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+                let num_cols = ceil_div(usize::from(image.width()), 8 * max_h_sampling);
 
-        #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
-        {
-            if std::is_x86_feature_detected!("avx2") {
-                use crate::avx2::*;
+                self.scratch_rows = self.init_rows(num_cols * 64 * max_h_sampling * max_v_sampling);
 
-                return match color_type {
-                    ColorType::Luma => self
-                        .encode_image_internal::<_, AVX2Operations>(GrayImage(data, width, height)),
-                    ColorType::Rgb => self.encode_image_internal::<_, AVX2Operations>(
-                        RgbImageAVX2(data, width, height),
-                    ),
-                    ColorType::Rgba => self.encode_image_internal::<_, AVX2Operations>(
-                        RgbaImageAVX2(data, width, height),
-                    ),
-                    ColorType::Bgr => self.encode_image_internal::<_, AVX2Operations>(
-                        BgrImageAVX2(data, width, height),
-                    ),
-                    ColorType::Bgra => self.encode_image_internal::<_, AVX2Operations>(
-                        BgraImageAVX2(data, width, height),
-                    ),
-                    ColorType::Ycbcr => self.encode_image_internal::<_, AVX2Operations>(
-                        YCbCrImage(data, width, height),
-                    ),
-                    ColorType::Cmyk => self
-                        .encode_image_internal::<_, AVX2Operations>(CmykImage(data, width, height)),
-                    ColorType::CmykAsYcck => self.encode_image_internal::<_, AVX2Operations>(
-                        CmykAsYcckImage(data, width, height),
-                    ),
-                    ColorType::Ycck => self
-                        .encode_image_internal::<_, AVX2Operations>(YcckImage(data, width, height)),
-                };
+                self.write_frame_header(&image, &q_tables)?;
+                let scan_start = self.write_interleaved_scan_header()?;
+
+                self.pending_rows = Some(PendingRows {
+                    scan: InterleavedScan::new(self.restart_interval, self.mcu_components()),
+                    q_tables,
+                    mcu_row: 0,
+                    num_mcu_rows: ceil_div(usize::from(image.height()), 8 * max_v_sampling),
+                    start,
+                    scan_start,
+                });
+
+                return Ok(());
             }
+        };
+
+        let mut row = core::mem::take(&mut self.scratch_rows);
+        let mut blocks = core::mem::take(&mut self.scratch_band);
+
+        let result = if self.full_range {
+            self.encode_interleaved_band::<_, OP>(
+                &image,
+                &frame.q_tables,
+                frame.mcu_row,
+                &mut row,
+                &mut blocks,
+            )
+        } else {
+            self.encode_interleaved_band::<_, OP>(
+                &LimitedRangeImage(image),
+                &frame.q_tables,
+                frame.mcu_row,
+                &mut row,
+                &mut blocks,
+            )
+        };
+
+        self.scratch_rows = row;
+        result?;
+
+        for block in &blocks {
+            frame.scan.write_block(self, block)?;
         }
 
-        match color_type {
-            ColorType::Luma => self.encode_image(GrayImage(data, width, height))?,
-            ColorType::Rgb => self.encode_image(RgbImage(data, width, height))?,
-            ColorType::Rgba => self.encode_image(RgbaImage(data, width, height))?,
-            ColorType::Bgr => self.encode_image(BgrImage(data, width, height))?,
-            ColorType::Bgra => self.encode_image(BgraImage(data, width, height))?,
-            ColorType::Ycbcr => self.encode_image(YCbCrImage(data, width, height))?,
-            ColorType::Cmyk => self.encode_image(CmykImage(data, width, height))?,
-            ColorType::CmykAsYcck => self.encode_image(CmykAsYcckImage(data, width, height))?,
-            ColorType::Ycck => self.encode_image(YcckImage(data, width, height))?,
+        self.scratch_band = blocks;
+        frame.mcu_row += 1;
+
+        if frame.mcu_row < frame.num_mcu_rows {
+            self.pending_rows = Some(frame);
+            return Ok(());
         }
 
-        Ok(())
+        self.finish_interleaved_scan(frame.scan_start)?;
+        self.end_frame(frame.start)
     }
 
-    /// Encode an image
-    pub fn encode_image<I: ImageBuffer>(self, image: I) -> Result<(), EncodingError> {
-        #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
-        {
-            if std::is_x86_feature_detected!("avx2") {
-                use crate::avx2::*;
-                return self.encode_image_internal::<_, AVX2Operations>(image);
-            }
-        }
-        self.encode_image_internal::<_, DefaultOperations>(image)
+    /// Enables or disables writing frames one MCU row at a time
+    ///
+    /// A frame which isn't complete yet is discarded.
+    #[cfg(feature = "async")]
+    pub(crate) fn set_write_rows(&mut self, write_rows: bool) {
+        self.write_rows = write_rows;
+        self.pending_rows = None;
     }
 
-    fn encode_image_internal<I: ImageBuffer, OP: Operations>(
-        mut self,
-        image: I,
+    /// Returns true if the frame written one MCU row at a time isn't complete yet
+    #[cfg(feature = "async")]
+    pub(crate) fn has_pending_rows(&self) -> bool {
+        self.pending_rows.is_some()
+    }
+
+    /// Writes the SOI marker and all segments before the frame header
+    fn write_header_segments(
+        &mut self,
+        jpeg_color_type: JpegColorType,
     ) -> Result<(), EncodingError> {
-        if image.width() == 0 || image.height() == 0 {
-            return Err(EncodingError::ZeroImageDimensions {
-                width: image.width(),
-                height: image.height(),
-            });
+        // Don't drop density settings silently without a JFIF header
+        if !self.write_jfif && (self.density != Density::None || self.jfif_thumbnail.is_some()) {
+            return Err(EncodingError::JfifRequired);
         }
 
-        let q_tables = [
-            QuantizationTable::new_with_quality(&self.quantization_tables[0], self.quality, true),
-            QuantizationTable::new_with_quality(&self.quantization_tables[1], self.quality, false),
-        ];
-
-        let jpeg_color_type = image.get_jpeg_color_type();
-        self.init_components(jpeg_color_type);
+        if self.write_soi_eoi {
+            self.writer.write_marker(Marker::SOI)?;
+        }
 
-        self.writer.write_marker(Marker::SOI)?;
+        if self.write_jfif {
+            self.writer.write_header(&self.density, self.jfif_version)?;
 
-        self.writer.write_header(&self.density)?;
+            if let Some(thumbnail) = &self.jfif_thumbnail {
+                self.writer.write_segment(Marker::APP(0), thumbnail)?;
+            }
+        }
 
-        if jpeg_color_type == JpegColorType::Cmyk {
+        let adobe_color_transform = match self.adobe_color_transform {
+            Some(transform) => Some(transform),
             //Set ColorTransform info to "Unknown"
-            let app_14 = b"Adobe\0\0\0\0\0\0\0";
-            self.writer
-                .write_segment(Marker::APP(14), app_14.as_ref())?;
-        } else if jpeg_color_type == JpegColorType::Ycck {
+            None if jpeg_color_type == JpegColorType::Cmyk => Some(0),
             //Set ColorTransform info to YCCK
-            let app_14 = b"Adobe\0\0\0\0\0\0\x02";
+            None if jpeg_color_type == JpegColorType::Ycck => Some(2),
+            None => None,
+        };
+
+        if let Some(transform) = adobe_color_transform {
+            let app_14 = [b'A', b'd', b'o', b'b', b'e', 0, 0, 0, 0, 0, 0, transform];
             self.writer
                 .write_segment(Marker::APP(14), app_14.as_ref())?;
         }
@@ -519,23 +2596,34 @@ impl<W: JfifWrite> Encoder<W> {
             self.writer.write_segment(Marker::APP(*nr), data)?;
         }
 
-        if let Some(scans) = self.progressive_scans {
-            self.encode_image_progressive::<_, OP>(image, scans, &q_tables)?;
-        } else if self.optimize_huffman_table || !self.sampling_factor.supports_interleaved() {
-            self.encode_image_sequential::<_, OP>(image, &q_tables)?;
-        } else {
-            self.encode_image_interleaved::<_, OP>(image, &q_tables)?;
+        for comment in &self.comments {
+            self.writer.write_segment(Marker::COM, comment)?;
         }
 
-        self.writer.write_marker(Marker::EOI)?;
-
         Ok(())
     }
 
+    fn encode_scans<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        if let Some(scans) = self.progressive_scans {
+            self.encode_image_progressive::<_, OP>(image, scans, q_tables)
+        } else if !self.supports_interleaved() {
+            self.encode_image_sequential::<_, OP>(image, q_tables)
+        } else {
+            self.encode_image_interleaved::<_, OP>(image, q_tables)
+        }
+    }
+
     fn init_components(&mut self, color: JpegColorType) {
         let (horizontal_sampling_factor, vertical_sampling_factor) =
             self.sampling_factor.get_sampling_factors();
 
+        // Components of a previous encode
+        self.components.clear();
+
         match color {
             JpegColorType::Luma => {
                 add_component!(self.components, 0, 0, 1, 1);
@@ -582,6 +2670,215 @@ impl<W: JfifWrite> Encoder<W> {
                 );
             }
         }
+
+        for (component, factors) in self
+            .components
+            .iter_mut()
+            .zip(&self.component_sampling_factors)
+        {
+            if let Some((h, v)) = *factors {
+                component.horizontal_sampling_factor = h;
+                component.vertical_sampling_factor = v;
+            }
+        }
+
+        self.quantization_sources = DEFAULT_QUANTIZATION_SOURCES;
+
+        // Components with their own table use the destinations the other components leave free
+        let mut used = [false; 4];
+
+        for (component, table) in self
+            .components
+            .iter()
+            .zip(&self.component_quantization_tables)
+        {
+            if table.is_none() {
+                used[usize::from(component.quantization_table)] = true;
+            }
+        }
+
+        for (i, (component, table)) in self
+            .components
+            .iter_mut()
+            .zip(&self.component_quantization_tables)
+            .enumerate()
+        {
+            if table.is_some() {
+                // There are never more than 4 components, so a destination is always free
+                let destination = used.iter().position(|&used| !used).unwrap();
+                used[destination] = true;
+
+                self.quantization_sources[destination] =
+                    (Some(i), component.quantization_table == 0);
+                component.quantization_table = destination as u8;
+            }
+        }
+
+        // Use a distinct table for each component of 4 component images
+        // if tables get optimized, so K isn't merged with any other component.
+        // Baseline images only have the two default destinations.
+        if self.optimize_huffman_table && self.components.len() == 4 && !self.force_baseline {
+            for (i, component) in self.components.iter_mut().enumerate() {
+                component.dc_huffman_table = i as u8;
+                component.ac_huffman_table = i as u8;
+            }
+        }
+
+        for (component, destinations) in self
+            .components
+            .iter_mut()
+            .zip(&self.component_huffman_tables)
+        {
+            if let Some((dc, ac)) = *destinations {
+                component.dc_huffman_table = dc;
+                component.ac_huffman_table = ac;
+            }
+        }
+    }
+
+    /// Returns the conversion for the chroma blocks if they are averaged in linear light
+    fn linear_chroma(&self) -> Option<LinearChroma> {
+        if self.downsample_filter == DownsampleFilter::AverageLinear && self.components.len() == 3 {
+            Some(LinearChroma {
+                color_space: self.color_space,
+                full_range: self.full_range,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the quality of the tables written into the image
+    fn written_quality(&self) -> f32 {
+        self.quality_regions
+            .iter()
+            .map(|region| self.quality + region.quality_delta)
+            .fold(self.quality, f32::max)
+    }
+
+    /// Create the tables written into the image and the tables of the quality regions
+    ///
+    /// Must be called after [init_components](Encoder::init_components).
+    fn create_quantization_tables(&mut self) -> [QuantizationTable; 4] {
+        if let Some(mut tables) = self.search_tables.take() {
+            // Only set without quality regions
+            for table in tables.iter_mut() {
+                table.rescale(self.quality);
+            }
+
+            self.region_tables = None;
+            return tables;
+        }
+
+        let quality = self.written_quality();
+
+        self.region_tables = if self.quality_regions.is_empty() {
+            None
+        } else {
+            let regions = self
+                .quality_regions
+                .iter()
+                .map(|region| {
+                    let quality = self.quality + region.quality_delta;
+                    (region.clone(), self.create_destination_tables(quality))
+                })
+                .collect();
+
+            let background = self.create_destination_tables(self.quality);
+
+            Some(RegionTables::new(background, regions))
+        };
+
+        self.create_destination_tables(quality)
+    }
+
+    /// Create the tables of all quantization table destinations
+    fn create_destination_tables(&self, quality: f32) -> [QuantizationTable; 4] {
+        self.quantization_sources.map(|(component, luma)| {
+            let table_type = component
+                .and_then(|component| self.component_quantization_tables[component].as_ref())
+                .unwrap_or(&self.quantization_tables[if luma { 0 } else { 1 }]);
+
+            self.create_quantization_table_from(table_type, luma, quality)
+        })
+    }
+
+    fn create_quantization_table(&self, luma: bool, quality: f32) -> QuantizationTable {
+        let index = if luma { 0 } else { 1 };
+        self.create_quantization_table_from(&self.quantization_tables[index], luma, quality)
+    }
+
+    fn create_quantization_table_from(
+        &self,
+        table_type: &QuantizationTableType,
+        luma: bool,
+        quality: f32,
+    ) -> QuantizationTable {
+        let mut table =
+            QuantizationTable::new_with_quality_f32(table_type, quality, luma, self.force_baseline);
+
+        if self.dead_zone > 0.0 {
+            table.set_dead_zone(self.dead_zone);
+        }
+
+        table
+    }
+
+    fn num_huffman_tables(&self) -> u8 {
+        self.components
+            .iter()
+            .map(|component| component.dc_huffman_table.max(component.ac_huffman_table) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns an error if a component uses a huffman table that isn't allowed in
+    /// a baseline frame while [force_baseline](Encoder::set_force_baseline) is set
+    fn check_baseline_huffman_tables(&self) -> Result<(), EncodingError> {
+        let num_huffman_tables = self.num_huffman_tables();
+
+        if self.force_baseline && self.progressive_scans.is_none() && num_huffman_tables > 2 {
+            return Err(EncodingError::BaselineHuffmanDestination(
+                num_huffman_tables - 1,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn supports_interleaved(&self) -> bool {
+        // Interleaved mode is only supported with h/v sampling factors of 1 or 2.
+        // Sampling factors of 4 needs sequential encoding
+        let factors_supported = self.components.iter().all(|component| {
+            component.horizontal_sampling_factor <= 2 && component.vertical_sampling_factor <= 2
+        });
+
+        // A MCU must not contain more than 10 blocks
+        let num_blocks: u8 = self
+            .components
+            .iter()
+            .map(|component| {
+                component.horizontal_sampling_factor * component.vertical_sampling_factor
+            })
+            .sum();
+
+        factors_supported && num_blocks <= 10
+    }
+
+    /// Returns true if the components of the image are read at their native resolution
+    fn reads_native_components<I: ImageBuffer>(&self, image: &I) -> bool {
+        if self.components.len() < 2 || !image.chroma_already_subsampled() {
+            return false;
+        }
+
+        let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+
+        self.components.iter().enumerate().all(|(i, component)| {
+            let h_scale = max_h_sampling / component.horizontal_sampling_factor as usize;
+            let v_scale = max_v_sampling / component.vertical_sampling_factor as usize;
+
+            image.component_subsampling(i) == (h_scale as u8, v_scale as u8)
+        })
     }
 
     fn get_max_sampling_size(&self) -> (usize, usize) {
@@ -599,32 +2896,90 @@ impl<W: JfifWrite> Encoder<W> {
     fn write_frame_header<I: ImageBuffer>(
         &mut self,
         image: &I,
-        q_tables: &[QuantizationTable; 2],
+        q_tables: &[QuantizationTable; 4],
     ) -> Result<(), EncodingError> {
-        self.writer.write_frame_header(
-            image.width(),
-            image.height(),
-            &self.components,
-            self.progressive_scans.is_some(),
-        )?;
+        self.write_frame_header_with_precision(image.width(), image.height(), 8, q_tables)
+    }
+
+    fn write_frame_header_with_precision(
+        &mut self,
+        width: u16,
+        height: u16,
+        precision: u8,
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        self.check_baseline_huffman_tables()?;
 
-        self.writer.write_quantization_segment(0, &q_tables[0])?;
-        self.writer.write_quantization_segment(1, &q_tables[1])?;
+        let num_huffman_tables = self.num_huffman_tables();
 
-        self.writer
-            .write_huffman_segment(CodingClass::Dc, 0, &self.huffman_tables[0].0)?;
+        let sof_type = if self.progressive_scans.is_some() {
+            SOFType::ProgressiveDCT
+        } else if num_huffman_tables > 2
+            || q_tables.iter().any(|table| table.is_16_bit())
+            || precision != 8
+        {
+            // Baseline only allows two huffman tables per class, 8 bit quantization values
+            // and 8 bit samples
+            SOFType::ExtendedSequentialDCT
+        } else {
+            SOFType::BaselineDCT
+        };
+
+        let height = if self.deferred_height {
+            self.pending_dnl = Some(height);
+            0
+        } else {
+            self.pending_dnl = None;
+            height
+        };
 
         self.writer
-            .write_huffman_segment(CodingClass::Ac, 0, &self.huffman_tables[0].1)?;
+            .write_frame_header(width, height, precision, &self.components, sof_type)?;
+
+        let tables_start = self.writer.bytes_written();
+
+        for (destination, table) in (0u8..).zip(q_tables) {
+            // Grayscale images don't need the chroma table
+            if self
+                .components
+                .iter()
+                .any(|component| component.quantization_table == destination)
+            {
+                self.writer.write_quantization_segment(destination, table)?;
+            }
+        }
 
-        if image.get_jpeg_color_type().get_num_components() >= 3 {
-            self.writer
-                .write_huffman_segment(CodingClass::Dc, 1, &self.huffman_tables[1].0)?;
+        self.stats.quantization_table_bytes += self.writer.bytes_written() - tables_start;
+        let tables_start = self.writer.bytes_written();
+
+        for table in 0..num_huffman_tables {
+            if self
+                .components
+                .iter()
+                .any(|component| component.dc_huffman_table == table)
+            {
+                self.writer.write_huffman_segment(
+                    CodingClass::Dc,
+                    table,
+                    &self.huffman_tables[table as usize].0,
+                )?;
+            }
 
-            self.writer
-                .write_huffman_segment(CodingClass::Ac, 1, &self.huffman_tables[1].1)?;
+            if self
+                .components
+                .iter()
+                .any(|component| component.ac_huffman_table == table)
+            {
+                self.writer.write_huffman_segment(
+                    CodingClass::Ac,
+                    table,
+                    &self.huffman_tables[table as usize].1,
+                )?;
+            }
         }
 
+        self.stats.huffman_table_bytes += self.writer.bytes_written() - tables_start;
+
         if let Some(restart_interval) = self.restart_interval {
             self.writer.write_dri(restart_interval)?;
         }
@@ -634,166 +2989,414 @@ impl<W: JfifWrite> Encoder<W> {
 
     fn init_rows(&mut self, buffer_size: usize) -> [Vec<u8>; 4] {
         // To simplify the code and to give the compiler more infos to optimize stuff we always initialize 4 components
-        // Resource overhead should be minimal because an empty Vec doesn't allocate
-
-        match self.components.len() {
-            1 => [
-                Vec::with_capacity(buffer_size),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-            ],
-            3 => [
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::new(),
-            ],
-            4 => [
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-            ],
-            len => unreachable!("Unsupported component length: {}", len),
+        // The buffers of previous encodes are reused, unused components stay empty
+
+        debug_assert!(self.components.len() <= 4);
+
+        let mut rows = core::mem::take(&mut self.scratch_rows);
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.clear();
+
+            if i < self.components.len() {
+                row.reserve(buffer_size);
+            }
+        }
+
+        rows
+    }
+
+    /// Encode all components with one scan
+    ///
+    /// This is only valid for sampling factors of 1 and 2.<br>
+    /// For optimized huffman tables the symbols are counted in a first pass over the blocks.
+    /// The blocks of the whole image are buffered for the second pass unless low memory mode
+    /// is enabled, in which case the image is read and transformed once more per component.
+    fn encode_image_interleaved<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+
+        let num_cols = ceil_div(usize::from(image.width()), 8 * max_h_sampling);
+        let num_mcu_rows = ceil_div(usize::from(image.height()), 8 * max_v_sampling);
+
+        let mut row = self.init_rows(num_cols * 64 * max_h_sampling * max_v_sampling);
+        let mut blocks = core::mem::take(&mut self.scratch_band);
+
+        let buffered = if self.optimize_huffman_table && !self.low_memory {
+            let mut image_blocks = core::mem::take(&mut self.scratch_blocks[0]);
+            image_blocks.clear();
+
+            for mcu_row in 0..num_mcu_rows {
+                self.encode_interleaved_band::<_, OP>(
+                    &image,
+                    q_tables,
+                    mcu_row,
+                    &mut row,
+                    &mut blocks,
+                )?;
+                image_blocks.extend_from_slice(&blocks);
+            }
+
+            Some(image_blocks)
+        } else {
+            None
+        };
+
+        let mcu_components = self.mcu_components();
+
+        if self.optimize_huffman_table {
+            self.optimize_huffman_table(true, |encoder, i, count| {
+                let mut count_band = |band: &[[i16; 64]]| {
+                    for (index, block) in band.iter().enumerate() {
+                        if mcu_components[index % mcu_components.len()] == i {
+                            count(block);
+                        }
+                    }
+                };
+
+                match &buffered {
+                    Some(image_blocks) => count_band(image_blocks),
+                    None => {
+                        for mcu_row in 0..num_mcu_rows {
+                            encoder.encode_interleaved_band::<_, OP>(
+                                &image,
+                                q_tables,
+                                mcu_row,
+                                &mut row,
+                                &mut blocks,
+                            )?;
+                            count_band(&blocks);
+                        }
+                    }
+                }
+
+                Ok(())
+            })?;
+        }
+
+        self.write_frame_header(&image, q_tables)?;
+        let scan_start = self.write_interleaved_scan_header()?;
+
+        let mut scan = InterleavedScan::new(self.restart_interval, mcu_components);
+
+        if let Some(image_blocks) = buffered {
+            for block in &image_blocks {
+                scan.write_block(self, block)?;
+            }
+
+            self.scratch_blocks[0] = image_blocks;
+        } else {
+            for mcu_row in 0..num_mcu_rows {
+                self.encode_interleaved_band::<_, OP>(
+                    &image,
+                    q_tables,
+                    mcu_row,
+                    &mut row,
+                    &mut blocks,
+                )?;
+
+                for block in &blocks {
+                    scan.write_block(self, block)?;
+                }
+            }
+        }
+
+        self.finish_interleaved_scan(scan_start)?;
+
+        self.scratch_rows = row;
+        self.scratch_band = blocks;
+
+        Ok(())
+    }
+
+    /// Writes the header of a scan with all components and returns the position of its data
+    fn write_interleaved_scan_header(&mut self) -> Result<u64, EncodingError> {
+        // Collected into an array to avoid an allocation for each encode
+        let mut components = [&self.components[0]; 4];
+        for (dst, component) in components.iter_mut().zip(&self.components) {
+            *dst = component;
+        }
+
+        self.writer
+            .write_scan_header(&components[..self.components.len()], None)?;
+
+        Ok(self.writer.bytes_written())
+    }
+
+    fn finish_interleaved_scan(&mut self, scan_start: u64) -> Result<(), EncodingError> {
+        self.writer.finalize_scan(self.pending_dnl.take())?;
+
+        self.stats.scans.push(ScanStats {
+            component: None,
+            spectral_selection: (0, 63),
+            bytes: self.writer.bytes_written() - scan_start,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the index of the component of each block in an MCU of an interleaved scan
+    fn mcu_components(&self) -> Vec<usize> {
+        let mut mcu_components = Vec::with_capacity(10);
+
+        for (i, component) in self.components.iter().enumerate() {
+            let num_blocks =
+                component.horizontal_sampling_factor * component.vertical_sampling_factor;
+            mcu_components.extend(core::iter::repeat(i).take(usize::from(num_blocks)));
         }
+
+        mcu_components
     }
 
-    /// Encode all components with one scan
+    /// Computes the blocks of all components which belong to the given MCU row
     ///
-    /// This is only valid for sampling factors of 1 and 2
-    fn encode_image_interleaved<I: ImageBuffer, OP: Operations>(
-        &mut self,
-        image: I,
-        q_tables: &[QuantizationTable; 2],
+    /// The blocks are in the same order as in an interleaved scan.
+    fn encode_interleaved_band<I: ImageBuffer, OP: Operations>(
+        &self,
+        image: &I,
+        q_tables: &[QuantizationTable; 4],
+        block_y: usize,
+        row: &mut [Vec<u8>; 4],
+        blocks: &mut Vec<[i16; 64]>,
     ) -> Result<(), EncodingError> {
-        self.write_frame_header(&image, q_tables)?;
-        self.writer
-            .write_scan_header(&self.components.iter().collect::<Vec<_>>(), None)?;
+        self.check_cancelled()?;
 
         let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
 
+        if self.components.len() == 1 && max_h_sampling == 1 && max_v_sampling == 1 {
+            self.encode_luma_band::<_, OP>(image, q_tables, block_y, row, blocks);
+            return Ok(());
+        }
+
         let width = image.width();
         let height = image.height();
 
         let num_cols = ceil_div(usize::from(width), 8 * max_h_sampling);
-        let num_rows = ceil_div(usize::from(height), 8 * max_v_sampling);
-
         let buffer_width = num_cols * 8 * max_h_sampling;
-        let buffer_size = buffer_width * 8 * max_v_sampling;
 
-        let mut row: [Vec<_>; 4] = self.init_rows(buffer_size);
+        let native_components = self.reads_native_components(image);
+        let linear_chroma = self.linear_chroma();
 
-        let mut prev_dc = [0i16; 4];
+        blocks.clear();
 
-        let restart_interval = self.restart_interval.unwrap_or(0);
-        let mut restarts = 0;
-        let mut restarts_to_go = restart_interval;
+        for r in row.iter_mut() {
+            r.clear();
+        }
 
-        for block_y in 0..num_rows {
-            for r in &mut row {
-                r.clear();
+        if native_components {
+            for (i, component) in self.components.iter().enumerate() {
+                let h_sampling = component.horizontal_sampling_factor as usize;
+                let v_sampling = component.vertical_sampling_factor as usize;
+
+                let start = block_y * 8 * v_sampling;
+
+                fill_component_rows(
+                    image,
+                    i,
+                    start..start + 8 * v_sampling,
+                    ceil_div(usize::from(height), max_v_sampling / v_sampling),
+                    num_cols * 8 * h_sampling,
+                    &mut row[i],
+                );
             }
-
+        } else {
             for y in 0..(8 * max_v_sampling) {
                 let y = y + block_y * 8 * max_v_sampling;
                 let y = (y.min(height as usize - 1)) as u16;
 
-                image.fill_buffers(y, &mut row);
+                image.fill_buffers(y, row);
 
                 for _ in usize::from(width)..buffer_width {
-                    for channel in &mut row {
+                    for channel in row.iter_mut() {
                         if !channel.is_empty() {
                             channel.push(channel[channel.len() - 1]);
                         }
                     }
                 }
             }
+        }
 
-            for block_x in 0..num_cols {
-                if restart_interval > 0 && restarts_to_go == 0 {
-                    self.writer.finalize_bit_buffer()?;
-                    self.writer
-                        .write_marker(Marker::RST((restarts % 8) as u8))?;
+        for block_x in 0..num_cols {
+            for (i, component) in self.components.iter().enumerate() {
+                let h_sampling = component.horizontal_sampling_factor as usize;
+                let v_sampling = component.vertical_sampling_factor as usize;
+
+                // Native components are already at the resolution of the blocks
+                let (x_start, h_scale, v_scale, row_width) = if native_components {
+                    (block_x * 8 * h_sampling, 1, 1, num_cols * 8 * h_sampling)
+                } else {
+                    (
+                        block_x * 8 * max_h_sampling,
+                        max_h_sampling / h_sampling,
+                        max_v_sampling / v_sampling,
+                        buffer_width,
+                    )
+                };
 
-                    prev_dc[0] = 0;
-                    prev_dc[1] = 0;
-                    prev_dc[2] = 0;
-                    prev_dc[3] = 0;
-                }
+                for v_offset in 0..v_sampling {
+                    for h_offset in 0..h_sampling {
+                        let x = x_start + (h_offset * 8);
+                        let y = v_offset * 8;
 
-                for (i, component) in self.components.iter().enumerate() {
-                    for v_offset in 0..component.vertical_sampling_factor as usize {
-                        for h_offset in 0..component.horizontal_sampling_factor as usize {
-                            let mut block = get_block(
+                        let mut block = match linear_chroma {
+                            Some(linear) => {
+                                linear.get_block(row, i, x, y, h_scale, v_scale, row_width)
+                            }
+                            None => get_block(
                                 &row[i],
-                                block_x * 8 * max_h_sampling + (h_offset * 8),
-                                v_offset * 8,
-                                max_h_sampling
-                                    / component.horizontal_sampling_factor as usize,
-                                max_v_sampling
-                                    / component.vertical_sampling_factor as usize,
-                                buffer_width,
-                            );
-
-                            OP::fdct(&mut block);
-
-                            let mut q_block = [0i16; 64];
-
-                            OP::quantize_block(
-                                &block,
-                                &mut q_block,
-                                &q_tables[component.quantization_table as usize],
-                            );
-
-                            self.writer.write_block(
-                                &q_block,
-                                prev_dc[i],
-                                &self.huffman_tables[component.dc_huffman_table as usize].0,
-                                &self.huffman_tables[component.ac_huffman_table as usize].1,
-                            )?;
-
-                            prev_dc[i] = q_block[0];
-                        }
+                                x,
+                                y,
+                                h_scale,
+                                v_scale,
+                                row_width,
+                                self.downsample_filter,
+                            ),
+                        };
+
+                        transform_block::<OP>(&mut block, self.adaptive_quantization);
+
+                        let region_table = self.region_tables.as_ref().map(|tables| {
+                            let block_width = 8 * max_h_sampling / h_sampling;
+                            let block_height = 8 * max_v_sampling / v_sampling;
+
+                            tables.get(
+                                component.quantization_table as usize,
+                                (block_x * h_sampling + h_offset) * block_width,
+                                (block_y * v_sampling + v_offset) * block_height,
+                                block_width,
+                                block_height,
+                            )
+                        });
+
+                        let mut q_block = [0i16; 64];
+
+                        quantize_block::<OP>(
+                            &block,
+                            &mut q_block,
+                            &q_tables[component.quantization_table as usize],
+                            self.trellis_table(component),
+                            region_table,
+                        );
+
+                        blocks.push(q_block);
                     }
                 }
+            }
+        }
 
-                if restart_interval > 0 {
-                    if restarts_to_go == 0 {
-                        restarts_to_go = restart_interval;
-                        restarts += 1;
-                        restarts &= 7;
-                    }
-                    restarts_to_go -= 1;
+        Ok(())
+    }
+
+    /// Computes the blocks of 8 rows of a grayscale image without subsampling
+    ///
+    /// Same as [encode_interleaved_band](Encoder::encode_interleaved_band), but the blocks are
+    /// copied directly from the luma row without the lookups needed for subsampled components.
+    fn encode_luma_band<I: ImageBuffer, OP: Operations>(
+        &self,
+        image: &I,
+        q_tables: &[QuantizationTable; 4],
+        block_y: usize,
+        row: &mut [Vec<u8>; 4],
+        blocks: &mut Vec<[i16; 64]>,
+    ) {
+        let width = usize::from(image.width());
+        let height = usize::from(image.height());
+
+        let num_cols = ceil_div(width, 8);
+        let buffer_width = num_cols * 8;
+
+        let component = &self.components[0];
+        let table = &q_tables[component.quantization_table as usize];
+        let trellis_table = self.trellis_table(component);
+
+        blocks.clear();
+
+        for r in row.iter_mut() {
+            r.clear();
+        }
+
+        for y in block_y * 8..block_y * 8 + 8 {
+            image.fill_buffers(y.min(height - 1) as u16, row);
+
+            let luma = &mut row[0];
+            let last = luma[luma.len() - 1];
+            luma.resize(luma.len() + buffer_width - width, last);
+        }
+
+        for block_x in 0..num_cols {
+            let mut block = [0i16; 64];
+
+            let lines = row[0][block_x * 8..].chunks(buffer_width);
+
+            for (values, line) in block.chunks_exact_mut(8).zip(lines) {
+                for (value, &sample) in values.iter_mut().zip(&line[..8]) {
+                    *value = i16::from(sample) - 128;
                 }
             }
-        }
 
-        self.writer.finalize_bit_buffer()?;
+            transform_block::<OP>(&mut block, self.adaptive_quantization);
 
-        Ok(())
+            let region_table = self.region_tables.as_ref().map(|tables| {
+                tables.get(
+                    component.quantization_table as usize,
+                    block_x * 8,
+                    block_y * 8,
+                    8,
+                    8,
+                )
+            });
+
+            let mut q_block = [0i16; 64];
+
+            quantize_block::<OP>(&block, &mut q_block, table, trellis_table, region_table);
+
+            blocks.push(q_block);
+        }
     }
 
     /// Encode components with one scan per component
     fn encode_image_sequential<I: ImageBuffer, OP: Operations>(
         &mut self,
         image: I,
-        q_tables: &[QuantizationTable; 2],
+        q_tables: &[QuantizationTable; 4],
     ) -> Result<(), EncodingError> {
-        let blocks = self.encode_blocks::<_, OP>(&image, q_tables);
+        if self.low_memory {
+            return self.encode_image_sequential_low_memory::<_, OP>(image, q_tables);
+        }
+
+        let blocks = self.encode_blocks::<_, OP>(&image, q_tables)?;
 
         if self.optimize_huffman_table {
-            self.optimize_huffman_table(&blocks);
+            self.optimize_huffman_table(false, |_, i, count| {
+                for block in &blocks[i] {
+                    count(block);
+                }
+                Ok(())
+            })?;
         }
 
         self.write_frame_header(&image, q_tables)?;
+        self.write_sequential_scans(&blocks)?;
+
+        self.scratch_blocks = blocks;
+
+        Ok(())
+    }
 
+    /// Writes one scan per component with the blocks of the component
+    fn write_sequential_scans(&mut self, blocks: &[Vec<[i16; 64]>]) -> Result<(), EncodingError> {
         for (i, component) in self.components.iter().enumerate() {
             let restart_interval = self.restart_interval.unwrap_or(0);
             let mut restarts = 0;
             let mut restarts_to_go = restart_interval;
 
+            self.check_cancelled()?;
+
             self.writer.write_scan_header(&[component], None)?;
+            let scan_start = self.writer.bytes_written();
 
             let mut prev_dc = 0;
 
@@ -825,7 +3428,251 @@ impl<W: JfifWrite> Encoder<W> {
                 }
             }
 
-            self.writer.finalize_bit_buffer()?;
+            self.writer.finalize_scan(self.pending_dnl.take())?;
+
+            self.stats.scans.push(ScanStats {
+                component: Some(i),
+                spectral_selection: (0, 63),
+                bytes: self.writer.bytes_written() - scan_start,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Encode components with one scan per component without buffering the blocks of the whole image
+    ///
+    /// The blocks are computed one MCU row at a time, so the image is read once per component
+    /// and once more per component if optimized huffman tables are enabled.
+    fn encode_image_sequential_low_memory<I: ImageBuffer, OP: Operations>(
+        &mut self,
+        image: I,
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<(), EncodingError> {
+        let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+
+        let buffer_width =
+            ceil_div(usize::from(image.width()), 8 * max_h_sampling) * max_h_sampling * 8;
+        let num_mcu_rows = ceil_div(usize::from(image.height()), 8 * max_v_sampling);
+
+        let row_size = if self.reads_native_components(&image) {
+            0
+        } else {
+            buffer_width * 8 * max_v_sampling
+        };
+
+        let mut row = self.init_rows(row_size);
+        let mut blocks = core::mem::take(&mut self.scratch_band);
+
+        if self.optimize_huffman_table {
+            // First pass only counts the symbols of the blocks
+            self.optimize_huffman_table(false, |encoder, i, count| {
+                for mcu_row in 0..num_mcu_rows {
+                    encoder.encode_component_band::<_, OP>(
+                        &image,
+                        q_tables,
+                        i,
+                        mcu_row,
+                        &mut row,
+                        &mut blocks,
+                    )?;
+
+                    for block in &blocks {
+                        count(block);
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        self.write_frame_header(&image, q_tables)?;
+
+        for i in 0..self.components.len() {
+            let restart_interval = self.restart_interval.unwrap_or(0);
+            let mut restarts = 0;
+            let mut restarts_to_go = restart_interval;
+
+            self.writer
+                .write_scan_header(&[&self.components[i]], None)?;
+            let scan_start = self.writer.bytes_written();
+
+            let mut prev_dc = 0;
+
+            for mcu_row in 0..num_mcu_rows {
+                self.encode_component_band::<_, OP>(
+                    &image,
+                    q_tables,
+                    i,
+                    mcu_row,
+                    &mut row,
+                    &mut blocks,
+                )?;
+
+                let component = &self.components[i];
+
+                for block in &blocks {
+                    if restart_interval > 0 && restarts_to_go == 0 {
+                        self.writer.finalize_bit_buffer()?;
+                        self.writer
+                            .write_marker(Marker::RST((restarts % 8) as u8))?;
+
+                        prev_dc = 0;
+                    }
+
+                    self.writer.write_block(
+                        block,
+                        prev_dc,
+                        &self.huffman_tables[component.dc_huffman_table as usize].0,
+                        &self.huffman_tables[component.ac_huffman_table as usize].1,
+                    )?;
+
+                    prev_dc = block[0];
+
+                    if restart_interval > 0 {
+                        if restarts_to_go == 0 {
+                            restarts_to_go = restart_interval;
+                            restarts += 1;
+                            restarts &= 7;
+                        }
+                        restarts_to_go -= 1;
+                    }
+                }
+            }
+
+            self.writer.finalize_scan(self.pending_dnl.take())?;
+
+            self.stats.scans.push(ScanStats {
+                component: Some(i),
+                spectral_selection: (0, 63),
+                bytes: self.writer.bytes_written() - scan_start,
+            });
+        }
+
+        self.scratch_rows = row;
+        self.scratch_band = blocks;
+
+        Ok(())
+    }
+
+    /// Computes the blocks of a component which belong to the given MCU row
+    ///
+    /// The blocks are in the same order as in the non-interleaved scan of the component.
+    fn encode_component_band<I: ImageBuffer, OP: Operations>(
+        &self,
+        image: &I,
+        q_tables: &[QuantizationTable; 4],
+        i: usize,
+        mcu_row: usize,
+        row: &mut [Vec<u8>; 4],
+        blocks: &mut Vec<[i16; 64]>,
+    ) -> Result<(), EncodingError> {
+        self.check_cancelled()?;
+
+        let width = image.width();
+        let height = image.height();
+
+        let (max_h_sampling, max_v_sampling) = self.get_max_sampling_size();
+
+        let component = &self.components[i];
+
+        let h_scale = max_h_sampling / component.horizontal_sampling_factor as usize;
+        let v_scale = max_v_sampling / component.vertical_sampling_factor as usize;
+
+        let cols = ceil_div(ceil_div(usize::from(width), 8), h_scale);
+        let rows = ceil_div(ceil_div(usize::from(height), 8), v_scale);
+
+        // Block rows of the component inside of this MCU row
+        let v_sampling = component.vertical_sampling_factor as usize;
+        let start_row = mcu_row * v_sampling;
+        let end_row = rows.min(start_row + v_sampling);
+
+        // Pixels of the image covered by a block
+        let (block_width, block_height) = (8 * h_scale, 8 * v_scale);
+
+        for r in row.iter_mut() {
+            r.clear();
+        }
+        blocks.clear();
+
+        let linear_chroma = self.linear_chroma();
+
+        let (h_scale, v_scale, row_width) = if self.reads_native_components(image) {
+            fill_component_rows(
+                image,
+                i,
+                start_row * 8..end_row * 8,
+                ceil_div(usize::from(height), v_scale),
+                cols * 8,
+                &mut row[i],
+            );
+
+            (1, 1, cols * 8)
+        } else {
+            let buffer_width =
+                ceil_div(usize::from(width), 8 * max_h_sampling) * max_h_sampling * 8;
+            let start_y = mcu_row * 8 * max_v_sampling;
+
+            for y in start_y..start_y + 8 * max_v_sampling {
+                let y = (y.min(usize::from(height) - 1)) as u16;
+
+                image.fill_buffers(y, row);
+
+                for _ in usize::from(width)..buffer_width {
+                    for channel in row.iter_mut() {
+                        if !channel.is_empty() {
+                            channel.push(channel[channel.len() - 1]);
+                        }
+                    }
+                }
+            }
+
+            (h_scale, v_scale, buffer_width)
+        };
+
+        let q_table = &q_tables[component.quantization_table as usize];
+
+        for block_y in 0..end_row - start_row {
+            for block_x in 0..cols {
+                let x = block_x * 8 * h_scale;
+                let y = block_y * 8 * v_scale;
+
+                let mut block = match linear_chroma {
+                    Some(linear) => linear.get_block(row, i, x, y, h_scale, v_scale, row_width),
+                    None => get_block(
+                        &row[i],
+                        x,
+                        y,
+                        h_scale,
+                        v_scale,
+                        row_width,
+                        self.downsample_filter,
+                    ),
+                };
+
+                transform_block::<OP>(&mut block, self.adaptive_quantization);
+
+                let region_table = self.region_tables.as_ref().map(|tables| {
+                    tables.get(
+                        component.quantization_table as usize,
+                        block_x * block_width,
+                        (start_row + block_y) * block_height,
+                        block_width,
+                        block_height,
+                    )
+                });
+
+                let mut q_block = [0i16; 64];
+
+                quantize_block::<OP>(
+                    &block,
+                    &mut q_block,
+                    q_table,
+                    self.trellis_table(component),
+                    region_table,
+                );
+
+                blocks.push(q_block);
+            }
         }
 
         Ok(())
@@ -838,12 +3685,17 @@ impl<W: JfifWrite> Encoder<W> {
         &mut self,
         image: I,
         scans: u8,
-        q_tables: &[QuantizationTable; 2],
+        q_tables: &[QuantizationTable; 4],
     ) -> Result<(), EncodingError> {
-        let blocks = self.encode_blocks::<_, OP>(&image, q_tables);
+        let blocks = self.encode_blocks::<_, OP>(&image, q_tables)?;
 
         if self.optimize_huffman_table {
-            self.optimize_huffman_table(&blocks);
+            self.optimize_huffman_table(false, |_, i, count| {
+                for block in &blocks[i] {
+                    count(block);
+                }
+                Ok(())
+            })?;
         }
 
         self.write_frame_header(&image, q_tables)?;
@@ -851,7 +3703,10 @@ impl<W: JfifWrite> Encoder<W> {
         // Phase 1: DC Scan
         //          Only the DC coefficients can be transfer in the first component scans
         for (i, component) in self.components.iter().enumerate() {
+            self.check_cancelled()?;
+
             self.writer.write_scan_header(&[component], Some((0, 0)))?;
+            let scan_start = self.writer.bytes_written();
 
             let restart_interval = self.restart_interval.unwrap_or(0);
             let mut restarts = 0;
@@ -886,30 +3741,27 @@ impl<W: JfifWrite> Encoder<W> {
                 }
             }
 
-            self.writer.finalize_bit_buffer()?;
+            self.writer.finalize_scan(self.pending_dnl.take())?;
+
+            self.stats.scans.push(ScanStats {
+                component: Some(i),
+                spectral_selection: (0, 0),
+                bytes: self.writer.bytes_written() - scan_start,
+            });
         }
 
         // Phase 2: AC scans
-        let scans = scans as usize - 1;
-
-        let values_per_scan = 64 / scans;
-
-        for scan in 0..scans {
-            let start = (scan * values_per_scan).max(1);
-            let end = if scan == scans - 1 {
-                // ensure last scan is always transfers the remaining coefficients
-                64
-            } else {
-                (scan + 1) * values_per_scan
-            };
-
+        for (start, end) in get_progressive_bands(scans) {
             for (i, component) in self.components.iter().enumerate() {
                 let restart_interval = self.restart_interval.unwrap_or(0);
                 let mut restarts = 0;
                 let mut restarts_to_go = restart_interval;
 
+                self.check_cancelled()?;
+
                 self.writer
                     .write_scan_header(&[component], Some((start as u8, end as u8 - 1)))?;
+                let scan_start = self.writer.bytes_written();
 
                 for block in &blocks[i] {
                     if restart_interval > 0 && restarts_to_go == 0 {
@@ -935,18 +3787,26 @@ impl<W: JfifWrite> Encoder<W> {
                     }
                 }
 
-                self.writer.finalize_bit_buffer()?;
+                self.writer.finalize_scan(self.pending_dnl.take())?;
+
+                self.stats.scans.push(ScanStats {
+                    component: Some(i),
+                    spectral_selection: (start as u8, end as u8 - 1),
+                    bytes: self.writer.bytes_written() - scan_start,
+                });
             }
         }
 
+        self.scratch_blocks = blocks;
+
         Ok(())
     }
 
     fn encode_blocks<I: ImageBuffer, OP: Operations>(
         &mut self,
         image: &I,
-        q_tables: &[QuantizationTable; 2],
-    ) -> [Vec<[i16; 64]>; 4] {
+        q_tables: &[QuantizationTable; 4],
+    ) -> Result<[Vec<[i16; 64]>; 4], EncodingError> {
         let width = image.width();
         let height = image.height();
 
@@ -961,17 +3821,26 @@ impl<W: JfifWrite> Encoder<W> {
         let buffer_width = num_cols * 8;
         let buffer_size = num_cols * num_rows * 64;
 
-        let mut row: [Vec<_>; 4] = self.init_rows(buffer_size);
+        let native_components = self.reads_native_components(image);
+        let linear_chroma = self.linear_chroma();
+
+        let mut row = self.init_rows(if native_components { 0 } else { buffer_size });
+
+        if !native_components {
+            for y in 0..num_rows * 8 {
+                if y % (8 * max_v_sampling) == 0 {
+                    self.check_cancelled()?;
+                }
 
-        for y in 0..num_rows * 8 {
-            let y = (y.min(usize::from(height) - 1)) as u16;
+                let y = (y.min(usize::from(height) - 1)) as u16;
 
-            image.fill_buffers(y, &mut row);
+                image.fill_buffers(y, &mut row);
 
-            for _ in usize::from(width)..num_cols * 8 {
-                for channel in &mut row {
-                    if !channel.is_empty() {
-                        channel.push(channel[channel.len() - 1]);
+                for _ in usize::from(width)..num_cols * 8 {
+                    for channel in &mut row {
+                        if !channel.is_empty() {
+                            channel.push(channel[channel.len() - 1]);
+                        }
                     }
                 }
             }
@@ -995,66 +3864,169 @@ impl<W: JfifWrite> Encoder<W> {
             debug_assert!(cols > 0);
             debug_assert!(rows > 0);
 
-            for block_y in 0..rows {
-                for block_x in 0..cols {
-                    let mut block = get_block(
-                        &row[i],
-                        block_x * 8 * h_scale,
-                        block_y * 8 * v_scale,
+            // Pixels of the image covered by a block
+            let (block_width, block_height) = (8 * h_scale, 8 * v_scale);
+
+            // Native components are read at the resolution of the blocks
+            let (h_scale, v_scale, row_width) = if native_components {
+                row[i].reserve(cols * rows * 64);
+
+                fill_component_rows(
+                    image,
+                    i,
+                    0..rows * 8,
+                    ceil_div(usize::from(height), v_scale),
+                    cols * 8,
+                    &mut row[i],
+                );
+
+                (1, 1, cols * 8)
+            } else {
+                (h_scale, v_scale, buffer_width)
+            };
+
+            // Only borrow what is needed to build a block, so this can be shared between threads
+            let component_row = &row[i];
+            let q_table = &q_tables[component.quantization_table as usize];
+            let trellis_table = self.trellis_table(component);
+            let region_tables = self.region_tables.as_ref();
+            let downsample_filter = self.downsample_filter;
+            let component_rows = &row;
+
+            let encode_block = |block_x: usize, block_y: usize| {
+                let x = block_x * 8 * h_scale;
+                let y = block_y * 8 * v_scale;
+
+                let mut block = match linear_chroma {
+                    Some(linear) => {
+                        linear.get_block(component_rows, i, x, y, h_scale, v_scale, row_width)
+                    }
+                    None => get_block(
+                        component_row,
+                        x,
+                        y,
                         h_scale,
                         v_scale,
-                        buffer_width,
-                    );
+                        row_width,
+                        downsample_filter,
+                    ),
+                };
+
+                transform_block::<OP>(&mut block, self.adaptive_quantization);
+
+                let region_table = region_tables.map(|tables| {
+                    tables.get(
+                        component.quantization_table as usize,
+                        block_x * block_width,
+                        block_y * block_height,
+                        block_width,
+                        block_height,
+                    )
+                });
+
+                let mut q_block = [0i16; 64];
+
+                quantize_block::<OP>(&block, &mut q_block, q_table, trellis_table, region_table);
+
+                q_block
+            };
+
+            // Every block row is written to its own slice, so the result doesn't depend on the number of threads
+            #[cfg(feature = "rayon")]
+            {
+                self.check_cancelled()?;
 
-                    OP::fdct(&mut block);
+                use rayon::prelude::*;
 
-                    let mut q_block = [0i16; 64];
+                blocks[i].resize(rows * cols, [0i16; 64]);
+
+                blocks[i]
+                    .par_chunks_mut(cols)
+                    .enumerate()
+                    .for_each(|(block_y, block_row)| {
+                        for (block_x, q_block) in block_row.iter_mut().enumerate() {
+                            *q_block = encode_block(block_x, block_y);
+                        }
+                    });
+            }
 
-                    OP::quantize_block(
-                        &block,
-                        &mut q_block,
-                        &q_tables[component.quantization_table as usize],
-                    );
+            #[cfg(not(feature = "rayon"))]
+            for block_y in 0..rows {
+                self.check_cancelled()?;
 
-                    blocks[i].push(q_block);
+                for block_x in 0..cols {
+                    blocks[i].push(encode_block(block_x, block_y));
                 }
             }
         }
-        blocks
+        self.scratch_rows = row;
+
+        Ok(blocks)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn writer_mut(&mut self) -> &mut W {
+        self.writer.inner_mut()
+    }
+
+    fn check_cancelled(&self) -> Result<(), EncodingError> {
+        match &self.cancel_check {
+            Some(cancel_check) if cancel_check() => Err(EncodingError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the AC huffman table of the component if trellis quantization is enabled
+    fn trellis_table(&self, component: &Component) -> Option<&HuffmanTable> {
+        if self.trellis_quantization {
+            Some(&self.huffman_tables[component.ac_huffman_table as usize].1)
+        } else {
+            None
+        }
     }
 
     fn init_block_buffers(&mut self, buffer_size: usize) -> [Vec<[i16; 64]>; 4] {
         // To simplify the code and to give the compiler more infos to optimize stuff we always initialize 4 components
-        // Resource overhead should be minimal because an empty Vec doesn't allocate
-
-        match self.components.len() {
-            1 => [
-                Vec::with_capacity(buffer_size),
-                Vec::new(),
-                Vec::new(),
-                Vec::new(),
-            ],
-            3 => [
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::new(),
-            ],
-            4 => [
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-                Vec::with_capacity(buffer_size),
-            ],
-            len => unreachable!("Unsupported component length: {}", len),
-        }
-    }
-
-    // Create new huffman tables optimized for this image
-    fn optimize_huffman_table(&mut self, blocks: &[Vec<[i16; 64]>; 4]) {
-        // TODO: Find out if it's possible to reuse some code from the writer
-
-        let max_tables = self.components.len().min(2) as u8;
+        // The buffers of previous encodes are reused, unused components stay empty
+
+        debug_assert!(self.components.len() <= 4);
+
+        let mut blocks = core::mem::take(&mut self.scratch_blocks);
+
+        for (i, component_blocks) in blocks.iter_mut().enumerate() {
+            component_blocks.clear();
+
+            if i < self.components.len() {
+                component_blocks.reserve(buffer_size);
+            }
+        }
+
+        blocks
+    }
+
+    /// Creates huffman tables optimized for this image
+    ///
+    /// `for_each_block` has to call the passed closure for all blocks of the component with the given index.
+    /// Replaces the huffman tables by tables optimized for the blocks
+    ///
+    /// `for_each_block` is called for each component to count the blocks of the component in
+    /// the order they are written. In `interleaved` scans, each MCU contains several blocks
+    /// of a component, which affects when the DC predictor is reset by a restart marker.
+    fn optimize_huffman_table<F>(
+        &mut self,
+        interleaved: bool,
+        mut for_each_block: F,
+    ) -> Result<(), EncodingError>
+    where
+        F: FnMut(&Self, usize, &mut dyn FnMut(&[i16; 64])) -> Result<(), EncodingError>,
+    {
+        let max_tables = self.num_huffman_tables();
+
+        // Sequential mode transfers all AC coefficients in one scan, just like the single AC
+        // scan of a progressive image with 2 scans
+        let bands = get_progressive_bands(self.progressive_scans.unwrap_or(2));
+
+        let restart_interval = usize::from(self.restart_interval.unwrap_or(0));
 
         for table in 0..max_tables {
             let mut dc_freq = [0u32; 257];
@@ -1066,14 +4038,36 @@ impl<W: JfifWrite> Encoder<W> {
             let mut had_dc = false;
 
             for (i, component) in self.components.iter().enumerate() {
-                if component.dc_huffman_table == table {
-                    had_dc = true;
+                let count_dc = component.dc_huffman_table == table;
+                let count_ac = component.ac_huffman_table == table;
+
+                had_dc |= count_dc;
+                had_ac |= count_ac;
+
+                if !count_dc && !count_ac {
+                    continue;
+                }
 
-                    let mut prev_dc = 0;
+                let mut prev_dc = 0;
+                let mut index = 0;
 
-                    debug_assert!(!blocks[i].is_empty());
+                let blocks_per_mcu = if interleaved {
+                    usize::from(
+                        component.horizontal_sampling_factor * component.vertical_sampling_factor,
+                    )
+                } else {
+                    1
+                };
+
+                // Count all bands of a block at once, so each block is only traversed once
+                for_each_block(self, i, &mut |block| {
+                    if count_dc {
+                        // The predictor is reset after each restart marker like in the writer
+                        if restart_interval > 0 && index % (restart_interval * blocks_per_mcu) == 0
+                        {
+                            prev_dc = 0;
+                        }
 
-                    for block in &blocks[i] {
                         let value = block[0];
                         let diff = value - prev_dc;
                         let num_bits = get_num_bits(diff);
@@ -1082,89 +4076,31 @@ impl<W: JfifWrite> Encoder<W> {
 
                         prev_dc = value;
                     }
-                }
-
-                if component.ac_huffman_table == table {
-                    had_ac = true;
-
-                    if let Some(scans) = self.progressive_scans {
-                        let scans = scans as usize - 1;
-
-                        let values_per_scan = 64 / scans;
-
-                        for scan in 0..scans {
-                            let start = (scan * values_per_scan).max(1);
-                            let end = if scan == scans - 1 {
-                                // Due to rounding we might need to transfer more than values_per_scan values in the last scan
-                                64
-                            } else {
-                                (scan + 1) * values_per_scan
-                            };
-
-                            debug_assert!(!blocks[i].is_empty());
-
-                            for block in &blocks[i] {
-                                let mut zero_run = 0;
-
-                                for &value in &block[start..end] {
-                                    if value == 0 {
-                                        zero_run += 1;
-                                    } else {
-                                        while zero_run > 15 {
-                                            ac_freq[0xF0] += 1;
-                                            zero_run -= 16;
-                                        }
-                                        let num_bits = get_num_bits(value);
-                                        let symbol = (zero_run << 4) | num_bits;
-
-                                        ac_freq[symbol as usize] += 1;
 
-                                        zero_run = 0;
-                                    }
-                                }
-
-                                if zero_run > 0 {
-                                    ac_freq[0] += 1;
-                                }
-                            }
-                        }
-                    } else {
-                        for block in &blocks[i] {
-                            let mut zero_run = 0;
-
-                            for &value in &block[1..] {
-                                if value == 0 {
-                                    zero_run += 1;
-                                } else {
-                                    while zero_run > 15 {
-                                        ac_freq[0xF0] += 1;
-                                        zero_run -= 16;
-                                    }
-                                    let num_bits = get_num_bits(value);
-                                    let symbol = (zero_run << 4) | num_bits;
-
-                                    ac_freq[symbol as usize] += 1;
-
-                                    zero_run = 0;
-                                }
-                            }
-
-                            if zero_run > 0 {
-                                ac_freq[0] += 1;
-                            }
+                    if count_ac {
+                        for (start, end) in bands.clone() {
+                            count_ac_symbols(&block[start..end], &mut ac_freq);
                         }
                     }
-                }
+
+                    index += 1;
+                })?;
+
+                debug_assert!(index > 0);
             }
 
-            assert!(had_dc, "Missing DC data for table {}", table);
-            assert!(had_ac, "Missing AC data for table {}", table);
+            // Destinations can be referenced by DC or AC coefficients only and unreferenced
+            // tables aren't written
+            if had_dc {
+                self.huffman_tables[table as usize].0 = HuffmanTable::new_optimized(dc_freq);
+            }
 
-            self.huffman_tables[table as usize] = (
-                HuffmanTable::new_optimized(dc_freq),
-                HuffmanTable::new_optimized(ac_freq),
-            );
+            if had_ac {
+                self.huffman_tables[table as usize].1 = HuffmanTable::new_optimized(ac_freq);
+            }
         }
+
+        Ok(())
     }
 }
 
@@ -1187,6 +4123,53 @@ impl Encoder<BufWriter<File>> {
     }
 }
 
+impl Encoder<Vec<u8>> {
+    /// Encode an image into a newly allocated `Vec`
+    ///
+    /// This is a shortcut for creating an encoder with [new](Encoder::new) that writes into a `Vec`
+    /// and calling [encode](Encoder::encode) on it. Default settings are used for all other options.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [encode](Encoder::encode)
+    pub fn encode_to_vec(
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+        quality: u8,
+    ) -> Result<Vec<u8>, EncodingError> {
+        // A quarter of the raw image size is enough for most images with a high quality setting.
+        // The buffer grows as usual if this is not the case.
+        let capacity = color_type.checked_data_len(width, height).unwrap_or(0) / 4;
+
+        let mut buf = Vec::with_capacity(capacity);
+        Encoder::new(&mut buf, quality).encode(data, width, height, color_type)?;
+
+        Ok(buf)
+    }
+}
+
+/// Add rows of a component at its native resolution and pad them to the row width
+fn fill_component_rows<I: ImageBuffer>(
+    image: &I,
+    component: usize,
+    rows: core::ops::Range<usize>,
+    height: usize,
+    row_width: usize,
+    buffer: &mut Vec<u8>,
+) {
+    for y in rows {
+        let y = y.min(height - 1) as u16;
+
+        let start = buffer.len();
+        image.fill_component_row(component, y, buffer);
+
+        let last = buffer[buffer.len() - 1];
+        buffer.resize(start + row_width, last);
+    }
+}
+
 fn get_block(
     data: &[u8],
     start_x: usize,
@@ -1194,9 +4177,33 @@ fn get_block(
     col_stride: usize,
     row_stride: usize,
     width: usize,
+    filter: DownsampleFilter,
 ) -> [i16; 64] {
     let mut block = [0i16; 64];
 
+    if filter != DownsampleFilter::Point && (col_stride > 1 || row_stride > 1) {
+        let count = (col_stride * row_stride) as u32;
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let ix = start_x + (x * col_stride);
+                let iy = start_y + (y * row_stride);
+
+                let mut sum = 0u32;
+
+                for row in data[iy * width..].chunks(width).take(row_stride) {
+                    for &value in &row[ix..ix + col_stride] {
+                        sum += u32::from(value);
+                    }
+                }
+
+                block[y * 8 + x] = ((sum + count / 2) / count) as i16 - 128;
+            }
+        }
+
+        return block;
+    }
+
     for y in 0..8 {
         for x in 0..8 {
             let ix = start_x + (x * col_stride);
@@ -1209,6 +4216,338 @@ fn get_block(
     block
 }
 
+/// Encodes the image buffer chosen by [InputFormat::visit]
+struct EncodeFrame<'e, W: JfifWrite>(&'e mut Encoder<W>);
+
+impl<'e, W: JfifWrite> ImageVisitor for EncodeFrame<'e, W> {
+    type Output = Result<(), EncodingError>;
+
+    fn visit<I: ImageBuffer>(self, image: I) -> Self::Output {
+        self.0.encode_image_frame(image)
+    }
+}
+
+/// Writes the blocks of an interleaved scan in the order of [encode_interleaved_band](Encoder::encode_interleaved_band)
+struct InterleavedScan {
+    mcu_components: Vec<usize>,
+    index: usize,
+    prev_dc: [i16; 4],
+    restart_interval: u16,
+    restarts: u8,
+    restarts_to_go: u16,
+}
+
+impl InterleavedScan {
+    fn new(restart_interval: Option<u16>, mcu_components: Vec<usize>) -> InterleavedScan {
+        let restart_interval = restart_interval.unwrap_or(0);
+
+        InterleavedScan {
+            mcu_components,
+            index: 0,
+            prev_dc: [0; 4],
+            restart_interval,
+            restarts: 0,
+            restarts_to_go: restart_interval,
+        }
+    }
+
+    fn write_block<W: JfifWrite>(
+        &mut self,
+        encoder: &mut Encoder<W>,
+        block: &[i16; 64],
+    ) -> Result<(), EncodingError> {
+        let position = self.index % self.mcu_components.len();
+
+        // A restart marker can only be written in front of a MCU
+        if position == 0 && self.restart_interval > 0 {
+            if self.restarts_to_go == 0 {
+                encoder.writer.finalize_bit_buffer()?;
+                encoder.writer.write_marker(Marker::RST(self.restarts))?;
+
+                self.prev_dc = [0; 4];
+                self.restarts = (self.restarts + 1) & 7;
+                self.restarts_to_go = self.restart_interval;
+            }
+
+            self.restarts_to_go -= 1;
+        }
+
+        let i = self.mcu_components[position];
+        let component = &encoder.components[i];
+
+        encoder.writer.write_block(
+            block,
+            self.prev_dc[i],
+            &encoder.huffman_tables[component.dc_huffman_table as usize].0,
+            &encoder.huffman_tables[component.ac_huffman_table as usize].1,
+        )?;
+
+        self.prev_dc[i] = block[0];
+        self.index += 1;
+
+        Ok(())
+    }
+}
+
+/// Frame which is written one MCU row at a time for the [AsyncEncoder](crate::AsyncEncoder)
+#[cfg(feature = "async")]
+struct PendingRows {
+    q_tables: [QuantizationTable; 4],
+    scan: InterleavedScan,
+    mcu_row: usize,
+    num_mcu_rows: usize,
+    start: u64,
+    scan_start: u64,
+}
+
+/// Downsampling of chroma blocks in linear light for [AverageLinear](DownsampleFilter::AverageLinear)
+#[derive(Copy, Clone)]
+struct LinearChroma {
+    color_space: ColorSpace,
+    full_range: bool,
+}
+
+impl LinearChroma {
+    /// Same as [get_block] but averages the colors of the Y, Cb and Cr `rows` in linear light
+    #[allow(clippy::too_many_arguments)]
+    fn get_block(
+        self,
+        rows: &[Vec<u8>; 4],
+        component: usize,
+        start_x: usize,
+        start_y: usize,
+        col_stride: usize,
+        row_stride: usize,
+        width: usize,
+    ) -> [i16; 64] {
+        let data = &rows[component];
+
+        if component == 0 || (col_stride == 1 && row_stride == 1) {
+            return get_block(
+                data,
+                start_x,
+                start_y,
+                col_stride,
+                row_stride,
+                width,
+                DownsampleFilter::Point,
+            );
+        }
+
+        let count = (col_stride * row_stride) as f32;
+
+        let mut block = [0i16; 64];
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let ix = start_x + (x * col_stride);
+                let iy = start_y + (y * row_stride);
+
+                let mut sum = [0f32; 3];
+
+                for row in iy..iy + row_stride {
+                    let range = row * width + ix..row * width + ix + col_stride;
+
+                    let pixels = rows[0][range.clone()]
+                        .iter()
+                        .zip(&rows[1][range.clone()])
+                        .zip(&rows[2][range]);
+
+                    for ((&y, &cb), &cr) in pixels {
+                        let rgb = self.to_rgb(y, cb, cr);
+
+                        for (sum, value) in sum.iter_mut().zip(rgb) {
+                            *sum += srgb_to_linear(value);
+                        }
+                    }
+                }
+
+                let [r, g, b] = sum.map(|sum| linear_to_srgb(sum / count));
+                let (cb, cr) = self.to_chroma(r, g, b);
+
+                let value = if component == 1 { cb } else { cr };
+                block[y * 8 + x] = i16::from(value) - 128;
+            }
+        }
+
+        block
+    }
+
+    fn to_rgb(self, y: u8, cb: u8, cr: u8) -> [u8; 3] {
+        if self.full_range {
+            return ycbcr_to_rgb(y, cb, cr, self.color_space);
+        }
+
+        // Undo the scaling of LimitedRangeImage
+        let expand = |value: u8, range: f32| {
+            ((f32::from(value) - 16.0) * 255.0 / range + 0.5).clamp(0.0, 255.0) as u8
+        };
+
+        ycbcr_to_rgb(
+            expand(y, 219.0),
+            expand(cb, 224.0),
+            expand(cr, 224.0),
+            self.color_space,
+        )
+    }
+
+    fn to_chroma(self, r: u8, g: u8, b: u8) -> (u8, u8) {
+        let (_, cb, cr) = match self.color_space {
+            ColorSpace::Bt601 => rgb_to_ycbcr(r, g, b),
+            ColorSpace::Bt709 => rgb_to_ycbcr_bt709(r, g, b),
+        };
+
+        if self.full_range {
+            (cb, cr)
+        } else {
+            let limit = |value: u8| (16 + (u32::from(value) * 224 + 127) / 255) as u8;
+            (limit(cb), limit(cr))
+        }
+    }
+}
+
+/// Get the spectral bands of the AC scans for progressive encoding
+///
+/// Returns the start and (exclusive) end index of the coefficients for each scan
+fn get_progressive_bands(scans: u8) -> impl Iterator<Item = (usize, usize)> + Clone {
+    let scans = scans as usize - 1;
+
+    let values_per_scan = 64 / scans;
+
+    (0..scans).map(move |scan| {
+        let start = (scan * values_per_scan).max(1);
+        let end = if scan == scans - 1 {
+            // ensure last scan is always transfers the remaining coefficients
+            64
+        } else {
+            (scan + 1) * values_per_scan
+        };
+
+        (start, end)
+    })
+}
+
+/// Count the huffman symbols of AC coefficients
+fn count_ac_symbols(values: &[i16], ac_freq: &mut [u32; 257]) {
+    // Uses the traversal of the writer, so the counted symbols are exactly the written ones
+    for_each_ac_symbol(values, |symbol, _, _| {
+        ac_freq[symbol as usize] += 1;
+        Ok::<(), Infallible>(())
+    })
+    .unwrap_or_else(|never| match never {});
+}
+
+/// Mean absolute difference between neighbouring chroma samples above which the
+/// [automatic sampling factor](Encoder::set_sampling_factor_auto) keeps the full chroma resolution
+///
+/// Smooth photographic content stays well below this value while colored text, lines
+/// and other hard color edges exceed it.
+pub const AUTO_SAMPLING_THRESHOLD: u32 = 4;
+
+/// Returns true if the Cb or Cr components of the image contain detail that would be lost
+/// by subsampling
+fn has_chroma_detail<I: ImageBuffer>(image: &I) -> bool {
+    let mut previous: [Vec<u8>; 4] = Default::default();
+    let mut current: [Vec<u8>; 4] = Default::default();
+
+    // The SIMD implementations of fill_buffers expect the capacity for a row to be reserved
+    for buffer in previous.iter_mut().chain(current.iter_mut()) {
+        buffer.reserve(usize::from(image.width()));
+    }
+
+    let mut sum = 0u64;
+    let mut count = 0u64;
+
+    for y in 0..image.height() {
+        for buffer in &mut current {
+            buffer.clear();
+        }
+
+        image.fill_buffers(y, &mut current);
+
+        for (row, previous_row) in current[1..3].iter().zip(&previous[1..3]) {
+            for values in row.windows(2) {
+                sum += u64::from(values[0].abs_diff(values[1]));
+            }
+            count += row.len().saturating_sub(1) as u64;
+
+            // The previous rows are empty for the first row of the image
+            for (&value, &above) in row.iter().zip(previous_row) {
+                sum += u64::from(value.abs_diff(above));
+                count += 1;
+            }
+        }
+
+        core::mem::swap(&mut previous, &mut current);
+    }
+
+    sum > u64::from(AUTO_SAMPLING_THRESHOLD) * count
+}
+
+/// Returns the quantized blocks of a 12 bit grayscale image in zigzag order
+///
+/// Blocks at the right and bottom edge are filled up by repeating the last column and row.
+fn get_blocks_12bit(
+    data: &[u16],
+    width: u16,
+    height: u16,
+    table: &QuantizationTable,
+) -> Vec<[i16; 64]> {
+    let width = usize::from(width);
+    let height = usize::from(height);
+
+    let num_cols = ceil_div(width, 8);
+    let num_rows = ceil_div(height, 8);
+
+    let mut blocks = Vec::with_capacity(num_cols * num_rows);
+
+    for block_y in 0..num_rows {
+        for block_x in 0..num_cols {
+            let mut block = [0i16; 64];
+
+            for y in 0..8 {
+                let offset = (block_y * 8 + y).min(height - 1) * width;
+
+                for x in 0..8 {
+                    let sample = data[offset + (block_x * 8 + x).min(width - 1)];
+                    block[y * 8 + x] = sample as i16 - 2048;
+                }
+            }
+
+            let coefficients = fdct_12bit(&block);
+
+            let mut q_block = [0i16; 64];
+            for (value, &index) in q_block.iter_mut().zip(ZIGZAG.iter()) {
+                let index = usize::from(index);
+                *value = table.quantize_12bit(coefficients[index], index);
+            }
+
+            blocks.push(q_block);
+        }
+    }
+
+    blocks
+}
+
+/// Returns the number of samples in a block aligned component buffer or `None` if the
+/// buffers for the image don't fit into the address space
+fn get_buffer_size(
+    width: u16,
+    height: u16,
+    max_h_sampling: usize,
+    max_v_sampling: usize,
+) -> Option<usize> {
+    let num_cols = ceil_div(usize::from(width), 8 * max_h_sampling) * max_h_sampling;
+    let num_rows = ceil_div(usize::from(height), 8 * max_v_sampling) * max_v_sampling;
+
+    let size = num_cols.checked_mul(num_rows)?.checked_mul(64)?;
+
+    // Blocks store the samples as i16 values
+    size.checked_mul(core::mem::size_of::<i16>())?;
+
+    Some(size)
+}
+
 fn ceil_div(value: usize, div: usize) -> usize {
     value / div + usize::from(value % div != 0)
 }
@@ -1228,6 +4567,85 @@ fn get_num_bits(mut value: i16) -> u8 {
     num_bits
 }
 
+/// Luma and chroma tables for the quantization table destinations without components
+/// that have their own table
+const DEFAULT_QUANTIZATION_SOURCES: [(Option<usize>, bool); 4] =
+    [(None, true), (None, false), (None, true), (None, false)];
+
+/// Size of the headers of an image with the default settings and huffman tables
+///
+/// Used by [estimated_size](Encoder::estimated_size).
+const ESTIMATED_HEADER_SIZE: usize = 620;
+
+/// Variance of the samples at which the AC coefficients of a block are scaled by 2/3
+///
+/// Blocks with a variance of zero are scaled by 1/2 and the factor approaches 1 for larger variances.
+const ADAPTIVE_QUANTIZATION_VARIANCE: i32 = 64;
+
+/// Transform a block and scale its AC coefficients based on the activity if requested
+#[inline(always)]
+fn transform_block<OP: Operations>(block: &mut [i16; 64], adaptive_quantization: bool) {
+    if adaptive_quantization {
+        let variance = block_variance(block);
+
+        OP::fdct(block);
+
+        scale_ac_coefficients(block, variance);
+    } else {
+        OP::fdct(block);
+    }
+}
+
+/// Returns the variance of the samples of a block
+fn block_variance(block: &[i16; 64]) -> i32 {
+    let mut sum = 0i32;
+    let mut sum_squares = 0i32;
+
+    for &value in block {
+        let value = i32::from(value);
+        sum += value;
+        sum_squares += value * value;
+    }
+
+    (sum_squares - sum * sum / 64) / 64
+}
+
+/// Scale the AC coefficients of a transformed block by `(variance + k) / (variance + 2k)`
+fn scale_ac_coefficients(block: &mut [i16; 64], variance: i32) {
+    let numerator = variance + ADAPTIVE_QUANTIZATION_VARIANCE;
+    let denominator = variance + 2 * ADAPTIVE_QUANTIZATION_VARIANCE;
+
+    for value in &mut block[1..] {
+        *value = (i32::from(*value) * numerator / denominator) as i16;
+    }
+}
+
+/// Quantize a block with the written table or the table of a quality region
+///
+/// Blocks quantized with a region table are converted to values of the written table.
+fn quantize_block<OP: Operations>(
+    block: &[i16; 64],
+    q_block: &mut [i16; 64],
+    table: &QuantizationTable,
+    trellis_table: Option<&HuffmanTable>,
+    region_table: Option<&QuantizationTable>,
+) {
+    let quantization_table = region_table.unwrap_or(table);
+
+    if let Some(ac_table) = trellis_table {
+        trellis_quantize_block(block, q_block, quantization_table, ac_table);
+    } else if quantization_table.exact_division() {
+        // The SIMD implementations only multiply with the reciprocals
+        DefaultOperations::quantize_block(block, q_block, quantization_table);
+    } else {
+        OP::quantize_block(block, q_block, quantization_table);
+    }
+
+    if let Some(region_table) = region_table {
+        region_table.requantize(q_block, table);
+    }
+}
+
 pub(crate) trait Operations {
     #[inline(always)]
     fn fdct(data: &mut [i16; 64]) {
@@ -1236,9 +4654,15 @@ pub(crate) trait Operations {
 
     #[inline(always)]
     fn quantize_block(block: &[i16; 64], q_block: &mut [i16; 64], table: &QuantizationTable) {
+        // Quantize in natural order first, so the compiler is able to vectorize this loop
+        let mut quantized = [0i16; 64];
+
         for i in 0..64 {
-            let z = ZIGZAG[i] as usize & 0x3f;
-            q_block[i] = table.quantize(block[z], z);
+            quantized[i] = table.quantize(block[i], i);
+        }
+
+        for i in 0..64 {
+            q_block[i] = quantized[ZIGZAG[i] as usize & 0x3f];
         }
     }
 }
@@ -1250,10 +4674,15 @@ impl Operations for DefaultOperations {}
 #[cfg(test)]
 mod tests {
     use alloc::vec;
+    use alloc::vec::Vec;
 
-    use crate::encoder::get_num_bits;
-    use crate::writer::get_code;
-    use crate::{Encoder, SamplingFactor};
+    use crate::encoder::{
+        block_variance, count_ac_symbols, get_block, get_buffer_size, get_num_bits,
+        scale_ac_coefficients, DefaultOperations, DownsampleFilter, Operations,
+    };
+    use crate::quantization::{QuantizationTable, QuantizationTableType};
+    use crate::writer::{get_code, magnitude_category, ZIGZAG};
+    use crate::{ColorType, Encoder, EncodingError, SamplingFactor};
 
     #[test]
     fn test_get_num_bits() {
@@ -1271,6 +4700,144 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_adaptive_quantization_scale() {
+        assert_eq!(block_variance(&[-100; 64]), 0);
+
+        let mut block = [0i16; 64];
+        for (i, value) in block.iter_mut().enumerate() {
+            *value = if i % 2 == 0 { 10 } else { -10 };
+        }
+        assert_eq!(block_variance(&block), 100);
+
+        // Flat blocks are scaled by a half
+        let mut block = [100i16; 64];
+        scale_ac_coefficients(&mut block, 0);
+        assert_eq!(block[0], 100);
+        assert!(block[1..].iter().all(|&v| v == 50));
+
+        // Busy blocks are barely changed
+        let mut block = [-1000i16; 64];
+        scale_ac_coefficients(&mut block, 10000);
+        assert_eq!(block[0], -1000);
+        assert!(block[1..].iter().all(|&v| v == -993));
+    }
+
+    #[test]
+    fn test_magnitude_category() {
+        // Coefficients of the encoder never need more than 14 bits
+        for value in -16383..=16383 {
+            assert_eq!(magnitude_category(value), get_code(value));
+        }
+
+        assert_eq!(magnitude_category(i16::MAX), (15, 0x7FFF));
+        assert_eq!(magnitude_category(-i16::MAX), (15, 0));
+        assert_eq!(magnitude_category(i16::MIN), (16, 0x7FFF));
+    }
+
+    #[test]
+    fn test_image_too_large() {
+        let max = u16::MAX;
+
+        // The largest images need 2^32 samples per component and 2^35 bytes of RGB f32 data
+        let buffer_size = get_buffer_size(max, max, 1, 1);
+        let data_len = ColorType::RgbF32.checked_data_len(max, max);
+
+        #[cfg(target_pointer_width = "64")]
+        {
+            assert_eq!(buffer_size, Some(8192 * 8192 * 64));
+            assert_eq!(data_len, Some(65535 * 65535 * 12));
+        }
+
+        #[cfg(not(target_pointer_width = "64"))]
+        {
+            assert_eq!(buffer_size, None);
+            assert_eq!(data_len, None);
+        }
+
+        assert_eq!(get_buffer_size(max, max, 4, 4), buffer_size);
+
+        // The stride is already at the usize boundary, so the region can't be addressed
+        let result = Encoder::new(Vec::new(), 100).encode_region(
+            &[0; 16],
+            1,
+            usize::MAX / 2,
+            0,
+            3,
+            1,
+            1,
+            ColorType::Luma,
+        );
+
+        assert!(matches!(
+            result,
+            Err(EncodingError::ImageTooLarge {
+                width: 1,
+                height: 1
+            })
+        ));
+
+        let result = Encoder::new(Vec::new(), 100).encode(&[0; 16], max, max, ColorType::RgbF32);
+
+        #[cfg(target_pointer_width = "64")]
+        assert!(matches!(result, Err(EncodingError::BadImageData { .. })));
+
+        #[cfg(not(target_pointer_width = "64"))]
+        assert!(matches!(result, Err(EncodingError::ImageTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_quantize_block_zigzag_order() {
+        let table =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 50, true, false);
+
+        let mut block = [0i16; 64];
+        for (i, v) in block.iter_mut().enumerate() {
+            *v = (i as i16 * 397 % 4096 - 2048) * 4;
+        }
+
+        let mut q_block = [0i16; 64];
+        DefaultOperations::quantize_block(&block, &mut q_block, &table);
+
+        for i in 0..64 {
+            let z = ZIGZAG[i] as usize;
+            assert_eq!(q_block[i], table.quantize(block[z], z));
+        }
+    }
+
+    #[test]
+    fn test_count_ac_symbols_zero_block() {
+        let mut ac_freq = [0u32; 257];
+
+        count_ac_symbols(&[0i16; 63], &mut ac_freq);
+
+        assert_eq!(ac_freq[0], 1);
+        assert_eq!(ac_freq.iter().sum::<u32>(), 1);
+
+        let mut values = [0i16; 63];
+        values[20] = 3;
+
+        count_ac_symbols(&values, &mut ac_freq);
+
+        assert_eq!(ac_freq[0], 2);
+        assert_eq!(ac_freq[0xF0], 1);
+        assert_eq!(ac_freq[0x42], 1);
+    }
+
+    #[test]
+    fn test_get_block_average() {
+        // Columns alternate between 0 and 255, so each 2x2 area has an average of 127.5
+        let data: Vec<u8> = (0..16 * 16)
+            .map(|i| if i % 2 == 0 { 0 } else { 255 })
+            .collect();
+
+        let block = get_block(&data, 0, 0, 2, 2, 16, DownsampleFilter::Point);
+        assert!(block.iter().all(|&v| v == -128));
+
+        let block = get_block(&data, 0, 0, 2, 2, 16, DownsampleFilter::Average);
+        assert!(block.iter().all(|&v| v == 0));
+    }
+
     #[test]
     fn sampling_factors() {
         assert_eq!(SamplingFactor::F_1_1.get_sampling_factors(), (1, 1));
@@ -1301,4 +4868,44 @@ mod tests {
         encoder.set_progressive(false);
         assert_eq!(encoder.progressive_scans(), None);
     }
+
+    #[test]
+    fn color_type_from_channels() {
+        assert_eq!(
+            ColorType::from_channels(1, false, false),
+            Some(ColorType::Luma)
+        );
+        assert_eq!(
+            ColorType::from_channels(1, false, true),
+            Some(ColorType::Luma)
+        );
+        assert_eq!(
+            ColorType::from_channels(3, false, false),
+            Some(ColorType::Rgb)
+        );
+        assert_eq!(
+            ColorType::from_channels(3, false, true),
+            Some(ColorType::Bgr)
+        );
+        assert_eq!(
+            ColorType::from_channels(4, true, false),
+            Some(ColorType::Rgba)
+        );
+        assert_eq!(
+            ColorType::from_channels(4, true, true),
+            Some(ColorType::Bgra)
+        );
+
+        assert_eq!(ColorType::from_channels(0, false, false), None);
+        assert_eq!(ColorType::from_channels(1, true, false), None);
+        assert_eq!(
+            ColorType::from_channels(2, true, false),
+            Some(ColorType::LumaA)
+        );
+
+        assert_eq!(ColorType::from_channels(2, false, false), None);
+        assert_eq!(ColorType::from_channels(3, true, false), None);
+        assert_eq!(ColorType::from_channels(4, false, false), None);
+        assert_eq!(ColorType::from_channels(5, true, false), None);
+    }
 }
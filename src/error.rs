@@ -14,12 +14,70 @@ pub enum EncodingError {
     /// Color profile exceeds maximum allowed data length
     IccTooLarge(usize),
 
+    /// Color profile is shorter than its header or its size field doesn't match the data length
+    InvalidIccProfile,
+
+    /// Comment exceeds maximum allowed data length
+    CommentTooLarge(usize),
+
+    /// Huffman table doesn't describe a valid huffman code
+    InvalidHuffmanTable,
+
     /// Image data is too short
     BadImageData { length: usize, required: usize },
 
     /// Width or height is zero
     ZeroImageDimensions { width: u16, height: u16 },
 
+    /// Sample of a 12 bit image exceeds 4095
+    SampleOutOfRange(u16),
+
+    /// Buffer sizes for the image dimensions exceed the addressable memory
+    ImageTooLarge { width: u16, height: u16 },
+
+    /// Number of components isn't supported
+    InvalidComponentCount(usize),
+
+    /// Component index doesn't exist in any supported color type
+    InvalidComponentIndex(usize),
+
+    /// Sampling factors of a component aren't supported
+    InvalidSamplingFactor { horizontal: u8, vertical: u8 },
+
+    /// Huffman table destination isn't between 0 and 3
+    InvalidHuffmanDestination(u8),
+
+    /// Huffman table destination above 1 is used while
+    /// [baseline](crate::Encoder::set_force_baseline) is forced
+    BaselineHuffmanDestination(u8),
+
+    /// Sampling factors of 4 are rejected in [strict compatibility](crate::Encoder::set_strict_compatibility) mode
+    UnsupportedSamplingFactor { horizontal: u8, vertical: u8 },
+
+    /// Number of progressive scans isn't between 2 and 64
+    InvalidProgressiveScans(u8),
+
+    /// Region doesn't fit into the image layout or doesn't start at a full pixel
+    InvalidRegion {
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    },
+
+    /// Density or JFIF thumbnail are set but the JFIF header is disabled
+    JfifRequired,
+
+    /// Output of the lowest quality exceeds the size given to
+    /// [encode_to_size](crate::Encoder::encode_to_size)
+    TargetSizeUnreachable { size: usize, max_bytes: usize },
+
+    /// Encoding was cancelled by the callback set with [set_cancel_check](crate::Encoder::set_cancel_check)
+    Cancelled,
+
+    /// Output doesn't fit into the buffer of a [SliceWriter](crate::SliceWriter)
+    BufferFull,
+
     /// An io error occurred during writing
     #[cfg(feature = "std")]
     IoError(std::io::Error),
@@ -50,6 +108,13 @@ impl Display for EncodingError {
                 "ICC profile exceeds maximum allowed data length: {}",
                 length
             ),
+            InvalidIccProfile => write!(f, "Invalid ICC profile"),
+            CommentTooLarge(length) => write!(
+                f,
+                "Comment exceeds maximum allowed data length of 65533: {}",
+                length
+            ),
+            InvalidHuffmanTable => write!(f, "Invalid huffman table"),
             BadImageData { length, required } => write!(
                 f,
                 "Image data too small for dimensions and color_type: {} need at least {}",
@@ -58,6 +123,60 @@ impl Display for EncodingError {
             ZeroImageDimensions { width, height } => {
                 write!(f, "Image dimensions must be non zero: {}x{}", width, height)
             }
+            SampleOutOfRange(value) => {
+                write!(f, "Sample value exceeds the 12 bit range: {}", value)
+            }
+            ImageTooLarge { width, height } => {
+                write!(f, "Image dimensions are too large: {}x{}", width, height)
+            }
+            InvalidComponentCount(count) => {
+                write!(f, "Invalid number of components: {}", count)
+            }
+            InvalidComponentIndex(index) => write!(f, "Invalid component index: {}", index),
+            InvalidSamplingFactor {
+                horizontal,
+                vertical,
+            } => write!(f, "Invalid sampling factor: {}x{}", horizontal, vertical),
+            InvalidHuffmanDestination(destination) => {
+                write!(f, "Invalid huffman table destination: {}", destination)
+            }
+            BaselineHuffmanDestination(destination) => write!(
+                f,
+                "Huffman table destination isn't allowed in baseline images: {}",
+                destination
+            ),
+            UnsupportedSamplingFactor {
+                horizontal,
+                vertical,
+            } => write!(
+                f,
+                "Sampling factor isn't supported by all decoders: {}x{}",
+                horizontal, vertical
+            ),
+            InvalidProgressiveScans(scans) => {
+                write!(f, "Invalid number of progressive scans: {}", scans)
+            }
+            InvalidRegion {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "Invalid region: {}x{} at position {},{}",
+                width, height, x, y
+            ),
+            JfifRequired => write!(
+                f,
+                "Density and thumbnail can't be written without the JFIF header"
+            ),
+            TargetSizeUnreachable { size, max_bytes } => write!(
+                f,
+                "Smallest output of {} bytes exceeds the target size of {} bytes",
+                size, max_bytes
+            ),
+            Cancelled => write!(f, "Encoding was cancelled"),
+            BufferFull => write!(f, "Output buffer is full"),
             #[cfg(feature = "std")]
             IoError(err) => err.fmt(f),
             Write(err) => write!(f, "{}", err),
@@ -71,8 +71,9 @@
  * scaled fixed-point arithmetic, with a minimal number of shifts.
  */
 
+use crate::encoder::{DefaultOperations, Operations};
+
 const CONST_BITS: i32 = 13;
-const PASS1_BITS: i32 = 2;
 
 const FIX_0_298631336: i32 = 2446;
 const FIX_0_390180644: i32 = 3196;
@@ -95,14 +96,29 @@ fn descale(x: i32, n: i32) -> i32 {
     (x + (1 << (n - 1))) >> n
 }
 
-#[inline(always)]
-fn into_el(v: i32) -> i16 {
-    v as i16
+pub fn fdct(data: &mut [i16; 64]) {
+    let mut coefficients = [0i32; 64];
+    fdct_internal::<2>(data, &mut coefficients);
+
+    for (value, &coefficient) in data.iter_mut().zip(coefficients.iter()) {
+        *value = coefficient as i16;
+    }
+}
+
+/// Forward DCT of a block of level shifted 12 bit samples
+///
+/// Like libjpeg, the first pass keeps one bit less than [fdct], so the intermediate values
+/// don't overflow. The coefficients are scaled up by 8 and exceed the range of `i16`.
+pub(crate) fn fdct_12bit(data: &[i16; 64]) -> [i32; 64] {
+    let mut coefficients = [0i32; 64];
+    fdct_internal::<1>(data, &mut coefficients);
+    coefficients
 }
 
+#[inline(always)]
 #[allow(clippy::erasing_op)]
 #[allow(clippy::identity_op)]
-pub fn fdct(data: &mut [i16; 64]) {
+fn fdct_internal<const PASS1_BITS: i32>(data: &[i16; 64], out: &mut [i32; 64]) {
     /* Pass 1: process rows. */
     /* Note results are scaled up by sqrt(8) compared to a true DCT; */
     /* furthermore, we scale the results by 2**PASS1_BITS. */
@@ -196,18 +212,12 @@ pub fn fdct(data: &mut [i16; 64]) {
         let tmp11 = tmp1 + tmp2;
         let tmp12 = tmp1 - tmp2;
 
-        data[DCT_SIZE * 0 + x] = into_el(descale(tmp10 + tmp11, PASS1_BITS));
-        data[DCT_SIZE * 4 + x] = into_el(descale(tmp10 - tmp11, PASS1_BITS));
+        out[DCT_SIZE * 0 + x] = descale(tmp10 + tmp11, PASS1_BITS);
+        out[DCT_SIZE * 4 + x] = descale(tmp10 - tmp11, PASS1_BITS);
 
         let z1 = (tmp12 + tmp13) * FIX_0_541196100;
-        data[DCT_SIZE * 2 + x] = into_el(descale(
-            z1 + tmp13 * FIX_0_765366865,
-            CONST_BITS + PASS1_BITS,
-        ));
-        data[DCT_SIZE * 6 + x] = into_el(descale(
-            z1 + tmp12 * -FIX_1_847759065,
-            CONST_BITS + PASS1_BITS,
-        ));
+        out[DCT_SIZE * 2 + x] = descale(z1 + tmp13 * FIX_0_765366865, CONST_BITS + PASS1_BITS);
+        out[DCT_SIZE * 6 + x] = descale(z1 + tmp12 * -FIX_1_847759065, CONST_BITS + PASS1_BITS);
 
         /* Odd part per figure 8 --- note paper omits factor of sqrt(2).
          * cK represents cos(K*pi/16).
@@ -232,10 +242,59 @@ pub fn fdct(data: &mut [i16; 64]) {
         let z3 = z3 + z5;
         let z4 = z4 + z5;
 
-        data[DCT_SIZE * 7 + x] = into_el(descale(tmp4 + z1 + z3, CONST_BITS + PASS1_BITS));
-        data[DCT_SIZE * 5 + x] = into_el(descale(tmp5 + z2 + z4, CONST_BITS + PASS1_BITS));
-        data[DCT_SIZE * 3 + x] = into_el(descale(tmp6 + z2 + z3, CONST_BITS + PASS1_BITS));
-        data[DCT_SIZE * 1 + x] = into_el(descale(tmp7 + z1 + z4, CONST_BITS + PASS1_BITS));
+        out[DCT_SIZE * 7 + x] = descale(tmp4 + z1 + z3, CONST_BITS + PASS1_BITS);
+        out[DCT_SIZE * 5 + x] = descale(tmp5 + z2 + z4, CONST_BITS + PASS1_BITS);
+        out[DCT_SIZE * 3 + x] = descale(tmp6 + z2 + z3, CONST_BITS + PASS1_BITS);
+        out[DCT_SIZE * 1 + x] = descale(tmp7 + z1 + z4, CONST_BITS + PASS1_BITS);
+    }
+}
+
+/// Forward DCT of a block of 8x8 samples
+///
+/// The input block contains the samples in row major order and must be level shifted,
+/// e.g. 8 bit samples minus 128. The coefficients are written back in the same order and are
+/// scaled up by a factor of 8 compared to a true DCT.<br>
+/// This is the same integer DCT as the `jpeg_fdct_islow` function of libjpeg.
+pub fn forward_dct(block: &mut [i16; 64]) {
+    fdct(block);
+}
+
+/// Forward DCT of multiple blocks
+///
+/// Same as calling [forward_dct] for each block, but uses the same SIMD implementations
+/// as the encoder if they are enabled and supported by the CPU. These can round some
+/// coefficients differently, so results may differ by 1 from [forward_dct].
+pub fn forward_dct_batch(blocks: &mut [[i16; 64]]) {
+    #[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if std::is_x86_feature_detected!("avx512f") && std::is_x86_feature_detected!("avx512bw") {
+            let mut pairs = blocks.chunks_exact_mut(2);
+            for pair in &mut pairs {
+                crate::avx512::fdct_avx512(pair.try_into().unwrap());
+            }
+            return forward_dct_blocks::<crate::avx2::AVX2Operations>(pairs.into_remainder());
+        }
+    }
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            return forward_dct_blocks::<crate::avx2::AVX2Operations>(blocks);
+        }
+    }
+
+    #[cfg(feature = "std_simd")]
+    {
+        return forward_dct_blocks::<crate::std_simd::StdSimdOperations>(blocks);
+    }
+
+    #[allow(unreachable_code)]
+    forward_dct_blocks::<DefaultOperations>(blocks)
+}
+
+fn forward_dct_blocks<OP: Operations>(blocks: &mut [[i16; 64]]) {
+    for block in blocks {
+        OP::fdct(block);
     }
 }
 
@@ -244,7 +303,9 @@ mod tests {
 
     // Inputs and outputs are taken from libjpegs jpeg_fdct_islow for a typical image
 
-    use super::fdct;
+    use alloc::vec::Vec;
+
+    use super::{fdct, fdct_12bit, forward_dct, forward_dct_batch};
 
     const INPUT1: [i16; 64] = [
         -70, -71, -70, -68, -67, -67, -67, -67, -72, -73, -72, -70, -69, -69, -68, -69, -75, -76,
@@ -275,12 +336,67 @@ mod tests {
 
     #[test]
     pub fn test_fdct_libjpeg() {
-        let mut i1 = INPUT1.clone();
+        let mut i1 = INPUT1;
         fdct(&mut i1);
         assert_eq!(i1, OUTPUT1);
 
-        let mut i2 = INPUT2.clone();
+        let mut i2 = INPUT2;
         fdct(&mut i2);
         assert_eq!(i2, OUTPUT2);
     }
+
+    #[test]
+    pub fn test_fdct_12bit() {
+        let mut input = INPUT1;
+        for value in input.iter_mut() {
+            *value *= 16;
+        }
+
+        let output = fdct_12bit(&input);
+
+        for (&value, &expected) in output.iter().zip(OUTPUT1.iter()) {
+            assert!((value - i32::from(expected) * 16).abs() <= 16);
+        }
+
+        // Extreme values must not overflow
+        let mut input = [0i16; 64];
+        for (i, value) in input.iter_mut().enumerate() {
+            *value = if (i + i / 8) % 2 == 0 { 2047 } else { -2048 };
+        }
+        fdct_12bit(&input);
+        fdct_12bit(&[2047; 64]);
+        fdct_12bit(&[-2048; 64]);
+    }
+
+    #[test]
+    pub fn test_forward_dct_batch() {
+        let mut blocks: Vec<[i16; 64]> = (0..16)
+            .map(|i| {
+                let mut block = [0i16; 64];
+                for (j, v) in block.iter_mut().enumerate() {
+                    *v = ((i * 64 + j) * 397 % 256) as i16 - 128;
+                }
+                block
+            })
+            .collect();
+
+        blocks.push(INPUT1);
+        blocks.push(INPUT2);
+
+        let mut expected = blocks.clone();
+        for block in &mut expected {
+            forward_dct(block);
+        }
+
+        forward_dct_batch(&mut blocks);
+
+        for (block, expected) in blocks.iter().zip(&expected) {
+            for (&v1, &v2) in block.iter().zip(expected) {
+                assert!((v1 - v2).abs() <= 1, "Difference in {} vs {}", v1, v2);
+            }
+        }
+
+        assert_eq!(expected[16], OUTPUT1);
+        assert_eq!(expected[17], OUTPUT2);
+    }
 }
@@ -5,6 +5,8 @@
 
 use alloc::vec::Vec;
 
+use crate::EncodingError;
+
 #[derive(Copy, Clone, Debug)]
 pub enum CodingClass {
     Dc = 0,
@@ -63,6 +65,11 @@ static DEFAULT_CHROMA_AC_VALUES: [u8; 162] = [
     0xF9, 0xFA,
 ];
 
+/// # Huffman table used for encoding
+///
+/// A table is defined by the number of codes for each code length from 1 to 16 bits
+/// and the values assigned to the codes, like it is stored in a DHT segment.
+#[derive(Clone, Debug)]
 pub struct HuffmanTable {
     lookup_table: [(u8, u16); 256],
     length: [u8; 16],
@@ -70,7 +77,48 @@ pub struct HuffmanTable {
 }
 
 impl HuffmanTable {
-    pub fn new(length: &[u8; 16], values: &[u8]) -> HuffmanTable {
+    /// Create a new huffman table
+    ///
+    /// - `length`: Number of codes for each code length from 1 to 16 bits
+    /// - `values`: Values in order of increasing code length
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of values doesn't match the number of codes,
+    /// if values are assigned more than once or if the code lengths don't describe a valid huffman code
+    pub fn new(length: &[u8; 16], values: &[u8]) -> Result<HuffmanTable, EncodingError> {
+        let num_codes: usize = length.iter().map(|&l| l as usize).sum();
+
+        if num_codes != values.len() || num_codes > 256 {
+            return Err(EncodingError::InvalidHuffmanTable);
+        }
+
+        let mut code = 0u32;
+
+        for (i, &l) in length.iter().enumerate() {
+            code += l as u32;
+
+            // The code consisting only of 1-bits is reserved
+            if code >= 1 << (i + 1) {
+                return Err(EncodingError::InvalidHuffmanTable);
+            }
+
+            code <<= 1;
+        }
+
+        let mut used = [false; 256];
+
+        for &value in values {
+            if used[value as usize] {
+                return Err(EncodingError::InvalidHuffmanTable);
+            }
+            used[value as usize] = true;
+        }
+
+        Ok(Self::new_unchecked(length, values))
+    }
+
+    pub(crate) fn new_unchecked(length: &[u8; 16], values: &[u8]) -> HuffmanTable {
         HuffmanTable {
             lookup_table: create_lookup_table(length, values),
             length: *length,
@@ -78,25 +126,29 @@ impl HuffmanTable {
         }
     }
 
+    /// Default table for luma DC coefficients as given in Annex K
     pub fn default_luma_dc() -> HuffmanTable {
-        Self::new(&DEFAULT_LUMA_DC_CODE_LENGTHS, &DEFAULT_LUMA_DC_VALUES)
+        Self::new_unchecked(&DEFAULT_LUMA_DC_CODE_LENGTHS, &DEFAULT_LUMA_DC_VALUES)
     }
 
+    /// Default table for luma AC coefficients as given in Annex K
     pub fn default_luma_ac() -> HuffmanTable {
-        Self::new(&DEFAULT_LUMA_AC_CODE_LENGTHS, &DEFAULT_LUMA_AC_VALUES)
+        Self::new_unchecked(&DEFAULT_LUMA_AC_CODE_LENGTHS, &DEFAULT_LUMA_AC_VALUES)
     }
 
+    /// Default table for chroma DC coefficients as given in Annex K
     pub fn default_chroma_dc() -> HuffmanTable {
-        Self::new(&DEFAULT_CHROMA_DC_CODE_LENGTHS, &DEFAULT_CHROMA_DC_VALUES)
+        Self::new_unchecked(&DEFAULT_CHROMA_DC_CODE_LENGTHS, &DEFAULT_CHROMA_DC_VALUES)
     }
 
+    /// Default table for chroma AC coefficients as given in Annex K
     pub fn default_chroma_ac() -> HuffmanTable {
-        Self::new(&DEFAULT_CHROMA_AC_CODE_LENGTHS, &DEFAULT_CHROMA_AC_VALUES)
+        Self::new_unchecked(&DEFAULT_CHROMA_AC_CODE_LENGTHS, &DEFAULT_CHROMA_AC_VALUES)
     }
 
     /// Generates an optimized huffman table as described in Section K.2
     #[allow(clippy::needless_range_loop)]
-    pub fn new_optimized(mut freq: [u32; 257]) -> HuffmanTable {
+    pub(crate) fn new_optimized(mut freq: [u32; 257]) -> HuffmanTable {
         let mut others = [-1i32; 257];
         let mut codesize = [0usize; 257];
 
@@ -221,16 +273,24 @@ impl HuffmanTable {
     }
 
     #[inline]
-    pub fn get_for_value(&self, value: u8) -> &(u8, u16) {
+    pub(crate) fn get_for_value(&self, value: u8) -> &(u8, u16) {
         let res = &self.lookup_table[value as usize];
         debug_assert!(res.0 > 0, "Got zero size code for value: {}", value);
         res
     }
 
+    /// Code length in bits of a value or 0 if the value isn't part of the table
+    #[inline]
+    pub(crate) fn code_length(&self, value: u8) -> u8 {
+        self.lookup_table[value as usize].0
+    }
+
+    /// Number of codes for each code length
     pub fn length(&self) -> &[u8; 16] {
         &self.length
     }
 
+    /// Values in order of increasing code length
     pub fn values(&self) -> &[u8] {
         &self.values
     }
@@ -286,3 +346,38 @@ fn create_lookup_table(code_length: &[u8; 16], values: &[u8]) -> [(u8, u16); 256
 
     lookup_table
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::huffman::{
+        HuffmanTable, DEFAULT_LUMA_AC_CODE_LENGTHS, DEFAULT_LUMA_AC_VALUES,
+        DEFAULT_LUMA_DC_CODE_LENGTHS, DEFAULT_LUMA_DC_VALUES,
+    };
+
+    #[test]
+    fn test_new() {
+        let table =
+            HuffmanTable::new(&DEFAULT_LUMA_DC_CODE_LENGTHS, &DEFAULT_LUMA_DC_VALUES).unwrap();
+        assert_eq!(table.length(), &DEFAULT_LUMA_DC_CODE_LENGTHS);
+        assert_eq!(table.values(), &DEFAULT_LUMA_DC_VALUES);
+
+        assert!(HuffmanTable::new(&DEFAULT_LUMA_AC_CODE_LENGTHS, &DEFAULT_LUMA_AC_VALUES).is_ok());
+    }
+
+    #[test]
+    fn test_new_invalid() {
+        // Number of values doesn't match
+        assert!(HuffmanTable::new(&DEFAULT_LUMA_DC_CODE_LENGTHS, &[0, 1, 2]).is_err());
+
+        // Two codes of length 1 would need the reserved all 1-bits code
+        let mut length = [0u8; 16];
+        length[0] = 2;
+        assert!(HuffmanTable::new(&length, &[0, 1]).is_err());
+
+        // Duplicate values
+        let mut length = [0u8; 16];
+        length[1] = 2;
+        assert!(HuffmanTable::new(&length, &[3, 3]).is_err());
+        assert!(HuffmanTable::new(&length, &[3, 4]).is_ok());
+    }
+}
@@ -1,8 +1,51 @@
 #![allow(clippy::identity_op)]
 
+use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::encoder::JpegColorType;
+use crate::encoder::{ColorType, Endianness, JpegColorType};
+use crate::EncodingError;
+
+/// # Color space used for the conversion from RGB to YCbCr
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ColorSpace {
+    /// ITU-R BT.601 coefficients as defined by JFIF
+    Bt601,
+
+    /// ITU-R BT.709 coefficients used by HD content
+    Bt709,
+}
+
+/// # Transfer function of floating point input
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Transfer {
+    /// Values are linear light and get encoded with the sRGB transfer function
+    Linear,
+
+    /// Values are already encoded with the sRGB transfer function
+    Srgb,
+}
+
+/// # Dithering of high bit depth input
+///
+/// Applied when [Luma16](ColorType::Luma16) and [RgbF32](ColorType::RgbF32) input is
+/// reduced to 8 bits to avoid banding in smooth gradients.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dither {
+    /// Values are rounded to the nearest 8 bit value
+    None,
+
+    /// Ordered dithering with an 8x8 Bayer matrix
+    ///
+    /// The threshold only depends on the position of a pixel, so no state is needed.
+    Ordered,
+
+    /// Error diffusion along each row
+    ///
+    /// The rounding error of a sample is carried over to the next sample of the same channel.
+    /// Rows are dithered independently of each other, so they can be converted in any order.
+    ErrorDiffusion,
+}
 
 /// Conversion from RGB to YCbCr
 #[inline]
@@ -30,6 +73,65 @@ pub fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     (y as u8, cb as u8, cr as u8)
 }
 
+/// Conversion from RGB to YCbCr using BT.709 coefficients
+#[inline]
+pub fn rgb_to_ycbcr_bt709(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    // Same scaling as in rgb_to_ycbcr
+    //
+    // Non scaled conversion:
+    // Y  =  0.21260 * R + 0.71520 * G + 0.07220 * B
+    // Cb = -0.11457 * R - 0.38543 * G + 0.50000 * B  + 128
+    // Cr =  0.50000 * R - 0.45415 * G - 0.04585 * B  + 128
+
+    let r = r as i32;
+    let g = g as i32;
+    let b = b as i32;
+
+    let y = 13933 * r + 46871 * g + 4732 * b;
+    let cb = -7509 * r - 25259 * g + 32768 * b + (128 << 16);
+    let cr = 32768 * r - 29763 * g - 3005 * b + (128 << 16);
+
+    let y = (y + 0x7FFF) >> 16;
+    let cb = (cb + 0x7FFF) >> 16;
+    let cr = (cr + 0x7FFF) >> 16;
+
+    (y as u8, cb as u8, cr as u8)
+}
+
+/// Conversion from YCbCr to RGB
+///
+/// Inverse of [rgb_to_ycbcr] or [rgb_to_ycbcr_bt709] depending on the color space.
+pub(crate) fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8, color_space: ColorSpace) -> [u8; 3] {
+    let (kr, kb) = match color_space {
+        ColorSpace::Bt601 => (0.299, 0.114),
+        ColorSpace::Bt709 => (0.2126, 0.0722),
+    };
+
+    let y = f32::from(y);
+    let cb = f32::from(cb) - 128.0;
+    let cr = f32::from(cr) - 128.0;
+
+    let r = y + 2.0 * (1.0 - kr) * cr;
+    let b = y + 2.0 * (1.0 - kb) * cb;
+    let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+
+    [r, g, b].map(|value| (value.clamp(0.0, 255.0) + 0.5) as u8)
+}
+
+// Same as the Y component of rgb_to_ycbcr
+#[inline(always)]
+fn rgb_to_luma(r: u8, g: u8, b: u8) -> u8 {
+    let y = 19595 * r as i32 + 38470 * g as i32 + 7471 * b as i32;
+    ((y + 0x7FFF) >> 16) as u8
+}
+
+// Same as the Y component of rgb_to_ycbcr_bt709
+#[inline(always)]
+fn rgb_to_luma_bt709(r: u8, g: u8, b: u8) -> u8 {
+    let y = 13933 * r as i32 + 46871 * g as i32 + 4732 * b as i32;
+    ((y + 0x7FFF) >> 16) as u8
+}
+
 /// Conversion from CMYK to YCCK (YCbCrK)
 #[inline]
 pub fn cmyk_to_ycck(c: u8, m: u8, y: u8, k: u8) -> (u8, u8, u8, u8) {
@@ -43,7 +145,7 @@ pub fn cmyk_to_ycck(c: u8, m: u8, y: u8, k: u8) -> (u8, u8, u8, u8) {
 /// as input for the image data. For convenience the [Encoder::encode](crate::Encoder::encode)
 /// function contains implementations for common byte based pixel formats.
 /// Users that needs other pixel formats or don't have the data available as byte slices
-/// can create their own buffer implementations.
+/// can create their own buffer implementations or use a closure with [FnImageBuffer].
 ///
 /// ## Example: ImageBuffer implementation for RgbImage from the `image` crate
 /// ```no_compile
@@ -95,6 +197,167 @@ pub trait ImageBuffer {
 
     /// Add color values for the row to color component buffers
     fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]);
+
+    /// Returns true if the components can be read at their native resolution
+    ///
+    /// If the [subsampling](ImageBuffer::component_subsampling) of all components matches the
+    /// sampling factors of the encoder, the rows are read with
+    /// [fill_component_row](ImageBuffer::fill_component_row) instead of
+    /// [fill_buffers](ImageBuffer::fill_buffers), so the chroma components aren't subsampled again.
+    /// Otherwise the encoder falls back to [fill_buffers](ImageBuffer::fill_buffers).
+    ///
+    /// Defaults to false.
+    fn chroma_already_subsampled(&self) -> bool {
+        false
+    }
+
+    /// Horizontal and vertical ratio between the image size and the size of a component
+    ///
+    /// Only used if [chroma_already_subsampled](ImageBuffer::chroma_already_subsampled) returns true.
+    /// Defaults to `(1, 1)` for full resolution components.
+    fn component_subsampling(&self, _component: usize) -> (u8, u8) {
+        (1, 1)
+    }
+
+    /// Add the values of a row of a component at its native resolution to the buffer
+    ///
+    /// A component with a [subsampling](ImageBuffer::component_subsampling) of `(h, v)` has
+    /// `ceil(width / h)` values per row and `ceil(height / v)` rows.<br>
+    /// Only called if [chroma_already_subsampled](ImageBuffer::chroma_already_subsampled) returns true.
+    ///
+    /// The default implementation averages the full resolution rows of
+    /// [fill_buffers](ImageBuffer::fill_buffers), which is slow and should be overridden
+    /// by buffers that store the components at their native resolution.
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        let (h, v) = self.component_subsampling(component);
+        let h = usize::from(h.max(1));
+        let v = usize::from(v.max(1));
+
+        let width = usize::from(self.width());
+        let height = usize::from(self.height());
+
+        let mut rows = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        let mut sums = vec![0u32; (width + h - 1) / h];
+
+        for row in usize::from(y) * v..(usize::from(y) + 1) * v {
+            for values in rows.iter_mut() {
+                values.clear();
+            }
+
+            self.fill_buffers(row.min(height - 1) as u16, &mut rows);
+
+            for (x, &value) in rows[component].iter().take(width).enumerate() {
+                sums[x / h] += u32::from(value);
+            }
+        }
+
+        for (x, sum) in sums.into_iter().enumerate() {
+            // The last column only covers the remaining pixels of the row
+            let count = ((width - x * h).min(h) * v) as u32;
+            buffer.push(((sum + count / 2) / count) as u8);
+        }
+    }
+}
+
+impl<I: ImageBuffer + ?Sized> ImageBuffer for &I {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        (**self).get_jpeg_color_type()
+    }
+
+    fn width(&self) -> u16 {
+        (**self).width()
+    }
+
+    fn height(&self) -> u16 {
+        (**self).height()
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        (**self).fill_buffers(y, buffers)
+    }
+
+    fn chroma_already_subsampled(&self) -> bool {
+        (**self).chroma_already_subsampled()
+    }
+
+    fn component_subsampling(&self, component: usize) -> (u8, u8) {
+        (**self).component_subsampling(component)
+    }
+
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        (**self).fill_component_row(component, y, buffer)
+    }
+}
+
+/// # ImageBuffer using a closure to fill the color component buffers
+///
+/// The closure is called for each row with the same arguments as
+/// [ImageBuffer::fill_buffers] and must add `width` values to the buffers of each component
+/// of the color type.
+///
+/// # Example: Palette-indexed image
+/// ```
+/// # use jpeg_encoder::EncodingError;
+/// # pub fn main() -> Result<(), EncodingError> {
+/// use jpeg_encoder::{rgb_to_ycbcr, Encoder, FnImageBuffer, JpegColorType};
+///
+/// let palette = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+/// let indices = [0u8, 1, 2, 2, 1, 0];
+///
+/// let image = FnImageBuffer {
+///     width: 3,
+///     height: 2,
+///     color_type: JpegColorType::Ycbcr,
+///     fill: |y: u16, buffers: &mut [Vec<u8>; 4]| {
+///         let start = usize::from(y) * 3;
+///
+///         for &index in &indices[start..start + 3] {
+///             let [r, g, b] = palette[usize::from(index)];
+///             let (y, cb, cr) = rgb_to_ycbcr(r, g, b);
+///
+///             buffers[0].push(y);
+///             buffers[1].push(cb);
+///             buffers[2].push(cr);
+///         }
+///     },
+/// };
+///
+/// let mut result = Vec::new();
+/// Encoder::new(&mut result, 90).encode_image(image)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct FnImageBuffer<F: Fn(u16, &mut [Vec<u8>; 4])> {
+    /// Width of the image
+    pub width: u16,
+
+    /// Height of the image
+    pub height: u16,
+
+    /// The color type used in the image encoding
+    pub color_type: JpegColorType,
+
+    /// Closure to add the color values of a row to the color component buffers
+    pub fill: F,
+}
+
+impl<F: Fn(u16, &mut [Vec<u8>; 4])> ImageBuffer for FnImageBuffer<F> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        self.color_type
+    }
+
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        (self.fill)(y, buffers)
+    }
 }
 
 pub(crate) struct GrayImage<'a>(pub &'a [u8], pub u16, pub u16);
@@ -121,6 +384,97 @@ impl<'a> ImageBuffer for GrayImage<'a> {
     }
 }
 
+pub(crate) struct GrayAlphaImage<'a>(pub &'a [u8], pub u16, pub u16, pub Option<[u8; 3]>);
+
+impl<'a> ImageBuffer for GrayAlphaImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Luma
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 2);
+
+        match self.3 {
+            Some([r, g, b]) => {
+                let (background, _, _) = rgb_to_ycbcr(r, g, b);
+
+                for pixel in line.chunks_exact(2) {
+                    buffers[0].push(blend(pixel[0], background, pixel[1]));
+                }
+            }
+            None => {
+                for pixel in line.chunks_exact(2) {
+                    buffers[0].push(pixel[0]);
+                }
+            }
+        }
+    }
+}
+
+/// The last field is the index of the first row in the encoded image, which aligns the
+/// pattern of ordered dithering for images of single rows
+pub(crate) struct Gray16Image<'a>(
+    pub &'a [u8],
+    pub u16,
+    pub u16,
+    pub Endianness,
+    pub Dither,
+    pub u16,
+);
+
+impl<'a> ImageBuffer for Gray16Image<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Luma
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 2);
+        fill_gray16_row(line, self.5 + y, self.3, self.4, &mut buffers[0]);
+    }
+}
+
+/// Converts a row of 16 bit grayscale samples, `y` is only used for dithering
+fn fill_gray16_row(
+    line: &[u8],
+    y: u16,
+    endianness: Endianness,
+    dither: Dither,
+    buffer: &mut Vec<u8>,
+) {
+    let mut carry = 0.0;
+
+    for (x, pixel) in line.chunks_exact(2).enumerate() {
+        let value = match endianness {
+            Endianness::Little => u16::from_le_bytes([pixel[0], pixel[1]]),
+            Endianness::Big => u16::from_be_bytes([pixel[0], pixel[1]]),
+        };
+
+        let value = match dither {
+            // Scale with rounding from 0-65535 to 0-255
+            Dither::None => ((u32::from(value) + 128) / 257) as u8,
+            dither => dither_to_u8(f32::from(value) / 257.0, dither, x, y, &mut carry),
+        };
+
+        buffer.push(value);
+    }
+}
+
 #[inline(always)]
 fn get_line(data: &[u8], y: u16, width:u16, num_colors: usize) -> &[u8] {
     let width= usize::from(width);
@@ -133,7 +487,7 @@ fn get_line(data: &[u8], y: u16, width:u16, num_colors: usize) -> &[u8] {
 }
 
 macro_rules! ycbcr_image {
-    ($name:ident, $num_colors:expr, $o1:expr, $o2:expr, $o3:expr) => {
+    ($name:ident, $num_colors:expr, $o1:expr, $o2:expr, $o3:expr, $convert:ident) => {
         pub(crate) struct $name<'a>(pub &'a [u8], pub u16, pub u16);
 
         impl<'a> ImageBuffer for $name<'a> {
@@ -154,7 +508,7 @@ macro_rules! ycbcr_image {
                 let line = get_line(self.0, y, self.width(), $num_colors);
 
                 for pixel in line.chunks_exact($num_colors) {
-                    let (y, cb, cr) = rgb_to_ycbcr(
+                    let (y, cb, cr) = $convert(
                         pixel[$o1],
                         pixel[$o2],
                         pixel[$o3],
@@ -169,69 +523,138 @@ macro_rules! ycbcr_image {
     };
 }
 
-ycbcr_image!(RgbImage, 3, 0, 1, 2);
-ycbcr_image!(RgbaImage, 4, 0, 1, 2);
-ycbcr_image!(BgrImage, 3, 2, 1, 0);
-ycbcr_image!(BgraImage, 4, 2, 1, 0);
+ycbcr_image!(RgbImage, 3, 0, 1, 2, rgb_to_ycbcr);
+ycbcr_image!(RgbaImage, 4, 0, 1, 2, rgb_to_ycbcr);
+ycbcr_image!(BgrImage, 3, 2, 1, 0, rgb_to_ycbcr);
+ycbcr_image!(BgraImage, 4, 2, 1, 0, rgb_to_ycbcr);
+ycbcr_image!(ArgbImage, 4, 1, 2, 3, rgb_to_ycbcr);
+ycbcr_image!(AbgrImage, 4, 3, 2, 1, rgb_to_ycbcr);
 
-pub(crate) struct YCbCrImage<'a>(pub &'a [u8], pub u16, pub u16);
+ycbcr_image!(RgbImageBt709, 3, 0, 1, 2, rgb_to_ycbcr_bt709);
+ycbcr_image!(RgbaImageBt709, 4, 0, 1, 2, rgb_to_ycbcr_bt709);
+ycbcr_image!(BgrImageBt709, 3, 2, 1, 0, rgb_to_ycbcr_bt709);
+ycbcr_image!(BgraImageBt709, 4, 2, 1, 0, rgb_to_ycbcr_bt709);
+ycbcr_image!(ArgbImageBt709, 4, 1, 2, 3, rgb_to_ycbcr_bt709);
+ycbcr_image!(AbgrImageBt709, 4, 3, 2, 1, rgb_to_ycbcr_bt709);
 
-impl<'a> ImageBuffer for YCbCrImage<'a> {
-    fn get_jpeg_color_type(&self) -> JpegColorType {
-        JpegColorType::Ycbcr
-    }
+macro_rules! luma_image {
+    ($name:ident, $num_colors:expr, $o1:expr, $o2:expr, $o3:expr, $alpha:expr) => {
+        pub(crate) struct $name<'a>(
+            pub &'a [u8],
+            pub u16,
+            pub u16,
+            pub ColorSpace,
+            pub Option<[u8; 3]>,
+        );
 
-    fn width(&self) -> u16 {
-        self.1
-    }
+        impl<'a> ImageBuffer for $name<'a> {
+            fn get_jpeg_color_type(&self) -> JpegColorType {
+                JpegColorType::Luma
+            }
 
-    fn height(&self) -> u16 {
-        self.2
-    }
+            fn width(&self) -> u16 {
+                self.1
+            }
 
-    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
-        let line = get_line(self.0, y, self.width(), 3);
+            fn height(&self) -> u16 {
+                self.2
+            }
 
-        for pixel in line.chunks_exact(3) {
-            buffers[0].push(pixel[0]);
-            buffers[1].push(pixel[1]);
-            buffers[2].push(pixel[2]);
+            fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+                let line = get_line(self.0, y, self.width(), $num_colors);
+
+                let convert = match self.3 {
+                    ColorSpace::Bt601 => rgb_to_luma,
+                    ColorSpace::Bt709 => rgb_to_luma_bt709,
+                };
+
+                let alpha: Option<usize> = $alpha;
+
+                for pixel in line.chunks_exact($num_colors) {
+                    let luma = match (alpha, self.4) {
+                        (Some(alpha), Some([r, g, b])) => convert(
+                            blend(pixel[$o1], r, pixel[alpha]),
+                            blend(pixel[$o2], g, pixel[alpha]),
+                            blend(pixel[$o3], b, pixel[alpha]),
+                        ),
+                        _ => convert(pixel[$o1], pixel[$o2], pixel[$o3]),
+                    };
+
+                    buffers[0].push(luma);
+                }
+            }
         }
-    }
+    };
 }
 
-pub(crate) struct CmykImage<'a>(pub &'a [u8], pub u16, pub u16);
+luma_image!(RgbLumaImage, 3, 0, 1, 2, None);
+luma_image!(RgbaLumaImage, 4, 0, 1, 2, Some(3));
+luma_image!(BgrLumaImage, 3, 2, 1, 0, None);
+luma_image!(BgraLumaImage, 4, 2, 1, 0, Some(3));
+luma_image!(ArgbLumaImage, 4, 1, 2, 3, Some(0));
+luma_image!(AbgrLumaImage, 4, 3, 2, 1, Some(0));
 
-impl<'a> ImageBuffer for CmykImage<'a> {
-    fn get_jpeg_color_type(&self) -> JpegColorType {
-        JpegColorType::Cmyk
-    }
+#[inline(always)]
+fn blend(foreground: u8, background: u8, alpha: u8) -> u8 {
+    let alpha = u32::from(alpha);
+    ((u32::from(foreground) * alpha + u32::from(background) * (255 - alpha)) / 255) as u8
+}
 
-    fn width(&self) -> u16 {
-        self.1
-    }
+macro_rules! alpha_blend_image {
+    ($name:ident, $o1:expr, $o2:expr, $o3:expr, $alpha:expr) => {
+        pub(crate) struct $name<'a>(pub &'a [u8], pub u16, pub u16, pub [u8; 3], pub ColorSpace);
 
-    fn height(&self) -> u16 {
-        self.2
-    }
+        impl<'a> ImageBuffer for $name<'a> {
+            fn get_jpeg_color_type(&self) -> JpegColorType {
+                JpegColorType::Ycbcr
+            }
 
-    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
-        let line = get_line(self.0, y, self.width(), 4);
+            fn width(&self) -> u16 {
+                self.1
+            }
 
-        for pixel in line.chunks_exact(4) {
-            buffers[0].push(255 - pixel[0]);
-            buffers[1].push(255 - pixel[1]);
-            buffers[2].push(255 - pixel[2]);
-            buffers[3].push(255 - pixel[3]);
+            fn height(&self) -> u16 {
+                self.2
+            }
+
+            fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+                let line = get_line(self.0, y, self.width(), 4);
+
+                let convert = match self.4 {
+                    ColorSpace::Bt601 => rgb_to_ycbcr,
+                    ColorSpace::Bt709 => rgb_to_ycbcr_bt709,
+                };
+
+                let [r, g, b] = self.3;
+
+                for pixel in line.chunks_exact(4) {
+                    let alpha = pixel[$alpha];
+
+                    let (y, cb, cr) = convert(
+                        blend(pixel[$o1], r, alpha),
+                        blend(pixel[$o2], g, alpha),
+                        blend(pixel[$o3], b, alpha),
+                    );
+
+                    buffers[0].push(y);
+                    buffers[1].push(cb);
+                    buffers[2].push(cr);
+                }
+            }
         }
-    }
+    };
 }
 
-pub(crate) struct CmykAsYcckImage<'a>(pub &'a [u8], pub u16, pub u16);
+alpha_blend_image!(RgbaBlendImage, 0, 1, 2, 3);
+alpha_blend_image!(BgraBlendImage, 2, 1, 0, 3);
+alpha_blend_image!(ArgbBlendImage, 1, 2, 3, 0);
+alpha_blend_image!(AbgrBlendImage, 3, 2, 1, 0);
 
-impl<'a> ImageBuffer for CmykAsYcckImage<'a> {
+pub(crate) struct Rgb565Image<'a>(pub &'a [u8], pub u16, pub u16, pub ColorSpace);
+
+impl<'a> ImageBuffer for Rgb565Image<'a> {
     fn get_jpeg_color_type(&self) -> JpegColorType {
-        JpegColorType::Ycck
+        JpegColorType::Ycbcr
     }
 
     fn width(&self) -> u16 {
@@ -243,30 +666,1092 @@ impl<'a> ImageBuffer for CmykAsYcckImage<'a> {
     }
 
     fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
-        let line = get_line(self.0, y, self.width(), 4);
+        let line = get_line(self.0, y, self.width(), 2);
 
-        for pixel in line.chunks_exact(4) {
+        let convert = match self.3 {
+            ColorSpace::Bt601 => rgb_to_ycbcr,
+            ColorSpace::Bt709 => rgb_to_ycbcr_bt709,
+        };
 
-            let (y, cb, cr, k) = cmyk_to_ycck(
-                pixel[0],
-                pixel[1],
-                pixel[2],
-                pixel[3],
+        for pixel in line.chunks_exact(2) {
+            let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+
+            let r = (value >> 11) as u8;
+            let g = ((value >> 5) & 0x3F) as u8;
+            let b = (value & 0x1F) as u8;
+
+            // Replicate the high bits into the low bits to cover the full range
+            let (y, cb, cr) = convert(
+                (r << 3) | (r >> 2),
+                (g << 2) | (g >> 4),
+                (b << 3) | (b >> 2),
             );
 
             buffers[0].push(y);
             buffers[1].push(cb);
             buffers[2].push(cr);
-            buffers[3].push(k);
         }
     }
 }
 
-pub(crate) struct YcckImage<'a>(pub &'a [u8], pub u16, pub u16);
+macro_rules! packed_422_image {
+    ($name:ident, $y0:expr, $cb:expr, $y1:expr, $cr:expr) => {
+        pub(crate) struct $name<'a>(pub &'a [u8], pub u16, pub u16);
 
-impl<'a> ImageBuffer for YcckImage<'a> {
+        impl<'a> ImageBuffer for $name<'a> {
+            fn get_jpeg_color_type(&self) -> JpegColorType {
+                JpegColorType::Ycbcr
+            }
+
+            fn width(&self) -> u16 {
+                self.1
+            }
+
+            fn height(&self) -> u16 {
+                self.2
+            }
+
+            fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+                let width = usize::from(self.width());
+
+                // Rows of odd width are padded to a full macro-pixel
+                let line_len = (width + 1) / 2 * 4;
+                let start = usize::from(y) * line_len;
+                let line = &self.0[start..start + line_len];
+
+                for x in 0..width {
+                    let pixel = &line[x / 2 * 4..x / 2 * 4 + 4];
+                    let luma = if x % 2 == 0 { pixel[$y0] } else { pixel[$y1] };
+
+                    buffers[0].push(luma);
+                    buffers[1].push(pixel[$cb]);
+                    buffers[2].push(pixel[$cr]);
+                }
+            }
+        }
+    };
+}
+
+packed_422_image!(YuyvImage, 0, 1, 2, 3);
+packed_422_image!(UyvyImage, 1, 0, 3, 2);
+
+// Linear values at the midpoints between two 8 bit sRGB values.
+// The sRGB value of a linear value is the number of thresholds below or equal to it.
+static SRGB_THRESHOLDS: [f32; 255] = [
+    0.00015176, 0.00045529, 0.00075882, 0.00106234, 0.00136587, 0.0016694, 0.00197293, 0.00227645,
+    0.00257998, 0.00288351, 0.0031883, 0.00350926, 0.00384831, 0.00420575, 0.00458183, 0.00497684,
+    0.00539102, 0.00582465, 0.00627797, 0.00675123, 0.00724467, 0.00775853, 0.00829305, 0.00884845,
+    0.00942497, 0.01002283, 0.01064224, 0.01128342, 0.01194659, 0.01263196, 0.01333973, 0.01407011,
+    0.0148233, 0.0155995, 0.01639891, 0.01722172, 0.01806811, 0.01893829, 0.01983244, 0.02075074,
+    0.02169338, 0.02266054, 0.02365239, 0.02466911, 0.02571089, 0.02677788, 0.02787027, 0.02898822,
+    0.0301319, 0.03130148, 0.03249712, 0.03371899, 0.03496724, 0.03624204, 0.03754355, 0.03887193,
+    0.04022732, 0.04160989, 0.04301978, 0.04445716, 0.04592217, 0.04741496, 0.04893569, 0.05048448,
+    0.05206151, 0.0536669, 0.0553008, 0.05696336, 0.05865472, 0.06037501, 0.06212438, 0.06390297,
+    0.06571092, 0.06754835, 0.06941541, 0.07131224, 0.07323896, 0.0751957, 0.07718262, 0.07919982,
+    0.08124744, 0.08332562, 0.08543449, 0.08757416, 0.08974477, 0.09194644, 0.0941793, 0.09644348,
+    0.09873909, 0.10106627, 0.10342513, 0.1058158, 0.1082384, 0.11069305, 0.11317986, 0.11569897,
+    0.11825048, 0.12083452, 0.1234512, 0.12610064, 0.12878294, 0.13149826, 0.13424668, 0.1370283,
+    0.13984327, 0.14269169, 0.14557366, 0.14848931, 0.15143873, 0.15442206, 0.1574394, 0.16049083,
+    0.16357648, 0.16669649, 0.16985093, 0.17303991, 0.17626356, 0.17952196, 0.18281525, 0.1861435,
+    0.18950683, 0.19290535, 0.19633915, 0.19980834, 0.20331305, 0.20685335, 0.21042934, 0.21404114,
+    0.21768884, 0.22137256, 0.2250924, 0.22884841, 0.23264076, 0.2364695, 0.24033476, 0.24423663,
+    0.2481752, 0.25215057, 0.25616285, 0.26021212, 0.26429847, 0.26842204, 0.2725829, 0.2767811,
+    0.2810168, 0.2852901, 0.28960103, 0.29394972, 0.2983363, 0.3027608, 0.30722335, 0.31172404,
+    0.31626296, 0.32084018, 0.32545584, 0.33010998, 0.33480275, 0.33953416, 0.34430438, 0.34911346,
+    0.3539615, 0.35884857, 0.36377478, 0.36874023, 0.373745, 0.37878913, 0.38387278, 0.388996,
+    0.3941589, 0.39936152, 0.40460402, 0.40988642, 0.41520882, 0.42057136, 0.42597404, 0.43141702,
+    0.43690035, 0.44242412, 0.44798842, 0.4535933, 0.45923892, 0.4649253, 0.47065252, 0.4764207,
+    0.48222992, 0.48808026, 0.49397177, 0.49990457, 0.5058787, 0.5118943, 0.5179514, 0.5240501,
+    0.5301905, 0.5363727, 0.54259676, 0.5488627, 0.55517066, 0.5615207, 0.5679129, 0.5743473,
+    0.58082414, 0.58734334, 0.593905, 0.6005092, 0.6071561, 0.6138457, 0.6205781, 0.62735337,
+    0.6341716, 0.6410329, 0.64793724, 0.6548848, 0.66187567, 0.6689098, 0.67598736, 0.68310845,
+    0.6902731, 0.69748133, 0.7047334, 0.71202916, 0.7193688, 0.7267524, 0.73418003, 0.7416518,
+    0.7491677, 0.7567278, 0.7643323, 0.7719811, 0.7796744, 0.7874123, 0.79519475, 0.8030219,
+    0.81089383, 0.8188105, 0.8267722, 0.8347788, 0.8428305, 0.8509273, 0.8590692, 0.8672565,
+    0.87548906, 0.88376707, 0.89209056, 0.9004596, 0.9088742, 0.91733456, 0.9258406, 0.9343926,
+    0.94299036, 0.95163417, 0.96032405, 0.96906, 0.97784215, 0.98667055, 0.99554527,
+];
+
+// Linear values of all 8 bit sRGB values
+static SRGB_TO_LINEAR: [f32; 256] = [
+    0.0,
+    0.000303527,
+    0.000607054,
+    0.000910581,
+    0.001214108,
+    0.001517635,
+    0.001821162,
+    0.0021246888,
+    0.002428216,
+    0.0027317428,
+    0.00303527,
+    0.0033465358,
+    0.0036765074,
+    0.004024717,
+    0.004391442,
+    0.0047769533,
+    0.0051815165,
+    0.0056053917,
+    0.006048833,
+    0.0065120906,
+    0.00699541,
+    0.007499032,
+    0.008023193,
+    0.008568126,
+    0.009134059,
+    0.009721218,
+    0.010329823,
+    0.010960094,
+    0.011612245,
+    0.012286488,
+    0.0129830325,
+    0.013702083,
+    0.014443844,
+    0.015208514,
+    0.015996294,
+    0.016807375,
+    0.017641954,
+    0.01850022,
+    0.019382361,
+    0.020288562,
+    0.02121901,
+    0.022173885,
+    0.023153367,
+    0.024157632,
+    0.02518686,
+    0.026241222,
+    0.027320892,
+    0.02842604,
+    0.029556835,
+    0.030713445,
+    0.031896032,
+    0.033104766,
+    0.034339808,
+    0.035601314,
+    0.03688945,
+    0.038204372,
+    0.039546236,
+    0.0409152,
+    0.04231141,
+    0.04373503,
+    0.045186203,
+    0.046665087,
+    0.048171826,
+    0.049706567,
+    0.051269457,
+    0.052860647,
+    0.054480277,
+    0.05612849,
+    0.05780543,
+    0.059511237,
+    0.061246052,
+    0.063010015,
+    0.064803265,
+    0.06662594,
+    0.06847817,
+    0.070360094,
+    0.07227185,
+    0.07421357,
+    0.07618538,
+    0.07818742,
+    0.08021982,
+    0.08228271,
+    0.08437621,
+    0.08650046,
+    0.08865558,
+    0.09084171,
+    0.093058966,
+    0.09530747,
+    0.09758735,
+    0.099898726,
+    0.10224173,
+    0.104616486,
+    0.107023105,
+    0.10946171,
+    0.11193243,
+    0.114435375,
+    0.116970666,
+    0.11953843,
+    0.122138776,
+    0.12477182,
+    0.12743768,
+    0.13013647,
+    0.13286832,
+    0.13563333,
+    0.13843161,
+    0.14126329,
+    0.14412847,
+    0.14702727,
+    0.14995979,
+    0.15292615,
+    0.15592647,
+    0.15896083,
+    0.16202937,
+    0.1651322,
+    0.1682694,
+    0.17144111,
+    0.1746474,
+    0.17788842,
+    0.18116425,
+    0.18447499,
+    0.18782078,
+    0.19120169,
+    0.19461784,
+    0.19806932,
+    0.20155625,
+    0.20507874,
+    0.20863687,
+    0.21223076,
+    0.2158605,
+    0.2195262,
+    0.22322796,
+    0.22696587,
+    0.23074006,
+    0.23455058,
+    0.23839757,
+    0.24228112,
+    0.24620132,
+    0.25015828,
+    0.2541521,
+    0.25818285,
+    0.26225066,
+    0.2663556,
+    0.2704978,
+    0.2746773,
+    0.27889428,
+    0.28314874,
+    0.28744084,
+    0.29177064,
+    0.29613826,
+    0.30054379,
+    0.3049873,
+    0.30946892,
+    0.31398872,
+    0.31854677,
+    0.3231432,
+    0.3277781,
+    0.33245152,
+    0.33716363,
+    0.34191442,
+    0.34670407,
+    0.3515326,
+    0.35640013,
+    0.3613068,
+    0.3662526,
+    0.3712377,
+    0.37626213,
+    0.38132602,
+    0.38642943,
+    0.39157248,
+    0.39675522,
+    0.40197778,
+    0.4072402,
+    0.4125426,
+    0.41788507,
+    0.42326766,
+    0.4286905,
+    0.43415365,
+    0.43965718,
+    0.4452012,
+    0.4507858,
+    0.45641103,
+    0.462077,
+    0.4677838,
+    0.47353148,
+    0.47932017,
+    0.48514995,
+    0.49102086,
+    0.49693298,
+    0.5028865,
+    0.50888133,
+    0.5149177,
+    0.52099556,
+    0.5271151,
+    0.5332764,
+    0.5394795,
+    0.54572445,
+    0.55201143,
+    0.5583404,
+    0.5647115,
+    0.57112485,
+    0.57758045,
+    0.58407843,
+    0.59061885,
+    0.59720176,
+    0.60382736,
+    0.61049557,
+    0.6172066,
+    0.6239604,
+    0.63075715,
+    0.63759685,
+    0.6444797,
+    0.65140563,
+    0.65837485,
+    0.6653873,
+    0.67244315,
+    0.6795425,
+    0.6866853,
+    0.69387174,
+    0.7011019,
+    0.70837575,
+    0.7156935,
+    0.7230551,
+    0.73046076,
+    0.7379104,
+    0.7454042,
+    0.7529422,
+    0.7605245,
+    0.76815116,
+    0.7758222,
+    0.7835378,
+    0.7912979,
+    0.7991027,
+    0.80695224,
+    0.8148466,
+    0.82278574,
+    0.8307699,
+    0.838799,
+    0.8468732,
+    0.8549926,
+    0.8631572,
+    0.8713671,
+    0.8796224,
+    0.8879231,
+    0.8962694,
+    0.9046612,
+    0.91309863,
+    0.92158186,
+    0.9301109,
+    0.9386857,
+    0.9473065,
+    0.9559733,
+    0.9646863,
+    0.9734453,
+    0.9822506,
+    0.9911021,
+    1.0,
+];
+
+/// Conversion of an 8 bit sRGB value to linear light
+#[inline]
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    SRGB_TO_LINEAR[usize::from(value)]
+}
+
+/// Conversion of a linear value to 8 bit sRGB
+#[inline]
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    f32_to_u8(value, Transfer::Linear)
+}
+
+/// Conversion of a float value to 8 bits
+///
+/// Values are clamped to 0.0 - 1.0 and non finite values are treated as 0.0.
+#[inline]
+fn f32_to_u8(value: f32, transfer: Transfer) -> u8 {
+    let value = clamp_f32(value);
+
+    match transfer {
+        Transfer::Linear => SRGB_THRESHOLDS.partition_point(|&threshold| threshold <= value) as u8,
+        Transfer::Srgb => (value * 255.0 + 0.5) as u8,
+    }
+}
+
+/// Conversion of a float value to the range 0.0 - 255.0 of 8 bit values without rounding
+///
+/// Linear values are interpolated between the two nearest 8 bit sRGB values.
+#[inline]
+fn f32_to_level(value: f32, transfer: Transfer) -> f32 {
+    let value = clamp_f32(value);
+
+    match transfer {
+        Transfer::Linear => {
+            let upper = SRGB_TO_LINEAR.partition_point(|&linear| linear <= value);
+
+            if upper == SRGB_TO_LINEAR.len() {
+                return 255.0;
+            }
+
+            let lower = SRGB_TO_LINEAR[upper - 1];
+            let step = SRGB_TO_LINEAR[upper] - lower;
+
+            (upper - 1) as f32 + (value - lower) / step
+        }
+        Transfer::Srgb => value * 255.0,
+    }
+}
+
+#[inline]
+fn clamp_f32(value: f32) -> f32 {
+    if value.is_finite() {
+        value.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+// Thresholds of ordered dithering in 1/64 steps
+static BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reduces a value in the range 0.0 - 255.0 to 8 bits
+///
+/// `carry` holds the rounding error of the previous sample of the row for error diffusion
+/// and has to start at 0.0 for each row.
+#[inline]
+fn dither_to_u8(level: f32, dither: Dither, x: usize, y: u16, carry: &mut f32) -> u8 {
+    let value = match dither {
+        Dither::None => level + 0.5,
+        Dither::Ordered => {
+            let threshold = BAYER_8X8[usize::from(y) % 8][x % 8];
+            level + (f32::from(threshold) + 0.5) / 64.0
+        }
+        Dither::ErrorDiffusion => {
+            let level = level + *carry;
+            let value = (level + 0.5).clamp(0.0, 255.0) as u8;
+            *carry = level - f32::from(value);
+            return value;
+        }
+    };
+
+    value.clamp(0.0, 255.0) as u8
+}
+
+/// The last field is the first row like in [Gray16Image]
+pub(crate) struct RgbF32Image<'a>(
+    pub &'a [u8],
+    pub u16,
+    pub u16,
+    pub ColorSpace,
+    pub Transfer,
+    pub Dither,
+    pub u16,
+);
+
+impl<'a> ImageBuffer for RgbF32Image<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycbcr
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 12);
+        fill_rgb_f32_row(line, self.6 + y, self.3, self.4, self.5, buffers);
+    }
+}
+
+/// Converts a row of RGB float samples, `y` is only used for dithering
+fn fill_rgb_f32_row(
+    line: &[u8],
+    y: u16,
+    color_space: ColorSpace,
+    transfer: Transfer,
+    dither: Dither,
+    buffers: &mut [Vec<u8>; 4],
+) {
+    let convert = match color_space {
+        ColorSpace::Bt601 => rgb_to_ycbcr,
+        ColorSpace::Bt709 => rgb_to_ycbcr_bt709,
+    };
+
+    let mut carry = [0.0; 3];
+
+    for (x, pixel) in line.chunks_exact(12).enumerate() {
+        let mut rgb = [0u8; 3];
+
+        for ((value, bytes), carry) in rgb.iter_mut().zip(pixel.chunks_exact(4)).zip(&mut carry) {
+            let float = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+            *value = match dither {
+                Dither::None => f32_to_u8(float, transfer),
+                dither => dither_to_u8(f32_to_level(float, transfer), dither, x, y, carry),
+            };
+        }
+
+        let (y, cb, cr) = convert(rgb[0], rgb[1], rgb[2]);
+
+        buffers[0].push(y);
+        buffers[1].push(cb);
+        buffers[2].push(cr);
+    }
+}
+
+pub(crate) struct YCbCrImage<'a>(pub &'a [u8], pub u16, pub u16);
+
+impl<'a> ImageBuffer for YCbCrImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycbcr
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 3);
+
+        for pixel in line.chunks_exact(3) {
+            buffers[0].push(pixel[0]);
+            buffers[1].push(pixel[1]);
+            buffers[2].push(pixel[2]);
+        }
+    }
+}
+
+pub(crate) struct CmykImage<'a>(pub &'a [u8], pub u16, pub u16);
+
+impl<'a> ImageBuffer for CmykImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Cmyk
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 4);
+
+        for pixel in line.chunks_exact(4) {
+            buffers[0].push(255 - pixel[0]);
+            buffers[1].push(255 - pixel[1]);
+            buffers[2].push(255 - pixel[2]);
+            buffers[3].push(255 - pixel[3]);
+        }
+    }
+}
+
+pub(crate) struct CmykAsYcckImage<'a>(pub &'a [u8], pub u16, pub u16);
+
+impl<'a> ImageBuffer for CmykAsYcckImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycck
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 4);
+
+        for pixel in line.chunks_exact(4) {
+
+            let (y, cb, cr, k) = cmyk_to_ycck(
+                pixel[0],
+                pixel[1],
+                pixel[2],
+                pixel[3],
+            );
+
+            buffers[0].push(y);
+            buffers[1].push(cb);
+            buffers[2].push(cr);
+            buffers[3].push(k);
+        }
+    }
+}
+
+pub(crate) struct YcckImage<'a>(pub &'a [u8], pub u16, pub u16);
+
+impl<'a> ImageBuffer for YcckImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycck
+    }
+
+    fn width(&self) -> u16 {
+        self.1
+    }
+
+    fn height(&self) -> u16 {
+        self.2
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let line = get_line(self.0, y, self.width(), 4);
+
+        for pixel in line.chunks_exact(4) {
+
+            buffers[0].push(pixel[0]);
+            buffers[1].push(pixel[1]);
+            buffers[2].push(pixel[2]);
+            buffers[3].push(pixel[3]);
+        }
+    }
+}
+
+/// # Planar YCbCr image with full resolution chroma planes (I444)
+///
+/// Each plane contains one component in row major order. Row `y` of a plane starts at
+/// `y * stride` and contains `width` samples, so the stride must be at least the width
+/// and each plane needs at least `(height - 1) * stride + width` samples.<br>
+/// The stride allows to use planes with padding at the end of each row, e.g. video frames.
+///
+/// # Panics
+/// The sizes of the planes are checked by [new](PlanarYCbCr::new). Encoding an image which
+/// is created without it panics if a plane is smaller than required by the dimensions and stride.
+///
+/// # Example
+/// ```
+/// # use jpeg_encoder::EncodingError;
+/// # pub fn main() -> Result<(), EncodingError> {
+/// use jpeg_encoder::{Encoder, PlanarYCbCr};
+///
+/// // A 2x2 image with rows padded to 4 samples
+/// let y = [16, 32, 0, 0, 48, 64, 0, 0];
+/// let cb = [128, 128, 0, 0, 128, 128, 0, 0];
+/// let cr = [128, 128, 0, 0, 128, 128, 0, 0];
+///
+/// let image = PlanarYCbCr::new(&y, &cb, &cr, 2, 2, 4, 4)?;
+///
+/// let mut result = Vec::new();
+/// Encoder::new(&mut result, 90).encode_image(image)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct PlanarYCbCr<'a> {
+    /// Luma plane
+    pub y: &'a [u8],
+
+    /// Blue difference chroma plane
+    pub cb: &'a [u8],
+
+    /// Red difference chroma plane
+    pub cr: &'a [u8],
+
+    /// Width of the image
+    pub width: u16,
+
+    /// Height of the image
+    pub height: u16,
+
+    /// Number of samples between the start of two rows of the luma plane
+    pub y_stride: usize,
+
+    /// Number of samples between the start of two rows of the chroma planes
+    pub chroma_stride: usize,
+}
+
+impl<'a> PlanarYCbCr<'a> {
+    /// Create an image after checking the sizes of the planes
+    ///
+    /// # Errors
+    ///
+    /// Returns [BadImageData](EncodingError::BadImageData) if a stride is smaller than the
+    /// width or a plane is smaller than required by the dimensions and its stride.
+    pub fn new(
+        y: &'a [u8],
+        cb: &'a [u8],
+        cr: &'a [u8],
+        width: u16,
+        height: u16,
+        y_stride: usize,
+        chroma_stride: usize,
+    ) -> Result<PlanarYCbCr<'a>, EncodingError> {
+        let plane_width = usize::from(width);
+        let plane_height = usize::from(height);
+
+        check_plane(y, plane_width, plane_height, y_stride)?;
+        check_plane(cb, plane_width, plane_height, chroma_stride)?;
+        check_plane(cr, plane_width, plane_height, chroma_stride)?;
+
+        Ok(PlanarYCbCr {
+            y,
+            cb,
+            cr,
+            width,
+            height,
+            y_stride,
+            chroma_stride,
+        })
+    }
+}
+
+impl<'a> ImageBuffer for PlanarYCbCr<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycbcr
+    }
+
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let width = usize::from(self.width);
+
+        let start = usize::from(y) * self.y_stride;
+        buffers[0].extend_from_slice(&self.y[start..start + width]);
+
+        let start = usize::from(y) * self.chroma_stride;
+        buffers[1].extend_from_slice(&self.cb[start..start + width]);
+        buffers[2].extend_from_slice(&self.cr[start..start + width]);
+    }
+}
+
+/// Checks that a plane contains `height` rows of `width` samples which start `stride` samples apart
+fn check_plane(
+    plane: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+) -> Result<(), EncodingError> {
+    if stride < width {
+        return Err(EncodingError::BadImageData {
+            length: stride,
+            required: width,
+        });
+    }
+
+    let required = match height.checked_sub(1) {
+        Some(rows) => rows
+            .checked_mul(stride)
+            .and_then(|len| len.checked_add(width))
+            .unwrap_or(usize::MAX),
+        None => 0,
+    };
+
+    if plane.len() < required {
+        return Err(EncodingError::BadImageData {
+            length: plane.len(),
+            required,
+        });
+    }
+
+    Ok(())
+}
+
+/// # Planar YCbCr image with half resolution chroma planes (I420)
+///
+/// The chroma planes have a size of `ceil(width / 2)` x `ceil(height / 2)` samples.
+/// Row `y` of a plane starts at `y * stride`, see [PlanarYCbCr] for details on the strides.<br>
+/// Chroma samples are replicated for the encoder. With the 4:2:0
+/// [sampling factor](crate::SamplingFactor::F_2_2) they are encoded without any change,
+/// independent of the [downsample filter](crate::Encoder::set_chroma_downsample_filter).
+///
+/// # Panics
+/// The sizes of the planes are checked by [new](I420::new). Encoding an image which
+/// is created without it panics if a plane is smaller than required by the dimensions and stride.
+#[derive(Copy, Clone, Debug)]
+pub struct I420<'a> {
+    /// Luma plane
+    pub y: &'a [u8],
+
+    /// Blue difference chroma plane
+    pub cb: &'a [u8],
+
+    /// Red difference chroma plane
+    pub cr: &'a [u8],
+
+    /// Width of the image
+    pub width: u16,
+
+    /// Height of the image
+    pub height: u16,
+
+    /// Number of samples between the start of two rows of the luma plane
+    pub y_stride: usize,
+
+    /// Number of samples between the start of two rows of the chroma planes
+    pub chroma_stride: usize,
+}
+
+impl<'a> I420<'a> {
+    /// Create an image after checking the sizes of the planes
+    ///
+    /// # Errors
+    ///
+    /// Returns [BadImageData](EncodingError::BadImageData) if a stride is smaller than the
+    /// width of its plane or a plane is smaller than required by the dimensions and its stride.
+    pub fn new(
+        y: &'a [u8],
+        cb: &'a [u8],
+        cr: &'a [u8],
+        width: u16,
+        height: u16,
+        y_stride: usize,
+        chroma_stride: usize,
+    ) -> Result<I420<'a>, EncodingError> {
+        let chroma_width = (usize::from(width) + 1) / 2;
+        let chroma_height = (usize::from(height) + 1) / 2;
+
+        check_plane(y, usize::from(width), usize::from(height), y_stride)?;
+        check_plane(cb, chroma_width, chroma_height, chroma_stride)?;
+        check_plane(cr, chroma_width, chroma_height, chroma_stride)?;
+
+        Ok(I420 {
+            y,
+            cb,
+            cr,
+            width,
+            height,
+            y_stride,
+            chroma_stride,
+        })
+    }
+}
+
+impl<'a> ImageBuffer for I420<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycbcr
+    }
+
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let width = usize::from(self.width);
+
+        let start = usize::from(y) * self.y_stride;
+        buffers[0].extend_from_slice(&self.y[start..start + width]);
+
+        let start = usize::from(y / 2) * self.chroma_stride;
+        let cb = &self.cb[start..];
+        let cr = &self.cr[start..];
+
+        for x in 0..width {
+            buffers[1].push(cb[x / 2]);
+            buffers[2].push(cr[x / 2]);
+        }
+    }
+
+    fn chroma_already_subsampled(&self) -> bool {
+        true
+    }
+
+    fn component_subsampling(&self, component: usize) -> (u8, u8) {
+        if component == 0 {
+            (1, 1)
+        } else {
+            (2, 2)
+        }
+    }
+
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        let chroma_width = (usize::from(self.width) + 1) / 2;
+
+        let (plane, stride, width) = match component {
+            0 => (self.y, self.y_stride, usize::from(self.width)),
+            1 => (self.cb, self.chroma_stride, chroma_width),
+            _ => (self.cr, self.chroma_stride, chroma_width),
+        };
+
+        let start = usize::from(y) * stride;
+        buffer.extend_from_slice(&plane[start..start + width]);
+    }
+}
+
+/// # Semi-planar YCbCr image with an interleaved half resolution chroma plane (NV12)
+///
+/// The chroma plane contains `ceil(width / 2)` pairs of Cb and Cr samples per row
+/// and `ceil(height / 2)` rows. Row `y` of a plane starts at `y * stride` where the
+/// stride of the chroma plane is given in bytes, see [PlanarYCbCr] for details on the strides.<br>
+/// As with [I420] the chroma samples are encoded without change with the 4:2:0
+/// [sampling factor](crate::SamplingFactor::F_2_2).
+///
+/// # Panics
+/// The sizes of the planes are checked by [new](Nv12::new). Encoding an image which
+/// is created without it panics if a plane is smaller than required by the dimensions and stride.
+#[derive(Copy, Clone, Debug)]
+pub struct Nv12<'a> {
+    /// Luma plane
+    pub y: &'a [u8],
+
+    /// Chroma plane with interleaved Cb and Cr samples
+    pub cbcr: &'a [u8],
+
+    /// Width of the image
+    pub width: u16,
+
+    /// Height of the image
+    pub height: u16,
+
+    /// Number of samples between the start of two rows of the luma plane
+    pub y_stride: usize,
+
+    /// Number of bytes between the start of two rows of the chroma plane
+    pub cbcr_stride: usize,
+}
+
+impl<'a> Nv12<'a> {
+    /// Create an image after checking the sizes of the planes
+    ///
+    /// # Errors
+    ///
+    /// Returns [BadImageData](EncodingError::BadImageData) if a stride is smaller than a row
+    /// of its plane or a plane is smaller than required by the dimensions and its stride.
+    pub fn new(
+        y: &'a [u8],
+        cbcr: &'a [u8],
+        width: u16,
+        height: u16,
+        y_stride: usize,
+        cbcr_stride: usize,
+    ) -> Result<Nv12<'a>, EncodingError> {
+        let cbcr_width = (usize::from(width) + 1) / 2 * 2;
+        let cbcr_height = (usize::from(height) + 1) / 2;
+
+        check_plane(y, usize::from(width), usize::from(height), y_stride)?;
+        check_plane(cbcr, cbcr_width, cbcr_height, cbcr_stride)?;
+
+        Ok(Nv12 {
+            y,
+            cbcr,
+            width,
+            height,
+            y_stride,
+            cbcr_stride,
+        })
+    }
+}
+
+impl<'a> ImageBuffer for Nv12<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        JpegColorType::Ycbcr
+    }
+
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let width = usize::from(self.width);
+
+        let start = usize::from(y) * self.y_stride;
+        buffers[0].extend_from_slice(&self.y[start..start + width]);
+
+        let start = usize::from(y / 2) * self.cbcr_stride;
+        let cbcr = &self.cbcr[start..];
+
+        for x in 0..width {
+            buffers[1].push(cbcr[x / 2 * 2]);
+            buffers[2].push(cbcr[x / 2 * 2 + 1]);
+        }
+    }
+
+    fn chroma_already_subsampled(&self) -> bool {
+        true
+    }
+
+    fn component_subsampling(&self, component: usize) -> (u8, u8) {
+        if component == 0 {
+            (1, 1)
+        } else {
+            (2, 2)
+        }
+    }
+
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        if component == 0 {
+            let start = usize::from(y) * self.y_stride;
+            buffer.extend_from_slice(&self.y[start..start + usize::from(self.width)]);
+        } else {
+            let width = (usize::from(self.width) + 1) / 2;
+            let start = usize::from(y) * self.cbcr_stride;
+
+            let line = &self.cbcr[start..start + width * 2];
+
+            for pixel in line.chunks_exact(2) {
+                buffer.push(pixel[component - 1]);
+            }
+        }
+    }
+}
+
+/// # Component plane used as input for [Encoder::encode_components](crate::Encoder::encode_components)
+///
+/// A plane contains the samples of one component at its native resolution.
+/// The sampling factors are the JPEG sampling factors of the component, so a plane with
+/// sampling factors of 1x1 next to a 2x2 luma plane contains a quarter of the samples.
+pub struct ComponentPlane<'a> {
+    /// Samples of the component in row major order
+    pub data: &'a [u8],
+
+    /// Horizontal sampling factor of the component (1, 2 or 4)
+    pub h_sampling: u8,
+
+    /// Vertical sampling factor of the component (1, 2 or 4)
+    pub v_sampling: u8,
+}
+
+impl<'a> ComponentPlane<'a> {
+    /// Size of the plane for an image with the given size and maximum sampling factors
+    pub(crate) fn size(&self, width: u16, height: u16, max_h: u8, max_v: u8) -> (usize, usize) {
+        let width = usize::from(width) * usize::from(self.h_sampling);
+        let height = usize::from(height) * usize::from(self.v_sampling);
+
+        let max_h = usize::from(max_h);
+        let max_v = usize::from(max_v);
+
+        (
+            width / max_h + usize::from(width % max_h != 0),
+            height / max_v + usize::from(height % max_v != 0),
+        )
+    }
+}
+
+/// Upsamples already subsampled component planes by replicating their samples
+///
+/// The encoder samples the replicated values at the same positions again so the
+/// planes are encoded without any change to their values.
+pub(crate) struct PlanarImage<'a>(pub &'a [ComponentPlane<'a>], pub u16, pub u16);
+
+impl<'a> PlanarImage<'a> {
+    pub(crate) fn max_sampling_factors(&self) -> (u8, u8) {
+        self.0.iter().fold((1, 1), |(h, v), plane| {
+            (h.max(plane.h_sampling), v.max(plane.v_sampling))
+        })
+    }
+}
+
+impl<'a> ImageBuffer for PlanarImage<'a> {
     fn get_jpeg_color_type(&self) -> JpegColorType {
-        JpegColorType::Ycck
+        match self.0.len() {
+            1 => JpegColorType::Luma,
+            3 => JpegColorType::Ycbcr,
+            4 => JpegColorType::Ycck,
+            len => unreachable!("Unsupported number of planes: {}", len),
+        }
     }
 
     fn width(&self) -> u16 {
@@ -278,21 +1763,385 @@ impl<'a> ImageBuffer for YcckImage<'a> {
     }
 
     fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
-        let line = get_line(self.0, y, self.width(), 4);
+        let (max_h, max_v) = self.max_sampling_factors();
 
-        for pixel in line.chunks_exact(4) {
+        for (plane, buffer) in self.0.iter().zip(buffers.iter_mut()) {
+            let (plane_width, _) = plane.size(self.1, self.2, max_h, max_v);
 
-            buffers[0].push(pixel[0]);
-            buffers[1].push(pixel[1]);
-            buffers[2].push(pixel[2]);
-            buffers[3].push(pixel[3]);
+            let h_scale = usize::from(max_h / plane.h_sampling);
+            let v_scale = usize::from(max_v / plane.v_sampling);
+
+            let start = usize::from(y) / v_scale * plane_width;
+            let line = &plane.data[start..start + plane_width];
+
+            for x in 0..usize::from(self.1) {
+                buffer.push(line[x / h_scale]);
+            }
+        }
+    }
+
+    fn chroma_already_subsampled(&self) -> bool {
+        true
+    }
+
+    fn component_subsampling(&self, component: usize) -> (u8, u8) {
+        let (max_h, max_v) = self.max_sampling_factors();
+        let plane = &self.0[component];
+
+        (max_h / plane.h_sampling, max_v / plane.v_sampling)
+    }
+
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        let (max_h, max_v) = self.max_sampling_factors();
+        let plane = &self.0[component];
+
+        let (plane_width, _) = plane.size(self.1, self.2, max_h, max_v);
+
+        let start = usize::from(y) * plane_width;
+        buffer.extend_from_slice(&plane.data[start..start + plane_width]);
+    }
+}
+
+/// Receives the image buffer chosen by [InputFormat::visit]
+pub(crate) trait ImageVisitor {
+    type Output;
+
+    fn visit<I: ImageBuffer>(self, image: I) -> Self::Output;
+}
+
+/// Settings of the encoder which define how raw data of a [ColorType] is read
+#[derive(Copy, Clone)]
+pub(crate) struct InputFormat {
+    pub grayscale: bool,
+    pub color_space: ColorSpace,
+    pub alpha_background: Option<[u8; 3]>,
+    pub endianness: Endianness,
+    pub transfer: Transfer,
+    pub dither: Dither,
+    /// Index of the first row of the data in the encoded image
+    pub first_row: u16,
+}
+
+impl InputFormat {
+    /// Passes the image buffer which reads `data` as `color_type` to the visitor
+    pub(crate) fn visit<V: ImageVisitor>(
+        self,
+        data: &[u8],
+        width: u16,
+        height: u16,
+        color_type: ColorType,
+        visitor: V,
+    ) -> V::Output {
+        let color_space = self.color_space;
+        let background = self.alpha_background;
+
+        if self.grayscale {
+            match color_type {
+                ColorType::Rgb => {
+                    return visitor.visit(RgbLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                ColorType::Rgba => {
+                    return visitor.visit(RgbaLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                ColorType::Bgr => {
+                    return visitor.visit(BgrLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                ColorType::Bgra => {
+                    return visitor.visit(BgraLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                ColorType::Argb => {
+                    return visitor.visit(ArgbLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                ColorType::Abgr => {
+                    return visitor.visit(AbgrLumaImage(
+                        data,
+                        width,
+                        height,
+                        color_space,
+                        background,
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(background) = background {
+            match color_type {
+                ColorType::Rgba => {
+                    return visitor.visit(RgbaBlendImage(
+                        data,
+                        width,
+                        height,
+                        background,
+                        color_space,
+                    ))
+                }
+                ColorType::Bgra => {
+                    return visitor.visit(BgraBlendImage(
+                        data,
+                        width,
+                        height,
+                        background,
+                        color_space,
+                    ))
+                }
+                ColorType::Argb => {
+                    return visitor.visit(ArgbBlendImage(
+                        data,
+                        width,
+                        height,
+                        background,
+                        color_space,
+                    ))
+                }
+                ColorType::Abgr => {
+                    return visitor.visit(AbgrBlendImage(
+                        data,
+                        width,
+                        height,
+                        background,
+                        color_space,
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        // There are no SIMD conversions for BT.709
+        if color_space == ColorSpace::Bt709 {
+            match color_type {
+                ColorType::Rgb => return visitor.visit(RgbImageBt709(data, width, height)),
+                ColorType::Rgba => return visitor.visit(RgbaImageBt709(data, width, height)),
+                ColorType::Bgr => return visitor.visit(BgrImageBt709(data, width, height)),
+                ColorType::Bgra => return visitor.visit(BgraImageBt709(data, width, height)),
+                ColorType::Argb => return visitor.visit(ArgbImageBt709(data, width, height)),
+                ColorType::Abgr => return visitor.visit(AbgrImageBt709(data, width, height)),
+                _ => {}
+            }
+        }
+
+        #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if std::is_x86_feature_detected!("avx2") {
+                use crate::avx2::*;
+
+                match color_type {
+                    ColorType::Rgb => return visitor.visit(RgbImageAVX2(data, width, height)),
+                    ColorType::Rgba => return visitor.visit(RgbaImageAVX2(data, width, height)),
+                    ColorType::Bgr => return visitor.visit(BgrImageAVX2(data, width, height)),
+                    ColorType::Bgra => return visitor.visit(BgraImageAVX2(data, width, height)),
+                    ColorType::CmykAsYcck => {
+                        return visitor.visit(CmykAsYcckImageAVX2(data, width, height))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        match color_type {
+            ColorType::Luma => visitor.visit(GrayImage(data, width, height)),
+            ColorType::Rgb => visitor.visit(RgbImage(data, width, height)),
+            ColorType::Rgba => visitor.visit(RgbaImage(data, width, height)),
+            ColorType::Bgr => visitor.visit(BgrImage(data, width, height)),
+            ColorType::Bgra => visitor.visit(BgraImage(data, width, height)),
+            ColorType::Ycbcr => visitor.visit(YCbCrImage(data, width, height)),
+            ColorType::Cmyk => visitor.visit(CmykImage(data, width, height)),
+            ColorType::CmykAsYcck => visitor.visit(CmykAsYcckImage(data, width, height)),
+            ColorType::Ycck => visitor.visit(YcckImage(data, width, height)),
+            ColorType::Luma16 => visitor.visit(Gray16Image(
+                data,
+                width,
+                height,
+                self.endianness,
+                self.dither,
+                self.first_row,
+            )),
+            ColorType::Rgb565 => visitor.visit(Rgb565Image(data, width, height, color_space)),
+            ColorType::RgbF32 => visitor.visit(RgbF32Image(
+                data,
+                width,
+                height,
+                color_space,
+                self.transfer,
+                self.dither,
+                self.first_row,
+            )),
+            ColorType::Yuyv => visitor.visit(YuyvImage(data, width, height)),
+            ColorType::Uyvy => visitor.visit(UyvyImage(data, width, height)),
+            ColorType::LumaA => visitor.visit(GrayAlphaImage(data, width, height, background)),
+            ColorType::Argb => visitor.visit(ArgbImage(data, width, height)),
+            ColorType::Abgr => visitor.visit(AbgrImage(data, width, height)),
+        }
+    }
+}
+
+struct JpegColorTypeOf;
+
+impl ImageVisitor for JpegColorTypeOf {
+    type Output = JpegColorType;
+
+    fn visit<I: ImageBuffer>(self, image: I) -> JpegColorType {
+        image.get_jpeg_color_type()
+    }
+}
+
+struct FillRow<'b>(&'b mut [Vec<u8>; 4]);
+
+impl<'b> ImageVisitor for FillRow<'b> {
+    type Output = ();
+
+    fn visit<I: ImageBuffer>(self, image: I) {
+        image.fill_buffers(0, self.0)
+    }
+}
+
+/// Rectangular region of a larger image with rows `stride` bytes apart
+///
+/// Each row is read by the same image buffer as the data of
+/// [encode_frame](crate::Encoder::encode_frame), cropped to a single row of the region.
+pub(crate) struct RegionImage<'a> {
+    /// Data starting at the first pixel of the region
+    pub data: &'a [u8],
+    pub width: u16,
+    pub height: u16,
+    pub stride: usize,
+    pub color_type: ColorType,
+    pub format: InputFormat,
+}
+
+impl<'a> ImageBuffer for RegionImage<'a> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        self.format
+            .visit(&[], self.width, 0, self.color_type, JpegColorTypeOf)
+    }
+
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let start = usize::from(y) * self.stride;
+        let line = &self.data[start..start + self.color_type.get_data_len(self.width, 1)];
+
+        let format = InputFormat {
+            first_row: y,
+            ..self.format
+        };
+
+        format.visit(line, self.width, 1, self.color_type, FillRow(buffers))
+    }
+}
+
+/// Scales the full range values of another buffer to the limited (studio) range
+///
+/// Luma values are scaled to 16-235 and chroma values to 16-240.
+/// The K channel of YCCK images and CMYK images are not changed.
+pub(crate) struct LimitedRangeImage<I: ImageBuffer>(pub I);
+
+impl<I: ImageBuffer> LimitedRangeImage<I> {
+    fn scale(&self, component: usize, values: &mut [u8]) {
+        let num_components = match self.get_jpeg_color_type() {
+            JpegColorType::Luma => 1,
+            JpegColorType::Ycbcr | JpegColorType::Ycck => 3,
+            JpegColorType::Cmyk => 0,
+        };
+
+        if component >= num_components {
+            return;
+        }
+
+        let range = if component == 0 { 219 } else { 224 };
+
+        for value in values {
+            *value = (16 + (u32::from(*value) * range + 127) / 255) as u8;
+        }
+    }
+}
+
+impl<I: ImageBuffer> ImageBuffer for LimitedRangeImage<I> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        self.0.get_jpeg_color_type()
+    }
+
+    fn width(&self) -> u16 {
+        self.0.width()
+    }
+
+    fn height(&self) -> u16 {
+        self.0.height()
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let start = [buffers[0].len(), buffers[1].len(), buffers[2].len()];
+
+        self.0.fill_buffers(y, buffers);
+
+        for (i, (buffer, start)) in buffers.iter_mut().zip(start).enumerate() {
+            self.scale(i, &mut buffer[start..]);
         }
     }
+
+    fn chroma_already_subsampled(&self) -> bool {
+        self.0.chroma_already_subsampled()
+    }
+
+    fn component_subsampling(&self, component: usize) -> (u8, u8) {
+        self.0.component_subsampling(component)
+    }
+
+    fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+        let start = buffer.len();
+
+        self.0.fill_component_row(component, y, buffer);
+
+        self.scale(component, &mut buffer[start..]);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::rgb_to_ycbcr;
+    use crate::encoder::Endianness;
+    use crate::image_buffer::{
+        f32_to_u8, CmykAsYcckImage, CmykImage, ColorSpace, Dither, Gray16Image, GrayAlphaImage,
+        GrayImage, LimitedRangeImage, Nv12, PlanarYCbCr, Rgb565Image, RgbF32Image, RgbImage,
+        RgbImageBt709, RgbaBlendImage, Transfer, UyvyImage, YCbCrImage, YcckImage, YuyvImage, I420,
+    };
+    use crate::{cmyk_to_ycck, rgb_to_ycbcr, rgb_to_ycbcr_bt709, ImageBuffer};
+    use alloc::vec::Vec;
 
     fn assert_rgb_to_ycbcr(rgb: [u8; 3], ycbcr: [u8; 3]) {
         let (y, cb, cr) = rgb_to_ycbcr(rgb[0], rgb[1], rgb[2]);
@@ -399,4 +2248,314 @@ mod tests {
         assert_rgb_to_ycbcr([144, 193, 75], [165, 77, 113]);
         assert_rgb_to_ycbcr([49, 94, 1], [70, 89, 113]);
     }
+
+    #[test]
+    fn test_rgb_to_ycbcr_bt709() {
+        assert_eq!(rgb_to_ycbcr_bt709(0, 0, 0), (0, 128, 128));
+        assert_eq!(rgb_to_ycbcr_bt709(255, 255, 255), (255, 128, 128));
+        assert_eq!(rgb_to_ycbcr_bt709(255, 0, 0), (54, 99, 255));
+        assert_eq!(rgb_to_ycbcr_bt709(0, 255, 0), (182, 30, 12));
+        assert_eq!(rgb_to_ycbcr_bt709(0, 0, 255), (18, 255, 116));
+
+        // Gray values are the same for both color spaces
+        for v in 0..=255 {
+            assert_eq!(rgb_to_ycbcr_bt709(v, v, v), rgb_to_ycbcr(v, v, v));
+        }
+    }
+
+    #[test]
+    fn test_gray16_buffers() {
+        let le = [0, 0, 0xFF, 0xFF, 0x81, 0x00, 0x7F, 0x01];
+        let be = [0, 0, 0xFF, 0xFF, 0x00, 0x81, 0x01, 0x7F];
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        Gray16Image(&le, 4, 1, Endianness::Little, Dither::None, 0).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers[0], [0, 255, 1, 1]);
+
+        let mut be_buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        Gray16Image(&be, 4, 1, Endianness::Big, Dither::None, 0).fill_buffers(0, &mut be_buffers);
+
+        assert_eq!(buffers, be_buffers);
+    }
+
+    #[test]
+    fn test_dither() {
+        // 128.5 in 8 bits
+        let line: Vec<u8> = (0..16).flat_map(|_| 33024u16.to_le_bytes()).collect();
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        Gray16Image(&line, 16, 1, Endianness::Little, Dither::None, 0)
+            .fill_buffers(0, &mut buffers);
+
+        assert!(buffers[0].iter().all(|&value| value == 128));
+
+        for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+            let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            Gray16Image(&line, 16, 1, Endianness::Little, dither, 0).fill_buffers(0, &mut buffers);
+
+            assert!(buffers[0].iter().all(|&value| value == 128 || value == 129));
+
+            let sum: u32 = buffers[0].iter().map(|&value| u32::from(value)).sum();
+            assert_eq!(sum, 16 * 128 + 8);
+        }
+
+        // The extremes aren't changed
+        for (value, expected) in [(0.0f32, 0), (1.0, 255)] {
+            let line: Vec<u8> = (0..24).flat_map(|_| value.to_ne_bytes()).collect();
+
+            for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+                for transfer in [Transfer::Srgb, Transfer::Linear] {
+                    let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+                    RgbF32Image(&line, 8, 1, ColorSpace::Bt601, transfer, dither, 0)
+                        .fill_buffers(0, &mut buffers);
+
+                    assert!(buffers[0].iter().all(|&y| y == expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rgb565_buffers() {
+        let rgb565 = [
+            0x00, 0x00, 0xFF, 0xFF, 0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00, 0x10, 0x84,
+        ];
+        let rgb = [
+            0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 132, 130, 132,
+        ];
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        Rgb565Image(&rgb565, 6, 1, ColorSpace::Bt601).fill_buffers(0, &mut buffers);
+
+        let mut expected = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        RgbImage(&rgb, 6, 1).fill_buffers(0, &mut expected);
+
+        assert_eq!(buffers, expected);
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        Rgb565Image(&rgb565, 6, 1, ColorSpace::Bt709).fill_buffers(0, &mut buffers);
+
+        let mut expected = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        RgbImageBt709(&rgb, 6, 1).fill_buffers(0, &mut expected);
+
+        assert_eq!(buffers, expected);
+    }
+
+    #[test]
+    fn test_f32_to_u8() {
+        for transfer in [Transfer::Linear, Transfer::Srgb] {
+            assert_eq!(f32_to_u8(0.0, transfer), 0);
+            assert_eq!(f32_to_u8(1.0, transfer), 255);
+            assert_eq!(f32_to_u8(-1.0, transfer), 0);
+            assert_eq!(f32_to_u8(2.0, transfer), 255);
+            assert_eq!(f32_to_u8(f32::NAN, transfer), 0);
+            assert_eq!(f32_to_u8(f32::INFINITY, transfer), 0);
+            assert_eq!(f32_to_u8(f32::NEG_INFINITY, transfer), 0);
+        }
+
+        for v in 0..=255u8 {
+            assert_eq!(f32_to_u8(f32::from(v) / 255.0, Transfer::Srgb), v);
+        }
+
+        // Linear 0.5 is about 188 in sRGB and 0.0031308 is the end of the linear segment
+        assert_eq!(f32_to_u8(0.5, Transfer::Linear), 188);
+        assert_eq!(f32_to_u8(0.0031308, Transfer::Linear), 10);
+        assert_eq!(f32_to_u8(0.2159, Transfer::Linear), 128);
+    }
+
+    #[test]
+    fn test_planar_ycbcr_buffers() {
+        let ycbcr = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+        let y = [0, 0, 0, 0, 7, 10, 0];
+        let cb = [0, 0, 8, 11];
+        let cr = [0, 0, 9, 12];
+
+        let image = PlanarYCbCr {
+            y: &y,
+            cb: &cb,
+            cr: &cr,
+            width: 2,
+            height: 2,
+            y_stride: 4,
+            chroma_stride: 2,
+        };
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        image.fill_buffers(1, &mut buffers);
+
+        let mut expected = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        YCbCrImage(&ycbcr, 2, 2).fill_buffers(1, &mut expected);
+
+        assert_eq!(buffers, expected);
+    }
+
+    #[test]
+    fn test_subsampled_planar_buffers() {
+        // 3x3 image with 2x2 chroma samples
+        let y = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let cb = [10, 11, 12, 13];
+        let cr = [20, 21, 22, 23];
+        let cbcr = [10, 20, 11, 21, 0, 0, 12, 22, 13, 23, 0, 0];
+
+        let i420 = I420 {
+            y: &y,
+            cb: &cb,
+            cr: &cr,
+            width: 3,
+            height: 3,
+            y_stride: 3,
+            chroma_stride: 2,
+        };
+
+        let nv12 = Nv12 {
+            y: &y,
+            cbcr: &cbcr,
+            width: 3,
+            height: 3,
+            y_stride: 3,
+            cbcr_stride: 6,
+        };
+
+        for (row, expected) in [
+            (0, [[1, 2, 3], [10, 10, 11], [20, 20, 21]]),
+            (1, [[4, 5, 6], [10, 10, 11], [20, 20, 21]]),
+            (2, [[7, 8, 9], [12, 12, 13], [22, 22, 23]]),
+        ] {
+            let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            i420.fill_buffers(row, &mut buffers);
+
+            assert_eq!(buffers[..3], expected);
+            assert!(buffers[3].is_empty());
+
+            let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            nv12.fill_buffers(row, &mut buffers);
+
+            assert_eq!(buffers[..3], expected);
+        }
+    }
+
+    #[test]
+    fn test_alpha_blend_buffers() {
+        let rgba = [10, 20, 30, 255, 10, 20, 30, 0, 255, 0, 100, 128];
+        let rgb = [10, 20, 30, 200, 150, 100, 227, 74, 100];
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        RgbaBlendImage(&rgba, 3, 1, [200, 150, 100], ColorSpace::Bt601)
+            .fill_buffers(0, &mut buffers);
+
+        let mut expected = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        RgbImage(&rgb, 3, 1).fill_buffers(0, &mut expected);
+
+        assert_eq!(buffers, expected);
+    }
+
+    #[test]
+    fn test_gray_alpha_buffers() {
+        let data = [10, 255, 20, 0, 200, 128];
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        GrayAlphaImage(&data, 3, 1, None).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers[0], [10, 20, 200]);
+        assert!(buffers[1].is_empty());
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        GrayAlphaImage(&data, 3, 1, Some([100, 100, 100])).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers[0], [10, 100, 150]);
+    }
+
+    #[test]
+    fn test_packed_422_buffers() {
+        // 3x2 image with padded rows
+        let yuyv = [1, 10, 2, 20, 3, 11, 0, 21, 4, 12, 5, 22, 6, 13, 0, 23];
+        let uyvy = [10, 1, 20, 2, 11, 3, 21, 0, 12, 4, 22, 5, 13, 6, 23, 0];
+
+        for (row, expected) in [
+            (0, [[1, 2, 3], [10, 10, 11], [20, 20, 21]]),
+            (1, [[4, 5, 6], [12, 12, 13], [22, 22, 23]]),
+        ] {
+            let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            YuyvImage(&yuyv, 3, 2).fill_buffers(row, &mut buffers);
+
+            assert_eq!(buffers[..3], expected);
+            assert!(buffers[3].is_empty());
+
+            let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            UyvyImage(&uyvy, 3, 2).fill_buffers(row, &mut buffers);
+
+            assert_eq!(buffers[..3], expected);
+        }
+    }
+
+    #[test]
+    fn test_limited_range() {
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        LimitedRangeImage(GrayImage(&[0, 128, 255], 3, 1)).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers[0], [16, 126, 235]);
+
+        let ycbcr = [0, 0, 0, 128, 128, 128, 255, 255, 255];
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        LimitedRangeImage(YCbCrImage(&ycbcr, 3, 1)).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers[0], [16, 126, 235]);
+        assert_eq!(buffers[1], [16, 128, 240]);
+        assert_eq!(buffers[2], [16, 128, 240]);
+
+        // K isn't scaled
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        LimitedRangeImage(YcckImage(&[0, 0, 0, 0], 1, 1)).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers, [[16], [16], [16], [0]]);
+
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        LimitedRangeImage(CmykImage(&[0, 0, 0, 0], 1, 1)).fill_buffers(0, &mut buffers);
+
+        assert_eq!(buffers, [[255], [255], [255], [255]]);
+    }
+
+    #[test]
+    fn test_cmyk_to_ycck() {
+        // The CMY channels are converted like RGB values and K is inverted
+        assert_eq!(cmyk_to_ycck(0, 0, 0, 0), (0, 128, 128, 255));
+        assert_eq!(cmyk_to_ycck(255, 255, 255, 255), (255, 128, 128, 0));
+        assert_eq!(cmyk_to_ycck(255, 0, 0, 100), (76, 85, 255, 155));
+        assert_eq!(cmyk_to_ycck(0, 255, 0, 1), (150, 44, 21, 254));
+        assert_eq!(cmyk_to_ycck(0, 0, 255, 254), (29, 255, 107, 1));
+        assert_eq!(cmyk_to_ycck(59, 109, 6, 128), (82, 85, 111, 127));
+    }
+
+    #[test]
+    fn test_cmyk_as_ycck_buffers() {
+        let cmyk = [
+            0, 0, 0, 0, 255, 255, 255, 255, 255, 0, 0, 100, 59, 109, 6, 128,
+        ];
+
+        let mut ycck = Vec::new();
+        for pixel in cmyk.chunks_exact(4) {
+            let (y, cb, cr, k) = cmyk_to_ycck(pixel[0], pixel[1], pixel[2], pixel[3]);
+            ycck.extend_from_slice(&[y, cb, cr, k]);
+        }
+
+        let mut cmyk_buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        CmykAsYcckImage(&cmyk, 2, 2).fill_buffers(1, &mut cmyk_buffers);
+
+        let mut ycck_buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        YcckImage(&ycck, 2, 2).fill_buffers(1, &mut ycck_buffers);
+
+        assert_eq!(cmyk_buffers, ycck_buffers);
+        assert_eq!(ycck_buffers[0], [76, 82]);
+        assert_eq!(ycck_buffers[3], [155, 127]);
+
+        // Plain CMYK images store all channels inverted
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        CmykImage(&cmyk, 2, 2).fill_buffers(1, &mut buffers);
+
+        assert_eq!(buffers[0], [0, 196]);
+        assert_eq!(buffers[3], [155, 127]);
+    }
 }
@@ -0,0 +1,138 @@
+use crate::{ColorType, Encoder, EncodingError, Endianness, JfifWrite};
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use image::error::{
+    EncodingError as ImageEncodingError, ImageError, ImageResult, LimitError, LimitErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
+use image::{ExtendedColorType, ImageFormat};
+
+/// # Encoder for the `image` crate
+///
+/// Implements the [ImageEncoder](image::ImageEncoder) trait of the `image` crate for an [Encoder],
+/// which allows to use this crate as a JPEG backend, e.g. with
+/// [DynamicImage::write_with_encoder](image::DynamicImage::write_with_encoder).<br>
+/// All settings like quality and sampling factor are taken from the wrapped encoder.
+///
+/// ```
+/// # pub fn main() -> Result<(), image::ImageError> {
+/// use jpeg_encoder::{Encoder, JpegImageEncoder, SamplingFactor};
+///
+/// let image = image::RgbImage::from_pixel(16, 16, image::Rgb([255, 0, 0]));
+///
+/// let mut result = Vec::new();
+///
+/// let encoder = Encoder::new(&mut result, 90).with_sampling_factor(SamplingFactor::F_1_1);
+/// image.write_with_encoder(JpegImageEncoder::new(encoder))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct JpegImageEncoder<W: JfifWrite> {
+    encoder: Encoder<W>,
+}
+
+impl<W: JfifWrite> JpegImageEncoder<W> {
+    /// Create a new image encoder with the settings of the given encoder
+    pub fn new(encoder: Encoder<W>) -> JpegImageEncoder<W> {
+        JpegImageEncoder { encoder }
+    }
+
+    /// Returns the wrapped encoder
+    pub fn encoder(&self) -> &Encoder<W> {
+        &self.encoder
+    }
+
+    /// Returns the wrapped encoder to change its settings
+    pub fn encoder_mut(&mut self) -> &mut Encoder<W> {
+        &mut self.encoder
+    }
+
+    /// Returns the wrapped encoder
+    pub fn into_inner(self) -> Encoder<W> {
+        self.encoder
+    }
+}
+
+impl<W: JfifWrite> From<Encoder<W>> for JpegImageEncoder<W> {
+    fn from(encoder: Encoder<W>) -> Self {
+        JpegImageEncoder::new(encoder)
+    }
+}
+
+impl<W: JfifWrite> image::ImageEncoder for JpegImageEncoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let color_type = match color_type {
+            ExtendedColorType::L8 => ColorType::Luma,
+            ExtendedColorType::La8 => ColorType::LumaA,
+            ExtendedColorType::L16 => {
+                // Samples of the image crate are in native byte order
+                let endianness = if cfg!(target_endian = "big") {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                };
+                self.encoder.set_luma16_endianness(endianness);
+                ColorType::Luma16
+            }
+            ExtendedColorType::Rgb8 => ColorType::Rgb,
+            ExtendedColorType::Rgba8 => ColorType::Rgba,
+            ExtendedColorType::Bgr8 => ColorType::Bgr,
+            ExtendedColorType::Bgra8 => ColorType::Bgra,
+            ExtendedColorType::Rgb32F => ColorType::RgbF32,
+            ExtendedColorType::Cmyk8 => ColorType::Cmyk,
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Jpeg.into(),
+                        UnsupportedErrorKind::Color(color_type),
+                    ),
+                ))
+            }
+        };
+
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(ImageError::Limits(LimitError::from_kind(
+                    LimitErrorKind::DimensionError,
+                )))
+            }
+        };
+
+        self.encoder
+            .encode(buf, width, height, color_type)
+            .map_err(to_image_error)
+    }
+
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> Result<(), UnsupportedError> {
+        self.encoder
+            .add_icc_profile(&icc_profile)
+            .map_err(to_unsupported_error)
+    }
+
+    fn set_exif_metadata(&mut self, exif: Vec<u8>) -> Result<(), UnsupportedError> {
+        self.encoder.add_exif(&exif).map_err(to_unsupported_error)
+    }
+}
+
+fn to_image_error(err: EncodingError) -> ImageError {
+    match err {
+        EncodingError::IoError(err) => ImageError::IoError(err),
+        err => ImageError::Encoding(ImageEncodingError::new(ImageFormat::Jpeg.into(), err)),
+    }
+}
+
+fn to_unsupported_error(err: EncodingError) -> UnsupportedError {
+    UnsupportedError::from_format_and_kind(
+        ImageFormat::Jpeg.into(),
+        UnsupportedErrorKind::GenericFeature(err.to_string()),
+    )
+}
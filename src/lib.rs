@@ -21,9 +21,42 @@
 //! encoder.encode(&data, 2, 2, ColorType::Rgb)?;
 //! # Ok(())
 //! # }
+//! ```
+//!
+//! ## Embedding images into a larger file
+//!
+//! The encoder always writes a self-contained image from SOI to EOI, even if the writer
+//! already contains other data. This allows to store multiple images in one file,
+//! e.g. with an offset table like in the multi picture format (MPF).
+//! ```
+//! # use jpeg_encoder::EncodingError;
+//! # pub fn main() -> Result<(), EncodingError> {
+//! use jpeg_encoder::{Encoder, ColorType};
+//!
+//! let images: [&[u8]; 2] = [&[255, 0, 0], &[0, 0, 255]];
+//!
+//! // Reserve space for a table with the offset and size of each image
+//! let mut file = vec![0u8; images.len() * 8];
+//!
+//! for (i, data) in images.iter().enumerate() {
+//!     let offset = file.len();
+//!     Encoder::new(&mut file, 90).encode(data, 1, 1, ColorType::Rgb)?;
+//!     let size = file.len() - offset;
+//!
+//!     file[i * 8..i * 8 + 4].copy_from_slice(&(offset as u32).to_be_bytes());
+//!     file[i * 8 + 4..i * 8 + 8].copy_from_slice(&(size as u32).to_be_bytes());
+//! }
+//!
+//! // The second image starts with its own SOI marker
+//! let offset = u32::from_be_bytes([file[8], file[9], file[10], file[11]]) as usize;
+//! assert_eq!(&file[offset..offset + 2], &[0xFF, 0xD8]);
+//! # Ok(())
+//! # }
+//! ```
 
 #![no_std]
 #![cfg_attr(not(feature = "simd"), forbid(unsafe_code))]
+#![cfg_attr(feature = "std_simd", feature(portable_simd))]
 
 #[cfg(feature = "std")]
 extern crate std;
@@ -31,32 +64,79 @@ extern crate std;
 extern crate alloc;
 extern crate core;
 
+#[cfg(feature = "async")]
+mod async_writer;
 #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
 mod avx2;
+#[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+#[clippy::msrv = "1.89"]
+mod avx512;
+mod config;
 mod encoder;
 mod error;
 mod fdct;
 mod huffman;
 mod image_buffer;
+#[cfg(feature = "image")]
+mod image_encoder;
 mod marker;
+mod md5;
 mod quantization;
+mod stats;
+#[cfg(feature = "std_simd")]
+mod std_simd;
+mod trellis;
 mod writer;
 
-pub use encoder::{ColorType, Encoder, JpegColorType, SamplingFactor};
+#[cfg(feature = "async")]
+pub use async_writer::{AsyncEncoder, AsyncJfifWrite};
+pub use config::EncoderConfig;
+pub use encoder::{
+    ColorType, DownsampleFilter, Encoder, Endianness, JpegColorType, SamplingFactor,
+    AUTO_SAMPLING_THRESHOLD,
+};
 pub use error::EncodingError;
-pub use image_buffer::{cmyk_to_ycck, rgb_to_ycbcr, ImageBuffer};
+pub use fdct::{forward_dct, forward_dct_batch};
+pub use huffman::HuffmanTable;
+pub use image_buffer::{
+    cmyk_to_ycck, rgb_to_ycbcr, rgb_to_ycbcr_bt709, ColorSpace, ComponentPlane, Dither,
+    FnImageBuffer, ImageBuffer, Nv12, PlanarYCbCr, Transfer, I420,
+};
+#[cfg(feature = "image")]
+pub use image_encoder::JpegImageEncoder;
 pub use quantization::QuantizationTableType;
-pub use writer::{Density, JfifWrite};
+pub use stats::{EncodingStats, ScanStats};
+pub use writer::{magnitude_category, CountingWriter, Density, JfifWrite, SliceWriter, ZIGZAG};
 
+#[cfg(all(
+    feature = "benchmark",
+    feature = "simd",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+pub use avx2::{fdct_avx2, quantize_block_avx2};
+#[cfg(all(
+    feature = "benchmark",
+    feature = "avx512",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+pub use avx512::fdct_avx512;
 #[cfg(feature = "benchmark")]
 pub use fdct::fdct;
-#[cfg(all(feature = "benchmark", feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
-pub use avx2::fdct_avx2;
+#[cfg(feature = "benchmark")]
+pub use quantization::{quantize_block, QuantizationTable};
+#[cfg(all(feature = "benchmark", feature = "std_simd"))]
+pub use std_simd::{fdct_std_simd, quantize_block_std_simd};
 
 #[cfg(test)]
 mod tests {
-    use crate::image_buffer::rgb_to_ycbcr;
-    use crate::{ColorType, Encoder, QuantizationTableType, SamplingFactor};
+    use crate::image_buffer::{cmyk_to_ycck, rgb_to_ycbcr, rgb_to_ycbcr_bt709};
+    use crate::writer::ZIGZAG;
+    use crate::{
+        ColorSpace, ColorType, ComponentPlane, CountingWriter, Density, Dither, DownsampleFilter,
+        Encoder, EncoderConfig, EncodingError, EncodingStats, Endianness, FnImageBuffer,
+        HuffmanTable, ImageBuffer, JpegColorType, Nv12, PlanarYCbCr, QuantizationTableType,
+        SamplingFactor, SliceWriter, Transfer, I420,
+    };
     use jpeg_decoder::{Decoder, ImageInfo, PixelFormat};
 
     use alloc::boxed::Box;
@@ -82,6 +162,12 @@ mod tests {
         (data, width as u16, height as u16)
     }
 
+    fn create_test_icc_profile(length: usize) -> Vec<u8> {
+        let mut icc: Vec<u8> = (0..length).map(|i| (i % 255) as u8).collect();
+        icc[0..4].copy_from_slice(&(length as u32).to_be_bytes());
+        icc
+    }
+
     fn create_test_img_rgba() -> (Vec<u8>, u16, u16) {
         // Ensure size which which ensures an odd MCU count per row to test chroma subsampling
         let width = 258;
@@ -144,6 +230,7 @@ mod tests {
         (decoder.decode().unwrap(), decoder.info().unwrap())
     }
 
+    #[allow(clippy::ptr_arg)]
     fn check_result(
         data: Vec<u8>,
         width: u16,
@@ -151,7 +238,7 @@ mod tests {
         result: &mut Vec<u8>,
         pixel_format: PixelFormat,
     ) {
-        let (img, info) = decode(&result);
+        let (img, info) = decode(result);
 
         assert_eq!(info.pixel_format, pixel_format);
         assert_eq!(info.width, width);
@@ -170,6 +257,20 @@ mod tests {
         }
     }
 
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<ColorType>();
+        assert_send_sync::<JpegColorType>();
+        assert_send_sync::<SamplingFactor>();
+        assert_send_sync::<QuantizationTableType>();
+        assert_send_sync::<Density>();
+        assert_send_sync::<EncodingError>();
+        assert_send_sync::<Encoder<Vec<u8>>>();
+        assert_send_sync::<Encoder<&mut Vec<u8>>>();
+    }
+
     #[test]
     fn test_gray_100() {
         let (data, width, height) = create_test_img_gray();
@@ -183,6 +284,263 @@ mod tests {
         check_result(data, width, height, &mut result, PixelFormat::L8);
     }
 
+    #[test]
+    fn test_gray_noise() {
+        // Noise produces large coefficients in all positions and many 0xFF bytes to stuff,
+        // the flat blocks in between produce empty AC data
+        let width = 131;
+        let height = 67;
+
+        let mut state = 0x1234_5678u32;
+        let mut data = Vec::with_capacity(usize::from(width) * usize::from(height));
+
+        for y in 0..height {
+            for x in 0..width {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+                if (x / 8 + y / 8) % 3 == 0 {
+                    data.push(200);
+                } else {
+                    data.push((state >> 16) as u8);
+                }
+            }
+        }
+
+        for progressive in [false, true] {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 100);
+            encoder.set_progressive(progressive);
+            encoder
+                .encode(&data, width, height, ColorType::Luma)
+                .unwrap();
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::L8);
+        }
+    }
+
+    #[test]
+    fn test_gray_alpha() {
+        let (data, width, height) = create_test_img_gray();
+
+        let mut luma_alpha = Vec::with_capacity(data.len() * 2);
+        for (i, &value) in data.iter().enumerate() {
+            luma_alpha.extend_from_slice(&[value, i as u8]);
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 100)
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .encode(&luma_alpha, width, height, ColorType::LumaA)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::L8);
+    }
+
+    #[test]
+    fn test_gray_band_edges() {
+        // The height isn't a multiple of 8, so the last band repeats the last row
+        let (data, width, _) = create_test_img_gray();
+        let height = 123;
+        let data = data[..usize::from(width) * usize::from(height)].to_vec();
+
+        for restart_interval in [0, 5] {
+            for trellis in [false, true] {
+                let encode = |optimize: bool, low_memory: bool| {
+                    let mut result = Vec::new();
+                    let mut encoder = Encoder::new(&mut result, 90);
+                    encoder.set_restart_interval(restart_interval);
+                    encoder.set_trellis_quantization(trellis);
+                    encoder.set_adaptive_quantization(trellis);
+                    encoder.set_optimized_huffman_tables(optimize);
+                    encoder.set_low_memory(low_memory);
+                    encoder.add_quality_region(20, 30, 50, 40, 10.0);
+                    encoder
+                        .encode(&data, width, height, ColorType::Luma)
+                        .unwrap();
+                    result
+                };
+
+                let mut result = encode(true, false);
+
+                // In low memory mode trellis quantization of the second pass uses the optimized tables
+                if !trellis {
+                    assert_eq!(result, encode(true, true));
+                }
+
+                check_result(data.clone(), width, height, &mut result, PixelFormat::L8);
+
+                let mut result = encode(false, false);
+                check_result(data.clone(), width, height, &mut result, PixelFormat::L8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_force_grayscale() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let luma: Vec<u8> = data
+            .chunks_exact(3)
+            .map(|rgb| rgb_to_ycbcr(rgb[0], rgb[1], rgb[2]).0)
+            .collect();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&luma, width, height, ColorType::Luma)
+            .unwrap();
+
+        let bgra: Vec<u8> = data
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[2], rgb[1], rgb[0], 255])
+            .collect();
+
+        for (data, color_type) in [(&data, ColorType::Rgb), (&bgra, ColorType::Bgra)] {
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 90)
+                .with_force_grayscale(true)
+                .encode(data, width, height, color_type)
+                .unwrap();
+
+            assert_eq!(result, expected);
+        }
+
+        // Only the luma quantization table is written
+        assert_eq!(expected.windows(2).filter(|w| w == b"\xFF\xDB").count(), 1);
+
+        check_result(luma, width, height, &mut expected, PixelFormat::L8);
+    }
+
+    #[test]
+    fn test_fn_image_buffer() {
+        let (data, width, height) = create_test_img_gray();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        let image = FnImageBuffer {
+            width,
+            height,
+            color_type: JpegColorType::Luma,
+            fill: |y: u16, buffers: &mut [Vec<u8>; 4]| {
+                let start = usize::from(y) * usize::from(width);
+                buffers[0].extend_from_slice(&data[start..start + usize::from(width)]);
+            },
+        };
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 90).encode_image(image).unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::L8);
+    }
+
+    #[test]
+    fn test_gray16() {
+        let (data, width, height) = create_test_img_gray();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 100)
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        let le: Vec<u8> = data
+            .iter()
+            .flat_map(|&v| (u16::from(v) * 257).to_le_bytes())
+            .collect();
+        let be: Vec<u8> = data
+            .iter()
+            .flat_map(|&v| (u16::from(v) * 257).to_be_bytes())
+            .collect();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .encode(&le, width, height, ColorType::Luma16)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .with_luma16_endianness(Endianness::Big)
+            .encode(&be, width, height, ColorType::Luma16)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let result = Encoder::new(Vec::new(), 100).encode(
+            &le[..le.len() - 1],
+            width,
+            height,
+            ColorType::Luma16,
+        );
+
+        assert!(matches!(result, Err(EncodingError::BadImageData { .. })));
+
+        check_result(data, width, height, &mut expected, PixelFormat::L8);
+    }
+
+    #[test]
+    fn test_luma16_dither() {
+        // A gradient with fractional 8 bit values
+        let width = 258;
+        let height = 64;
+
+        let mut data = Vec::with_capacity(usize::from(width) * usize::from(height));
+        let mut luma16 = Vec::with_capacity(data.capacity() * 2);
+
+        for _ in 0..height {
+            for x in 0..width {
+                let value = u32::from(x) * 65535 / u32::from(width - 1);
+                data.push(((value + 128) / 257) as u8);
+                luma16.extend_from_slice(&(value as u16).to_le_bytes());
+            }
+        }
+
+        let mut rounded = Vec::new();
+        Encoder::new(&mut rounded, 100)
+            .encode(&luma16, width, height, ColorType::Luma16)
+            .unwrap();
+
+        for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 100)
+                .with_input_dither(dither)
+                .encode(&luma16, width, height, ColorType::Luma16)
+                .unwrap();
+
+            assert_ne!(result, rounded);
+
+            // Rows of a region are dithered like the rows of the whole image
+            let mut region = Vec::new();
+            Encoder::new(&mut region, 100)
+                .with_input_dither(dither)
+                .encode_region(
+                    &luma16,
+                    width,
+                    usize::from(width) * 2,
+                    0,
+                    0,
+                    width,
+                    height,
+                    ColorType::Luma16,
+                )
+                .unwrap();
+
+            assert_eq!(region, result);
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::L8);
+        }
+    }
+
     #[test]
     fn test_rgb_100() {
         let (data, width, height) = create_test_img_rgb();
@@ -247,216 +605,2629 @@ mod tests {
     }
 
     #[test]
-    fn test_rgb_2_2() {
+    fn test_rgb_custom_q_table_zero_values() {
         let (data, width, height) = create_test_img_rgb();
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_2_2);
-        encoder
-            .encode(&data, width, height, ColorType::Rgb)
-            .unwrap();
+        let mut table = [0; 64];
+        table[63] = u16::MAX;
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
-    }
+        let table = QuantizationTableType::Custom(Box::new(table));
 
-    #[test]
-    fn test_rgb_2_1() {
-        let (data, width, height) = create_test_img_rgb();
+        for force_baseline in [true, false] {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 100);
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_2_1);
-        encoder
-            .encode(&data, width, height, ColorType::Rgb)
-            .unwrap();
+            encoder.set_quantization_tables(table.clone(), table.clone());
+            encoder.set_force_baseline(force_baseline);
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
     }
 
     #[test]
-    fn test_rgb_4_1() {
+    fn test_rgb_q_table_presets() {
         let (data, width, height) = create_test_img_rgb();
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_4_1);
-        encoder
+        let presets = [
+            QuantizationTableType::Default,
+            QuantizationTableType::Flat,
+            QuantizationTableType::CustomMsSsim,
+            QuantizationTableType::CustomPsnrHvs,
+            QuantizationTableType::ImageMagick,
+            QuantizationTableType::KleinSilversteinCarney,
+            QuantizationTableType::DentalXRays,
+            QuantizationTableType::VisualDetectionModel,
+            QuantizationTableType::ImprovedDetectionModel,
+        ];
+
+        let mut default = Vec::new();
+        Encoder::new(&mut default, 75)
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+        for preset in presets {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 75);
+            encoder.set_quantization_tables(preset.clone(), preset.clone());
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            if matches!(preset, QuantizationTableType::Default) {
+                assert_eq!(result, default);
+            } else {
+                assert_ne!(result, default, "Same output for {:?}", preset);
+            }
+
+            let (_, info) = decode(&result);
+            assert_eq!(info.width, width);
+            assert_eq!(info.height, height);
+        }
     }
 
     #[test]
-    fn test_rgb_1_1() {
+    fn test_quality_f32() {
         let (data, width, height) = create_test_img_rgb();
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_1_1);
-        encoder
+        let encode = |quality: f32| {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 75);
+            encoder.set_quality_f32(quality);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+            result
+        };
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 75)
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
-    }
+        assert_eq!(encode(75.0), expected);
 
-    #[test]
-    fn test_rgb_1_4() {
-        let (data, width, height) = create_test_img_rgb();
+        let mut result = encode(75.5);
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_1_4);
-        encoder
-            .encode(&data, width, height, ColorType::Rgb)
-            .unwrap();
+        assert_ne!(result, expected);
+        assert_ne!(result, encode(76.0));
 
         check_result(data, width, height, &mut result, PixelFormat::RGB24);
     }
 
     #[test]
-    fn test_rgb_progressive() {
+    fn test_quantization_table() {
         let (data, width, height) = create_test_img_rgb();
 
-        let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_2_1);
-        encoder.set_progressive(true);
+        let mut expected = Vec::new();
+        let encoder = Encoder::new(&mut expected, 50);
+
+        let luma = encoder.quantization_table(JpegColorType::Ycbcr, 0).unwrap();
+        let chroma = encoder.quantization_table(JpegColorType::Ycbcr, 1).unwrap();
+
+        // Quality 50 results in the unscaled Annex K tables
+        assert_eq!(&luma[0..8], &[16, 11, 10, 16, 24, 40, 51, 61]);
+        assert_eq!(&chroma[0..8], &[17, 18, 24, 47, 99, 99, 99, 99]);
 
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
-    }
-
-    #[test]
-    fn test_rgb_optimized() {
-        let (data, width, height) = create_test_img_rgb();
-
+        // Using the effective tables as custom tables gives the same result
         let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_2_2);
-        encoder.set_optimized_huffman_tables(true);
-
+        let mut encoder = Encoder::new(&mut result, 50);
+        encoder.set_quantization_tables(
+            QuantizationTableType::Custom(Box::new(luma)),
+            QuantizationTableType::Custom(Box::new(chroma)),
+        );
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+        assert_eq!(result, expected);
     }
 
     #[test]
-    fn test_rgb_optimized_progressive() {
+    fn test_force_baseline() {
         let (data, width, height) = create_test_img_rgb();
 
         let mut result = Vec::new();
         let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_2_1);
-        encoder.set_progressive(true);
-        encoder.set_optimized_huffman_tables(true);
+
+        let mut values = [300; 64];
+        values[0] = 16;
+
+        let table = QuantizationTableType::Custom(Box::new(values));
+
+        encoder.set_quantization_tables(table.clone(), table);
+        encoder.set_force_baseline(true);
 
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+        let dqt = result
+            .windows(2)
+            .position(|w| w == [0xFF, 0xDB])
+            .expect("Missing DQT segment");
+
+        // Skip marker, length and precision/destination
+        let values = &result[dqt + 5..dqt + 5 + 64];
+
+        assert_eq!(values[0], 16);
+        assert!(values[1..].iter().all(|&v| v == 255));
+
+        assert!(result.windows(2).any(|w| w == [0xFF, 0xC0]));
+
+        let (_, info) = decode(&result);
+
+        assert_eq!(info.width, width);
+        assert_eq!(info.height, height);
     }
 
     #[test]
-    fn test_cmyk() {
+    fn test_force_baseline_huffman_tables() {
         let (data, width, height) = create_test_img_cmyk();
 
+        // Optimized tables of CMYK images share the two baseline destinations
         let mut result = Vec::new();
-        let encoder = Encoder::new(&mut result, 100);
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_optimized_huffman_tables(true);
+        encoder.set_force_baseline(true);
         encoder
             .encode(&data, width, height, ColorType::Cmyk)
             .unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
-    }
+        assert!(result.windows(2).any(|w| w == [0xFF, 0xC0]));
+        assert!(!result.windows(2).any(|w| w == [0xFF, 0xC1]));
 
-    #[test]
-    fn test_ycck() {
-        let (data, width, height) = create_test_img_cmyk();
+        check_result(
+            data.clone(),
+            width,
+            height,
+            &mut result,
+            PixelFormat::CMYK32,
+        );
 
+        // Destinations of extended frames are rejected before anything is written
         let mut result = Vec::new();
-        let encoder = Encoder::new(&mut result, 100);
-        encoder
-            .encode(&data, width, height, ColorType::CmykAsYcck)
-            .unwrap();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_force_baseline(true);
+        encoder.set_component_huffman(3, 2, 2).unwrap();
 
-        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+        assert!(matches!(
+            encoder.encode(&data, width, height, ColorType::Cmyk),
+            Err(EncodingError::BaselineHuffmanDestination(2))
+        ));
+        assert!(result.is_empty());
     }
 
     #[test]
-    fn test_restart_interval() {
+    fn test_custom_huffman_tables() {
         let (data, width, height) = create_test_img_rgb();
 
+        // Use the luma tables for all components
+        let luma = (
+            HuffmanTable::default_luma_dc(),
+            HuffmanTable::default_luma_ac(),
+        );
+
         let mut result = Vec::new();
         let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_optimized_huffman_tables(true);
+        encoder.set_huffman_tables(luma.clone(), luma.clone());
 
-        encoder.set_restart_interval(32);
-        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+        assert!(!encoder.optimized_huffman_tables());
 
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        assert!(result
-            .as_slice()
-            .windows(DRI_DATA.len())
-            .any(|w| w == DRI_DATA));
+        // The luma DC table is written a second time with destination 1
+        let mut dht = vec![0xFF, 0xC4, 0x00, 0x1F, 0x01];
+        dht.extend_from_slice(luma.0.length());
+        dht.extend_from_slice(luma.0.values());
+
+        assert!(result.windows(dht.len()).any(|w| w == dht));
 
         check_result(data, width, height, &mut result, PixelFormat::RGB24);
     }
 
     #[test]
-    fn test_restart_interval_4_1() {
+    fn test_16_bit_q_table() {
         let (data, width, height) = create_test_img_rgb();
 
         let mut result = Vec::new();
         let mut encoder = Encoder::new(&mut result, 100);
-        encoder.set_sampling_factor(SamplingFactor::F_4_1);
 
-        encoder.set_restart_interval(32);
-        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+        let mut values = [300; 64];
+        values[0] = 16;
+
+        let table = QuantizationTableType::Custom(Box::new(values));
+
+        encoder.set_quantization_tables(table.clone(), table);
 
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        assert!(result
-            .as_slice()
-            .windows(DRI_DATA.len())
-            .any(|w| w == DRI_DATA));
+        let dqt = result
+            .windows(2)
+            .position(|w| w == [0xFF, 0xDB])
+            .expect("Missing DQT segment");
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+        // Length, precision/destination and the first two values in 16 bits
+        assert_eq!(&result[dqt + 2..dqt + 9], &[0, 131, 0x10, 0, 16, 1, 44]);
+
+        assert!(result.windows(2).any(|w| w == [0xFF, 0xC1]));
+
+        let (_, info) = decode(&result);
+
+        assert_eq!(info.width, width);
+        assert_eq!(info.height, height);
     }
 
     #[test]
-    fn test_restart_interval_progressive() {
+    fn test_raw_q_table() {
         let (data, width, height) = create_test_img_rgb();
 
+        let mut luma = [0u16; 64];
+        let mut chroma = [0u16; 64];
+        for i in 0..64 {
+            luma[i] = (i as u16 * 97) % 4000 + 1;
+            chroma[i] = i as u16 * 3 + 2;
+        }
+
         let mut result = Vec::new();
-        let mut encoder = Encoder::new(&mut result, 85);
-        encoder.set_progressive(true);
+        let mut encoder = Encoder::new(&mut result, 50);
+        encoder.set_quantization_tables(
+            QuantizationTableType::Raw(luma),
+            QuantizationTableType::Raw(chroma),
+        );
 
-        encoder.set_restart_interval(32);
-        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 0),
+            Some(luma)
+        );
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 1),
+            Some(chroma)
+        );
 
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
-        assert!(result
-            .as_slice()
+        let (_, info) = decode(&result);
+
+        assert_eq!(info.width, width);
+        assert_eq!(info.height, height);
+
+        let mut tables = Vec::new();
+        let mut pos = 2;
+        while result[pos + 1] != 0xDA {
+            let length = u16::from_be_bytes([result[pos + 2], result[pos + 3]]) as usize;
+
+            if result[pos + 1] == 0xDB {
+                let segment = &result[pos + 4..pos + 2 + length];
+                let mut table = [0u16; 64];
+
+                for (i, &z) in ZIGZAG.iter().enumerate() {
+                    table[z as usize] = if segment[0] >> 4 == 1 {
+                        u16::from_be_bytes([segment[1 + i * 2], segment[2 + i * 2]])
+                    } else {
+                        segment[1 + i] as u16
+                    };
+                }
+
+                tables.push(table);
+            }
+
+            pos += 2 + length;
+        }
+
+        assert_eq!(tables, [luma, chroma]);
+    }
+
+    #[test]
+    fn test_raw_q_table_large_divisor() {
+        let width = 16;
+        let height = 16;
+        let data = vec![255u8; width as usize * height as usize];
+
+        let mut table = [1u16; 64];
+        table[0] = 2500;
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_quantization_tables(
+            QuantizationTableType::Raw(table),
+            QuantizationTableType::Raw(table),
+        );
+
+        encoder
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        // A DC of 8128 is far below half of the divisor and must be quantized to zero
+        let (pixels, _) = decode(&result);
+        assert!(pixels.iter().all(|&p| p == 128), "{:?}", &pixels[..8]);
+    }
+
+    #[test]
+    fn test_rgb_2_2() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_2_1() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_4_1() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_4_1);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_1_1() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_1_1);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_1_4() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_1_4);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_progressive() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        encoder.set_progressive(true);
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_progressive_scans() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut encoder = Encoder::new(Vec::new(), 100);
+
+        for scans in [0, 1, 65, u8::MAX] {
+            assert!(matches!(
+                encoder.set_progressive_scans(scans),
+                Err(EncodingError::InvalidProgressiveScans(s)) if s == scans
+            ));
+            assert_eq!(encoder.progressive_scans(), None);
+        }
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .with_progressive_scans(2)
+            .unwrap()
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_component_sampling() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut encoder = Encoder::new(Vec::new(), 100);
+
+        assert!(matches!(
+            encoder.set_component_sampling(4, 1, 1),
+            Err(EncodingError::InvalidComponentIndex(4))
+        ));
+        assert!(matches!(
+            encoder.set_component_sampling(0, 3, 1),
+            Err(EncodingError::InvalidSamplingFactor {
+                horizontal: 3,
+                vertical: 1
+            })
+        ));
+        assert_eq!(encoder.component_sampling(0), None);
+
+        // Overriding the luma factors is the same as setting the sampling factor
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 100)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_1_1);
+        encoder.set_component_sampling(0, 2, 2).unwrap();
+        assert_eq!(encoder.component_sampling(0), Some((2, 2)));
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let factors: [&[(usize, u8, u8)]; 3] = [
+            // 4:4:0
+            &[(0, 1, 2)],
+            // Only Cr is subsampled
+            &[(0, 2, 2), (1, 2, 2)],
+            // Needs one scan per component
+            &[(0, 1, 4), (1, 1, 2)],
+        ];
+
+        for factors in factors {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 100);
+            encoder.set_sampling_factor(SamplingFactor::F_1_1);
+
+            for &(component, h, v) in factors {
+                encoder.set_component_sampling(component, h, v).unwrap();
+            }
+
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let sof = result
+                .windows(2)
+                .position(|w| w == b"\xFF\xC0")
+                .expect("Missing SOF segment");
+
+            for &(component, h, v) in factors {
+                assert_eq!(result[sof + 11 + component * 3], h << 4 | v);
+            }
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_component_quantization() {
+        // Returns the destination and first value of each DQT segment and the table
+        // destination of each component in the frame header
+        fn get_tables(result: &[u8]) -> (Vec<(u8, u8)>, Vec<u8>) {
+            let tables = result
+                .windows(2)
+                .enumerate()
+                .filter(|(_, w)| w == b"\xFF\xDB")
+                .map(|(i, _)| (result[i + 4], result[i + 5]))
+                .collect();
+
+            let sof = result
+                .windows(2)
+                .position(|w| w == b"\xFF\xC0")
+                .expect("Missing SOF segment");
+
+            let destinations = (0..usize::from(result[sof + 9]))
+                .map(|i| result[sof + 12 + i * 3])
+                .collect();
+
+            (tables, destinations)
+        }
+
+        let mut encoder = Encoder::new(Vec::new(), 100);
+
+        assert!(matches!(
+            encoder.set_component_quantization(4, QuantizationTableType::Flat),
+            Err(EncodingError::InvalidComponentIndex(4))
+        ));
+        assert!(encoder.component_quantization(0).is_none());
+
+        // Without their own tables C, M and Y use the chroma table and K the luma table
+        let encoder = Encoder::new(Vec::new(), 50);
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Cmyk, 0),
+            encoder.quantization_table(JpegColorType::Ycbcr, 1)
+        );
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Cmyk, 3),
+            encoder.quantization_table(JpegColorType::Ycbcr, 0)
+        );
+
+        // A distinct table for each CMYK channel
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        for component in 0..4 {
+            let table = QuantizationTableType::Raw([component as u16 + 1; 64]);
+            encoder
+                .set_component_quantization(component, table)
+                .unwrap();
+        }
+        assert!(matches!(
+            encoder.component_quantization(3),
+            Some(QuantizationTableType::Raw([4, ..]))
+        ));
+        encoder
+            .encode(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        let (tables, destinations) = get_tables(&result);
+        assert_eq!(tables, [(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert_eq!(destinations, [0, 1, 2, 3]);
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+
+        // Y and Cb keep the default tables
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder
+            .set_component_quantization(2, QuantizationTableType::Raw([2; 64]))
+            .unwrap();
+
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 1),
+            Some([1; 64])
+        );
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 2),
+            Some([2; 64])
+        );
+        assert_eq!(encoder.quantization_table(JpegColorType::Ycbcr, 3), None);
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let (tables, destinations) = get_tables(&result);
+        assert_eq!(tables, [(0, 1), (1, 1), (2, 2)]);
+        assert_eq!(destinations, [0, 1, 2]);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+
+        // Tables of components which don't exist aren't written
+        let (data, width, height) = create_test_img_gray();
+
+        let expected = Encoder::encode_to_vec(&data, width, height, ColorType::Luma, 90).unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 90);
+        encoder
+            .set_component_quantization(1, QuantizationTableType::Flat)
+            .unwrap();
+        encoder
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_dead_zone() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut encoder = Encoder::new(Vec::new(), 80);
+        assert_eq!(encoder.dead_zone(), 0.0);
+
+        encoder.set_dead_zone(1.0);
+        assert_eq!(encoder.dead_zone(), 0.5);
+
+        encoder.set_dead_zone(f32::NAN);
+        assert_eq!(encoder.dead_zone(), 0.0);
+
+        let expected = Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 80).unwrap();
+
+        let mut previous_len = usize::MAX;
+
+        for dead_zone in [0.0, 0.25, 0.5] {
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_dead_zone(dead_zone)
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            // No dead zone is the default
+            if dead_zone == 0.0 {
+                assert_eq!(result, expected);
+            }
+
+            assert!(result.len() < previous_len);
+            previous_len = result.len();
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_quantization() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let expected = Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 90).unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 90);
+        assert!(!encoder.adaptive_quantization());
+
+        encoder.set_adaptive_quantization(false);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+        assert_eq!(result, expected);
+
+        for progressive in [false, true] {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 90).with_adaptive_quantization(true);
+            assert!(encoder.adaptive_quantization());
+
+            if progressive {
+                encoder.set_progressive(true);
+            }
+
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            // The test image is smooth, so most blocks are quantized harder
+            assert!(result.len() < expected.len());
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_quality_region() {
+        let (data, width, height) = create_test_img_rgb();
+
+        // Sum of the absolute errors inside and outside of the region at 0,0 with 64x64 pixels
+        let errors = |jpeg: &[u8]| {
+            let decoded = Decoder::new(jpeg).decode().unwrap();
+
+            let mut errors = (0u64, 0u64);
+
+            for (i, (&a, &b)) in data.iter().zip(decoded.iter()).enumerate() {
+                let x = (i / 3) % usize::from(width);
+                let y = (i / 3) / usize::from(width);
+
+                let error = u64::from(a.abs_diff(b));
+
+                if x < 64 && y < 64 {
+                    errors.0 += error;
+                } else {
+                    errors.1 += error;
+                }
+            }
+
+            errors
+        };
+
+        let expected = Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 30).unwrap();
+
+        // A region without a delta doesn't change the output
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 30);
+        encoder.add_quality_region(0, 0, 64, 64, 0.0);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 30);
+        encoder.add_quality_region(0, 0, 64, 64, 60.0);
+        encoder.add_quality_region(32, 32, 16, 16, 10.0);
+
+        // The written tables are based on the highest quality
+        let high_quality = Encoder::new(Vec::new(), 90);
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 0),
+            high_quality.quantization_table(JpegColorType::Ycbcr, 0)
+        );
+
+        encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        encoder.clear_quality_regions();
+        let low_quality = Encoder::new(Vec::new(), 30);
+        assert_eq!(
+            encoder.quantization_table(JpegColorType::Ycbcr, 1),
+            low_quality.quantization_table(JpegColorType::Ycbcr, 1)
+        );
+
+        let (expected_inside, expected_outside) = errors(&expected);
+        let (inside, outside) = errors(&result);
+
+        assert!(inside * 2 < expected_inside);
+        assert!(outside <= expected_outside + expected_outside / 10);
+
+        let full_quality =
+            Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 90).unwrap();
+        assert!(result.len() > expected.len());
+        assert!(result.len() < full_quality.len());
+    }
+
+    #[test]
+    fn test_quantize_external_block() {
+        let mut encoder = Encoder::new(Vec::new(), 100);
+
+        let q_block = encoder.quantize_external_block(&[50; 64], true);
+        assert_eq!(q_block[0], 400);
+        assert!(q_block[1..].iter().all(|&v| v == 0));
+
+        let mut block = [0i16; 64];
+        for (i, v) in block.iter_mut().enumerate() {
+            *v = (i as i16 % 8) * 16 + (i as i16 / 8) * 4 - 128;
+        }
+
+        let mut coefficients = block;
+        crate::forward_dct(&mut coefficients);
+
+        encoder.set_quality_f32(75.0);
+        encoder.set_dead_zone(0.2);
+
+        for luma in [true, false] {
+            let mut table = crate::quantization::QuantizationTable::new_with_quality(
+                &QuantizationTableType::Default,
+                75,
+                luma,
+                false,
+            );
+            table.set_dead_zone(0.2);
+
+            let q_block = encoder.quantize_external_block(&block, luma);
+
+            for (i, &index) in ZIGZAG.iter().enumerate() {
+                let index = usize::from(index);
+                assert_eq!(q_block[i], table.quantize(coefficients[index], index));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_luma12() {
+        let (width, height) = (37u16, 21u16);
+
+        let data: Vec<u16> = (0..u32::from(width) * u32::from(height))
+            .map(|i| ((i % 37) * 100 + (i / 37) * 70) as u16 % 4096)
+            .collect();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 90);
+        encoder.set_restart_interval(3);
+        encoder.encode_luma12(&data, width, height).unwrap();
+
+        // SOF1 with a precision of 12 bits
+        let sof = [0xFF, 0xC1, 0, 11, 12, 0, 21, 0, 37, 1];
+        assert!(result.windows(sof.len()).any(|w| w == sof));
+        assert!(result.windows(2).all(|w| w != [0xFF, 0xC0]));
+        assert_eq!(result[result.len() - 2..], [0xFF, 0xD9]);
+
+        // jpeg-decoder only supports 8 bit samples for DCT based images
+        let mut decoder = Decoder::new(result.as_slice());
+        assert!(matches!(
+            decoder.read_info(),
+            Err(jpeg_decoder::Error::Unsupported(_))
+        ));
+
+        // Extreme values must not overflow the coefficients
+        let mut result = Vec::new();
+        let data: Vec<u16> = (0..64)
+            .map(|i| if (i + i / 8) % 2 == 0 { 4095 } else { 0 })
+            .collect();
+        Encoder::new(&mut result, 100)
+            .encode_luma12(&data, 8, 8)
+            .unwrap();
+
+        let mut data = data;
+        data[5] = 4096;
+        assert!(matches!(
+            Encoder::new(Vec::new(), 100).encode_luma12(&data, 8, 8),
+            Err(EncodingError::SampleOutOfRange(4096))
+        ));
+
+        assert!(matches!(
+            Encoder::new(Vec::new(), 100).encode_luma12(&data[..63], 8, 8),
+            Err(EncodingError::BadImageData {
+                length: 63,
+                required: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_estimated_size() {
+        let mut encoder = Encoder::new(Vec::new(), 75);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+
+        let rgb = encoder.estimated_size(640, 480, ColorType::Rgb);
+        let luma = encoder.estimated_size(640, 480, ColorType::Luma);
+        let cmyk = encoder.estimated_size(640, 480, ColorType::Cmyk);
+
+        assert!(luma < rgb && rgb < cmyk);
+        assert_eq!(rgb, encoder.estimated_size(640, 480, ColorType::Bgra));
+
+        // Typical photos need about 1.5 - 2.5 bits per pixel at quality 75
+        assert!(rgb > 640 * 480 * 3 / 16 && rgb < 640 * 480 * 5 / 16);
+
+        encoder.set_sampling_factor(SamplingFactor::F_1_1);
+        assert!(encoder.estimated_size(640, 480, ColorType::Rgb) > rgb);
+        assert_eq!(encoder.estimated_size(640, 480, ColorType::Luma), luma);
+
+        encoder.set_quality_f32(90.0);
+        let high_quality = encoder.estimated_size(640, 480, ColorType::Rgb);
+        encoder.set_quality_f32(30.0);
+        let low_quality = encoder.estimated_size(640, 480, ColorType::Rgb);
+        assert!(low_quality < high_quality);
+
+        encoder.add_comment(b"estimate").unwrap();
+        assert_eq!(
+            encoder.estimated_size(640, 480, ColorType::Rgb),
+            low_quality + 12
+        );
+
+        encoder.set_force_grayscale(true);
+        assert_eq!(
+            encoder.estimated_size(640, 480, ColorType::Rgba),
+            encoder.estimated_size(640, 480, ColorType::Luma)
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        assert_eq!(encoder.stats(), &EncodingStats::default());
+
+        encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+        let stats = encoder.stats().clone();
+
+        assert_eq!(stats.total_bytes, result.len() as u64);
+        assert_eq!(stats.quantization_table_bytes, 2 * 69);
+        assert!(stats.huffman_table_bytes > 0);
+
+        // All components are in one interleaved scan
+        assert_eq!(stats.scans.len(), 1);
+        assert_eq!(stats.scans[0].component, None);
+        assert_eq!(stats.scans[0].spectral_selection, (0, 63));
+        assert_eq!(stats.component_bytes(0), 0);
+
+        let overhead = result.len() as u64 - stats.scan_bytes();
+        assert!(overhead > stats.quantization_table_bytes + stats.huffman_table_bytes);
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_sampling_factor(SamplingFactor::F_4_1);
+        encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+        let stats = encoder.stats().clone();
+
+        assert_eq!(stats.total_bytes, result.len() as u64);
+        assert_eq!(stats.scans.len(), 3);
+
+        for (i, scan) in stats.scans.iter().enumerate() {
+            assert_eq!(scan.component, Some(i));
+            assert_eq!(stats.component_bytes(i), scan.bytes);
+        }
+
+        // Subsampled chroma components need less data
+        assert!(stats.component_bytes(0) > stats.component_bytes(1));
+        assert!(stats.component_bytes(0) > stats.component_bytes(2));
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_progressive_scans(4).unwrap();
+        encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+        let stats = encoder.stats().clone();
+
+        assert_eq!(stats.total_bytes, result.len() as u64);
+
+        // One DC scan and three AC scans per component
+        assert_eq!(stats.scans.len(), 12);
+        assert_eq!(stats.scans[0].spectral_selection, (0, 0));
+        assert_eq!(stats.scans[3].spectral_selection.0, 1);
+        assert_eq!(stats.scans[11].spectral_selection.1, 63);
+        assert_eq!(
+            stats.scan_bytes(),
+            (0..3).map(|i| stats.component_bytes(i)).sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn test_optimized_huffman_tables_interleaved() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let sampling_factors = [
+            SamplingFactor::F_1_1,
+            SamplingFactor::F_2_1,
+            SamplingFactor::F_2_2,
+        ];
+
+        for sampling_factor in sampling_factors {
+            for restart_interval in [0, 3] {
+                let encode = |optimize: bool, low_memory: bool| {
+                    let mut result = Vec::new();
+                    let mut encoder = Encoder::new(&mut result, 80);
+                    encoder.set_sampling_factor(sampling_factor);
+                    encoder.set_restart_interval(restart_interval);
+                    encoder.set_optimized_huffman_tables(optimize);
+                    encoder.set_low_memory(low_memory);
+                    encoder
+                        .encode_frame(&data, width, height, ColorType::Rgb)
+                        .unwrap();
+
+                    // All components are in one interleaved scan of a baseline image
+                    assert_eq!(encoder.stats().scans.len(), 1);
+                    assert_eq!(encoder.stats().scans[0].component, None);
+
+                    result
+                };
+
+                let mut result = encode(true, false);
+
+                assert!(result.windows(2).any(|marker| marker == [0xFF, 0xC0]));
+                assert!(result.len() < encode(false, false).len());
+                assert_eq!(result, encode(true, true));
+
+                check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+            }
+        }
+
+        // Four components use a distinct table each
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_optimized_huffman_tables(true);
+        encoder.set_restart_interval(2);
+        encoder
+            .encode_frame(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        assert_eq!(encoder.stats().scans.len(), 1);
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+    }
+
+    #[test]
+    fn test_encode_to_size() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let max_size = Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 60)
+            .unwrap()
+            .len();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+
+        let size = encoder
+            .encode_to_size(&data, width, height, ColorType::Rgb, max_size)
+            .unwrap();
+
+        let quality = encoder.quality();
+        assert!((60.0..100.0).contains(&quality));
+
+        assert_eq!(size, result.len());
+        assert!(size <= max_size);
+
+        // Output is the same as encoding with the chosen quality
+        let mut expected = Vec::new();
+        let mut encoder = Encoder::new(&mut expected, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+        encoder.set_quality_f32(quality);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+
+        assert!(matches!(
+            encoder.encode_to_size(&data, width, height, ColorType::Rgb, 100),
+            Err(EncodingError::TargetSizeUnreachable { max_bytes: 100, .. })
+        ));
+        assert_eq!(encoder.quality(), 80.0);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_new_no_subsampling() {
+        let (data, width, height) = create_test_img_rgb();
+
+        for quality in [20, 75, 95] {
+            let mut expected = Vec::new();
+            Encoder::new(&mut expected, quality)
+                .with_sampling_factor(SamplingFactor::F_1_1)
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let mut result = Vec::new();
+            let encoder = Encoder::new_no_subsampling(&mut result, quality);
+            assert_eq!(encoder.sampling_factor(), SamplingFactor::F_1_1);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            assert_eq!(result, expected);
+        }
+
+        // The default still subsamples below quality 90
+        assert_eq!(
+            Encoder::new(Vec::new(), 75).sampling_factor(),
+            SamplingFactor::F_2_2
+        );
+    }
+
+    #[test]
+    fn test_strict_compatibility() {
+        assert!(SamplingFactor::F_2_2.is_widely_supported());
+        assert!(SamplingFactor::R_4_4_0.is_widely_supported());
+        assert!(!SamplingFactor::F_4_1.is_widely_supported());
+        assert!(!SamplingFactor::R_4_2_1.is_widely_supported());
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 80)
+            .with_strict_compatibility(true)
+            .with_sampling_factor(SamplingFactor::F_4_1);
+        assert!(encoder.strict_compatibility());
+        assert!(matches!(
+            encoder.encode(&data, width, height, ColorType::Rgb),
+            Err(EncodingError::UnsupportedSamplingFactor {
+                horizontal: 4,
+                vertical: 1
+            })
+        ));
+        assert!(result.is_empty());
+
+        let mut encoder = Encoder::new(Vec::new(), 80).with_strict_compatibility(true);
+        encoder.set_component_sampling(1, 1, 4).unwrap();
+        assert!(matches!(
+            encoder.encode(&data, width, height, ColorType::Rgb),
+            Err(EncodingError::UnsupportedSamplingFactor {
+                horizontal: 1,
+                vertical: 4
+            })
+        ));
+
+        // Components which don't exist in the image don't matter
+        let mut encoder = Encoder::new(Vec::new(), 80).with_strict_compatibility(true);
+        encoder.set_sampling_factor(SamplingFactor::F_4_2);
+        let (gray, gray_width, gray_height) = create_test_img_gray();
+        encoder
+            .encode(&gray, gray_width, gray_height, ColorType::Luma)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_strict_compatibility(true)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_sampling_factor_auto() {
+        let (smooth, width, height) = create_test_img_rgb();
+
+        // Alternating red and blue columns
+        let mut sharp = Vec::with_capacity(smooth.len());
+        for _ in 0..height {
+            for x in 0..width {
+                if x % 2 == 0 {
+                    sharp.extend_from_slice(&[255, 0, 0]);
+                } else {
+                    sharp.extend_from_slice(&[0, 0, 255]);
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        assert!(!encoder.sampling_factor_auto());
+
+        encoder.set_sampling_factor_auto();
+        assert!(encoder.sampling_factor_auto());
+
+        encoder
+            .encode_frame(&smooth, width, height, ColorType::Rgb)
+            .unwrap();
+        assert_eq!(encoder.sampling_factor(), SamplingFactor::F_2_2);
+
+        encoder
+            .encode_frame(&sharp, width, height, ColorType::Rgb)
+            .unwrap();
+        assert_eq!(encoder.sampling_factor(), SamplingFactor::F_1_1);
+
+        // Other color types keep the current factor
+        let luma = &smooth[..usize::from(width) * usize::from(height)];
+        encoder
+            .encode_frame(luma, width, height, ColorType::Luma)
+            .unwrap();
+        assert_eq!(encoder.sampling_factor(), SamplingFactor::F_1_1);
+
+        // Setting a factor disables the automatic selection
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        assert!(!encoder.sampling_factor_auto());
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 50)
+            .with_sampling_factor_auto()
+            .encode(&sharp, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let sof = result
+            .windows(2)
+            .position(|w| w == b"\xFF\xC0")
+            .expect("Missing SOF segment");
+
+        assert_eq!(result[sof + 11], 0x11);
+
+        check_result(sharp, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_chroma_sampling() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut encoder = Encoder::new(Vec::new(), 100);
+        assert_eq!(encoder.component_sampling(1), None);
+
+        // Invalid factors don't change any component
+        assert!(matches!(
+            encoder.set_chroma_sampling((2, 1), (3, 1)),
+            Err(EncodingError::InvalidSamplingFactor {
+                horizontal: 3,
+                vertical: 1
+            })
+        ));
+        assert_eq!(encoder.component_sampling(1), None);
+
+        // Cb with 4:2:2 and Cr with 4:2:0
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 100)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .with_chroma_sampling((2, 1), (1, 1))
+            .unwrap();
+        assert_eq!(encoder.component_sampling(1), Some((2, 1)));
+        assert_eq!(encoder.component_sampling(2), Some((1, 1)));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let sof = result
+            .windows(2)
+            .position(|w| w == b"\xFF\xC0")
+            .expect("Missing SOF segment");
+
+        assert_eq!(result[sof + 11], 0x22);
+        assert_eq!(result[sof + 14], 0x21);
+        assert_eq!(result[sof + 17], 0x11);
+
+        // All components still fit into one interleaved scan
+        assert_eq!(result.windows(2).filter(|w| w == b"\xFF\xDA").count(), 1);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_downsample_filter() {
+        let (data, width, height) = create_test_img_rgb();
+
+        for filter in [
+            DownsampleFilter::Point,
+            DownsampleFilter::Average,
+            DownsampleFilter::AverageLinear,
+        ] {
+            for sampling_factor in [SamplingFactor::F_2_2, SamplingFactor::F_4_1] {
+                let mut result = Vec::new();
+                let mut encoder = Encoder::new(&mut result, 100);
+                encoder.set_sampling_factor(sampling_factor);
+                encoder.set_chroma_downsample_filter(filter);
+
+                encoder
+                    .encode(&data, width, height, ColorType::Rgb)
+                    .unwrap();
+
+                check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+            }
+        }
+
+        // Without subsampling the filter has no effect
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 100)
+            .with_sampling_factor(SamplingFactor::F_1_1)
+            .with_chroma_downsample_filter(DownsampleFilter::Point)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .with_sampling_factor(SamplingFactor::F_1_1)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_downsample_average_linear() {
+        use crate::image_buffer::srgb_to_linear;
+
+        // Red and green checker pattern
+        let (width, height) = (64u16, 64u16);
+
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let rgb = if (x + y) % 2 == 0 {
+                    [255, 0, 0]
+                } else {
+                    [0, 255, 0]
+                };
+                data.extend_from_slice(&rgb);
+            }
+        }
+
+        let mean_linear = |data: &[u8]| {
+            let mut sum = [0f32; 3];
+            for rgb in data.chunks_exact(3) {
+                for (sum, &value) in sum.iter_mut().zip(rgb) {
+                    *sum += srgb_to_linear(value);
+                }
+            }
+            sum.map(|sum| sum / (data.len() / 3) as f32)
+        };
+
+        let expected = mean_linear(&data);
+
+        for (color_space, full_range) in [
+            (ColorSpace::Bt601, true),
+            (ColorSpace::Bt709, true),
+            (ColorSpace::Bt601, false),
+        ] {
+            // Difference of the mean linear light intensity of all channels
+            let error = |filter| {
+                let mut result = Vec::new();
+                Encoder::new(&mut result, 100)
+                    .with_sampling_factor(SamplingFactor::F_2_2)
+                    .with_chroma_downsample_filter(filter)
+                    .with_color_space(color_space)
+                    .with_full_range(full_range)
+                    .encode(&data, width, height, ColorType::Rgb)
+                    .unwrap();
+
+                let (decoded, _) = decode(&result);
+
+                mean_linear(&decoded)
+                    .iter()
+                    .zip(expected)
+                    .map(|(mean, expected)| (mean - expected).abs())
+                    .sum::<f32>()
+            };
+
+            let average = error(DownsampleFilter::Average);
+            let linear = error(DownsampleFilter::AverageLinear);
+
+            assert!(linear < average * 0.95, "{} vs {}", linear, average);
+        }
+    }
+
+    #[test]
+    fn test_rgba_alpha_background() {
+        let (data, width, height) = create_test_img_rgba();
+
+        let background = [255, 128, 0];
+
+        let mut rgb = Vec::with_capacity(data.len() / 4 * 3);
+        for pixel in data.chunks_exact(4) {
+            let alpha = u32::from(pixel[3]);
+
+            for (&foreground, &background) in pixel[..3].iter().zip(background.iter()) {
+                let value = u32::from(foreground) * alpha + u32::from(background) * (255 - alpha);
+                rgb.push((value / 255) as u8);
+            }
+        }
+
+        let mut bgra = data.clone();
+        for pixel in bgra.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .encode(&rgb, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_alpha_background(Some(background))
+            .encode(&data, width, height, ColorType::Rgba)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_alpha_background(Some(background))
+            .encode(&bgra, width, height, ColorType::Bgra)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(rgb, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_argb_abgr() {
+        let (data, width, height) = create_test_img_rgba();
+
+        let mut argb = Vec::with_capacity(data.len());
+        let mut abgr = Vec::with_capacity(data.len());
+
+        for pixel in data.chunks_exact(4) {
+            argb.extend_from_slice(&[pixel[3], pixel[0], pixel[1], pixel[2]]);
+            abgr.extend_from_slice(&[pixel[3], pixel[2], pixel[1], pixel[0]]);
+        }
+
+        for background in [None, Some([255, 255, 255])] {
+            for color_space in [ColorSpace::Bt601, ColorSpace::Bt709] {
+                let mut expected = Vec::new();
+                Encoder::new(&mut expected, 80)
+                    .with_alpha_background(background)
+                    .with_color_space(color_space)
+                    .encode(&data, width, height, ColorType::Rgba)
+                    .unwrap();
+
+                for (data, color_type) in [(&argb, ColorType::Argb), (&abgr, ColorType::Abgr)] {
+                    let mut result = Vec::new();
+                    Encoder::new(&mut result, 80)
+                        .with_alpha_background(background)
+                        .with_color_space(color_space)
+                        .encode(data, width, height, color_type)
+                        .unwrap();
+
+                    assert_eq!(result, expected);
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .encode(&argb, width, height, ColorType::Argb)
+            .unwrap();
+
+        let (data, width, height) = create_test_img_rgb();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_bt709() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut ycbcr = Vec::with_capacity(data.len());
+        for pixel in data.chunks_exact(3) {
+            let (y, cb, cr) = rgb_to_ycbcr_bt709(pixel[0], pixel[1], pixel[2]);
+            ycbcr.extend_from_slice(&[y, cb, cr]);
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&ycbcr, width, height, ColorType::Ycbcr)
+            .unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 90);
+        encoder.set_color_space(ColorSpace::Bt709);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        // The default color space doesn't change the output of the YCbCr input
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 90)
+            .with_color_space(ColorSpace::Bt709)
+            .encode(&ycbcr, width, height, ColorType::Ycbcr)
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_rgb_full_range() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_full_range(true);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_limited_range() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_full_range(false);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        // The decoder expects full range values, so the colors are compressed into 16-235
+        let expected = data
+            .iter()
+            .map(|&v| (16 + u32::from(v) * 219 / 255) as u8)
+            .collect();
+
+        check_result(expected, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_gray_limited_range() {
+        let (data, width, height) = create_test_img_gray();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .with_full_range(false)
+            .encode(&data, width, height, ColorType::Luma)
+            .unwrap();
+
+        let (img, _) = decode(&result);
+
+        assert!(img.iter().all(|&v| (16..=235).contains(&v)));
+
+        let expected = data
+            .iter()
+            .map(|&v| (16 + u32::from(v) * 219 / 255) as u8)
+            .collect();
+
+        check_result(expected, width, height, &mut result, PixelFormat::L8);
+    }
+
+    #[test]
+    fn test_rgb565() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut rgb565 = Vec::with_capacity(data.len() / 3 * 2);
+        let mut rgb = Vec::with_capacity(data.len());
+
+        for pixel in data.chunks_exact(3) {
+            let r = u16::from(pixel[0] >> 3);
+            let g = u16::from(pixel[1] >> 2);
+            let b = u16::from(pixel[2] >> 3);
+
+            rgb565.extend_from_slice(&(r << 11 | g << 5 | b).to_le_bytes());
+
+            rgb.push((r << 3 | r >> 2) as u8);
+            rgb.push((g << 2 | g >> 4) as u8);
+            rgb.push((b << 3 | b >> 2) as u8);
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&rgb, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 90)
+            .encode(&rgb565, width, height, ColorType::Rgb565)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_f32() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let floats: Vec<u8> = data
+            .iter()
+            .flat_map(|&v| (f32::from(v) / 255.0).to_ne_bytes())
+            .collect();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 90)
+            .encode(&floats, width, height, ColorType::RgbF32)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        // Out of range and non finite values are clamped
+        let mut floats: Vec<f32> = data.iter().map(|&v| f32::from(v) / 255.0).collect();
+        let mut clamped = data.clone();
+
+        for (i, (float, value)) in floats.iter_mut().zip(clamped.iter_mut()).enumerate() {
+            match i % 5 {
+                0 => {
+                    *float = f32::NAN;
+                    *value = 0;
+                }
+                1 => {
+                    *float = 1.5;
+                    *value = 255;
+                }
+                2 => {
+                    *float = -0.5;
+                    *value = 0;
+                }
+                _ => {}
+            }
+        }
+
+        let floats: Vec<u8> = floats.iter().flat_map(|v| v.to_ne_bytes()).collect();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 90)
+            .encode(&clamped, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 90)
+            .with_input_transfer(Transfer::Srgb)
+            .encode(&floats, width, height, ColorType::RgbF32)
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_rgb_f32_linear() {
+        let (data, width, height) = create_test_img_rgb();
+
+        // Approximation of the inverse sRGB transfer function
+        let floats: Vec<u8> = data
+            .iter()
+            .flat_map(|&v| {
+                let v = f32::from(v) / 255.0;
+                let linear = if v <= 0.04045 {
+                    v / 12.92
+                } else {
+                    let v = (v + 0.055) / 1.055;
+                    v * v * (0.5 * v + 0.5)
+                };
+                linear.to_ne_bytes()
+            })
+            .collect();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 100)
+            .with_input_transfer(Transfer::Linear)
+            .encode(&floats, width, height, ColorType::RgbF32)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_optimized() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+        encoder.set_optimized_huffman_tables(true);
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_optimized_progressive() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        encoder.set_progressive(true);
+        encoder.set_optimized_huffman_tables(true);
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_trellis_quantization() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut expected = Vec::new();
+        let encoder = Encoder::new(&mut expected, 80);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_trellis_quantization(true);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert!(result.len() < expected.len());
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_rgb_trellis_quantization_optimized_progressive() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_trellis_quantization(true);
+        encoder.set_progressive(true);
+        encoder.set_optimized_huffman_tables(true);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_rayon_thread_count() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let encode = |num_threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+
+            pool.install(|| {
+                let mut result = Vec::new();
+                let mut encoder = Encoder::new(&mut result, 80);
+                encoder.set_progressive(true);
+                encoder.set_sampling_factor(SamplingFactor::F_2_2);
+                encoder
+                    .encode(&data, width, height, ColorType::Rgb)
+                    .unwrap();
+                result
+            })
+        };
+
+        let mut result = encode(1);
+
+        assert_eq!(result, encode(4));
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_image_encoder() {
+        use crate::JpegImageEncoder;
+        use image::ImageEncoder;
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let image =
+            image::RgbImage::from_raw(u32::from(width), u32::from(height), data.clone()).unwrap();
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 80).with_sampling_factor(SamplingFactor::F_1_1);
+        image
+            .write_with_encoder(JpegImageEncoder::new(encoder))
+            .unwrap();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .with_sampling_factor(SamplingFactor::F_1_1)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        let (data, width, height) = create_test_img_gray();
+        let data16: Vec<u8> = data
+            .iter()
+            .flat_map(|&v| (u16::from(v) * 257).to_ne_bytes())
+            .collect();
+
+        let mut result = Vec::new();
+        JpegImageEncoder::new(Encoder::new(&mut result, 80))
+            .write_image(
+                &data16,
+                u32::from(width),
+                u32::from(height),
+                image::ExtendedColorType::L16,
+            )
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::L8);
+
+        let result = JpegImageEncoder::new(Encoder::new(Vec::new(), 80)).write_image(
+            &[0; 6],
+            1,
+            1,
+            image::ExtendedColorType::Rgb16,
+        );
+        assert!(matches!(result, Err(image::ImageError::Unsupported(_))));
+
+        let result = JpegImageEncoder::new(Encoder::new(Vec::new(), 80)).write_image(
+            &[],
+            70000,
+            1,
+            image::ExtendedColorType::L8,
+        );
+        assert!(matches!(result, Err(image::ImageError::Limits(_))));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_encoder() {
+        use crate::AsyncEncoder;
+        use core::future::Future;
+        use core::pin::pin;
+        use core::task::{Context, Poll};
+        use std::sync::Arc;
+        use std::task::{Wake, Waker};
+
+        struct NoopWaker;
+
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut encoder = AsyncEncoder::new(Vec::new(), 80);
+        encoder.encoder_mut().set_progressive(true);
+
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut context = Context::from_waker(&waker);
+
+        let output = {
+            let mut future = pin!(async {
+                for _ in 0..2 {
+                    encoder
+                        .encode(&data, width, height, ColorType::Rgb)
+                        .await
+                        .unwrap();
+                }
+                encoder.into_inner()
+            });
+
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                    break output;
+                }
+            }
+        };
+
+        let mut expected = Vec::new();
+        let mut sync_encoder = Encoder::new(&mut expected, 80);
+        sync_encoder.set_progressive(true);
+        sync_encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(output.len(), expected.len() * 2);
+        assert_eq!(&output[..expected.len()], &expected[..]);
+        assert_eq!(&output[expected.len()..], &expected[..]);
+
+        check_result(data, width, height, &mut expected, PixelFormat::RGB24);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "tokio")))]
+    #[test]
+    fn test_async_encoder_rows() {
+        use crate::{AsyncEncoder, AsyncJfifWrite};
+        use core::future::Future;
+        use core::pin::pin;
+        use core::task::{Context, Poll};
+        use std::sync::Arc;
+        use std::task::{Wake, Waker};
+
+        struct NoopWaker;
+
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        #[derive(Default)]
+        struct ChunkWriter(Vec<Vec<u8>>);
+
+        impl AsyncJfifWrite for ChunkWriter {
+            async fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+                self.0.push(buf.to_vec());
+                Ok(())
+            }
+        }
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut context = Context::from_waker(&waker);
+
+        for progressive in [false, true] {
+            let mut encoder = AsyncEncoder::new(ChunkWriter::default(), 80);
+            encoder.encoder_mut().set_progressive(progressive);
+
+            let chunks = {
+                let mut future = pin!(async {
+                    encoder
+                        .encode(&data, width, height, ColorType::Rgb)
+                        .await
+                        .unwrap();
+                    encoder.into_inner().0
+                });
+
+                loop {
+                    if let Poll::Ready(chunks) = future.as_mut().poll(&mut context) {
+                        break chunks;
+                    }
+                }
+            };
+
+            let mut expected = Vec::new();
+            let mut sync_encoder = Encoder::new(&mut expected, 80);
+            sync_encoder.set_progressive(progressive);
+            sync_encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            assert_eq!(chunks.concat(), expected);
+
+            if progressive {
+                assert_eq!(chunks.len(), 1);
+            } else {
+                // The headers and one chunk for each of the 16 pixel high MCU rows
+                assert_eq!(chunks.len(), 1 + usize::from(height) / 16);
+                assert!(chunks.iter().all(|chunk| chunk.len() < expected.len() / 4));
+            }
+        }
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let encoder = Encoder::new(vec![1, 2, 3], 80);
+        assert_eq!(encoder.into_inner(), vec![1, 2, 3]);
+
+        let mut encoder = Encoder::new(Vec::new(), 80);
+        encoder
+            .encode_frame(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = encoder.into_inner();
+        let length = result.len();
+        result.extend_from_slice(b"trailer");
+
+        assert_eq!(&result[length - 2..length], &[0xFF, 0xD9]);
+        assert_eq!(&result[length..], b"trailer");
+
+        result.truncate(length);
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let config = EncoderConfig {
+            quality: 85,
+            sampling_factor: Some(SamplingFactor::F_2_1),
+            progressive_scans: Some(6),
+            optimized_huffman_tables: true,
+            restart_interval: Some(3),
+            density: Density::Inch { x: 300, y: 300 },
+            luma_quantization_table: QuantizationTableType::ImageMagick,
+            chroma_quantization_table: QuantizationTableType::Flat,
+        };
+
+        let mut result = Vec::new();
+        Encoder::from_config(&mut result, &config)
+            .unwrap()
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut expected = Vec::new();
+        let mut encoder = Encoder::new(&mut expected, 85);
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        encoder.set_progressive_scans(6).unwrap();
+        encoder.set_optimized_huffman_tables(true);
+        encoder.set_restart_interval(3);
+        encoder.set_density(Density::Inch { x: 300, y: 300 });
+        encoder.set_quantization_tables(
+            QuantizationTableType::ImageMagick,
+            QuantizationTableType::Flat,
+        );
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+
+        // The default config is the same as a new encoder with quality 75
+        let encoder = Encoder::from_config(Vec::new(), &EncoderConfig::default()).unwrap();
+        assert_eq!(encoder.quality(), 75.0);
+        assert_eq!(encoder.sampling_factor(), SamplingFactor::F_2_2);
+        assert_eq!(encoder.progressive_scans(), None);
+        assert_eq!(encoder.restart_interval(), None);
+
+        let config = EncoderConfig {
+            progressive_scans: Some(1),
+            ..EncoderConfig::default()
+        };
+        assert!(matches!(
+            Encoder::from_config(Vec::new(), &config),
+            Err(EncodingError::InvalidProgressiveScans(1))
+        ));
+    }
+
+    #[test]
+    fn test_q_tables_independent_of_sampling_factor() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let dqt_segments = |sampling_factor| {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 70);
+            encoder.set_sampling_factor(sampling_factor);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let mut segments = Vec::new();
+            let mut pos = 2;
+
+            // Walk the marker segments up to the start of scan
+            while result[pos + 1] != 0xDA {
+                let len = usize::from(result[pos + 2]) << 8 | usize::from(result[pos + 3]);
+
+                if result[pos + 1] == 0xDB {
+                    segments.push(result[pos..pos + 2 + len].to_vec());
+                }
+
+                pos += 2 + len;
+            }
+
+            segments
+        };
+
+        let subsampled = dqt_segments(SamplingFactor::R_4_2_0);
+        let full = dqt_segments(SamplingFactor::R_4_4_4);
+
+        assert_eq!(subsampled.len(), 2);
+        assert_eq!(subsampled, full);
+    }
+
+    #[test]
+    fn test_cmyk() {
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 100);
+        encoder
+            .encode(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+    }
+
+    #[test]
+    fn test_ycck() {
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 100);
+        encoder
+            .encode(&data, width, height, ColorType::CmykAsYcck)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+    }
+
+    #[test]
+    fn test_cmyk_optimized() {
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut default = Vec::new();
+        let encoder = Encoder::new(&mut default, 100);
+        encoder
+            .encode(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_optimized_huffman_tables(true);
+        encoder
+            .encode(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        assert!(result.len() <= default.len());
+
+        // Four huffman tables need an extended sequential frame
+        assert!(result.windows(2).any(|w| w == [0xFF, 0xC1]));
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+    }
+
+    #[test]
+    fn test_component_huffman() {
+        // Returns the class and destination of each DHT segment and the
+        // table selectors of the components in all scan headers
+        fn get_tables(result: &[u8]) -> (Vec<(u8, u8)>, Vec<u8>) {
+            let positions = |marker: [u8; 2]| {
+                result
+                    .windows(2)
+                    .enumerate()
+                    .filter(move |(_, w)| *w == marker)
+                    .map(|(i, _)| i)
+            };
+
+            let tables = positions([0xFF, 0xC4])
+                .map(|i| (result[i + 4] >> 4, result[i + 4] & 0xF))
+                .collect();
+
+            let selectors = positions([0xFF, 0xDA])
+                .flat_map(|sos| {
+                    (0..usize::from(result[sos + 4])).map(move |i| result[sos + 6 + i * 2])
+                })
+                .collect();
+
+            (tables, selectors)
+        }
+
+        let mut encoder = Encoder::new(Vec::new(), 100);
+
+        assert!(matches!(
+            encoder.set_component_huffman(4, 0, 0),
+            Err(EncodingError::InvalidComponentIndex(4))
+        ));
+        assert!(matches!(
+            encoder.set_component_huffman(0, 0, 4),
+            Err(EncodingError::InvalidHuffmanDestination(4))
+        ));
+        assert_eq!(encoder.component_huffman(0), None);
+
+        // One optimized table shared by all CMYK components
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_optimized_huffman_tables(true);
+        for component in 0..4 {
+            encoder.set_component_huffman(component, 0, 0).unwrap();
+        }
+        assert_eq!(encoder.component_huffman(3), Some((0, 0)));
+        encoder
+            .encode(&data, width, height, ColorType::Cmyk)
+            .unwrap();
+
+        let (tables, selectors) = get_tables(&result);
+        assert_eq!(tables, [(0, 0), (1, 0)]);
+        assert_eq!(selectors, [0x00; 4]);
+
+        // Two tables per class are allowed in baseline frames
+        assert!(result.windows(2).any(|w| w == [0xFF, 0xC0]));
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+
+        // Cb uses separate destinations for DC and AC
+        let (data, width, height) = create_test_img_rgb();
+
+        for optimized in [false, true] {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 100);
+            encoder.set_optimized_huffman_tables(optimized);
+            encoder.set_component_huffman(1, 2, 3).unwrap();
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let (tables, selectors) = get_tables(&result);
+            assert_eq!(tables, [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 3)]);
+            assert_eq!(selectors, [0x00, 0x23, 0x11]);
+
+            assert!(result.windows(2).any(|w| w == [0xFF, 0xC1]));
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_low_memory() {
+        let (rgb, width, height) = create_test_img_rgb();
+        let (cmyk, cmyk_width, cmyk_height) = create_test_img_cmyk();
+
+        let encode = |low_memory: bool,
+                      sampling_factor: SamplingFactor,
+                      optimized: bool,
+                      restart_interval: u16,
+                      color_type: ColorType| {
+            let (data, width, height) = match color_type {
+                ColorType::Cmyk => (&cmyk, cmyk_width, cmyk_height),
+                _ => (&rgb, width, height),
+            };
+
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_low_memory(low_memory)
+                .with_sampling_factor(sampling_factor)
+                .with_optimized_huffman_tables(optimized)
+                .with_restart_interval(restart_interval)
+                .encode(data, width, height, color_type)
+                .unwrap();
+            result
+        };
+
+        for (sampling_factor, optimized, restart_interval, color_type) in [
+            (SamplingFactor::F_2_2, true, 0, ColorType::Rgb),
+            (SamplingFactor::F_2_2, true, 5, ColorType::Rgb),
+            (SamplingFactor::F_4_1, false, 0, ColorType::Rgb),
+            (SamplingFactor::F_1_4, true, 7, ColorType::Rgb),
+            (SamplingFactor::F_1_1, true, 0, ColorType::Luma),
+            (SamplingFactor::F_2_1, true, 0, ColorType::Cmyk),
+        ] {
+            let mut result = encode(
+                true,
+                sampling_factor,
+                optimized,
+                restart_interval,
+                color_type,
+            );
+
+            assert_eq!(
+                result,
+                encode(
+                    false,
+                    sampling_factor,
+                    optimized,
+                    restart_interval,
+                    color_type
+                )
+            );
+
+            if matches!(color_type, ColorType::Rgb) {
+                check_result(rgb.clone(), width, height, &mut result, PixelFormat::RGB24);
+            }
+        }
+
+        // Components read at their native resolution
+        let [luma, cb, cr] = create_planes(&rgb, width, height, 2, 2);
+
+        let i420 = I420 {
+            y: &luma,
+            cb: &cb,
+            cr: &cr,
+            width,
+            height,
+            y_stride: usize::from(width),
+            chroma_stride: (usize::from(width) + 1) / 2,
+        };
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .with_optimized_huffman_tables(true)
+            .encode_image(i420)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .with_optimized_huffman_tables(true)
+            .with_low_memory(true)
+            .encode_image(i420)
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        let (rgb, width, height) = create_test_img_rgb();
+        let (gray, gray_width, gray_height) = create_test_img_gray();
+        let (cmyk, cmyk_width, cmyk_height) = create_test_img_cmyk();
+
+        let frames = [
+            (&rgb, width, height, ColorType::Rgb),
+            (&gray, gray_width, gray_height, ColorType::Luma),
+            (&rgb, width, height, ColorType::Rgb),
+            (&cmyk, cmyk_width, cmyk_height, ColorType::Cmyk),
+            (&rgb, width, height, ColorType::Rgb),
+        ];
+
+        for (progressive, optimized, low_memory) in [
+            (false, false, false),
+            (true, false, false),
+            (false, true, true),
+            (true, true, false),
+        ] {
+            let mut expected = Vec::new();
+            for &(data, width, height, color_type) in &frames {
+                Encoder::new(&mut expected, 80)
+                    .with_progressive(progressive)
+                    .with_optimized_huffman_tables(optimized)
+                    .with_low_memory(low_memory)
+                    .encode(data, width, height, color_type)
+                    .unwrap();
+            }
+
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 80)
+                .with_progressive(progressive)
+                .with_optimized_huffman_tables(optimized)
+                .with_low_memory(low_memory);
+            for &(data, width, height, color_type) in &frames {
+                encoder
+                    .encode_frame(data, width, height, color_type)
+                    .unwrap();
+            }
+            drop(encoder);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_slice_writer() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut buffer = vec![0u8; expected.len()];
+        let mut writer = SliceWriter::new(&mut buffer);
+        Encoder::new(&mut writer, 80)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(writer.bytes_written(), expected.len());
+        assert_eq!(buffer, expected);
+
+        let mut buffer = vec![0u8; expected.len() - 1];
+        let mut writer = SliceWriter::new(&mut buffer);
+        let res = Encoder::new(&mut writer, 80).encode(&data, width, height, ColorType::Rgb);
+
+        assert!(matches!(res, Err(EncodingError::BufferFull)));
+        assert!(writer.bytes_written() < expected.len());
+    }
+
+    #[test]
+    fn test_counting_writer() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut writer = CountingWriter::new(Vec::new());
+        Encoder::new(&mut writer, 80)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let count = writer.bytes_written();
+        let mut result = writer.into_inner();
+
+        assert_eq!(count, result.len() as u64);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_ycck_round_trip() {
+        let (data, width, height) = create_test_img_cmyk();
+
+        let mut ycck = Vec::with_capacity(data.len());
+        for pixel in data.chunks_exact(4) {
+            let (y, cb, cr, k) = cmyk_to_ycck(pixel[0], pixel[1], pixel[2], pixel[3]);
+            ycck.extend_from_slice(&[y, cb, cr, k]);
+        }
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 100);
+        encoder
+            .encode(&ycck, width, height, ColorType::Ycck)
+            .unwrap();
+
+        let mut expected = Vec::new();
+        let encoder = Encoder::new(&mut expected, 100);
+        encoder
+            .encode(&data, width, height, ColorType::CmykAsYcck)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::CMYK32);
+    }
+
+    #[test]
+    fn test_restart_interval() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        assert_eq!(encoder.restart_interval(), None);
+
+        encoder.set_restart_interval(0);
+        assert_eq!(encoder.restart_interval(), None);
+
+        encoder.set_restart_interval(32);
+        assert_eq!(encoder.restart_interval(), Some(32));
+
+        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert!(result
+            .as_slice()
             .windows(DRI_DATA.len())
             .any(|w| w == DRI_DATA));
 
-        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_restart_interval_4_1() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_sampling_factor(SamplingFactor::F_4_1);
+
+        encoder.set_restart_interval(32);
+        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert!(result
+            .as_slice()
+            .windows(DRI_DATA.len())
+            .any(|w| w == DRI_DATA));
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_restart_interval_progressive() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 85);
+        encoder.set_progressive(true);
+
+        encoder.set_restart_interval(32);
+        const DRI_DATA: &[u8; 6] = b"\xFF\xDD\0\x04\0\x20";
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert!(result
+            .as_slice()
+            .windows(DRI_DATA.len())
+            .any(|w| w == DRI_DATA));
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_deferred_height() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let encode = |deferred_height: bool, progressive: bool, optimize: bool| {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 90);
+
+            encoder.set_deferred_height(deferred_height);
+            encoder.set_progressive(progressive);
+            encoder.set_optimized_huffman_tables(optimize);
+            encoder.set_restart_interval(8);
+
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            result
+        };
+
+        let dnl = [0xFF, 0xDC, 0, 4, (height >> 8) as u8, height as u8];
+
+        for (progressive, optimize) in [(false, false), (false, true), (true, false)] {
+            let expected = encode(false, progressive, optimize);
+            let mut result = encode(true, progressive, optimize);
+
+            let find = |data: &[u8], segment: &[u8]| {
+                data.windows(segment.len()).position(|w| w == segment)
+            };
+
+            let sof_pos = result
+                .windows(2)
+                .position(|w| w[0] == 0xFF && matches!(w[1], 0xC0..=0xC2))
+                .expect("Missing SOF segment");
+            let first_scan = find(&result, &[0xFF, 0xDA]).expect("Missing SOS segment");
+            let dnl_pos = find(&result, &dnl).expect("Missing DNL segment");
+
+            assert_eq!(&result[sof_pos + 5..sof_pos + 7], &[0, 0]);
+            assert!(first_scan < dnl_pos);
+
+            // The DNL segment must directly follow the first scan
+            if let Some(second_scan) = find(&result[first_scan + 2..], &[0xFF, 0xDA]) {
+                assert!(dnl_pos < first_scan + 2 + second_scan);
+            }
+
+            // Without the DNL segment and with the height in the frame header both are the same
+            result.drain(dnl_pos..dnl_pos + dnl.len());
+            result[sof_pos + 5..sof_pos + 7].copy_from_slice(&height.to_be_bytes());
+
+            assert!(find(&result, &dnl[..2]).is_none());
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_restart_markers() {
+        let (data, width, height) = create_test_img_rgb();
+
+        for (progressive, optimize) in [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 90);
+            encoder.set_sampling_factor(SamplingFactor::F_2_2);
+            encoder.set_progressive(progressive);
+            encoder.set_optimized_huffman_tables(optimize);
+            encoder.set_restart_interval(3);
+
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let markers: Vec<u8> = result
+                .windows(2)
+                .filter(|w| w[0] == 0xFF && (0xD0..=0xD7).contains(&w[1]))
+                .map(|w| w[1] & 7)
+                .collect();
+
+            if !progressive && !optimize {
+                // Interleaved scan with 17x8 MCUs
+                assert_eq!(markers.len(), (17 * 8 + 2) / 3 - 1);
+
+                for (i, &marker) in markers.iter().enumerate() {
+                    assert_eq!(marker as usize, i % 8);
+                }
+            } else {
+                assert!(!markers.is_empty());
+            }
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_cancel_check() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut expected = Vec::new();
+        let encoder = Encoder::new(&mut expected, 80);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_cancel_check(Box::new(|| false));
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        for progressive in [false, true] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let cancel_calls = calls.clone();
+
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 80);
+            encoder.set_progressive(progressive);
+            encoder.set_cancel_check(Box::new(move || {
+                cancel_calls.fetch_add(1, Ordering::Relaxed) == 2
+            }));
+
+            let res = encoder.encode(&data, width, height, ColorType::Rgb);
+
+            assert!(matches!(res, Err(EncodingError::Cancelled)));
+            assert_eq!(calls.load(Ordering::Relaxed), 3);
+            assert!(!result.ends_with(&[0xFF, 0xD9]));
+        }
     }
 
     #[test]
@@ -480,6 +3251,428 @@ mod tests {
             .any(|w| w == segment_data));
     }
 
+    #[test]
+    fn test_append_to_existing_data() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = vec![1, 2, 3];
+        let encoder = Encoder::new(&mut result, 80);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(&result[0..5], &[1, 2, 3, 0xFF, 0xD8]);
+        assert_eq!(&result[result.len() - 2..], &[0xFF, 0xD9]);
+
+        let mut image = result[3..].to_vec();
+        check_result(data.clone(), width, height, &mut image, PixelFormat::RGB24);
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_write_soi_eoi(false);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(&result[0..2], &[0xFF, 0xE0]);
+        assert_ne!(&result[result.len() - 2..], &[0xFF, 0xD9]);
+
+        // Framing the image externally results in the same file
+        let mut framed = vec![0xFF, 0xD8];
+        framed.extend_from_slice(&result);
+        framed.extend_from_slice(&[0xFF, 0xD9]);
+
+        assert_eq!(framed, image);
+    }
+
+    #[test]
+    fn test_write_jfif() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 80);
+        encoder.set_write_jfif(false);
+        encoder.add_exif(b"MM\0\x2a").unwrap();
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(&result[0..6], b"\xFF\xD8\xFF\xE1\0\x0C");
+        assert!(!result.windows(4).any(|w| w == b"JFIF"));
+
+        check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+
+        let mut encoder = Encoder::new(Vec::new(), 80).with_write_jfif(false);
+        encoder.set_density(Density::Inch { x: 300, y: 300 });
+
+        assert!(matches!(
+            encoder.encode(&data, width, height, ColorType::Rgb),
+            Err(EncodingError::JfifRequired)
+        ));
+
+        let mut encoder = Encoder::new(Vec::new(), 80).with_write_jfif(false);
+        encoder.set_jfif_thumbnail(&[0, 0, 0], 1, 1).unwrap();
+
+        assert!(matches!(
+            encoder.encode(&data, width, height, ColorType::Rgb),
+            Err(EncodingError::JfifRequired)
+        ));
+    }
+
+    #[test]
+    fn test_jfif_version() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 80);
+        assert_eq!(encoder.jfif_version(), (1, 2));
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(&result[2..13], b"\xFF\xE0\0\x10JFIF\0\x01\x02");
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_jfif_version(1, 1)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(&result[2..13], b"\xFF\xE0\0\x10JFIF\0\x01\x01");
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_comment() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        encoder.add_comment(b"first").unwrap();
+        encoder.add_comment(b"second").unwrap();
+
+        assert!(matches!(
+            encoder.add_comment(&vec![0; 65534]),
+            Err(EncodingError::CommentTooLarge(65534))
+        ));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let first = b"\xFF\xFE\0\x07first";
+        let second = b"\xFF\xFE\0\x08second";
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let first_pos = find(first).expect("Missing first comment");
+        let second_pos = find(second).expect("Missing second comment");
+
+        assert!(first_pos < second_pos);
+    }
+
+    #[test]
+    fn test_jfif_thumbnail() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        let thumbnail = [10, 20, 30, 40, 50, 60];
+
+        assert!(matches!(
+            encoder.set_jfif_thumbnail(&thumbnail, 2, 2),
+            Err(EncodingError::BadImageData {
+                length: 6,
+                required: 12
+            })
+        ));
+        assert!(matches!(
+            encoder.set_jfif_thumbnail(&vec![0; 255 * 86 * 3], 255, 86),
+            Err(EncodingError::AppSegmentTooLarge(65798))
+        ));
+
+        encoder.set_jfif_thumbnail(&thumbnail, 2, 1).unwrap();
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        // The JFXX segment directly follows the JFIF header
+        assert_eq!(
+            &result[20..20 + 18],
+            b"\xFF\xE0\0\x10JFXX\0\x13\x02\x01\x0A\x14\x1E\x28\x32\x3C"
+        );
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_adobe_color_transform() {
+        let (data, width, height) = create_test_img_rgb();
+
+        const ADOBE: &[u8] = b"\xFF\xEE\0\x0EAdobe";
+
+        let mut result = Vec::new();
+        let encoder = Encoder::new(&mut result, 100);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert!(!result.windows(ADOBE.len()).any(|w| w == ADOBE));
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+        encoder.set_adobe_color_transform(Some(1));
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let pos = result
+            .windows(ADOBE.len())
+            .position(|w| w == ADOBE)
+            .expect("Missing Adobe segment");
+
+        assert_eq!(result[pos + 15], 1);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_exif() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        encoder
+            .add_icc_profile(&create_test_icc_profile(128))
+            .unwrap();
+        encoder.add_exif(b"MM\0\x2a").unwrap();
+
+        assert!(matches!(
+            encoder.add_exif(&vec![0; 65528]),
+            Err(EncodingError::AppSegmentTooLarge(65534))
+        ));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let exif_pos = find(b"\xFF\xE1\0\x0CExif\0\0MM\0\x2a").expect("Missing exif segment");
+        let icc_pos = find(b"\xFF\xE2").expect("Missing icc segment");
+
+        assert!(exif_pos < icc_pos);
+    }
+
+    #[test]
+    fn test_xmp() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        let xmp = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+
+        encoder
+            .add_icc_profile(&create_test_icc_profile(128))
+            .unwrap();
+        encoder.add_exif(b"MM\0\x2a").unwrap();
+        encoder.add_xmp(xmp).unwrap();
+
+        assert!(matches!(
+            encoder.add_xmp(&vec![0; 65505]),
+            Err(EncodingError::AppSegmentTooLarge(65534))
+        ));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let mut segment = b"\xFF\xE1\0\x4Fhttp://ns.adobe.com/xap/1.0/\0".to_vec();
+        segment.extend_from_slice(xmp);
+
+        let exif_pos = find(b"\xFF\xE1\0\x0CExif\0\0").expect("Missing exif segment");
+        let xmp_pos = find(&segment).expect("Missing xmp segment");
+        let icc_pos = find(b"\xFF\xE2").expect("Missing icc segment");
+
+        assert!(exif_pos < xmp_pos);
+        assert!(xmp_pos < icc_pos);
+    }
+
+    #[test]
+    fn test_extended_xmp() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        let xmp = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+        let extended: Vec<u8> = (0..140000).map(|i| (i * 7 % 251) as u8).collect();
+
+        assert!(matches!(
+            encoder.add_extended_xmp(&vec![0; 65505], &extended),
+            Err(EncodingError::AppSegmentTooLarge(65534))
+        ));
+
+        encoder.add_extended_xmp(xmp, &extended).unwrap();
+        encoder
+            .add_icc_profile(&create_test_icc_profile(128))
+            .unwrap();
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let xmp_pos = find(b"http://ns.adobe.com/xap/1.0/\0").expect("Missing xmp segment");
+        let icc_pos = find(b"\xFF\xE2").expect("Missing icc segment");
+
+        let marker = b"\xFF\xE1";
+        let namespace = b"http://ns.adobe.com/xmp/extension/\0";
+
+        let mut pos = xmp_pos;
+        let mut chunks = 0;
+        let mut reassembled = vec![0; extended.len()];
+
+        while let Some(offset) = result[pos..]
+            .windows(namespace.len())
+            .position(|w| w == namespace)
+        {
+            let start = pos + offset;
+            assert_eq!(&result[start - 4..start - 2], marker);
+
+            let length = usize::from(u16::from_be_bytes([result[start - 2], result[start - 1]]));
+            let segment = &result[start + namespace.len()..start - 2 + length];
+
+            assert_eq!(&segment[..32], b"7754A02853E21FC82323E047A4AE5EF7");
+            assert_eq!(&segment[32..36], &140000u32.to_be_bytes());
+
+            let offset = u32::from_be_bytes([segment[36], segment[37], segment[38], segment[39]]);
+            let chunk = &segment[40..];
+            reassembled[offset as usize..offset as usize + chunk.len()].copy_from_slice(chunk);
+
+            pos = start + namespace.len();
+            chunks += 1;
+        }
+
+        assert_eq!(chunks, 3);
+        assert!(pos < icc_pos);
+        assert_eq!(reassembled, extended);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_photoshop_irb() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        // IPTC record with an object name
+        let irb = b"8BIM\x04\x04\0\0\0\0\0\x08\x1C\x02\x05\0\x03abc";
+
+        encoder
+            .add_icc_profile(&create_test_icc_profile(128))
+            .unwrap();
+        encoder.add_photoshop_irb(irb).unwrap();
+        encoder.add_exif(b"MM\0\x2a").unwrap();
+
+        assert!(matches!(
+            encoder.add_photoshop_irb(&vec![0; 65520]),
+            Err(EncodingError::AppSegmentTooLarge(65534))
+        ));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let mut segment = b"\xFF\xED\0\x24Photoshop 3.0\0".to_vec();
+        segment.extend_from_slice(irb);
+
+        let exif_pos = find(b"\xFF\xE1\0\x0CExif\0\0").expect("Missing exif segment");
+        let irb_pos = find(&segment).expect("Missing photoshop segment");
+        let icc_pos = find(b"\xFF\xE2").expect("Missing icc segment");
+
+        assert!(exif_pos < irb_pos);
+        assert!(irb_pos < icc_pos);
+    }
+
+    #[test]
+    fn test_mpf() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut result = Vec::new();
+        let mut encoder = Encoder::new(&mut result, 100);
+
+        // Big endian MP header with an empty IFD
+        let mpf = b"MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+
+        encoder.add_exif(b"MM\0\x2a").unwrap();
+        encoder.add_mpf(mpf).unwrap();
+
+        assert!(matches!(
+            encoder.add_mpf(&vec![0; 65530]),
+            Err(EncodingError::AppSegmentTooLarge(65534))
+        ));
+
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let find = |segment: &[u8]| {
+            result
+                .as_slice()
+                .windows(segment.len())
+                .position(|w| w == segment)
+        };
+
+        let mut segment = b"\xFF\xE2\0\x14MPF\0".to_vec();
+        segment.extend_from_slice(mpf);
+
+        let exif_pos = find(b"\xFF\xE1\0\x0CExif\0\0").expect("Missing exif segment");
+        let mpf_pos = find(&segment).expect("Missing mpf segment");
+
+        assert!(exif_pos < mpf_pos);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
     #[test]
     fn test_icc_profile() {
         let (data, width, height) = create_test_img_rgb();
@@ -487,20 +3680,42 @@ mod tests {
         let mut result = Vec::new();
         let mut encoder = Encoder::new(&mut result, 100);
 
-        let mut icc = Vec::with_capacity(128 * 1024);
+        let icc = create_test_icc_profile(128 * 1024);
 
-        for i in 0..128 * 1024 {
-            icc.push((i % 255) as u8);
+        assert_eq!(encoder.icc_profile(), None);
+
+        for invalid in [&icc[..127], &icc[..4096], &icc[4..]] {
+            assert!(matches!(
+                encoder.add_icc_profile(invalid),
+                Err(EncodingError::InvalidIccProfile)
+            ));
         }
 
+        assert_eq!(encoder.icc_profile(), None);
+
+        // The second profile replaces the first one
+        encoder
+            .add_icc_profile(&create_test_icc_profile(128))
+            .unwrap();
         encoder.add_icc_profile(&icc).unwrap();
 
+        assert_eq!(encoder.icc_profile(), Some(icc.as_slice()));
+
         encoder
             .encode(&data, width, height, ColorType::Rgb)
             .unwrap();
 
         const MARKER: &[u8; 12] = b"ICC_PROFILE\0";
 
+        assert_eq!(
+            result
+                .as_slice()
+                .windows(MARKER.len())
+                .filter(|w| w == MARKER)
+                .count(),
+            3
+        );
+
         assert!(result.as_slice().windows(MARKER.len()).any(|w| w == MARKER));
 
         let mut decoder = Decoder::new(result.as_slice());
@@ -528,4 +3743,740 @@ mod tests {
 
         check_result(data, 1, 1, &mut result, PixelFormat::RGB24);
     }
+
+    struct CustomRgbImage<'a>(&'a [u8], u16, u16);
+
+    impl<'a> ImageBuffer for CustomRgbImage<'a> {
+        fn get_jpeg_color_type(&self) -> JpegColorType {
+            JpegColorType::Ycbcr
+        }
+
+        fn width(&self) -> u16 {
+            self.1
+        }
+
+        fn height(&self) -> u16 {
+            self.2
+        }
+
+        fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+            let line = &self.0[usize::from(y) * usize::from(self.1) * 3..];
+
+            for pixel in line.chunks_exact(3).take(usize::from(self.1)) {
+                let (y, cb, cr) = rgb_to_ycbcr(pixel[0], pixel[1], pixel[2]);
+
+                buffers[0].push(y);
+                buffers[1].push(cb);
+                buffers[2].push(cr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_matches_encode_image() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let configs: [fn(&mut Encoder<&mut Vec<u8>>); 4] = [
+            |_| {},
+            |encoder| encoder.set_sampling_factor(SamplingFactor::F_1_1),
+            |encoder| encoder.set_optimized_huffman_tables(true),
+            |encoder| encoder.set_progressive(true),
+        ];
+
+        for config in configs {
+            let mut expected = Vec::new();
+            let mut encoder = Encoder::new(&mut expected, 80);
+            config(&mut encoder);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            let mut result = Vec::new();
+            let mut encoder = Encoder::new(&mut result, 80);
+            config(&mut encoder);
+            encoder
+                .encode_image(CustomRgbImage(&data, width, height))
+                .unwrap();
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    fn create_planes(
+        data: &[u8],
+        width: u16,
+        height: u16,
+        h_scale: usize,
+        v_scale: usize,
+    ) -> [Vec<u8>; 3] {
+        let width = usize::from(width);
+        let height = usize::from(height);
+
+        let mut planes = [Vec::new(), Vec::new(), Vec::new()];
+
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = &data[(y * width + x) * 3..];
+                let (luma, cb, cr) = rgb_to_ycbcr(pixel[0], pixel[1], pixel[2]);
+
+                planes[0].push(luma);
+
+                if x % h_scale == 0 && y % v_scale == 0 {
+                    planes[1].push(cb);
+                    planes[2].push(cr);
+                }
+            }
+        }
+
+        planes
+    }
+
+    #[test]
+    fn test_encode_components() {
+        let (data, width, height) = create_test_img_rgb();
+
+        for (h, v) in [(1, 1), (2, 2), (2, 1), (4, 1), (1, 4)] {
+            let [luma, cb, cr] = create_planes(&data, width, height, h, v);
+
+            let planes = [
+                ComponentPlane {
+                    data: &luma,
+                    h_sampling: h as u8,
+                    v_sampling: v as u8,
+                },
+                ComponentPlane {
+                    data: &cb,
+                    h_sampling: 1,
+                    v_sampling: 1,
+                },
+                ComponentPlane {
+                    data: &cr,
+                    h_sampling: 1,
+                    v_sampling: 1,
+                },
+            ];
+
+            let mut result = Vec::new();
+            let encoder = Encoder::new(&mut result, 80);
+            encoder.encode_components(&planes, width, height).unwrap();
+
+            // The planes above are point sampled
+            let mut expected = Vec::new();
+            let mut encoder = Encoder::new(&mut expected, 80);
+            encoder.set_sampling_factor(SamplingFactor::from_factors(h as u8, v as u8).unwrap());
+            encoder.set_chroma_downsample_filter(DownsampleFilter::Point);
+            encoder
+                .encode(&data, width, height, ColorType::Rgb)
+                .unwrap();
+
+            assert_eq!(result, expected);
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_encode_components_invalid() {
+        let (data, width, height) = create_test_img_rgb();
+        let [luma, cb, _] = create_planes(&data, width, height, 2, 2);
+
+        let plane = |data, h_sampling, v_sampling| ComponentPlane {
+            data,
+            h_sampling,
+            v_sampling,
+        };
+
+        let mut result = Vec::new();
+
+        let planes = [plane(&luma, 2, 2), plane(&cb, 1, 1)];
+        assert!(matches!(
+            Encoder::new(&mut result, 80).encode_components(&planes, width, height),
+            Err(EncodingError::InvalidComponentCount(2))
+        ));
+
+        let planes = [plane(&luma, 3, 2), plane(&cb, 1, 1), plane(&cb, 1, 1)];
+        assert!(matches!(
+            Encoder::new(&mut result, 80).encode_components(&planes, width, height),
+            Err(EncodingError::InvalidSamplingFactor {
+                horizontal: 3,
+                vertical: 2
+            })
+        ));
+
+        let planes = [plane(&luma, 1, 1), plane(&cb, 1, 1), plane(&cb, 1, 1)];
+        assert!(matches!(
+            Encoder::new(&mut result, 80).encode_components(&planes, width, height),
+            Err(EncodingError::BadImageData {
+                length: 8256,
+                required: 33024
+            })
+        ));
+    }
+
+    #[test]
+    fn test_planar_ycbcr() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let stride = usize::from(width) + 3;
+
+        let mut ycbcr = Vec::with_capacity(data.len());
+        let mut planes = [Vec::new(), Vec::new(), Vec::new()];
+
+        for row in data.chunks_exact(usize::from(width) * 3) {
+            for pixel in row.chunks_exact(3) {
+                let (y, cb, cr) = rgb_to_ycbcr(pixel[0], pixel[1], pixel[2]);
+                ycbcr.extend_from_slice(&[y, cb, cr]);
+
+                planes[0].push(y);
+                planes[1].push(cb);
+                planes[2].push(cr);
+            }
+
+            // Padding at the end of each row
+            for plane in &mut planes {
+                plane.extend_from_slice(&[0, 0, 0]);
+            }
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .encode(&ycbcr, width, height, ColorType::Ycbcr)
+            .unwrap();
+
+        let image = PlanarYCbCr::new(
+            &planes[0], &planes[1], &planes[2], width, height, stride, stride,
+        )
+        .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80).encode_image(image).unwrap();
+
+        assert_eq!(result, expected);
+
+        // The last row doesn't need padding
+        let required = (usize::from(height) - 1) * stride + usize::from(width);
+
+        assert!(matches!(
+            PlanarYCbCr::new(
+                &planes[0][..required],
+                &planes[1],
+                &planes[2][..required - 1],
+                width,
+                height,
+                stride,
+                stride,
+            ),
+            Err(EncodingError::BadImageData { length, required: r })
+                if length == required - 1 && r == required
+        ));
+
+        assert!(matches!(
+            PlanarYCbCr::new(&planes[0], &planes[1], &planes[2], width, height, stride, 10),
+            Err(EncodingError::BadImageData {
+                length: 10,
+                required: 258
+            })
+        ));
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_i420_nv12() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let [luma, cb, cr] = create_planes(&data, width, height, 2, 2);
+
+        let cbcr: Vec<u8> = cb
+            .iter()
+            .zip(cr.iter())
+            .flat_map(|(&cb, &cr)| [cb, cr])
+            .collect();
+
+        let chroma_width = (usize::from(width) + 1) / 2;
+
+        for filter in [DownsampleFilter::Point, DownsampleFilter::Average] {
+            let planes = [
+                ComponentPlane {
+                    data: &luma,
+                    h_sampling: 2,
+                    v_sampling: 2,
+                },
+                ComponentPlane {
+                    data: &cb,
+                    h_sampling: 1,
+                    v_sampling: 1,
+                },
+                ComponentPlane {
+                    data: &cr,
+                    h_sampling: 1,
+                    v_sampling: 1,
+                },
+            ];
+
+            let mut expected = Vec::new();
+            Encoder::new(&mut expected, 80)
+                .encode_components(&planes, width, height)
+                .unwrap();
+
+            let i420 = I420::new(
+                &luma,
+                &cb,
+                &cr,
+                width,
+                height,
+                usize::from(width),
+                chroma_width,
+            )
+            .unwrap();
+
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_sampling_factor(SamplingFactor::F_2_2)
+                .with_chroma_downsample_filter(filter)
+                .encode_image(i420)
+                .unwrap();
+
+            assert_eq!(result, expected);
+
+            let nv12 = Nv12::new(
+                &luma,
+                &cbcr,
+                width,
+                height,
+                usize::from(width),
+                chroma_width * 2,
+            )
+            .unwrap();
+
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_sampling_factor(SamplingFactor::F_2_2)
+                .with_chroma_downsample_filter(filter)
+                .encode_image(nv12)
+                .unwrap();
+
+            assert_eq!(result, expected);
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+
+        let stride = usize::from(width);
+
+        // Chroma planes of 129x64 samples
+        assert!(matches!(
+            I420::new(&luma, &cb, &cr[..129 * 63], width, height, stride, 129),
+            Err(EncodingError::BadImageData {
+                length: 8127,
+                required: 8256
+            })
+        ));
+
+        assert!(matches!(
+            I420::new(&luma, &cb, &cr, width, height, stride, 128),
+            Err(EncodingError::BadImageData {
+                length: 128,
+                required: 129
+            })
+        ));
+
+        assert!(matches!(
+            Nv12::new(&luma[1..], &cbcr, width, height, stride, 258),
+            Err(EncodingError::BadImageData {
+                length: 33023,
+                required: 33024
+            })
+        ));
+
+        assert!(matches!(
+            Nv12::new(&luma, &cbcr, width, height, stride, 257),
+            Err(EncodingError::BadImageData {
+                length: 257,
+                required: 258
+            })
+        ));
+    }
+
+    #[test]
+    fn test_yuyv_uyvy() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let [luma, cb, cr] = create_planes(&data, width, height, 2, 1);
+
+        let mut yuyv = Vec::with_capacity(luma.len() * 2);
+        let mut uyvy = Vec::with_capacity(luma.len() * 2);
+
+        for (i, (&cb, &cr)) in cb.iter().zip(cr.iter()).enumerate() {
+            let y0 = luma[i * 2];
+            let y1 = luma[i * 2 + 1];
+
+            yuyv.extend_from_slice(&[y0, cb, y1, cr]);
+            uyvy.extend_from_slice(&[cb, y0, cr, y1]);
+        }
+
+        let planes = [
+            ComponentPlane {
+                data: &luma,
+                h_sampling: 2,
+                v_sampling: 1,
+            },
+            ComponentPlane {
+                data: &cb,
+                h_sampling: 1,
+                v_sampling: 1,
+            },
+            ComponentPlane {
+                data: &cr,
+                h_sampling: 1,
+                v_sampling: 1,
+            },
+        ];
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .encode_components(&planes, width, height)
+            .unwrap();
+
+        for (packed, color_type) in [(&yuyv, ColorType::Yuyv), (&uyvy, ColorType::Uyvy)] {
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_sampling_factor(SamplingFactor::R_4_2_2)
+                .encode(packed, width, height, color_type)
+                .unwrap();
+
+            assert_eq!(result, expected);
+
+            check_result(data.clone(), width, height, &mut result, PixelFormat::RGB24);
+        }
+    }
+
+    #[test]
+    fn test_yuyv_odd_width() {
+        // Rows of 3 pixels are padded to 2 macro-pixels
+        let data = [0u8; 2 * 4 * 5];
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .encode(&data[..data.len() - 1], 3, 5, ColorType::Yuyv)
+            .unwrap_err();
+
+        Encoder::new(&mut result, 80)
+            .encode(&data, 3, 5, ColorType::Yuyv)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_encode_region() {
+        let (data, width, height) = create_test_img_rgb();
+
+        // Place the image at position 5,3 of a larger buffer with padded rows
+        let full_width = width + 11;
+        let stride = usize::from(full_width) * 3 + 7;
+
+        let mut full = vec![0u8; stride * (usize::from(height) + 9)];
+
+        for (y, row) in data.chunks_exact(usize::from(width) * 3).enumerate() {
+            let start = (y + 3) * stride + 5 * 3;
+            full[start..start + row.len()].copy_from_slice(row);
+        }
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .encode_region(
+                &full,
+                full_width,
+                stride,
+                5,
+                3,
+                width,
+                height,
+                ColorType::Rgb,
+            )
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        // Settings of the color conversion apply to regions like to whole images
+        let mut expected_gray = Vec::new();
+        Encoder::new(&mut expected_gray, 80)
+            .with_force_grayscale(true)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result_gray = Vec::new();
+        Encoder::new(&mut result_gray, 80)
+            .with_force_grayscale(true)
+            .encode_region(
+                &full,
+                full_width,
+                stride,
+                5,
+                3,
+                width,
+                height,
+                ColorType::Rgb,
+            )
+            .unwrap();
+
+        assert_eq!(result_gray, expected_gray);
+        assert_eq!(decode(&result_gray).1.pixel_format, PixelFormat::L8);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_encode_region_errors() {
+        let data = [0u8; 100 * 10];
+
+        // All regions have a height of 5 rows with a stride of 100 bytes
+        let encode = |full_width, x, y, width, color_type| {
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .encode_region(&data, full_width, 100, x, y, width, 5, color_type)
+        };
+
+        assert!(matches!(
+            encode(90, 80, 0, 11, ColorType::Luma),
+            Err(EncodingError::InvalidRegion {
+                x: 80,
+                y: 0,
+                width: 11,
+                height: 5
+            })
+        ));
+
+        assert!(matches!(
+            encode(40, 0, 0, 40, ColorType::Rgb),
+            Err(EncodingError::InvalidRegion { .. })
+        ));
+
+        assert!(matches!(
+            encode(50, 1, 0, 8, ColorType::Yuyv),
+            Err(EncodingError::InvalidRegion { .. })
+        ));
+
+        assert!(matches!(
+            encode(90, 10, 6, 80, ColorType::Luma),
+            Err(EncodingError::BadImageData {
+                length: 1000,
+                required: 1090
+            })
+        ));
+
+        assert!(encode(90, 10, 5, 80, ColorType::Luma).is_ok());
+    }
+
+    #[test]
+    fn test_native_components() {
+        // Panics if the encoder falls back to full resolution rows
+        struct NativeOnly<'a>(I420<'a>);
+
+        impl<'a> ImageBuffer for NativeOnly<'a> {
+            fn get_jpeg_color_type(&self) -> JpegColorType {
+                JpegColorType::Ycbcr
+            }
+
+            fn width(&self) -> u16 {
+                self.0.width
+            }
+
+            fn height(&self) -> u16 {
+                self.0.height
+            }
+
+            fn fill_buffers(&self, _y: u16, _buffers: &mut [Vec<u8>; 4]) {
+                panic!("Components should be read at their native resolution");
+            }
+
+            fn chroma_already_subsampled(&self) -> bool {
+                true
+            }
+
+            fn component_subsampling(&self, component: usize) -> (u8, u8) {
+                self.0.component_subsampling(component)
+            }
+
+            fn fill_component_row(&self, component: usize, y: u16, buffer: &mut Vec<u8>) {
+                self.0.fill_component_row(component, y, buffer)
+            }
+        }
+
+        let (data, width, height) = create_test_img_rgb();
+
+        let [luma, cb, cr] = create_planes(&data, width, height, 2, 2);
+
+        let i420 = I420 {
+            y: &luma,
+            cb: &cb,
+            cr: &cr,
+            width,
+            height,
+            y_stride: usize::from(width),
+            chroma_stride: (usize::from(width) + 1) / 2,
+        };
+
+        for (progressive, optimized, full_range, low_memory) in [
+            (false, false, true, false),
+            (false, true, true, false),
+            (true, false, true, false),
+            (false, false, false, false),
+            (false, false, true, true),
+            (false, true, true, true),
+        ] {
+            // Point sampling of the replicated values gives the same result as the native path
+            let replicated = FnImageBuffer {
+                width,
+                height,
+                color_type: JpegColorType::Ycbcr,
+                fill: |y: u16, buffers: &mut [Vec<u8>; 4]| i420.fill_buffers(y, buffers),
+            };
+
+            let mut expected = Vec::new();
+            Encoder::new(&mut expected, 80)
+                .with_sampling_factor(SamplingFactor::F_2_2)
+                .with_chroma_downsample_filter(DownsampleFilter::Point)
+                .with_progressive(progressive)
+                .with_optimized_huffman_tables(optimized)
+                .with_full_range(full_range)
+                .with_low_memory(low_memory)
+                .encode_image(replicated)
+                .unwrap();
+
+            let mut result = Vec::new();
+            Encoder::new(&mut result, 80)
+                .with_sampling_factor(SamplingFactor::F_2_2)
+                .with_progressive(progressive)
+                .with_optimized_huffman_tables(optimized)
+                .with_full_range(full_range)
+                .with_low_memory(low_memory)
+                .encode_image(NativeOnly(i420))
+                .unwrap();
+
+            assert_eq!(result, expected);
+        }
+
+        // Falls back to full resolution rows if the sampling factors don't match
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_sampling_factor(SamplingFactor::F_1_1)
+            .encode_image(i420)
+            .unwrap();
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_native_components_default() {
+        // Only full resolution rows, so the default native rows are averaged from them
+        struct FullRows<'a>(I420<'a>);
+
+        impl<'a> ImageBuffer for FullRows<'a> {
+            fn get_jpeg_color_type(&self) -> JpegColorType {
+                JpegColorType::Ycbcr
+            }
+
+            fn width(&self) -> u16 {
+                self.0.width
+            }
+
+            fn height(&self) -> u16 {
+                self.0.height
+            }
+
+            fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+                self.0.fill_buffers(y, buffers)
+            }
+
+            fn chroma_already_subsampled(&self) -> bool {
+                true
+            }
+
+            fn component_subsampling(&self, component: usize) -> (u8, u8) {
+                self.0.component_subsampling(component)
+            }
+        }
+
+        let (data, _, _) = create_test_img_rgb();
+
+        // Odd dimensions for partial chroma samples at the edges
+        let width = 255;
+        let height = 127;
+        let data = data[..usize::from(width) * usize::from(height) * 3].to_vec();
+
+        let [luma, cb, cr] = create_planes(&data, width, height, 2, 2);
+
+        let i420 = I420 {
+            y: &luma,
+            cb: &cb,
+            cr: &cr,
+            width,
+            height,
+            y_stride: usize::from(width),
+            chroma_stride: (usize::from(width) + 1) / 2,
+        };
+
+        let mut expected = Vec::new();
+        Encoder::new(&mut expected, 80)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .encode_image(i420)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_sampling_factor(SamplingFactor::F_2_2)
+            .encode_image(FullRows(i420))
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_encode_to_vec() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut expected = Vec::new();
+        let encoder = Encoder::new(&mut expected, 80);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Encoder::encode_to_vec(&data, width, height, ColorType::Rgb, 80).unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
+
+    #[test]
+    fn test_builder_matches_setters() {
+        let (data, width, height) = create_test_img_rgb();
+
+        let mut expected = Vec::new();
+        let mut encoder = Encoder::new(&mut expected, 80);
+        encoder.set_sampling_factor(SamplingFactor::F_2_1);
+        encoder.set_progressive(true);
+        encoder.set_optimized_huffman_tables(true);
+        encoder.set_restart_interval(16);
+        encoder
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        let mut result = Vec::new();
+        Encoder::new(&mut result, 80)
+            .with_sampling_factor(SamplingFactor::F_2_1)
+            .with_progressive(true)
+            .with_optimized_huffman_tables(true)
+            .with_restart_interval(16)
+            .encode(&data, width, height, ColorType::Rgb)
+            .unwrap();
+
+        assert_eq!(result, expected);
+
+        check_result(data, width, height, &mut result, PixelFormat::RGB24);
+    }
 }
@@ -1,10 +1,15 @@
+use crate::writer::ZIGZAG;
+
 use alloc::boxed::Box;
-use core::num::NonZeroU16;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::num::NonZeroU32;
 
 /// # Quantization table used for encoding
 ///
 /// Tables are based on tables from mozjpeg
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QuantizationTableType {
     /// Sample quantization tables given in Annex K (Clause K.1) of Recommendation ITU-T T.81 (1992) | ISO/IEC 10918-1:1994.
     Default,
@@ -36,7 +41,20 @@ pub enum QuantizationTableType {
     ImprovedDetectionModel,
 
     /// A user supplied quantization table
-    Custom(Box<[u16; 64]>),
+    ///
+    /// Values are limited to the range of 1 to 2048 or 255 if baseline is forced.
+    /// This means zero values are treated as 1.
+    Custom(
+        #[cfg_attr(feature = "serde", serde(with = "crate::config::serde_table::boxed"))]
+        Box<[u16; 64]>,
+    ),
+
+    /// A user supplied quantization table that is written as is
+    ///
+    /// Unlike [Custom](QuantizationTableType::Custom) values are neither scaled by the quality
+    /// nor limited, which allows reusing the tables of an existing JPEG file.
+    /// Zero values are replaced by 1 and values are only clamped to 255 if baseline is forced.
+    Raw(#[cfg_attr(feature = "serde", serde(with = "crate::config::serde_table"))] [u16; 64]),
 }
 
 impl QuantizationTableType {
@@ -53,7 +71,7 @@ impl QuantizationTableType {
             DentalXRays => 6,
             VisualDetectionModel => 7,
             ImprovedDetectionModel => 8,
-            Custom(_) => panic!("Custom types not supported"),
+            Custom(_) | Raw(_) => panic!("Custom types not supported"),
         }
     }
 }
@@ -182,7 +200,7 @@ static DEFAULT_CHROMA_TABLES: [[u16; 64]; 9] = [
     ],
 ];
 
-const SHIFT: u32 = 2 * 8 - 1;
+pub(crate) const SHIFT: u32 = 2 * 8 - 1;
 
 fn compute_reciprocal(divisor: u32) -> (i32, i32) {
     if divisor <= 1 {
@@ -207,9 +225,16 @@ fn compute_reciprocal(divisor: u32) -> (i32, i32) {
 }
 
 pub struct QuantizationTable {
-    table: [NonZeroU16; 64],
+    table: [NonZeroU32; 64],
     reciprocals: [i32; 64],
     corrections: [i32; 64],
+
+    // Preset the table was scaled from, None for custom and raw tables
+    base: Option<&'static [u16; 64]>,
+    dead_zone: f32,
+
+    // The reciprocals are too coarse for 16 bit values, so these tables are divided exactly
+    exact_division: bool,
 }
 
 impl QuantizationTable {
@@ -217,48 +242,138 @@ impl QuantizationTable {
         table: &QuantizationTableType,
         quality: u8,
         luma: bool,
+        force_baseline: bool,
     ) -> QuantizationTable {
-        let table = match table {
-            QuantizationTableType::Custom(table) => Self::get_user_table(table),
+        match table {
+            QuantizationTableType::Custom(table) => {
+                Self::from_table(Self::get_user_table(table, force_baseline), None)
+            }
+            QuantizationTableType::Raw(table) => {
+                Self::from_table(Self::get_raw_table(table, force_baseline), None)
+            }
             table => {
-                let table = if luma {
-                    &DEFAULT_LUMA_TABLES[table.index()]
-                } else {
-                    &DEFAULT_CHROMA_TABLES[table.index()]
-                };
-                Self::get_with_quality(table, quality)
+                let base = Self::get_base(table, luma);
+                Self::from_table(Self::get_with_quality(base, quality), Some(base))
             }
+        }
+    }
+
+    /// Create a table for a fractional quality
+    ///
+    /// Integral values give the same result as [new_with_quality](QuantizationTable::new_with_quality).
+    pub fn new_with_quality_f32(
+        table: &QuantizationTableType,
+        quality: f32,
+        luma: bool,
+        force_baseline: bool,
+    ) -> QuantizationTable {
+        match table {
+            QuantizationTableType::Custom(_) | QuantizationTableType::Raw(_) => {
+                Self::new_with_quality(table, quality as u8, luma, force_baseline)
+            }
+            table => {
+                let base = Self::get_base(table, luma);
+                Self::from_table(Self::get_with_quality_any(base, quality), Some(base))
+            }
+        }
+    }
+
+    /// Change the quality of the table in place
+    ///
+    /// Gives the same result as creating a new table with
+    /// [new_with_quality_f32](QuantizationTable::new_with_quality_f32) but only recomputes the
+    /// reciprocals of values that changed, which helps when searching for a quality.
+    /// The dead zone is kept. Custom and raw tables don't depend on the quality and stay
+    /// unchanged.
+    pub fn rescale(&mut self, quality: f32) {
+        let base = match self.base {
+            Some(base) => base,
+            None => return,
         };
 
-        let mut reciprocals = [0i32; 64];
-        let mut corrections = [0i32; 64];
+        let table = Self::get_with_quality_any(base, quality);
+
+        for (i, &value) in table.iter().enumerate() {
+            if self.table[i] != value {
+                self.table[i] = value;
+                self.update_reciprocal(i);
+            }
+        }
+    }
+
+    fn from_table(table: [NonZeroU32; 64], base: Option<&'static [u16; 64]>) -> QuantizationTable {
+        let mut q_table = QuantizationTable {
+            table,
+            reciprocals: [0; 64],
+            corrections: [0; 64],
+            base,
+            dead_zone: 0.0,
+            exact_division: table.iter().any(|v| (v.get() >> 3) > 255),
+        };
 
         for i in 0..64 {
-            let (reciprocal, correction) = compute_reciprocal(table[i].get() as u32);
+            q_table.update_reciprocal(i);
+        }
 
-            reciprocals[i] = reciprocal;
-            corrections[i] = correction;
+        q_table
+    }
+
+    fn update_reciprocal(&mut self, index: usize) {
+        let divisor = self.table[index].get();
+        let (reciprocal, correction) = compute_reciprocal(divisor);
+
+        let reduction = (divisor as f32 * self.dead_zone + 0.5) as i32;
+
+        self.reciprocals[index] = reciprocal;
+        self.corrections[index] = (correction - reduction).max(0);
+    }
+
+    fn get_base(table: &QuantizationTableType, luma: bool) -> &'static [u16; 64] {
+        if luma {
+            &DEFAULT_LUMA_TABLES[table.index()]
+        } else {
+            &DEFAULT_CHROMA_TABLES[table.index()]
         }
+    }
 
-        QuantizationTable {
-            table,
-            reciprocals,
-            corrections,
+    // Integral qualities use the integer version to get exactly the same tables
+    fn get_with_quality_any(table: &[u16; 64], quality: f32) -> [NonZeroU32; 64] {
+        let quality = if quality.is_nan() {
+            1.0
+        } else {
+            quality.clamp(1.0, 100.0)
+        };
+
+        if quality == (quality as u8) as f32 {
+            Self::get_with_quality(table, quality as u8)
+        } else {
+            Self::get_with_quality_f32(table, quality)
+        }
+    }
+
+    fn get_user_table(table: &[u16; 64], force_baseline: bool) -> [NonZeroU32; 64] {
+        // Baseline JPEG only allows 8 bit quantization values
+        let max_value = if force_baseline { 255 } else { 2 << 10 };
+
+        let mut q_table = [NonZeroU32::new(1).unwrap(); 64];
+        for (i, &v) in table.iter().enumerate() {
+            // Zero values are clamped to 1, so the result is never zero
+            q_table[i] = NonZeroU32::new((v.clamp(1, max_value) as u32) << 3).unwrap();
         }
+        q_table
     }
 
-    fn get_user_table(table: &[u16; 64]) -> [NonZeroU16; 64] {
-        let mut q_table = [NonZeroU16::new(1).unwrap(); 64];
+    fn get_raw_table(table: &[u16; 64], force_baseline: bool) -> [NonZeroU32; 64] {
+        let max_value = if force_baseline { 255 } else { u16::MAX };
+
+        let mut q_table = [NonZeroU32::new(1).unwrap(); 64];
         for (i, &v) in table.iter().enumerate() {
-            q_table[i] = match NonZeroU16::new(v.clamp(1, 2 << 10) << 3) {
-                Some(v) => v,
-                None => panic!("Invalid quantization table value: {}", v),
-            };
+            q_table[i] = NonZeroU32::new((v.clamp(1, max_value) as u32) << 3).unwrap();
         }
         q_table
     }
 
-    fn get_with_quality(table: &[u16; 64], quality: u8) -> [NonZeroU16; 64] {
+    fn get_with_quality(table: &[u16; 64], quality: u8) -> [NonZeroU32; 64] {
         let quality = quality.clamp(1, 100) as u32;
 
         let scale = if quality < 50 {
@@ -267,30 +382,134 @@ impl QuantizationTable {
             200 - quality * 2
         };
 
-        let mut q_table = [NonZeroU16::new(1).unwrap(); 64];
+        let mut q_table = [NonZeroU32::new(1).unwrap(); 64];
 
         for (i, &v) in table.iter().enumerate() {
             let v = v as u32;
 
             let v = (v * scale + 50) / 100;
 
-            let v = v.clamp(1, 255) as u16;
+            let v = v.clamp(1, 255);
 
             // Table values are premultiplied with 8 because dct is scaled by 8
-            q_table[i] = NonZeroU16::new(v << 3).unwrap();
+            q_table[i] = NonZeroU32::new(v << 3).unwrap();
         }
         q_table
     }
 
+    fn get_with_quality_f32(table: &[u16; 64], quality: f32) -> [NonZeroU32; 64] {
+        let scale = if quality < 50.0 {
+            5000.0 / quality
+        } else {
+            200.0 - quality * 2.0
+        };
+
+        let mut q_table = [NonZeroU32::new(1).unwrap(); 64];
+
+        for (i, &v) in table.iter().enumerate() {
+            // Same rounding as the integer version, values are always positive
+            let v = ((v as f32 * scale + 50.0) / 100.0) as u32;
+
+            let v = v.clamp(1, 255);
+
+            // Table values are premultiplied with 8 because dct is scaled by 8
+            q_table[i] = NonZeroU32::new(v << 3).unwrap();
+        }
+        q_table
+    }
+
+    /// Widen the interval around zero which is quantized to zero
+    ///
+    /// The rounding correction is reduced by `dead_zone` times the divisor, so 0.0 rounds to the
+    /// nearest value and 0.5 truncates towards zero. Values outside of this range are clamped.
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = if dead_zone.is_nan() {
+            0.0
+        } else {
+            dead_zone.clamp(0.0, 0.5)
+        };
+
+        for i in 0..64 {
+            self.update_reciprocal(i);
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> u16 {
+        (self.table[index].get() >> 3) as u16
+    }
+
+    /// Returns true if the table needs 16 bit precision for its values
+    pub fn is_16_bit(&self) -> bool {
+        self.table.iter().any(|v| (v.get() >> 3) > 255)
+    }
+
+    /// Returns true if coefficients are quantized with an exact division instead of the
+    /// [reciprocals](QuantizationTable::reciprocals), which is the case for 16 bit tables
     #[inline]
-    pub fn get(&self, index: usize) -> u8 {
-        (self.table[index].get() >> 3) as u8
+    pub(crate) fn exact_division(&self) -> bool {
+        self.exact_division
+    }
+
+    /// Reciprocals of the table values scaled by 2^[SHIFT]
+    #[cfg(any(
+        all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")),
+        feature = "std_simd"
+    ))]
+    #[inline]
+    pub(crate) fn reciprocals(&self) -> &[i32; 64] {
+        &self.reciprocals
+    }
+
+    /// Rounding corrections added to the absolute values before multiplying with the reciprocals
+    #[cfg(any(
+        all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")),
+        feature = "std_simd"
+    ))]
+    #[inline]
+    pub(crate) fn corrections(&self) -> &[i32; 64] {
+        &self.corrections
+    }
+
+    /// Convert a block quantized with this table into values of the `target` table
+    ///
+    /// The block is in zig-zag order. The values are rounded to the nearest value of the target,
+    /// so a finer target keeps the precision of this table.
+    pub fn requantize(&self, q_block: &mut [i16; 64], target: &QuantizationTable) {
+        for (value, &index) in q_block.iter_mut().zip(ZIGZAG.iter()) {
+            let divisor = i32::from(target.get(index as usize));
+
+            let product = i32::from(*value) * i32::from(self.get(index as usize));
+            let rounded = (2 * product.abs() + divisor) / (2 * divisor);
+
+            *value = (rounded * product.signum()) as i16;
+        }
+    }
+
+    /// Quantize a coefficient of a 12 bit block, which exceeds the range of `i16`
+    ///
+    /// Uses an exact division because the reciprocals are only precise for 8 bit blocks.
+    pub fn quantize_12bit(&self, value: i32, index: usize) -> i16 {
+        self.quantize_exact(value, index)
+    }
+
+    fn quantize_exact(&self, value: i32, index: usize) -> i16 {
+        let divisor = self.table[index].get() as i32;
+        let reduction = (divisor as f32 * self.dead_zone + 0.5) as i32;
+
+        let quotient = (value.abs() + (divisor / 2 - reduction).max(0)) / divisor;
+
+        (quotient * value.signum()) as i16
     }
 
     #[inline]
     pub fn quantize(&self, in_value: i16, index: usize) -> i16 {
         let value = in_value as i32;
 
+        if self.exact_division {
+            return self.quantize_exact(value, index);
+        }
+
         let reciprocal = self.reciprocals[index];
         let corrections = self.corrections[index];
 
@@ -307,20 +526,145 @@ impl QuantizationTable {
     }
 }
 
+/// Area of the image which is quantized with a different quality
+#[derive(Clone, Debug)]
+pub(crate) struct QualityRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub quality_delta: f32,
+}
+
+impl QualityRegion {
+    fn overlaps(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        self.x < x + width
+            && x < self.x + self.width
+            && self.y < y + height
+            && y < self.y + self.height
+    }
+}
+
+/// Tables used to quantize the blocks inside and outside of quality regions
+pub(crate) struct RegionTables {
+    background: [QuantizationTable; 4],
+
+    // Sorted by descending quality, so the first overlapping region has the maximum boost
+    regions: Vec<(QualityRegion, [QuantizationTable; 4])>,
+}
+
+impl RegionTables {
+    pub fn new(
+        background: [QuantizationTable; 4],
+        mut regions: Vec<(QualityRegion, [QuantizationTable; 4])>,
+    ) -> RegionTables {
+        regions.sort_by(|(a, _), (b, _)| {
+            b.quality_delta
+                .partial_cmp(&a.quality_delta)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        RegionTables {
+            background,
+            regions,
+        }
+    }
+
+    /// Returns the table for a block which covers the given pixels of the image
+    pub fn get(
+        &self,
+        table: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> &QuantizationTable {
+        self.regions
+            .iter()
+            .find(|(region, _)| region.overlaps(x, y, width, height))
+            .map_or(&self.background[table], |(_, tables)| &tables[table])
+    }
+}
+
+/// Quantizes a block with the scalar implementation
+///
+/// The input block is in natural order while the quantized block is in zigzag order.
+#[cfg(feature = "benchmark")]
+pub fn quantize_block(block: &[i16; 64], q_block: &mut [i16; 64], table: &QuantizationTable) {
+    use crate::encoder::{DefaultOperations, Operations};
+
+    DefaultOperations::quantize_block(block, q_block, table);
+}
+
+/// Compares a SIMD implementation of the block quantization with the scalar one
+#[cfg(all(
+    test,
+    any(
+        feature = "std_simd",
+        all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64"))
+    )
+))]
+pub(crate) fn check_quantize_block_parity(
+    quantize_block: fn(&[i16; 64], &mut [i16; 64], &QuantizationTable),
+) {
+    use crate::encoder::{DefaultOperations, Operations};
+
+    let tables = [
+        QuantizationTable::new_with_quality(&QuantizationTableType::Default, 50, true, false),
+        QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, false, false),
+        QuantizationTable::new_with_quality(&QuantizationTableType::Flat, 1, true, false),
+        QuantizationTable::new_with_quality(
+            &QuantizationTableType::Custom(Box::new([255; 64])),
+            100,
+            true,
+            false,
+        ),
+        QuantizationTable::new_with_quality(&QuantizationTableType::Raw([1; 64]), 50, true, false),
+    ];
+
+    let mut block = [0i16; 64];
+    for (i, v) in block.iter_mut().enumerate() {
+        *v = (i as i16 * 397 % 4096 - 2048) * 4;
+    }
+    block[1] = i16::MIN;
+    block[2] = i16::MAX;
+    block[3] = 0;
+
+    for table in tables.iter() {
+        // Tables divided exactly are always quantized by the scalar implementation
+        assert!(!table.exact_division());
+
+        let mut expected = [0i16; 64];
+        DefaultOperations::quantize_block(&block, &mut expected, table);
+
+        let mut q_block = [0i16; 64];
+        quantize_block(&block, &mut q_block, table);
+
+        assert_eq!(q_block, expected);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::quantization::{QuantizationTable, QuantizationTableType};
+    use crate::quantization::{
+        QualityRegion, QuantizationTable, QuantizationTableType, RegionTables,
+    };
+    use crate::writer::ZIGZAG;
+    use alloc::boxed::Box;
+    use alloc::vec;
 
     #[test]
     fn test_new_100() {
-        let q = QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, true);
+        let q =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, true, false);
 
         for &v in &q.table {
             let v = v.get();
             assert_eq!(v, 1 << 3);
         }
 
-        let q = QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, false);
+        let q =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, false, false);
 
         for &v in &q.table {
             let v = v.get();
@@ -328,12 +672,268 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_custom_zero_values() {
+        let mut table = [16; 64];
+        table[0] = 0;
+        table[1] = u16::MAX;
+
+        let q = QuantizationTable::new_with_quality(
+            &QuantizationTableType::Custom(Box::new(table)),
+            50,
+            true,
+            true,
+        );
+
+        assert_eq!(q.get(0), 1);
+        assert_eq!(q.get(1), 255);
+        assert_eq!(q.get(2), 16);
+
+        let q = QuantizationTable::new_with_quality(
+            &QuantizationTableType::Custom(Box::new(table)),
+            50,
+            true,
+            false,
+        );
+
+        assert_eq!(q.get(0), 1);
+        assert_eq!(q.get(1), 2048);
+    }
+
+    #[test]
+    fn test_quantize_16_bit() {
+        let mut table = [16; 64];
+        table[0] = 2500;
+        table[1] = u16::MAX;
+
+        let q = QuantizationTable::new_with_quality(
+            &QuantizationTableType::Raw(table),
+            50,
+            true,
+            false,
+        );
+
+        for value in (i16::MIN + 1..=i16::MAX).step_by(7) {
+            for index in 0..3 {
+                assert_eq!(
+                    q.quantize(value, index),
+                    q.quantize_12bit(value as i32, index),
+                );
+            }
+        }
+
+        assert_eq!(q.quantize(8128, 0), 0);
+        assert_eq!(q.quantize(10000, 0), 1);
+    }
+
+    #[test]
+    fn test_dead_zone() {
+        let mut q =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 75, true, false);
+
+        let count_zeros = |q: &QuantizationTable| {
+            (1..64)
+                .flat_map(|i| (-2048..2048).map(move |v| (i, v)))
+                .filter(|&(i, v)| q.quantize(v, i) == 0)
+                .count()
+        };
+
+        let default_corrections = q.corrections;
+        let mut zeros = count_zeros(&q);
+
+        q.set_dead_zone(0.0);
+        assert_eq!(q.corrections, default_corrections);
+
+        for dead_zone in [0.1, 0.25, 0.5] {
+            q.set_dead_zone(dead_zone);
+
+            let count = count_zeros(&q);
+            assert!(count > zeros);
+            zeros = count;
+        }
+    }
+
+    #[test]
+    fn test_rescale() {
+        let types = [
+            QuantizationTableType::Default,
+            QuantizationTableType::Flat,
+            QuantizationTableType::ImageMagick,
+        ];
+
+        for table_type in types.iter() {
+            for luma in [true, false] {
+                let mut q = QuantizationTable::new_with_quality_f32(table_type, 90.0, luma, false);
+                q.set_dead_zone(0.2);
+
+                for quality in [45.0, 1.0, 100.0, 72.5, 50.0, 0.0, f32::NAN] {
+                    q.rescale(quality);
+
+                    let mut expected =
+                        QuantizationTable::new_with_quality_f32(table_type, quality, luma, false);
+                    expected.set_dead_zone(0.2);
+
+                    assert_eq!(q.table, expected.table);
+                    assert_eq!(q.reciprocals, expected.reciprocals);
+                    assert_eq!(q.corrections, expected.corrections);
+                }
+            }
+        }
+
+        let custom = QuantizationTableType::Custom(Box::new([20; 64]));
+        let mut q = QuantizationTable::new_with_quality_f32(&custom, 90.0, true, false);
+        q.rescale(10.0);
+
+        assert_eq!(q.get(0), 20);
+    }
+
+    #[test]
+    fn test_requantize() {
+        let coarse =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 50, true, false);
+        let fine =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 90, true, false);
+
+        let mut q_block = [0i16; 64];
+        for (i, value) in q_block.iter_mut().enumerate() {
+            *value = i as i16 - 32;
+        }
+
+        let mut requantized = q_block;
+        coarse.requantize(&mut requantized, &fine);
+
+        // The dequantized values are nearly the same
+        for i in 0..64 {
+            let index = ZIGZAG[i] as usize;
+            let expected = i32::from(q_block[i]) * i32::from(coarse.get(index));
+            let value = i32::from(requantized[i]) * i32::from(fine.get(index));
+
+            assert!((expected - value).abs() * 2 <= i32::from(fine.get(index)));
+        }
+
+        // Requantizing with the same table doesn't change anything
+        let mut same = q_block;
+        coarse.requantize(&mut same, &coarse);
+        assert_eq!(same, q_block);
+    }
+
+    #[test]
+    fn test_region_tables() {
+        let create = |quality| {
+            QuantizationTable::new_with_quality(
+                &QuantizationTableType::Default,
+                quality,
+                true,
+                false,
+            )
+        };
+        let tables = |quality| {
+            [
+                create(quality),
+                create(quality),
+                create(quality),
+                create(quality),
+            ]
+        };
+
+        let region = |x, y, quality_delta| QualityRegion {
+            x,
+            y,
+            width: 16,
+            height: 16,
+            quality_delta,
+        };
+
+        let region_tables = RegionTables::new(
+            tables(50),
+            vec![
+                (region(0, 0, 10.0), tables(60)),
+                (region(8, 8, 30.0), tables(80)),
+            ],
+        );
+
+        let quality_of = |x, y| {
+            let table = region_tables.get(0, x, y, 8, 8);
+            [50, 60, 80]
+                .into_iter()
+                .find(|&quality| table.table == create(quality).table)
+                .unwrap()
+        };
+
+        assert_eq!(quality_of(0, 0), 60);
+        assert_eq!(quality_of(32, 0), 50);
+        assert_eq!(quality_of(16, 16), 80);
+
+        // Overlapping regions use the maximum boost
+        assert_eq!(quality_of(8, 8), 80);
+        assert_eq!(quality_of(4, 4), 80);
+        assert_eq!(quality_of(0, 8), 60);
+    }
+
     #[test]
     fn test_new_100_quantize() {
-        let q = QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, true);
+        let q =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 100, true, false);
 
         for i in -255..255 {
             assert_eq!(i, q.quantize(i << 3, 0));
         }
     }
+
+    #[test]
+    fn test_new_with_quality_f32() {
+        for quality in 1..=100 {
+            let q1 = QuantizationTable::new_with_quality(
+                &QuantizationTableType::Default,
+                quality,
+                true,
+                false,
+            );
+            let q2 = QuantizationTable::new_with_quality_f32(
+                &QuantizationTableType::Default,
+                quality as f32,
+                true,
+                false,
+            );
+
+            assert_eq!(q1.table, q2.table);
+        }
+
+        let q = QuantizationTable::new_with_quality_f32(
+            &QuantizationTableType::Default,
+            f32::NAN,
+            true,
+            false,
+        );
+        assert_eq!(q.get(0), 255);
+
+        // Values between two integral qualities are between their tables
+        let q1 =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 30, true, false);
+        let q2 =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 31, true, false);
+        let q = QuantizationTable::new_with_quality_f32(
+            &QuantizationTableType::Default,
+            30.5,
+            true,
+            false,
+        );
+
+        for i in 0..64 {
+            assert!(q.get(i) <= q1.get(i) && q.get(i) >= q2.get(i));
+        }
+        assert_ne!(q.table, q1.table);
+        assert_ne!(q.table, q2.table);
+    }
+
+    #[test]
+    fn test_force_baseline() {
+        let table = QuantizationTableType::Custom(Box::new([1000; 64]));
+
+        let q = QuantizationTable::new_with_quality(&table, 100, true, true);
+
+        for i in 0..64 {
+            assert_eq!(q.get(i), 255);
+        }
+    }
 }
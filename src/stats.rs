@@ -0,0 +1,62 @@
+use alloc::vec::Vec;
+
+/// Byte counts of a single scan
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanStats {
+    /// Index of the component in the scan or `None` for an interleaved scan of all components
+    pub component: Option<usize>,
+
+    /// First and last coefficient of the scan
+    ///
+    /// This is `(0, 63)` for sequential scans.
+    pub spectral_selection: (u8, u8),
+
+    /// Number of entropy coded bytes
+    ///
+    /// Includes restart markers and a DNL segment written after the scan.
+    pub bytes: u64,
+}
+
+/// Byte counts of an encoded image
+///
+/// Available after encoding with [stats](crate::Encoder::stats).
+///
+/// The bytes of an interleaved scan can't be assigned to a single component. Progressive images
+/// and images with sampling factors of 4 are encoded with one scan per component.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EncodingStats {
+    /// All scans in the order they were written
+    pub scans: Vec<ScanStats>,
+
+    /// Bytes of all DQT segments
+    pub quantization_table_bytes: u64,
+
+    /// Bytes of all DHT segments
+    pub huffman_table_bytes: u64,
+
+    /// Number of bytes written for the image
+    pub total_bytes: u64,
+}
+
+impl EncodingStats {
+    /// Returns the entropy coded bytes of all scans that only contain the component
+    pub fn component_bytes(&self, component: usize) -> u64 {
+        self.scans
+            .iter()
+            .filter(|scan| scan.component == Some(component))
+            .map(|scan| scan.bytes)
+            .sum()
+    }
+
+    /// Returns the entropy coded bytes of all scans
+    pub fn scan_bytes(&self) -> u64 {
+        self.scans.iter().map(|scan| scan.bytes).sum()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.scans.clear();
+        self.quantization_table_bytes = 0;
+        self.huffman_table_bytes = 0;
+        self.total_bytes = 0;
+    }
+}
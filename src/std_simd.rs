@@ -0,0 +1,21 @@
+mod fdct;
+mod quantize;
+
+use crate::encoder::Operations;
+use crate::quantization::QuantizationTable;
+pub use fdct::fdct_std_simd;
+pub use quantize::quantize_block_std_simd;
+
+pub(crate) struct StdSimdOperations;
+
+impl Operations for StdSimdOperations {
+    #[inline(always)]
+    fn fdct(data: &mut [i16; 64]) {
+        fdct_std_simd(data);
+    }
+
+    #[inline(always)]
+    fn quantize_block(block: &[i16; 64], q_block: &mut [i16; 64], table: &QuantizationTable) {
+        quantize_block_std_simd(block, q_block, table);
+    }
+}
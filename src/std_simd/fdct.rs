@@ -0,0 +1,183 @@
+/*
+ * Portable SIMD version of the accurate integer forward DCT in fdct.rs
+ *
+ * Like the AVX2 implementation, both passes process all eight rows or columns at once.
+ * The values are kept in 32 bit lanes, so the result is identical to the scalar version.
+ */
+
+use core::simd::i32x8;
+
+const CONST_BITS: i32 = 13;
+const PASS1_BITS: i32 = 2;
+
+const FIX_0_298631336: i32 = 2446;
+const FIX_0_390180644: i32 = 3196;
+const FIX_0_541196100: i32 = 4433;
+const FIX_0_765366865: i32 = 6270;
+const FIX_0_899976223: i32 = 7373;
+const FIX_1_175875602: i32 = 9633;
+const FIX_1_501321110: i32 = 12299;
+const FIX_1_847759065: i32 = 15137;
+const FIX_1_961570560: i32 = 16069;
+const FIX_2_053119869: i32 = 16819;
+const FIX_2_562915447: i32 = 20995;
+const FIX_3_072711026: i32 = 25172;
+
+const DESCALE_P1: i32 = CONST_BITS - PASS1_BITS;
+const DESCALE_P2: i32 = CONST_BITS + PASS1_BITS;
+
+#[inline(always)]
+fn splat(v: i32) -> i32x8 {
+    i32x8::splat(v)
+}
+
+#[inline(always)]
+fn descale(x: i32x8, n: i32) -> i32x8 {
+    // right shift with rounding
+    (x + splat(1 << (n - 1))) >> splat(n)
+}
+
+#[inline(always)]
+fn transpose(rows: [i32x8; 8]) -> [i32x8; 8] {
+    let rows = rows.map(|row| row.to_array());
+
+    let mut columns = [[0i32; 8]; 8];
+
+    for (y, row) in rows.iter().enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            columns[x][y] = value;
+        }
+    }
+
+    columns.map(i32x8::from_array)
+}
+
+/// One dimensional DCT of the values in `data[0]` to `data[7]` for all lanes
+#[inline(always)]
+fn do_dct(first_pass: bool, data: [i32x8; 8]) -> [i32x8; 8] {
+    let tmp0 = data[0] + data[7];
+    let tmp7 = data[0] - data[7];
+    let tmp1 = data[1] + data[6];
+    let tmp6 = data[1] - data[6];
+    let tmp2 = data[2] + data[5];
+    let tmp5 = data[2] - data[5];
+    let tmp3 = data[3] + data[4];
+    let tmp4 = data[3] - data[4];
+
+    // Even part
+
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    let mut out = [splat(0); 8];
+
+    let descale_bits = if first_pass { DESCALE_P1 } else { DESCALE_P2 };
+
+    if first_pass {
+        out[0] = (tmp10 + tmp11) << splat(PASS1_BITS);
+        out[4] = (tmp10 - tmp11) << splat(PASS1_BITS);
+    } else {
+        out[0] = descale(tmp10 + tmp11, PASS1_BITS);
+        out[4] = descale(tmp10 - tmp11, PASS1_BITS);
+    }
+
+    let z1 = (tmp12 + tmp13) * splat(FIX_0_541196100);
+    out[2] = descale(z1 + tmp13 * splat(FIX_0_765366865), descale_bits);
+    out[6] = descale(z1 + tmp12 * splat(-FIX_1_847759065), descale_bits);
+
+    // Odd part
+
+    let z1 = tmp4 + tmp7;
+    let z2 = tmp5 + tmp6;
+    let z3 = tmp4 + tmp6;
+    let z4 = tmp5 + tmp7;
+    let z5 = (z3 + z4) * splat(FIX_1_175875602);
+
+    let tmp4 = tmp4 * splat(FIX_0_298631336);
+    let tmp5 = tmp5 * splat(FIX_2_053119869);
+    let tmp6 = tmp6 * splat(FIX_3_072711026);
+    let tmp7 = tmp7 * splat(FIX_1_501321110);
+    let z1 = z1 * splat(-FIX_0_899976223);
+    let z2 = z2 * splat(-FIX_2_562915447);
+    let z3 = z3 * splat(-FIX_1_961570560);
+    let z4 = z4 * splat(-FIX_0_390180644);
+
+    let z3 = z3 + z5;
+    let z4 = z4 + z5;
+
+    out[7] = descale(tmp4 + z1 + z3, descale_bits);
+    out[5] = descale(tmp5 + z2 + z4, descale_bits);
+    out[3] = descale(tmp6 + z2 + z3, descale_bits);
+    out[1] = descale(tmp7 + z1 + z4, descale_bits);
+
+    out
+}
+
+pub fn fdct_std_simd(data: &mut [i16; 64]) {
+    let mut rows = [splat(0); 8];
+
+    for (row, values) in rows.iter_mut().zip(data.chunks_exact(8)) {
+        let mut array = [0i32; 8];
+
+        for (value, &v) in array.iter_mut().zip(values) {
+            *value = i32::from(v);
+        }
+
+        *row = i32x8::from_array(array);
+    }
+
+    // Pass 1: process rows.
+    // Each vector contains one column of the block, so the lanes are the rows.
+    let columns = do_dct(true, transpose(rows));
+
+    // Pass 2: process columns.
+    // The output of pass 1 contains the columns, so the lanes are the columns after transposing.
+    let rows = do_dct(false, transpose(columns));
+
+    for (row, values) in rows.iter().zip(data.chunks_exact_mut(8)) {
+        for (value, &v) in values.iter_mut().zip(row.as_array()) {
+            *value = v as i16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fdct::fdct;
+    use crate::std_simd::fdct::fdct_std_simd;
+
+    #[test]
+    fn test_fdct_parity() {
+        let mut seed = 0x1234_5678u32;
+
+        for _ in 0..1000 {
+            let mut block = [0i16; 64];
+
+            for value in block.iter_mut() {
+                // Simple LCG to cover the full range of level shifted samples
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                *value = ((seed >> 16) % 256) as i16 - 128;
+            }
+
+            let mut expected = block;
+            fdct(&mut expected);
+
+            fdct_std_simd(&mut block);
+
+            assert_eq!(block, expected);
+        }
+
+        for value in [-128, 0, 127] {
+            let mut block = [value; 64];
+
+            let mut expected = block;
+            fdct(&mut expected);
+
+            fdct_std_simd(&mut block);
+
+            assert_eq!(block, expected);
+        }
+    }
+}
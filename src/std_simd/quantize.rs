@@ -0,0 +1,54 @@
+use core::simd::i32x8;
+use core::simd::num::SimdInt;
+
+use crate::quantization::{QuantizationTable, SHIFT};
+use crate::writer::ZIGZAG;
+
+/// Quantizes a block with the same math as [QuantizationTable::quantize]
+///
+/// The input block is in natural order while the quantized block is in zigzag order.
+pub fn quantize_block_std_simd(
+    block: &[i16; 64],
+    q_block: &mut [i16; 64],
+    table: &QuantizationTable,
+) {
+    let reciprocals = table.reciprocals();
+    let corrections = table.corrections();
+
+    let mut quantized = [0i32; 64];
+
+    for i in (0..64).step_by(8) {
+        let mut values = [0i32; 8];
+
+        for (value, &v) in values.iter_mut().zip(&block[i..i + 8]) {
+            *value = i32::from(v);
+        }
+
+        let value = i32x8::from_array(values);
+        let reciprocal = i32x8::from_slice(&reciprocals[i..i + 8]);
+        let correction = i32x8::from_slice(&corrections[i..i + 8]);
+
+        let product = ((value.abs() + correction) * reciprocal) >> i32x8::splat(SHIFT as i32);
+
+        // Restore the sign: all bits set for negative values, which negates the product
+        let sign = value >> i32x8::splat(31);
+        let product = (product ^ sign) - sign;
+
+        product.copy_to_slice(&mut quantized[i..i + 8]);
+    }
+
+    for i in 0..64 {
+        q_block[i] = quantized[ZIGZAG[i] as usize & 0x3f] as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quantization::check_quantize_block_parity;
+    use crate::std_simd::quantize::quantize_block_std_simd;
+
+    #[test]
+    fn test_quantize_block_parity() {
+        check_quantize_block_parity(quantize_block_std_simd);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::huffman::HuffmanTable;
+use crate::quantization::QuantizationTable;
+use crate::writer::ZIGZAG;
+
+// Lambda parameters based on the defaults of mozjpeg (2^(14.75 - 12) and 2^(16.5 - 12))
+const LAMBDA_SCALE_1: f32 = 6.727_171;
+const LAMBDA_SCALE_2: f32 = 22.627_417;
+
+/// Quantize a block with a rate-distortion optimization of the AC coefficients
+///
+/// The input block is in natural order while the quantized block is in zigzag order.<br>
+/// For each AC coefficient the rounded value and the next value towards zero are candidates.
+/// A Viterbi search over the position of the last non-zero coefficient selects the candidates
+/// with the lowest sum of weighted distortion and bits needed by the AC huffman table.
+pub(crate) fn trellis_quantize_block(
+    block: &[i16; 64],
+    q_block: &mut [i16; 64],
+    table: &QuantizationTable,
+    ac_table: &HuffmanTable,
+) {
+    let mut coefficients = [0f32; 64];
+    let mut quantized = [0i16; 64];
+    let mut weights = [0f32; 64];
+
+    let mut norm = 0.0;
+
+    for i in 0..64 {
+        let z = ZIGZAG[i] as usize & 0x3f;
+
+        coefficients[i] = f32::from(block[z]);
+        quantized[i] = table.quantize(block[z], z);
+
+        if i > 0 {
+            norm += coefficients[i] * coefficients[i];
+        }
+    }
+
+    // Blocks with a lot of energy hide errors better, so the distortion gets less weight
+    let lambda = LAMBDA_SCALE_1 / (LAMBDA_SCALE_2 + norm / 63.0);
+
+    for i in 1..64 {
+        let q = f32::from(table.get(ZIGZAG[i] as usize & 0x3f));
+        weights[i] = lambda / (q * q);
+    }
+
+    // Accumulated distortion if all coefficients up to an index are set to zero
+    let mut zero_costs = [0f32; 64];
+
+    for i in 1..64 {
+        zero_costs[i] = zero_costs[i - 1] + weights[i] * coefficients[i] * coefficients[i];
+    }
+
+    let zrl_bits = ac_table.code_length(0xF0);
+    let eob_bits = ac_table.code_length(0x00);
+
+    // Best cost for coding all coefficients up to an index if this is the last non-zero one.
+    // Index 0 is the start state where no AC coefficient was coded yet.
+    let mut costs = [f32::INFINITY; 64];
+    let mut values = [0i16; 64];
+    let mut previous = [0usize; 64];
+
+    costs[0] = 0.0;
+
+    let mut states = [0usize; 64];
+    let mut num_states = 1;
+
+    for i in 1..64 {
+        let value = quantized[i];
+
+        if value == 0 {
+            continue;
+        }
+
+        let abs_value = value.unsigned_abs();
+        let q = f32::from(table.get(ZIGZAG[i] as usize & 0x3f)) * 8.0;
+
+        let candidates = [abs_value, abs_value - 1];
+
+        for &candidate in &candidates {
+            if candidate == 0 {
+                continue;
+            }
+
+            let size = (16 - candidate.leading_zeros()) as u8;
+
+            let error = abs(coefficients[i]) - f32::from(candidate) * q;
+            let distortion = weights[i] * error * error;
+
+            for &j in &states[..num_states] {
+                let run = i - j - 1;
+
+                let num_zrl = run / 16;
+                if num_zrl > 0 && zrl_bits == 0 {
+                    continue;
+                }
+
+                let symbol = (((run % 16) as u8) << 4) | size;
+                let symbol_bits = ac_table.code_length(symbol);
+                if symbol_bits == 0 {
+                    continue;
+                }
+
+                let bits = num_zrl * usize::from(zrl_bits) + usize::from(symbol_bits + size);
+
+                let cost =
+                    costs[j] + (zero_costs[i - 1] - zero_costs[j]) + bits as f32 + distortion;
+
+                if cost < costs[i] {
+                    costs[i] = cost;
+                    values[i] = if value < 0 {
+                        -(candidate as i16)
+                    } else {
+                        candidate as i16
+                    };
+                    previous[i] = j;
+                }
+            }
+        }
+
+        if costs[i].is_finite() {
+            states[num_states] = i;
+            num_states += 1;
+        }
+    }
+
+    let mut best_cost = f32::INFINITY;
+    let mut last = None;
+
+    for &j in &states[..num_states] {
+        let bits = if j < 63 {
+            if eob_bits == 0 {
+                continue;
+            }
+            eob_bits
+        } else {
+            0
+        };
+
+        let cost = costs[j] + (zero_costs[63] - zero_costs[j]) + f32::from(bits);
+
+        if cost < best_cost {
+            best_cost = cost;
+            last = Some(j);
+        }
+    }
+
+    let mut last = match last {
+        Some(last) => last,
+        None => {
+            // The table can't code any of the candidates, so keep the plain quantization
+            *q_block = quantized;
+            return;
+        }
+    };
+
+    *q_block = [0i16; 64];
+    q_block[0] = quantized[0];
+
+    while last > 0 {
+        q_block[last] = values[last];
+        last = previous[last];
+    }
+}
+
+#[inline]
+fn abs(value: f32) -> f32 {
+    if value < 0.0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoder::{DefaultOperations, Operations};
+    use crate::huffman::HuffmanTable;
+    use crate::quantization::{QuantizationTable, QuantizationTableType};
+    use crate::trellis::trellis_quantize_block;
+
+    #[test]
+    fn test_trellis_quantize_block() {
+        let table =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 75, true, false);
+        let ac_table = HuffmanTable::default_luma_ac();
+
+        let mut block = [0i16; 64];
+        for (i, v) in block.iter_mut().enumerate() {
+            *v = (i as i16 * 397 % 512 - 256) * 4;
+        }
+
+        let mut q_block = [0i16; 64];
+        DefaultOperations::quantize_block(&block, &mut q_block, &table);
+
+        let mut t_block = [0i16; 64];
+        trellis_quantize_block(&block, &mut t_block, &table, &ac_table);
+
+        assert_eq!(q_block[0], t_block[0]);
+
+        // Values are only moved towards zero by at most one step
+        for i in 1..64 {
+            let diff = q_block[i].abs() - t_block[i].abs();
+            assert!(diff == 0 || diff == 1 || t_block[i] == 0);
+            assert!(t_block[i] == 0 || t_block[i].signum() == q_block[i].signum());
+        }
+    }
+
+    #[test]
+    fn test_trellis_quantize_zero_block() {
+        let table =
+            QuantizationTable::new_with_quality(&QuantizationTableType::Default, 75, true, false);
+        let ac_table = HuffmanTable::default_luma_ac();
+
+        let mut block = [0i16; 64];
+        block[0] = 800;
+
+        let mut t_block = [0i16; 64];
+        trellis_quantize_block(&block, &mut t_block, &table, &ac_table);
+
+        assert_eq!(t_block[0], table.quantize(800, 0));
+        assert!(t_block[1..].iter().all(|&v| v == 0));
+    }
+}
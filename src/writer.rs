@@ -4,21 +4,35 @@ use crate::marker::{Marker, SOFType};
 use crate::quantization::QuantizationTable;
 use crate::EncodingError;
 
+use alloc::vec::Vec;
+
 /// Density settings
+///
+/// The density is written into the JFIF APP0 header as a unit and a horizontal and vertical value.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Density {
-    /// No pixel density is set, which means "1 pixel per pixel"
+    /// No physical pixel density is set, which means "1 pixel per pixel"
+    ///
+    /// Written with the unit 0, where the values only define the pixel aspect ratio of 1:1.
     None,
 
     /// Horizontal and vertical dots per inch (dpi)
+    ///
+    /// Written with the unit 1.
     Inch { x: u16, y: u16 },
 
     /// Horizontal and vertical dots per centimeters
+    ///
+    /// Written with the unit 2.
     Centimeter { x: u16, y: u16 },
 }
 
 /// Zig-zag sequence of quantized DCT coefficients
 ///
+/// Maps the position in the zig-zag sequence to the index of the coefficient in a block in
+/// row major order, e.g. `ZIGZAG[2]` is 8, the first coefficient of the second row.
+///
 /// Figure A.6
 pub static ZIGZAG: [u8; 64] = [
     0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
@@ -26,7 +40,8 @@ pub static ZIGZAG: [u8; 64] = [
     52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
 ];
 
-const BUFFER_SIZE: usize = core::mem::size_of::<usize>() * 8;
+// The bit buffer has a fixed width, so the output doesn't depend on the target's pointer width
+const BUFFER_SIZE: usize = 64;
 
 /// A no_std alternative for `std::io::Write`
 ///
@@ -64,10 +79,138 @@ impl<W: std::io::Write + ?Sized> JfifWrite for W {
     }
 }
 
+/// A writer into a fixed size buffer
+///
+/// This allows encoding images without allocating memory for the output, e.g. on embedded targets.
+/// The encoder itself still needs `alloc` for its internal buffers.
+///
+/// Writing more data than fits into the buffer fails with [EncodingError::BufferFull].
+///
+/// # Example
+/// ```
+/// # use jpeg_encoder::EncodingError;
+/// # pub fn main() -> Result<(), EncodingError> {
+/// use jpeg_encoder::{ColorType, Encoder, SliceWriter};
+///
+/// let mut buffer = [0u8; 1024];
+/// let mut writer = SliceWriter::new(&mut buffer);
+///
+/// let encoder = Encoder::new(&mut writer, 90);
+/// encoder.encode(&[255, 0, 0, 0, 255, 0], 2, 1, ColorType::Rgb)?;
+///
+/// let length = writer.bytes_written();
+/// let jpeg = &buffer[..length];
+/// # assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    /// Create a writer which starts writing at the beginning of the buffer
+    pub fn new(buf: &'a mut [u8]) -> SliceWriter<'a> {
+        SliceWriter { buf, pos: 0 }
+    }
+
+    /// Returns the number of bytes written into the buffer
+    pub fn bytes_written(&self) -> usize {
+        self.pos
+    }
+}
+
+impl JfifWrite for SliceWriter<'_> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        let end = self.pos + buf.len();
+
+        if end > self.buf.len() {
+            return Err(EncodingError::BufferFull);
+        }
+
+        self.buf[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+
+        Ok(())
+    }
+}
+
+// Without std this is covered by the implementation for all mutable references
+#[cfg(feature = "std")]
+impl JfifWrite for &mut SliceWriter<'_> {
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        (**self).write_all(buf)
+    }
+}
+
+/// A writer which counts the bytes written into another writer
+///
+/// # Example
+/// ```
+/// # use jpeg_encoder::EncodingError;
+/// # pub fn main() -> Result<(), EncodingError> {
+/// use jpeg_encoder::{ColorType, CountingWriter, Encoder};
+///
+/// let mut writer = CountingWriter::new(Vec::new());
+///
+/// let encoder = Encoder::new(&mut writer, 90);
+/// encoder.encode(&[255, 0, 0, 0, 255, 0], 2, 1, ColorType::Rgb)?;
+///
+/// assert_eq!(writer.bytes_written(), writer.into_inner().len() as u64);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CountingWriter<W: JfifWrite> {
+    w: W,
+    count: u64,
+}
+
+impl<W: JfifWrite> CountingWriter<W> {
+    /// Create a writer which forwards all data to `w`
+    pub fn new(w: W) -> CountingWriter<W> {
+        CountingWriter { w, count: 0 }
+    }
+
+    /// Returns the number of bytes written
+    pub fn bytes_written(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+impl<W: JfifWrite> JfifWrite for CountingWriter<W> {
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        self.w.write_all(buf)?;
+        self.count += buf.len() as u64;
+        Ok(())
+    }
+}
+
+// Without std this is covered by the implementation for all mutable references
+#[cfg(feature = "std")]
+impl<W: JfifWrite> JfifWrite for &mut CountingWriter<W> {
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        (**self).write_all(buf)
+    }
+}
+
 pub(crate) struct JfifWriter<W: JfifWrite> {
     w: W,
-    bit_buffer: usize,
+    bit_buffer: u64,
     free_bits: i8,
+    // Collects the output instead of the writer while set
+    scratch: Option<Vec<u8>>,
+    bytes_written: u64,
 }
 
 impl<W: JfifWrite> JfifWriter<W> {
@@ -76,22 +219,65 @@ impl<W: JfifWrite> JfifWriter<W> {
             w,
             bit_buffer: 0,
             free_bits: BUFFER_SIZE as i8,
+            scratch: None,
+            bytes_written: 0,
+        }
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    /// Returns the underlying writer
+    #[cfg(feature = "async")]
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// Number of bytes written since the writer was created, including scratch output
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Write all following output into `buffer` instead of the writer until
+    /// [end_scratch](JfifWriter::end_scratch) is called
+    pub fn begin_scratch(&mut self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        self.scratch = Some(buffer);
+    }
+
+    /// Stop writing into the scratch buffer and return it
+    pub fn end_scratch(&mut self) -> Vec<u8> {
+        self.scratch.take().unwrap_or_default()
+    }
+
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
+        self.bytes_written += buf.len() as u64;
+
+        match &mut self.scratch {
+            Some(scratch) => {
+                scratch.extend_from_slice(buf);
+                Ok(())
+            }
+            None => self.w.write_all(buf),
         }
     }
 
     #[inline(always)]
     pub fn write(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
-        self.w.write_all(buf)
+        self.write_all(buf)
     }
 
     #[inline(always)]
     pub fn write_u8(&mut self, value: u8) -> Result<(), EncodingError> {
-        self.w.write_all(&[value])
+        self.write_all(&[value])
     }
 
     #[inline(always)]
     pub fn write_u16(&mut self, value: u16) -> Result<(), EncodingError> {
-        self.w.write_all(&value.to_be_bytes())
+        self.write_all(&value.to_be_bytes())
     }
 
     pub fn finalize_bit_buffer(&mut self) -> Result<(), EncodingError> {
@@ -126,7 +312,6 @@ impl<W: JfifWrite> JfifWriter<W> {
     }
 
     #[inline(always)]
-    #[allow(overflowing_literals)]
     fn write_bit_buffer(&mut self) -> Result<(), EncodingError> {
         if (self.bit_buffer
             & 0x8080808080808080
@@ -138,13 +323,13 @@ impl<W: JfifWrite> JfifWriter<W> {
             }
             Ok(())
         } else {
-            self.w.write_all(&self.bit_buffer.to_be_bytes())
+            self.write_all(&self.bit_buffer.to_be_bytes())
         }
     }
 
     pub fn write_bits(&mut self, value: u32, size: u8) -> Result<(), EncodingError> {
         let size = size as i8;
-        let value = value as usize;
+        let value = u64::from(value);
 
         let free_bits = self.free_bits - size;
 
@@ -172,12 +357,16 @@ impl<W: JfifWrite> JfifWriter<W> {
         Ok(())
     }
 
-    pub fn write_header(&mut self, density: &Density) -> Result<(), EncodingError> {
+    pub fn write_header(
+        &mut self,
+        density: &Density,
+        version: (u8, u8),
+    ) -> Result<(), EncodingError> {
         self.write_marker(Marker::APP(0))?;
         self.write_u16(16)?;
 
         self.write(b"JFIF\0")?;
-        self.write(&[0x01, 0x02])?;
+        self.write(&[version.0, version.1])?;
 
         match *density {
             Density::None => {
@@ -232,7 +421,7 @@ impl<W: JfifWrite> JfifWriter<W> {
 
     /// Append a quantization table
     ///
-    /// - `precision`: 0 which means 1 byte per value.
+    /// - `precision`: 0 for 1 byte per value or 1 for 2 bytes per value if any value exceeds 255
     /// - `dest`: 0 for luma or 1 for chroma tables
     ///
     /// Layout:
@@ -250,12 +439,23 @@ impl<W: JfifWrite> JfifWriter<W> {
         assert!(destination < 4, "Bad destination: {}", destination);
 
         self.write_marker(Marker::DQT)?;
-        self.write_u16(2 + 1 + 64)?;
 
-        self.write_u8(destination)?;
+        if table.is_16_bit() {
+            self.write_u16(2 + 1 + 64 * 2)?;
+
+            self.write_u8((1 << 4) | destination)?;
+
+            for &v in ZIGZAG.iter() {
+                self.write_u16(table.get(v as usize))?;
+            }
+        } else {
+            self.write_u16(2 + 1 + 64)?;
+
+            self.write_u8(destination)?;
 
-        for &v in ZIGZAG.iter() {
-            self.write_u8(table.get(v as usize))?;
+            for &v in ZIGZAG.iter() {
+                self.write_u8(table.get(v as usize) as u8)?;
+            }
         }
 
         Ok(())
@@ -267,12 +467,6 @@ impl<W: JfifWrite> JfifWriter<W> {
         self.write_u16(restart_interval)
     }
 
-    #[inline]
-    pub fn huffman_encode(&mut self, val: u8, table: &HuffmanTable) -> Result<(), EncodingError> {
-        let &(size, code) = table.get_for_value(val);
-        self.write_bits(code as u32, size)
-    }
-
     #[inline]
     pub fn huffman_encode_value(
         &mut self,
@@ -297,8 +491,14 @@ impl<W: JfifWrite> JfifWriter<W> {
         dc_table: &HuffmanTable,
         ac_table: &HuffmanTable,
     ) -> Result<(), EncodingError> {
-        self.write_dc(block[0], prev_dc, dc_table)?;
-        self.write_ac_block(block, 1, 64, ac_table)
+        let mut codes = CodeBuffer::default();
+
+        let (size, value) = get_code(block[0] - prev_dc);
+        codes.push_value(self, size, size, value, dc_table)?;
+
+        self.encode_ac(&block[1..], ac_table, &mut codes)?;
+
+        codes.flush(self)
     }
 
     pub fn write_dc(
@@ -322,28 +522,35 @@ impl<W: JfifWrite> JfifWriter<W> {
         end: usize,
         ac_table: &HuffmanTable,
     ) -> Result<(), EncodingError> {
-        let mut zero_run = 0;
+        let mut codes = CodeBuffer::default();
 
-        for &value in &block[start..end] {
-            if value == 0 {
-                zero_run += 1;
-            } else {
-                while zero_run > 15 {
-                    self.huffman_encode(0xF0, ac_table)?;
-                    zero_run -= 16;
-                }
+        self.encode_ac(&block[start..end], ac_table, &mut codes)?;
 
-                let (size, value) = get_code(value);
-                let symbol = (zero_run << 4) | size;
+        codes.flush(self)
+    }
+
+    #[inline(always)]
+    fn encode_ac(
+        &mut self,
+        values: &[i16],
+        ac_table: &HuffmanTable,
+        codes: &mut CodeBuffer,
+    ) -> Result<(), EncodingError> {
+        debug_assert!(values.len() < 64);
 
-                self.huffman_encode_value(size, symbol, value, ac_table)?;
+        for_each_ac_symbol(values, |symbol, size, value| {
+            codes.push_value(self, size, symbol, value, ac_table)
+        })
+    }
 
-                zero_run = 0;
-            }
-        }
+    /// Flushes the bit buffer at the end of a scan
+    ///
+    /// If a number of lines is given it's written in a DNL segment directly after the scan.
+    pub fn finalize_scan(&mut self, num_lines: Option<u16>) -> Result<(), EncodingError> {
+        self.finalize_bit_buffer()?;
 
-        if zero_run > 0 {
-            self.huffman_encode(0x00, ac_table)?;
+        if let Some(num_lines) = num_lines {
+            self.write_segment(Marker::DNL, &num_lines.to_be_bytes())?;
         }
 
         Ok(())
@@ -353,19 +560,15 @@ impl<W: JfifWrite> JfifWriter<W> {
         &mut self,
         width: u16,
         height: u16,
+        precision: u8,
         components: &[Component],
-        progressive: bool,
+        sof_type: SOFType,
     ) -> Result<(), EncodingError> {
-        if progressive {
-            self.write_marker(Marker::SOF(SOFType::ProgressiveDCT))?;
-        } else {
-            self.write_marker(Marker::SOF(SOFType::BaselineDCT))?;
-        }
+        self.write_marker(Marker::SOF(sof_type))?;
 
         self.write_u16(2 + 1 + 2 + 2 + 1 + (components.len() as u16) * 3)?;
 
-        // Precision
-        self.write_u8(8)?;
+        self.write_u8(precision)?;
 
         self.write_u16(height)?;
         self.write_u16(width)?;
@@ -414,6 +617,137 @@ impl<W: JfifWrite> JfifWriter<W> {
     }
 }
 
+/// Returns the magnitude category and the additional bits of a coefficient
+///
+/// The category (SSSS in the JPEG standard) is the number of bits needed for the absolute value
+/// of a DC difference or an AC coefficient. The additional bits contain the value itself for
+/// positive values and the value minus 1 for negative ones, limited to the number of bits of the
+/// category as described in section F.1.2.1.
+///
+/// # Example
+/// ```
+/// use jpeg_encoder::magnitude_category;
+///
+/// assert_eq!(magnitude_category(0), (0, 0));
+/// assert_eq!(magnitude_category(5), (3, 0b101));
+/// assert_eq!(magnitude_category(-5), (3, 0b010));
+/// ```
+pub fn magnitude_category(value: i16) -> (u8, u16) {
+    // Unlike get_code this handles the full range of i16 values
+    let value = i32::from(value);
+
+    let num_bits = 32 - value.unsigned_abs().leading_zeros();
+    let bits = (value - i32::from(value < 0)) & ((1 << num_bits) - 1);
+
+    (num_bits as u8, bits as u16)
+}
+
+/// Collects the Huffman codes of a block to pass them to [JfifWriter::write_bits] in
+/// chunks instead of one call per code
+#[derive(Default)]
+struct CodeBuffer {
+    bits: u32,
+    size: u8,
+}
+
+impl CodeBuffer {
+    // A chunk is passed to JfifWriter::write_bits as a single u32 value. Like a single code
+    // with its value, a chunk has at most 31 bits.
+    const MAX_SIZE: u8 = 31;
+
+    #[inline(always)]
+    fn push<W: JfifWrite>(
+        &mut self,
+        writer: &mut JfifWriter<W>,
+        code: u32,
+        size: u8,
+    ) -> Result<(), EncodingError> {
+        if self.size + size > Self::MAX_SIZE {
+            self.flush(writer)?;
+        }
+
+        self.bits = (self.bits << size) | code;
+        self.size += size;
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn push_value<W: JfifWrite>(
+        &mut self,
+        writer: &mut JfifWriter<W>,
+        size: u8,
+        symbol: u8,
+        value: u16,
+        table: &HuffmanTable,
+    ) -> Result<(), EncodingError> {
+        let &(num_bits, code) = table.get_for_value(symbol);
+        self.push(
+            writer,
+            (u32::from(code) << size) | u32::from(value),
+            num_bits + size,
+        )
+    }
+
+    #[inline(always)]
+    fn flush<W: JfifWrite>(&mut self, writer: &mut JfifWriter<W>) -> Result<(), EncodingError> {
+        if self.size > 0 {
+            writer.write_bits(self.bits, self.size)?;
+            self.bits = 0;
+            self.size = 0;
+        }
+
+        Ok(())
+    }
+}
+
+/// Walks the run length coded huffman symbols of a band of AC coefficients
+///
+/// `symbol` is called with each symbol and the size and bits of the coefficient value that
+/// follows it. Runs of 16 zeros and the end of the band have no value and a size of 0.
+#[inline(always)]
+pub(crate) fn for_each_ac_symbol<E, F>(values: &[i16], mut symbol: F) -> Result<(), E>
+where
+    F: FnMut(u8, u8, u16) -> Result<(), E>,
+{
+    // Bit i is set for a non zero value at position i, so the zero runs can be counted
+    // with trailing_zeros instead of checking every value
+    let mut non_zero = values.iter().enumerate().fold(0u64, |mask, (i, &value)| {
+        mask | (u64::from(value != 0) << i)
+    });
+
+    // Blocks of flat areas often contain no AC data at all
+    if non_zero == 0 {
+        return symbol(0x00, 0, 0);
+    }
+
+    let mut position = 0;
+
+    while non_zero != 0 {
+        let mut zero_run = non_zero.trailing_zeros() as usize;
+
+        position += zero_run;
+        non_zero >>= zero_run;
+
+        while zero_run > 15 {
+            symbol(0xF0, 0, 0)?;
+            zero_run -= 16;
+        }
+
+        let (size, value) = get_code(values[position]);
+        symbol(((zero_run as u8) << 4) | size, size, value)?;
+
+        position += 1;
+        non_zero >>= 1;
+    }
+
+    if position < values.len() {
+        symbol(0x00, 0, 0)?;
+    }
+
+    Ok(())
+}
+
 #[inline]
 pub(crate) fn get_code(value: i16) -> (u8, u16) {
     let temp = value - (value.is_negative() as i16);
@@ -426,7 +760,185 @@ pub(crate) fn get_code(value: i16) -> (u8, u16) {
      */
     let num_bits = 15 - (temp2 << 1 | 1).leading_zeros() as u16;
 
-    let coefficient = temp & ((1 << num_bits as usize) - 1);
+    // The mask is computed with 32 bits for values of 12 bit images which need 15 bits
+    let coefficient = temp & ((1i32 << num_bits) - 1) as i16;
 
     (num_bits as u8, coefficient as u16)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{get_code, JfifWriter};
+    use crate::huffman::HuffmanTable;
+
+    // Writes one code per call to write_bits
+    fn write_ac_reference(
+        writer: &mut JfifWriter<&mut Vec<u8>>,
+        values: &[i16],
+        table: &HuffmanTable,
+    ) {
+        let mut zero_run = 0;
+
+        for &value in values {
+            if value == 0 {
+                zero_run += 1;
+            } else {
+                while zero_run > 15 {
+                    let &(size, code) = table.get_for_value(0xF0);
+                    writer.write_bits(u32::from(code), size).unwrap();
+                    zero_run -= 16;
+                }
+
+                let (size, value) = get_code(value);
+                writer
+                    .huffman_encode_value(size, (zero_run << 4) | size, value, table)
+                    .unwrap();
+
+                zero_run = 0;
+            }
+        }
+
+        if zero_run > 0 {
+            let &(size, code) = table.get_for_value(0x00);
+            writer.write_bits(u32::from(code), size).unwrap();
+        }
+    }
+
+    fn test_blocks() -> Vec<[i16; 64]> {
+        let mut blocks = Vec::new();
+
+        blocks.push([0; 64]);
+        blocks.push([-1023; 64]);
+        blocks.push([1023; 64]);
+
+        let mut block = [0; 64];
+        block[0] = 2047;
+        block[17] = -1;
+        block[63] = 1;
+        blocks.push(block);
+
+        let mut block = [0; 64];
+        block[1] = 5;
+        block[40] = -300;
+        blocks.push(block);
+
+        let mut block = [0; 64];
+        for (i, v) in block.iter_mut().enumerate() {
+            *v = match i % 7 {
+                0 => (i as i16 * 31) % 1024,
+                3 => -(i as i16 * 17) % 1024,
+                _ => 0,
+            };
+        }
+        blocks.push(block);
+
+        blocks
+    }
+
+    #[test]
+    fn test_write_bits() {
+        let mut data = Vec::new();
+        let mut writer = JfifWriter::new(&mut data);
+
+        // Writes bits one by one, so the bytes don't depend on the width of the bit buffer
+        let mut bits = Vec::new();
+
+        for i in 0..500u32 {
+            let size = (i * 7 % 31 + 1) as u8;
+            let value = if i % 3 == 0 {
+                (1 << size) - 1
+            } else {
+                i.wrapping_mul(0x9E37_79B9) >> (32 - size)
+            };
+
+            writer.write_bits(value, size).unwrap();
+            bits.extend((0..size).rev().map(|bit| (value >> bit) & 1 == 1));
+        }
+        writer.finalize_bit_buffer().unwrap();
+
+        // Padding with 1 bits
+        while bits.len() % 8 != 0 {
+            bits.push(true);
+        }
+
+        let mut expected = Vec::new();
+        for byte in bits.chunks(8) {
+            let byte = byte
+                .iter()
+                .fold(0u8, |byte, &bit| (byte << 1) | u8::from(bit));
+
+            expected.push(byte);
+            if byte == 0xFF {
+                expected.push(0x00);
+            }
+        }
+
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_write_block() {
+        let dc_table = HuffmanTable::default_luma_dc();
+        let ac_table = HuffmanTable::default_luma_ac();
+
+        let mut expected = Vec::new();
+        let mut reference = JfifWriter::new(&mut expected);
+
+        let mut prev_dc = 0;
+        for block in test_blocks() {
+            reference.write_dc(block[0], prev_dc, &dc_table).unwrap();
+            write_ac_reference(&mut reference, &block[1..], &ac_table);
+            prev_dc = block[0];
+        }
+        reference.finalize_scan(None).unwrap();
+
+        let mut data = Vec::new();
+        let mut writer = JfifWriter::new(&mut data);
+
+        let mut prev_dc = 0;
+        for block in test_blocks() {
+            writer
+                .write_block(&block, prev_dc, &dc_table, &ac_table)
+                .unwrap();
+            prev_dc = block[0];
+        }
+        writer.finalize_scan(None).unwrap();
+
+        assert_eq!(data, expected);
+
+        // The large values must produce 0xFF bytes that are followed by a stuffed zero
+        assert!(data.windows(2).any(|bytes| bytes == [0xFF, 0x00]));
+        assert!(!data
+            .windows(2)
+            .any(|bytes| bytes[0] == 0xFF && bytes[1] != 0x00));
+    }
+
+    #[test]
+    fn test_write_ac_block() {
+        let ac_table = HuffmanTable::default_chroma_ac();
+
+        for &(start, end) in &[(1, 64), (1, 6), (6, 64), (40, 64), (63, 64)] {
+            let mut expected = Vec::new();
+            let mut reference = JfifWriter::new(&mut expected);
+
+            for block in test_blocks() {
+                write_ac_reference(&mut reference, &block[start..end], &ac_table);
+            }
+            reference.finalize_scan(None).unwrap();
+
+            let mut data = Vec::new();
+            let mut writer = JfifWriter::new(&mut data);
+
+            for block in test_blocks() {
+                writer
+                    .write_ac_block(&block, start, end, &ac_table)
+                    .unwrap();
+            }
+            writer.finalize_scan(None).unwrap();
+
+            assert_eq!(data, expected);
+        }
+    }
+}